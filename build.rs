@@ -0,0 +1,30 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+// Build scripts may only write to OUT_DIR. The copy of the header checked in under include/ is
+// regenerated with the cbindgen command line tool, and CI checks that it matches src/ffi.rs.
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    use std::path::PathBuf;
+
+    let crate_directory = PathBuf::from(
+        std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR should be set by Cargo"),
+    );
+    let out_directory =
+        PathBuf::from(std::env::var("OUT_DIR").expect("OUT_DIR should be set by Cargo"));
+
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(crate_directory.join("cbindgen.toml"))
+        .expect("cbindgen.toml should be a valid cbindgen configuration");
+
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_directory.join("src").join("ffi.rs"))
+        .generate()
+        .expect("C header generation should succeed")
+        .write_to_file(out_directory.join("video_encoding_wrapper.h"));
+}
@@ -0,0 +1,83 @@
+#ifndef VIDEO_ENCODING_WRAPPER_H
+#define VIDEO_ENCODING_WRAPPER_H
+
+/* This file is generated by cbindgen from src/ffi.rs. Do not edit it manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Opaque configuration handle built up from command line style arguments.
+ */
+typedef struct VewConfig VewConfig;
+
+/**
+ * Called with the stage name, the current position and the total length (or 0 if unknown).
+ */
+typedef void (*VewProgressCallback)(const char *stage,
+                                    uint64_t position,
+                                    uint64_t length,
+                                    void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a configuration for encoding `source` into `output_directory`. Returns null on failure.
+ *
+ * # Safety
+ *
+ * Both arguments must be valid NUL-terminated strings. The returned handle must be released with
+ * `vew_config_free`.
+ */
+struct VewConfig *vew_config_new(const char *source,
+                                 const char *output_directory);
+
+/**
+ * Appends a command line argument such as `--encoder` or `svt-av1`. Returns 0 on success.
+ *
+ * # Safety
+ *
+ * `config` must be a handle returned by `vew_config_new` and `argument` a valid NUL-terminated
+ * string.
+ */
+int vew_config_add_argument(struct VewConfig *config,
+                            const char *argument);
+
+/**
+ * Releases a configuration handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * `config` must be null or a handle returned by `vew_config_new` that has not yet been freed.
+ */
+void vew_config_free(struct VewConfig *config);
+
+/**
+ * Runs the full pipeline. Returns 0 on success, -1 on failure or -2 if the run panicked, with
+ * details available from `vew_last_error`.
+ *
+ * # Safety
+ *
+ * `config` must be a handle returned by `vew_config_new`. The callback, if provided, is invoked
+ * from worker threads and must be safe to call concurrently with `user_data`.
+ */
+int vew_run(const struct VewConfig *config,
+            VewProgressCallback callback,
+            void *user_data);
+
+/**
+ * Returns the last error message on the calling thread, or null if there has been none. The
+ * pointer remains valid until the next failing call on the same thread.
+ */
+const char *vew_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VIDEO_ENCODING_WRAPPER_H */
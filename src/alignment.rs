@@ -0,0 +1,117 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{anyhow, Context};
+
+use crate::control::track;
+use crate::ffmpeg::{create_child_read, SourceRange};
+
+// An 8x8 average hash survives encoding and scaling, but two neighbouring frames with any motion
+// between them already differ in a good number of its bits.
+const HASH_FILTER: &str = "scale=8:8:flags=area";
+
+// Hashes of the same frame before and after a reasonable encode differ in only a few bits.
+const MAXIMUM_DISTANCE: u32 = 12;
+
+// Each bit of the hash says whether one pixel of the tiny frame is brighter than their mean.
+fn average_hash(plane: &[u8]) -> u64 {
+    let sum = plane.iter().map(|pixel| u64::from(*pixel)).sum::<u64>();
+    let count = u64::try_from(plane.len().max(1)).unwrap_or(u64::MAX);
+
+    plane
+        .iter()
+        .take(64)
+        .enumerate()
+        .filter(|(_, pixel)| u64::from(**pixel) * count > sum)
+        .fold(0, |hash, (bit, _)| hash | (1 << bit))
+}
+
+// Returns the hashes of the first and last frames and the number of frames decoded.
+fn edge_hashes(
+    input: &Path,
+    filter: Option<&str>,
+    range: Option<SourceRange>,
+) -> anyhow::Result<(u64, u64, usize)> {
+    let filter = filter.map_or_else(
+        || HASH_FILTER.to_owned(),
+        |filter| format!("{filter},{HASH_FILTER}"),
+    );
+
+    let mut child = create_child_read(
+        input,
+        Some(&filter),
+        "yuv420p",
+        range,
+        Stdio::null(),
+        Stdio::piped(),
+        Stdio::null(),
+    )
+    .context("Unable to spawn alignment decoder subprocess")?;
+
+    let tracker = track(&child);
+
+    let mut decoder = y4m::Decoder::new(
+        child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("Unable to access stdout for alignment decoder"))?,
+    )
+    .context("Unable to create alignment YUV4MPEG decoder")?;
+
+    let mut first = None;
+    let mut last = 0;
+    let mut frames = 0_usize;
+
+    while let Ok(frame) = decoder.read_frame() {
+        last = average_hash(frame.get_y_plane());
+        first.get_or_insert(last);
+        frames += 1;
+    }
+
+    tracker
+        .wait(&mut child)
+        .context("Unable to wait for alignment decoder subprocess")?;
+
+    let first = first.ok_or_else(|| anyhow!("Alignment decoder produced no frames"))?;
+
+    Ok((first, last, frames))
+}
+
+/// Compares the first and last frames of an encoded clip with those of its reference.
+///
+/// Scores measured against a reference shifted by even one frame look like a loss of quality,
+/// so a clear mismatch is reported as an error instead.
+pub fn verify(
+    distorted: &Path,
+    reference: &Path,
+    reference_filter: Option<&str>,
+    reference_range: Option<SourceRange>,
+) -> anyhow::Result<()> {
+    let (distorted_first, distorted_last, distorted_frames) = edge_hashes(distorted, None, None)
+        .with_context(|| format!("Unable to hash frames of {distorted:?}"))?;
+
+    let (reference_first, reference_last, reference_frames) =
+        edge_hashes(reference, reference_filter, reference_range)
+            .with_context(|| format!("Unable to hash frames of {reference:?}"))?;
+
+    if distorted_frames != reference_frames {
+        return Err(anyhow!(
+            "{distorted:?} has {distorted_frames} frames but its reference has {reference_frames}"
+        ));
+    }
+
+    for (position, distorted_hash, reference_hash) in [
+        ("first", distorted_first, reference_first),
+        ("last", distorted_last, reference_last),
+    ] {
+        let distance = (distorted_hash ^ reference_hash).count_ones();
+
+        if distance > MAXIMUM_DISTANCE {
+            return Err(anyhow!(
+                "The {position} frame of {distorted:?} does not match its reference ({distance} of 64 hash bits differ)"
+            ));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::GlobalObjective;
+
+// Bounds of the Lagrange multiplier search, in kbps per metric point. They are wide enough to reach
+// both ends of every scene's candidates for any of the supported metrics.
+const MINIMUM_LAMBDA: f64 = 1e-6;
+const MAXIMUM_LAMBDA: f64 = 1e9;
+const SEARCH_ITERATIONS: usize = 100;
+
+/// A probe encode of a scene, with its metric value and bitrate in kbps.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RatePoint {
+    pub quality: f64,
+    pub metric: f64,
+    pub bitrate: f64,
+}
+
+/// The measured rate-distortion behavior of one scene.
+pub struct SceneModel {
+    pub index: usize,
+    pub frames: f64,
+    pub points: Vec<RatePoint>,
+    pub floor: Option<f64>,
+}
+
+impl SceneModel {
+    // Between two probes the metric is interpolated linearly and the bitrate geometrically, since
+    // bitrate grows roughly exponentially as the quality value improves. Nothing is extrapolated.
+    fn predict(&self, quality: f64) -> Option<(f64, f64)> {
+        let mut points = self.points.clone();
+        points.sort_by(|a, b| a.quality.total_cmp(&b.quality));
+
+        if let [point] = points.as_slice() {
+            return ((point.quality - quality).abs() < f64::EPSILON)
+                .then_some((point.metric, point.bitrate));
+        }
+
+        points.windows(2).find_map(|pair| {
+            let [low, high] = pair else {
+                return None;
+            };
+
+            if quality < low.quality || quality > high.quality {
+                return None;
+            }
+
+            let span = high.quality - low.quality;
+            let position = if span > 0.0 {
+                (quality - low.quality) / span
+            } else {
+                0.0
+            };
+
+            let metric = (high.metric - low.metric).mul_add(position, low.metric);
+            let bitrate = if low.bitrate > 0.0 && high.bitrate > 0.0 {
+                (high.bitrate.ln() - low.bitrate.ln())
+                    .mul_add(position, low.bitrate.ln())
+                    .exp()
+            } else {
+                (high.bitrate - low.bitrate).mul_add(position, low.bitrate)
+            };
+
+            Some((metric, bitrate))
+        })
+    }
+}
+
+/// Per-scene qualities chosen together, with the predicted length-weighted metric and bitrate.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Allocation {
+    pub qualities: BTreeMap<usize, f64>,
+    pub metric: f64,
+    pub bitrate: f64,
+    pub lambda: f64,
+    pub satisfied: bool,
+}
+
+struct Candidate {
+    quality: f64,
+    metric: f64,
+    bitrate: f64,
+}
+
+// A scene's per-scene target acts as a floor, unless no candidate reaches it, in which case the
+// scene is free to go wherever the trade-off puts it.
+fn scene_candidates(model: &SceneModel, qualities: &[f64]) -> Vec<Candidate> {
+    let candidates = qualities
+        .iter()
+        .filter_map(|&quality| {
+            model.predict(quality).map(|(metric, bitrate)| Candidate {
+                quality,
+                metric,
+                bitrate,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    match model.floor {
+        Some(floor) if candidates.iter().any(|candidate| candidate.metric >= floor) => candidates
+            .into_iter()
+            .filter(|candidate| candidate.metric >= floor)
+            .collect(),
+        _ => candidates,
+    }
+}
+
+fn allocate_for(scenes: &[(&SceneModel, Vec<Candidate>)], lambda: f64) -> Allocation {
+    let mut allocation = Allocation {
+        lambda,
+        ..Allocation::default()
+    };
+
+    let mut frames = 0.0;
+
+    for (model, candidates) in scenes {
+        let Some(choice) = candidates.iter().min_by(|a, b| {
+            (-lambda)
+                .mul_add(a.metric, a.bitrate)
+                .total_cmp(&(-lambda).mul_add(b.metric, b.bitrate))
+        }) else {
+            continue;
+        };
+
+        allocation.qualities.insert(model.index, choice.quality);
+        allocation.metric += choice.metric * model.frames;
+        allocation.bitrate += choice.bitrate * model.frames;
+        frames += model.frames;
+    }
+
+    if frames > 0.0 {
+        allocation.metric /= frames;
+        allocation.bitrate /= frames;
+    }
+
+    allocation
+}
+
+/// Chooses a quality for every scene from the candidates.
+///
+/// Each scene takes the candidate that minimizes its bitrate less lambda times its metric value,
+/// which traces out the best trade-offs between total bitrate and length-weighted metric as lambda
+/// grows. Lambda is then bisected until the objective's constraint is just met.
+#[must_use]
+pub fn allocate(
+    models: &[SceneModel],
+    qualities: &[f64],
+    objective: GlobalObjective,
+    limit: f64,
+) -> Allocation {
+    let scenes = models
+        .iter()
+        .map(|model| (model, scene_candidates(model, qualities)))
+        .collect::<Vec<_>>();
+
+    let meets_limit = |allocation: &Allocation| match objective {
+        GlobalObjective::Bitrate => allocation.metric >= limit,
+        GlobalObjective::Quality => allocation.bitrate <= limit,
+    };
+
+    // The metric rises with lambda, so the cheapest allocation meeting a quality target is found at
+    // the smallest satisfying lambda and the best one within a bitrate budget at the largest.
+    let (mut low, mut high) = (MINIMUM_LAMBDA.ln(), MAXIMUM_LAMBDA.ln());
+    let mut best = None;
+
+    for _ in 0..SEARCH_ITERATIONS {
+        let middle = (low + high) / 2.0;
+        let allocation = allocate_for(&scenes, middle.exp());
+        let satisfied = meets_limit(&allocation);
+
+        match (objective, satisfied) {
+            (GlobalObjective::Bitrate, true) | (GlobalObjective::Quality, false) => high = middle,
+            (GlobalObjective::Bitrate, false) | (GlobalObjective::Quality, true) => low = middle,
+        }
+
+        if satisfied {
+            best = Some(allocation);
+        }
+    }
+
+    best.map_or_else(
+        || {
+            let extreme = match objective {
+                GlobalObjective::Bitrate => MAXIMUM_LAMBDA,
+                GlobalObjective::Quality => MINIMUM_LAMBDA,
+            };
+
+            allocate_for(&scenes, extreme)
+        },
+        |allocation| Allocation {
+            satisfied: true,
+            ..allocation
+        },
+    )
+}
@@ -0,0 +1,158 @@
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::control::track;
+use crate::ffmpeg::{create_child_read, SourceRange};
+use crate::util::{read_json, verify_filename, write_json};
+
+// The statistics are coarse enough that a downscaled decode gives the same answer much faster.
+const ANALYSIS_FILTER: &str = "scale=-2:'min(540,ih)'";
+
+// Luma steps between neighbouring pixels, in 8-bit code values, below which an area counts as flat
+// and above which a step counts as a hard edge.
+const FLAT_STEP: u8 = 2;
+const EDGE_STEP: u8 = 32;
+
+// Mean absolute luma difference below which a frame counts as a repeat of the previous one.
+const DUPLICATE_DIFFERENCE: f64 = 0.5;
+
+const MINIMUM_FLAT_FRACTION: f64 = 0.5;
+const MINIMUM_EDGE_FRACTION: f64 = 0.15;
+const MINIMUM_DUPLICATE_FRACTION: f64 = 0.25;
+
+/// Texture and motion statistics of a single scene.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct AnimationStatistics {
+    pub flat_fraction: f64,
+    pub edge_fraction: f64,
+    pub duplicate_fraction: f64,
+}
+
+impl AnimationStatistics {
+    // Animation is mostly flat fills bounded by hard line art, and is often drawn on twos or threes,
+    // so large flat areas plus either strong edges or many repeated frames are taken as animation.
+    #[must_use]
+    pub fn animated(&self) -> bool {
+        self.flat_fraction >= MINIMUM_FLAT_FRACTION
+            && (self.edge_fraction >= MINIMUM_EDGE_FRACTION
+                || self.duplicate_fraction >= MINIMUM_DUPLICATE_FRACTION)
+    }
+}
+
+/// Animation analysis of a single scene and whether the animation tuning was applied to it.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct AnimationDecision {
+    #[serde(flatten)]
+    pub statistics: AnimationStatistics,
+    pub animated: bool,
+}
+
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+pub fn analyze(
+    input: &Path,
+    filter: Option<&str>,
+    range: Option<SourceRange>,
+    cache_path: &Path,
+) -> anyhow::Result<AnimationStatistics> {
+    verify_filename(cache_path).with_context(|| {
+        format!("Unable to verify animation analysis cache path {cache_path:?}")
+    })?;
+
+    if let Some(statistics) = read_json(cache_path)
+        .with_context(|| format!("Unable to read animation analysis cache {cache_path:?}"))?
+    {
+        return Ok(statistics);
+    }
+
+    let filter = filter.map_or_else(
+        || ANALYSIS_FILTER.to_owned(),
+        |filter| format!("{filter},{ANALYSIS_FILTER}"),
+    );
+
+    let mut child = create_child_read(
+        input,
+        Some(&filter),
+        "yuv420p",
+        range,
+        Stdio::null(),
+        Stdio::piped(),
+        Stdio::null(),
+    )
+    .context("Unable to spawn animation analysis decoder subprocess")?;
+
+    let tracker = track(&child);
+
+    let mut decoder = y4m::Decoder::new(
+        child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("Unable to access stdout for animation analysis decoder"))?,
+    )
+    .context("Unable to create animation analysis YUV4MPEG decoder")?;
+
+    let width = decoder.get_width();
+
+    let mut flat_steps = 0_usize;
+    let mut edge_steps = 0_usize;
+    let mut total_steps = 0_usize;
+    let mut frames = 0_usize;
+    let mut duplicate_frames = 0_usize;
+    let mut previous_plane: Option<Vec<u8>> = None;
+
+    while let Ok(frame) = decoder.read_frame() {
+        let plane = frame.get_y_plane();
+
+        // Every other row is plenty to estimate the texture of a frame.
+        for row in plane.chunks_exact(width).step_by(2) {
+            for pair in row.windows(2) {
+                let step = pair[0].abs_diff(pair[1]);
+
+                if step <= FLAT_STEP {
+                    flat_steps += 1;
+                } else if step >= EDGE_STEP {
+                    edge_steps += 1;
+                }
+
+                total_steps += 1;
+            }
+        }
+
+        if let Some(previous_plane) = &previous_plane {
+            let difference: u64 = plane
+                .iter()
+                .zip(previous_plane)
+                .map(|(current, previous)| u64::from(current.abs_diff(*previous)))
+                .sum();
+
+            if (difference as f64 / plane.len().max(1) as f64) < DUPLICATE_DIFFERENCE {
+                duplicate_frames += 1;
+            }
+        }
+
+        previous_plane = Some(plane.to_vec());
+        frames += 1;
+    }
+
+    tracker
+        .wait(&mut child)
+        .context("Unable to wait for animation analysis decoder subprocess")?;
+
+    if frames == 0 {
+        return Err(anyhow!("Animation analysis decoder produced no frames"));
+    }
+
+    let statistics = AnimationStatistics {
+        flat_fraction: flat_steps as f64 / total_steps.max(1) as f64,
+        edge_fraction: edge_steps as f64 / (total_steps - flat_steps).max(1) as f64,
+        duplicate_fraction: duplicate_frames as f64 / (frames - 1).max(1) as f64,
+    };
+
+    write_json(cache_path, &statistics)
+        .with_context(|| format!("Unable to write animation analysis cache {cache_path:?}"))?;
+
+    Ok(statistics)
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Context};
+use ffmpeg::{format, media};
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::tools::command;
+use crate::util::{hash_source, verify_directory};
+
+// libopus rejects some common layouts by name, such as 5.1(side), so every layout is mapped onto
+// the nearest one it accepts.
+const OPUS_LAYOUT_FILTER: &str = "aformat=channel_layouts=7.1|5.1|stereo|mono";
+
+/// An audio transcode running in the background while the video encodes.
+///
+/// If the encode fails before the audio is collected, dropping this waits for the transcode to
+/// finish, so its subprocess never outlives the encode.
+pub struct Transcode(Option<JoinHandle<anyhow::Result<Option<PathBuf>>>>);
+
+impl Transcode {
+    /// Starts transcoding the source audio on its own thread.
+    #[must_use]
+    pub fn spawn(config: &Config) -> Self {
+        let config = config.clone();
+
+        Self(Some(thread::spawn(move || transcode(&config))))
+    }
+
+    /// Waits for the transcode and returns its result.
+    pub fn join(mut self) -> anyhow::Result<Option<PathBuf>> {
+        self.0
+            .take()
+            .ok_or_else(|| anyhow!("Unreachable code reached"))?
+            .join()
+            .map_err(|error| anyhow!("Audio transcoding thread panicked: {error:?}"))?
+            .context("Unable to transcode source audio")
+    }
+}
+
+impl Drop for Transcode {
+    fn drop(&mut self) {
+        if let Some(handle) = self.0.take() {
+            drop(handle.join());
+        }
+    }
+}
+
+/// Transcodes every audio track of the source for muxing alongside the video, returning `None` if
+/// no audio encoder was requested or the source has no audio.
+///
+/// The result is kept beside the other caches and named after the source and the audio settings,
+/// so a resumed run reuses it.
+fn transcode(config: &Config) -> anyhow::Result<Option<PathBuf>> {
+    let Some(audio_encoder) = config.audio_encoder else {
+        return Ok(None);
+    };
+
+    let input_context = format::input(&config.source)
+        .with_context(|| format!("Unable to open {:?} with FFmpeg", &config.source))?;
+
+    if !input_context
+        .streams()
+        .any(|stream| stream.parameters().medium() == media::Type::Audio)
+    {
+        warn!("The source has no audio, so the output will only contain video");
+        return Ok(None);
+    }
+
+    let audio_directory = config.output_directory.join("audio");
+
+    verify_directory(&audio_directory)
+        .with_context(|| format!("Unable to verify audio directory {audio_directory:?}"))?;
+
+    let source_hash = hash_source(&config.source)
+        .with_context(|| format!("Unable to hash source file {:?}", &config.source))?;
+
+    let audio_path = audio_directory.join(format!(
+        "{source_hash}-{audio_encoder}-{}.mka",
+        config.audio_bitrate
+    ));
+
+    if audio_path.exists() {
+        return Ok(Some(audio_path));
+    }
+
+    let temporary_path = audio_path.with_extension("tmp.mka");
+
+    debug!("Transcoding audio to {audio_path:?}");
+
+    let output = command("ffmpeg")
+        .arg("-y")
+        .arg("-i")
+        .arg(&config.source)
+        .args(["-map", "0:a", "-vn", "-sn", "-dn"])
+        .args(["-af", OPUS_LAYOUT_FILTER])
+        .args(["-c:a", audio_encoder.codec(), "-b:a", &config.audio_bitrate])
+        .arg(&temporary_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Unable to run audio transcoding subprocess")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Audio transcoding did not complete successfully: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    fs::rename(&temporary_path, &audio_path)
+        .with_context(|| format!("Unable to rename {temporary_path:?} to {audio_path:?}"))?;
+
+    Ok(Some(audio_path))
+}
@@ -0,0 +1,365 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use indicatif::HumanBytes;
+use prettytable::{format::consts, row, Cell, Table};
+use serde::{Deserialize, Serialize};
+use statrs::statistics::{Data, Distribution};
+
+use crate::config::Config;
+use crate::ffmpeg::get_metadata;
+use crate::presplit;
+use crate::util::{
+    generate_scene_chart, hash_string, verify_directory, verify_filename, write_json, HumanBitrate,
+};
+
+// Everything the roll-up needs from one encode. Titles are keyed by the full source path, so
+// re-encoding a title replaces its earlier entry instead of counting it twice, while sources that
+// only share a file name are kept apart.
+#[derive(Serialize, Deserialize)]
+struct TitleSummary {
+    title: String,
+    encode_identifier: String,
+    duration: f64,
+    source_size: u64,
+    output_size: u64,
+    scenes: usize,
+    mean_quality: Option<f64>,
+    metrics: BTreeMap<String, f64>,
+}
+
+// Titles without a duration or a source size have no meaningful rate or saving, so they are
+// reported as not available rather than as infinite.
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+fn bitrate(output_size: u64, duration: f64) -> Option<f64> {
+    (duration > 0.0).then(|| output_size as f64 * 8.0 / duration)
+}
+
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+fn saved(source_size: u64, output_size: u64) -> Option<f64> {
+    (source_size > 0).then(|| 100.0 * (1.0 - output_size as f64 / source_size as f64))
+}
+
+impl TitleSummary {
+    fn bitrate(&self) -> Option<f64> {
+        bitrate(self.output_size, self.duration)
+    }
+
+    fn saved(&self) -> Option<f64> {
+        saved(self.source_size, self.output_size)
+    }
+}
+
+// Pre-split chunks stand in for the source, whose index alone says nothing about its size.
+fn source_size(config: &Config) -> anyhow::Result<u64> {
+    let paths = if config.pre_split {
+        presplit::load(config)
+            .context("Unable to load chunk index")?
+            .into_iter()
+            .map(|chunk| chunk.file)
+            .collect()
+    } else {
+        vec![config.source.clone()]
+    };
+
+    paths.iter().try_fold(0, |total, path| {
+        Ok(total
+            + fs::metadata(path)
+                .with_context(|| format!("Unable to read source file size {path:?}"))?
+                .len())
+    })
+}
+
+fn read_summaries(path: &Path) -> anyhow::Result<Vec<TitleSummary>> {
+    let mut summaries = vec![];
+
+    for entry in
+        fs::read_dir(path).with_context(|| format!("Unable to read batch directory {path:?}"))?
+    {
+        let entry_path = entry
+            .with_context(|| format!("Unable to read entry in batch directory {path:?}"))?
+            .path();
+
+        if entry_path
+            .extension()
+            .is_none_or(|extension| extension != "json")
+        {
+            continue;
+        }
+
+        let file = File::open(&entry_path)
+            .with_context(|| format!("Unable to open title summary {entry_path:?}"))?;
+
+        summaries.push(
+            serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("Unable to deserialize title summary {entry_path:?}"))?,
+        );
+    }
+
+    summaries.sort_by(|a: &TitleSummary, b| a.title.cmp(&b.title));
+
+    Ok(summaries)
+}
+
+// Records this encode in the batch directory and regenerates the roll-up from every title recorded
+// there so far, so the report is complete after whichever title finishes last.
+pub fn update(
+    config: &Config,
+    batch_directory: &Path,
+    output_path: &Path,
+    scene_qualities: &BTreeMap<usize, f64>,
+    metrics: &[(String, Vec<f64>)],
+) -> anyhow::Result<()> {
+    let metadata = get_metadata(config).context("Unable to fetch video metadata")?;
+    let titles_path = batch_directory.join("titles");
+
+    verify_directory(&titles_path)
+        .with_context(|| format!("Unable to verify batch directory {titles_path:?}"))?;
+
+    let title = config.source.file_stem().map_or_else(
+        || config.source.to_string_lossy().into_owned(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+
+    let summary = TitleSummary {
+        title: title.clone(),
+        encode_identifier: config.encode_identifier(true),
+        duration: metadata.duration,
+        source_size: source_size(config)?,
+        output_size: fs::metadata(output_path)
+            .with_context(|| format!("Unable to read output file size {output_path:?}"))?
+            .len(),
+        scenes: scene_qualities.len(),
+        mean_quality: Data::new(scene_qualities.values().copied().collect::<Vec<_>>()).mean(),
+        metrics: metrics
+            .iter()
+            .filter_map(|(name, values)| {
+                Data::new(values.clone())
+                    .mean()
+                    .map(|mean| (name.clone(), mean))
+            })
+            .collect(),
+    };
+
+    let source_path = fs::canonicalize(&config.source).unwrap_or_else(|_| config.source.clone());
+    let summary_path = titles_path.join(format!(
+        "{title}-{}.json",
+        &hash_string(&source_path.to_string_lossy())[..8]
+    ));
+
+    write_json(&summary_path, &summary)
+        .with_context(|| format!("Unable to write title summary {summary_path:?}"))?;
+
+    let summaries = read_summaries(&titles_path).context("Unable to read title summaries")?;
+
+    let metric_names: Vec<String> = summaries
+        .iter()
+        .flat_map(|summary| summary.metrics.keys().cloned())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    write_table(
+        &batch_directory.join("batch.csv"),
+        &summaries,
+        &metric_names,
+    )
+    .context("Unable to write batch table")?;
+
+    write_chart(
+        &batch_directory.join("batch.svg"),
+        &summaries,
+        &metric_names,
+    )
+    .context("Unable to generate batch chart")?;
+
+    print_table(&summaries, &metric_names);
+
+    Ok(())
+}
+
+fn write_table(
+    output_filename: &Path,
+    summaries: &[TitleSummary],
+    metric_names: &[String],
+) -> anyhow::Result<()> {
+    verify_filename(output_filename)
+        .with_context(|| format!("Unable to verify batch table filename {output_filename:?}"))?;
+
+    let file = File::create(output_filename)
+        .with_context(|| format!("Unable to create batch table {output_filename:?}"))?;
+
+    let mut writer = BufWriter::new(file);
+
+    let mut header = [
+        "title",
+        "encode",
+        "duration",
+        "source_size",
+        "output_size",
+        "saved",
+        "bitrate",
+        "scenes",
+        "quality",
+    ]
+    .map(ToOwned::to_owned)
+    .to_vec();
+
+    header.extend(metric_names.iter().map(|name| name.to_lowercase()));
+
+    writeln!(writer, "{}", header.join(",")).context("Unable to write batch table header")?;
+
+    for summary in summaries {
+        let mut columns = vec![
+            summary.title.clone(),
+            summary.encode_identifier.clone(),
+            format!("{:.3}", summary.duration),
+            summary.source_size.to_string(),
+            summary.output_size.to_string(),
+            summary
+                .saved()
+                .map_or_else(String::new, |saved| format!("{saved:.3}")),
+            summary
+                .bitrate()
+                .map_or_else(String::new, |bitrate| format!("{bitrate:.0}")),
+            summary.scenes.to_string(),
+            summary
+                .mean_quality
+                .map_or_else(String::new, |quality| format!("{quality:.3}")),
+        ];
+
+        columns.extend(metric_names.iter().map(|name| {
+            summary
+                .metrics
+                .get(name)
+                .map_or_else(String::new, |value| format!("{value:.3}"))
+        }));
+
+        writeln!(writer, "{}", columns.join(",")).context("Unable to write batch table row")?;
+    }
+
+    writer.flush().context("Unable to flush batch table")?;
+
+    Ok(())
+}
+
+fn write_chart(
+    output_filename: &PathBuf,
+    summaries: &[TitleSummary],
+    metric_names: &[String],
+) -> anyhow::Result<()> {
+    let mut panels = vec![
+        (
+            "Bitrate (kbps)".to_owned(),
+            summaries
+                .iter()
+                .map(|summary| {
+                    summary
+                        .bitrate()
+                        .map_or(f64::NAN, |bitrate| bitrate / 1000.0)
+                })
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "Size saved (%)".to_owned(),
+            summaries
+                .iter()
+                .map(|summary| summary.saved().unwrap_or(f64::NAN))
+                .collect(),
+        ),
+    ];
+
+    for name in metric_names {
+        panels.push((
+            format!("{name} (mean)"),
+            summaries
+                .iter()
+                .map(|summary| summary.metrics.get(name).copied().unwrap_or(f64::NAN))
+                .collect(),
+        ));
+    }
+
+    // Each title takes one unit of the horizontal axis, in title order.
+    let panels = panels
+        .into_iter()
+        .map(|(label, values)| {
+            (
+                label,
+                values
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, value)| (i, i + 1, value))
+                    .collect(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    generate_scene_chart(output_filename, "Batch", &panels)
+}
+
+#[expect(clippy::print_stdout)]
+fn print_table(summaries: &[TitleSummary], metric_names: &[String]) {
+    let mut header = row!["Title", "Duration", "Source", "Output", "Saved", "Bitrate", "Quality"];
+
+    for name in metric_names {
+        header.add_cell(Cell::new(name));
+    }
+
+    let mut table = Table::new();
+    table.set_format(*consts::FORMAT_BOX_CHARS);
+    table.add_row(header);
+
+    for summary in summaries {
+        let mut row = row![
+            summary.title,
+            format!("{:.0}s", summary.duration),
+            HumanBytes(summary.source_size),
+            HumanBytes(summary.output_size),
+            summary
+                .saved()
+                .map_or_else(|| "N/A".to_owned(), |saved| format!("{saved:.1}%")),
+            summary.bitrate().map_or_else(
+                || "N/A".to_owned(),
+                |bitrate| HumanBitrate(bitrate).to_string()
+            ),
+            summary
+                .mean_quality
+                .map_or_else(String::new, |quality| format!("{quality:.2}")),
+        ];
+
+        for name in metric_names {
+            row.add_cell(Cell::new(
+                &summary
+                    .metrics
+                    .get(name)
+                    .map_or_else(String::new, |value| format!("{value:8.3}")),
+            ));
+        }
+
+        table.add_row(row);
+    }
+
+    let duration: f64 = summaries.iter().map(|summary| summary.duration).sum();
+    let source_size: u64 = summaries.iter().map(|summary| summary.source_size).sum();
+    let output_size: u64 = summaries.iter().map(|summary| summary.output_size).sum();
+
+    println!("Batch summary ({} title(s)):", summaries.len());
+    table.printstd();
+    println!(
+        "Total: {} -> {} ({} saved, {}) at {}",
+        HumanBytes(source_size),
+        HumanBytes(output_size),
+        HumanBytes(source_size.saturating_sub(output_size)),
+        saved(source_size, output_size)
+            .map_or_else(|| "N/A".to_owned(), |saved| format!("{saved:.1}%")),
+        bitrate(output_size, duration).map_or_else(
+            || "N/A".to_owned(),
+            |bitrate| HumanBitrate(bitrate).to_string()
+        )
+    );
+}
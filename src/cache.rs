@@ -0,0 +1,60 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::config::Config;
+use crate::presplit;
+use crate::util::verify_filename;
+
+// The shared cache is laid out by source hash so that any output directory encoding the same source
+// can find the results of previous runs, regardless of where the source file lives.
+pub fn shared_directory(config: &Config) -> anyhow::Result<Option<PathBuf>> {
+    config
+        .cache_directory
+        .as_ref()
+        .map(|directory| -> anyhow::Result<PathBuf> {
+            let source_hash = presplit::source_hash(config).context("Unable to hash source")?;
+
+            Ok(directory.join(source_hash))
+        })
+        .transpose()
+}
+
+pub fn restore(local_path: &Path, shared_path: Option<&Path>) -> anyhow::Result<()> {
+    if let Some(shared_path) = shared_path {
+        if !local_path.exists() && shared_path.exists() {
+            verify_filename(local_path)
+                .with_context(|| format!("Unable to verify cache path {local_path:?}"))?;
+
+            fs::copy(shared_path, local_path).with_context(|| {
+                format!("Unable to copy shared cache {shared_path:?} to {local_path:?}")
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn publish(local_path: &Path, shared_path: Option<&Path>) -> anyhow::Result<()> {
+    if let Some(shared_path) = shared_path {
+        verify_filename(shared_path)
+            .with_context(|| format!("Unable to verify shared cache path {shared_path:?}"))?;
+
+        // Other runs may be reading the shared cache concurrently, so never expose a partial copy.
+        let temporary_path = shared_path.with_extension("tmp");
+
+        fs::copy(local_path, &temporary_path).with_context(|| {
+            format!("Unable to copy cache {local_path:?} to {temporary_path:?}")
+        })?;
+
+        File::open(&temporary_path)
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("Unable to sync {temporary_path:?}"))?;
+
+        fs::rename(&temporary_path, shared_path)
+            .with_context(|| format!("Unable to rename {temporary_path:?} to {shared_path:?}"))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::probes::ProbeIndex;
+
+/// Why a file left behind in the output directory is considered junk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orphan {
+    Temporary,
+    Probe,
+}
+
+impl Orphan {
+    const fn description(self) -> &'static str {
+        match self {
+            Self::Temporary => "temporary file",
+            Self::Probe => "unreferenced probe encode",
+        }
+    }
+}
+
+// Every file written through a temporary name carries a .tmp component, either before the final
+// extension or in place of it, and is renamed once complete. Anything still named that way was
+// abandoned by a run that stopped partway.
+fn temporary(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.contains(".tmp.") || name.ends_with(".tmp"))
+}
+
+// Chunks are only worth keeping if a later run can make use of them. Within a scene that has a
+// probe index, a chunk that neither appears in the index nor has measured metrics is a leftover
+// from a search that was interrupted before the probe was scored.
+fn unreferenced_probe(path: &Path, index: &ProbeIndex) -> bool {
+    let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+        return false;
+    };
+
+    let chunk = path.extension().is_some_and(|extension| {
+        ["mkv", "ivf", "hevc"]
+            .iter()
+            .any(|chunk| extension == *chunk)
+    });
+
+    chunk && !index.contains(&name) && !path.with_extension("metrics.json").exists()
+}
+
+fn scan(directory: &Path, orphans: &mut Vec<(PathBuf, Orphan)>) -> anyhow::Result<()> {
+    let probe_index_path = directory.join("probes.json");
+
+    let probe_index = if probe_index_path.exists() {
+        Some(ProbeIndex::load(&probe_index_path).context("Unable to load scene probe index")?)
+    } else {
+        None
+    };
+
+    let mut entries = fs::read_dir(directory)
+        .with_context(|| format!("Unable to read directory {directory:?}"))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Unable to read directory entry in {directory:?}"))?;
+
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            scan(&path, orphans)?;
+        } else if temporary(&path) {
+            orphans.push((path, Orphan::Temporary));
+        } else if probe_index
+            .as_ref()
+            .is_some_and(|index| unreferenced_probe(&path, index))
+        {
+            orphans.push((path, Orphan::Probe));
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the files under an output directory that crashed or interrupted runs left behind.
+///
+/// Nothing is locked while an encode runs, so this must not be used on a directory that is still
+/// in use, as its in-progress files look exactly like abandoned ones.
+pub fn find(directory: &Path) -> anyhow::Result<Vec<(PathBuf, Orphan)>> {
+    let mut orphans = vec![];
+
+    scan(directory, &mut orphans)
+        .with_context(|| format!("Unable to scan output directory {directory:?}"))?;
+
+    Ok(orphans)
+}
+
+#[expect(clippy::print_stdout)]
+pub fn run(directory: &Path, delete: bool) -> anyhow::Result<()> {
+    let orphans = find(directory)?;
+    let mut total_size = 0;
+
+    for (path, orphan) in &orphans {
+        let size = fs::metadata(path)
+            .with_context(|| format!("Unable to read metadata for {path:?}"))?
+            .len();
+
+        total_size += size;
+
+        println!(
+            "{:>12} bytes  {:<26} {}",
+            size,
+            orphan.description(),
+            path.display()
+        );
+
+        if delete {
+            fs::remove_file(path).with_context(|| format!("Unable to delete {path:?}"))?;
+        }
+    }
+
+    if orphans.is_empty() {
+        println!("No orphaned files found");
+    } else if delete {
+        println!(
+            "Deleted {} files totalling {total_size} bytes",
+            orphans.len()
+        );
+    } else {
+        println!(
+            "Found {} files totalling {total_size} bytes. Run again with --delete to remove them.",
+            orphans.len()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context;
+use clap::CommandFactory;
+use clap_mangen::Man;
+
+use crate::clean;
+use crate::config::{Cli, Command};
+use crate::util::verify_directory;
+
+fn write_manual_page(command: clap::Command, path: &Path) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(
+        File::create(path).with_context(|| format!("Unable to create manual page {path:?}"))?,
+    );
+
+    Man::new(command)
+        .render(&mut writer)
+        .with_context(|| format!("Unable to render manual page {path:?}"))?;
+
+    writer
+        .flush()
+        .with_context(|| format!("Unable to write manual page {path:?}"))
+}
+
+// Each subcommand gets its own page, named the way man(1) expects for git-style commands.
+fn write_manual_pages(directory: &Path) -> anyhow::Result<()> {
+    verify_directory(directory)
+        .with_context(|| format!("Unable to verify manual page directory {directory:?}"))?;
+
+    let command = Cli::command();
+    let name = command.get_name().to_owned();
+
+    for subcommand in command.get_subcommands() {
+        let page_name = format!("{name}-{}", subcommand.get_name());
+
+        write_manual_page(
+            subcommand.clone().display_name(page_name.clone()),
+            &directory.join(format!("{page_name}.1")),
+        )?;
+    }
+
+    write_manual_page(command, &directory.join(format!("{name}.1")))
+}
+
+pub fn run(command: &Command) -> anyhow::Result<()> {
+    match command {
+        Command::Completions { shell } => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_owned();
+
+            clap_complete::generate(*shell, &mut command, name, &mut io::stdout());
+
+            Ok(())
+        }
+        Command::Man { directory } => {
+            write_manual_pages(directory).context("Unable to write manual pages")
+        }
+        Command::Clean { directory, delete } => {
+            clean::run(directory, *delete).context("Unable to clean output directory")
+        }
+    }
+}
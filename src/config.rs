@@ -1,90 +1,236 @@
 use std::fmt;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::thread;
 
+use anyhow::anyhow;
 use base16ct::lower::encode_string;
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
 use sha2::{Digest, Sha256};
 
+use crate::ffmpeg::Metadata;
+use crate::levels;
+use crate::util::{available_memory, hash_string};
+
+// How the encoded quality responds to the quality parameter. The search only needs the overall
+// direction; parameters that are noisy locally still work since the best probe is always kept.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Monotonicity {
+    Increasing,
+    Decreasing,
+}
+
+// The search runs over an ordered list of candidate values rather than an integer range, so float
+// valued and sparse parameters are handled the same way as plain quantizers.
 #[derive(Debug)]
 pub struct QualityRange {
-    minimum: i64,
-    maximum: i64,
-    divisor: i64,
-    bitrate: bool,
+    values: Vec<f64>,
+    monotonicity: Monotonicity,
+    minimum: usize,
+    maximum: usize,
+    exhausted: bool,
+    hint: Option<usize>,
+    step: usize,
 }
 
 impl QualityRange {
     #[must_use]
-    pub const fn new(minimum: i64, maximum: i64, divisor: i64, bitrate: bool) -> Self {
-        if bitrate {
-            Self {
-                minimum: minimum / divisor,
-                maximum: maximum / divisor,
-                divisor,
-                bitrate,
-            }
-        } else {
-            Self {
-                minimum: minimum * divisor,
-                maximum: maximum * divisor,
-                divisor,
-                bitrate,
-            }
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_possible_truncation)]
+    #[expect(clippy::cast_precision_loss)]
+    #[expect(clippy::cast_sign_loss)]
+    pub fn stepped(minimum: f64, maximum: f64, step: f64, monotonicity: Monotonicity) -> Self {
+        let count = ((maximum - minimum) / step).round().max(0.0) as usize + 1;
+
+        Self::from_values(
+            (0..count)
+                .map(|i| (i as f64).mul_add(step, minimum))
+                .collect(),
+            monotonicity,
+        )
+    }
+
+    #[must_use]
+    pub fn from_values(mut values: Vec<f64>, monotonicity: Monotonicity) -> Self {
+        values.retain(|value| value.is_finite());
+        values.sort_by(f64::total_cmp);
+        values.dedup();
+
+        Self {
+            maximum: values.len().saturating_sub(1),
+            exhausted: values.is_empty(),
+            values,
+            monotonicity,
+            minimum: 0,
+            hint: None,
+            step: 1,
         }
     }
 
+    // With a hint, the search starts there and gallops outward in growing steps until the answer
+    // is bracketed, so an accurate hint settles in two or three probes instead of a full bisection.
     #[must_use]
-    const fn midpoint(&self) -> i64 {
-        (self.minimum + self.maximum) / 2
+    pub fn with_hint(mut self, quality: f64) -> Self {
+        self.hint = self.nearest(quality);
+        self
+    }
+
+    fn nearest(&self, quality: f64) -> Option<usize> {
+        self.values
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| (*a - quality).abs().total_cmp(&(*b - quality).abs()))
+            .map(|(index, _)| index)
+    }
+
+    #[must_use]
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    // The value in the range closest to an arbitrary quality.
+    #[must_use]
+    pub fn snap(&self, quality: f64) -> Option<f64> {
+        self.nearest(quality)
+            .and_then(|index| self.values.get(index).copied())
+    }
+
+    #[must_use]
+    const fn valid_hint(&self) -> Option<usize> {
+        match self.hint {
+            Some(hint) if hint >= self.minimum && hint <= self.maximum => Some(hint),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    const fn midpoint(&self) -> usize {
+        match self.valid_hint() {
+            Some(hint) => hint,
+            None => (self.minimum + self.maximum) / 2,
+        }
     }
 
     #[must_use]
-    #[expect(clippy::cast_precision_loss)]
     pub fn current(&self) -> Option<f64> {
-        if self.minimum > self.maximum {
+        if self.exhausted {
             None
-        } else if self.bitrate {
-            Some(self.midpoint() as f64 * self.divisor as f64)
         } else {
-            Some(self.midpoint() as f64 / self.divisor as f64)
+            self.values.get(self.midpoint()).copied()
         }
     }
 
     pub fn lower(&mut self) {
-        self.maximum = self.midpoint() - 1;
+        let midpoint = self.midpoint();
+        self.hint = self
+            .valid_hint()
+            .and_then(|hint| hint.checked_sub(self.step));
+        self.step *= 2;
+
+        match midpoint.checked_sub(1) {
+            Some(maximum) if maximum >= self.minimum => self.maximum = maximum,
+            _ => self.exhausted = true,
+        }
     }
 
     pub fn higher(&mut self) {
-        self.minimum = self.midpoint() + 1;
+        let midpoint = self.midpoint();
+        self.hint = self.valid_hint().map(|hint| hint + self.step);
+        self.step *= 2;
+
+        if midpoint < self.maximum {
+            self.minimum = midpoint + 1;
+        } else {
+            self.exhausted = true;
+        }
+    }
+
+    // Moves the search toward better or worse quality, whichever way the parameter runs.
+    pub fn better(&mut self) {
+        match self.monotonicity {
+            Monotonicity::Increasing => self.higher(),
+            Monotonicity::Decreasing => self.lower(),
+        }
+    }
+
+    pub fn worse(&mut self) {
+        match self.monotonicity {
+            Monotonicity::Increasing => self.lower(),
+            Monotonicity::Decreasing => self.higher(),
+        }
     }
 
     #[must_use]
-    pub const fn integer(&self) -> bool {
-        self.bitrate || self.divisor == 1
+    pub const fn monotonicity(&self) -> Monotonicity {
+        self.monotonicity
     }
 
+    // Whether `a` is expected to give better quality than `b`.
     #[must_use]
-    #[expect(clippy::cast_precision_loss)]
-    pub fn minimum(&self) -> f64 {
-        if self.bitrate {
-            self.minimum as f64 * self.divisor as f64
-        } else {
-            self.minimum as f64 / self.divisor as f64
+    pub fn is_better(&self, a: f64, b: f64) -> bool {
+        match self.monotonicity {
+            Monotonicity::Increasing => a > b,
+            Monotonicity::Decreasing => a < b,
         }
     }
 
     #[must_use]
-    #[expect(clippy::cast_precision_loss)]
-    pub fn maximum(&self) -> f64 {
-        if self.bitrate {
-            self.maximum as f64 * self.divisor as f64
+    pub fn integer(&self) -> bool {
+        self.values
+            .iter()
+            .all(|value| (value - value.round()).abs() < f64::EPSILON)
+    }
+
+    // The next candidate value in the direction of better (positive) or worse (negative) quality,
+    // or the value itself at the edge of the range.
+    #[must_use]
+    pub fn adjacent(&self, quality: f64, direction: f64) -> f64 {
+        let Some(index) = self.nearest(quality) else {
+            return quality;
+        };
+
+        let upward = (direction > 0.0) == (self.monotonicity == Monotonicity::Increasing);
+
+        let index = if upward {
+            (index + 1).min(self.values.len() - 1)
         } else {
-            self.maximum as f64 / self.divisor as f64
+            index.saturating_sub(1)
+        };
+
+        self.values.get(index).copied().unwrap_or(quality)
+    }
+
+    #[must_use]
+    pub fn minimum(&self) -> f64 {
+        self.values.get(self.minimum).copied().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn maximum(&self) -> f64 {
+        self.values.get(self.maximum).copied().unwrap_or_default()
+    }
+
+    // The quality of the worst candidate, used as the starting point of a search.
+    #[must_use]
+    pub fn worst(&self) -> f64 {
+        match self.monotonicity {
+            Monotonicity::Increasing => self.minimum(),
+            Monotonicity::Decreasing => self.maximum(),
+        }
+    }
+
+    #[must_use]
+    pub fn best(&self) -> f64 {
+        match self.monotonicity {
+            Monotonicity::Increasing => self.maximum(),
+            Monotonicity::Decreasing => self.minimum(),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
 pub enum QualityRule {
     Maximum,
     Minimum,
@@ -102,7 +248,25 @@ impl fmt::Display for QualityRule {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GlobalObjective {
+    Bitrate,
+    Quality,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for GlobalObjective {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Bitrate => write!(f, "bitrate"),
+            Self::Quality => write!(f, "quality"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
 pub enum Mode {
     QP,
     CRF,
@@ -120,7 +284,8 @@ impl fmt::Display for Mode {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
 pub enum Metric {
     Direct,
     PSNR,
@@ -144,7 +309,243 @@ impl fmt::Display for Metric {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneDetection {
+    Fast,
+    Accurate,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for SceneDetection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fast => write!(f, "fast"),
+            Self::Accurate => write!(f, "accurate"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationDetection {
+    Off,
+    Scene,
+    Source,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for AnimationDetection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Scene => write!(f, "scene"),
+            Self::Source => write!(f, "source"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BitDepth {
+    Auto,
+    #[value(name = "8")]
+    #[serde(rename = "8")]
+    Eight,
+    #[value(name = "10")]
+    #[serde(rename = "10")]
+    Ten,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for BitDepth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Auto => write!(f, "auto"),
+            Self::Eight => write!(f, "8"),
+            Self::Ten => write!(f, "10"),
+        }
+    }
+}
+
+impl BitDepth {
+    // An unresolved automatic depth falls back to 10 bits, which was the only depth before the
+    // choice existed.
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        match self {
+            Self::Eight => 8,
+            Self::Auto | Self::Ten => 10,
+        }
+    }
+
+    #[must_use]
+    pub const fn pixel_format(self) -> &'static str {
+        match self {
+            Self::Eight => "yuv420p",
+            Self::Auto | Self::Ten => "yuv420p10le",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsEffort {
+    Fast,
+    Full,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for MetricsEffort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Fast => write!(f, "fast"),
+            Self::Full => write!(f, "full"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceDepth {
+    Upconvert,
+    Keep,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for SourceDepth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Upconvert => write!(f, "upconvert"),
+            Self::Keep => write!(f, "keep"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioEncoder {
+    Opus,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for AudioEncoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Opus => write!(f, "opus"),
+        }
+    }
+}
+
+impl AudioEncoder {
+    #[must_use]
+    pub const fn codec(self) -> &'static str {
+        match self {
+            Self::Opus => "libopus",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneWeighting {
+    Scene,
+    Length,
+    Both,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for SceneWeighting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Scene => write!(f, "scene"),
+            Self::Length => write!(f, "length"),
+            Self::Both => write!(f, "both"),
+        }
+    }
+}
+
+impl SceneWeighting {
+    #[must_use]
+    pub const fn per_scene(self) -> bool {
+        matches!(self, Self::Scene | Self::Both)
+    }
+
+    #[must_use]
+    pub const fn by_length(self) -> bool {
+        matches!(self, Self::Length | Self::Both)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Container {
+    Mp4,
+    Webm,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mp4 => write!(f, "MP4"),
+            Self::Webm => write!(f, "WebM"),
+        }
+    }
+}
+
+impl Container {
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::Webm => "webm",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewMode {
+    SideBySide,
+    Split,
+    Difference,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for ReviewMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SideBySide => write!(f, "side-by-side"),
+            Self::Split => write!(f, "split"),
+            Self::Difference => write!(f, "difference"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneFormat {
+    Edl,
+    Qpfile,
+    Av1an,
+    Csv,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for SceneFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Edl => write!(f, "edl"),
+            Self::Qpfile => write!(f, "qpfile"),
+            Self::Av1an => write!(f, "av1an"),
+            Self::Csv => write!(f, "csv"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 pub enum Encoder {
     Aomenc,
     Rav1e,
@@ -152,6 +553,9 @@ pub enum Encoder {
     Vpxenc,
     X264,
     X265,
+    Ffv1,
+    Prores,
+    Dnxhr,
 }
 
 #[expect(clippy::min_ident_chars)]
@@ -164,6 +568,9 @@ impl fmt::Display for Encoder {
             Self::Vpxenc => write!(f, "vpxenc"),
             Self::X264 => write!(f, "x264"),
             Self::X265 => write!(f, "x265"),
+            Self::Ffv1 => write!(f, "ffv1"),
+            Self::Prores => write!(f, "prores"),
+            Self::Dnxhr => write!(f, "dnxhr"),
         }
     }
 }
@@ -173,66 +580,253 @@ impl Encoder {
     pub fn extension(&self) -> String {
         match self {
             Self::Aomenc | Self::Rav1e | Self::SvtAv1 | Self::Vpxenc => "ivf",
-            Self::X264 => "mkv",
+            Self::X264 | Self::Ffv1 | Self::Prores | Self::Dnxhr => "mkv",
             Self::X265 => "hevc",
         }
         .to_owned()
     }
 
+    // The encoder names used by av1an zones, which only cover the encoders av1an supports.
+    #[must_use]
+    pub const fn av1an_name(&self) -> Option<&'static str> {
+        match self {
+            Self::Aomenc => Some("aom"),
+            Self::Rav1e => Some("rav1e"),
+            Self::SvtAv1 => Some("svt-av1"),
+            Self::Vpxenc => Some("vpx"),
+            Self::X264 => Some("x264"),
+            Self::X265 => Some("x265"),
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => None,
+        }
+    }
+
+    // Raw elementary streams carry no container timing, so their chunks are wrapped in Matroska.
+    #[must_use]
+    pub const fn elementary_stream(&self) -> bool {
+        matches!(self, Self::X265)
+    }
+
+    #[must_use]
+    pub fn chunk_extension(&self) -> String {
+        if self.elementary_stream() {
+            "mkv".to_owned()
+        } else {
+            self.extension()
+        }
+    }
+
     #[must_use]
     pub fn command(&self) -> String {
         match self {
             Self::Aomenc | Self::Rav1e | Self::Vpxenc | Self::X264 | Self::X265 => self.to_string(),
             Self::SvtAv1 => "SvtAv1EncApp".to_owned(),
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => "ffmpeg".to_owned(),
         }
     }
 
+    // Mezzanine formats are lossless or fixed-quality intermediates, so there is nothing to search.
     #[must_use]
-    pub const fn quality_range(&self, mode: &Mode) -> QualityRange {
+    pub const fn is_mezzanine(&self) -> bool {
+        matches!(self, Self::Ffv1 | Self::Prores | Self::Dnxhr)
+    }
+
+    // The next preset that spends more effort per frame, if the encoder has one.
+    #[must_use]
+    pub fn slower_preset(&self, preset: &str) -> Option<String> {
+        match self {
+            Self::X264 | Self::X265 => {
+                let presets = [
+                    "ultrafast",
+                    "superfast",
+                    "veryfast",
+                    "faster",
+                    "fast",
+                    "medium",
+                    "slow",
+                    "slower",
+                    "veryslow",
+                    "placebo",
+                ];
+
+                let position = presets.iter().position(|candidate| *candidate == preset)?;
+                presets.get(position + 1).map(|&slower| slower.to_owned())
+            }
+            Self::Aomenc | Self::Rav1e | Self::SvtAv1 | Self::Vpxenc => preset
+                .parse::<i32>()
+                .ok()
+                .filter(|speed| *speed > 0)
+                .map(|speed| format!("{}", speed - 1)),
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => None,
+        }
+    }
+
+    // The libaom and libvpx first passes only gather statistics with a fixed quantizer, so their
+    // output does not depend on the rate control target and can be shared between probes.
+    // x264 splits work between frame threads, which changes its decisions and so the bitstream.
+    // The others keep the same output at any thread count.
+    #[must_use]
+    pub const fn thread_dependent(&self) -> bool {
+        matches!(self, Self::X264)
+    }
+
+    #[must_use]
+    pub const fn reusable_first_pass(&self) -> bool {
+        matches!(self, Self::Aomenc | Self::Vpxenc)
+    }
+
+    #[must_use]
+    pub fn quality_range(&self, mode: &Mode) -> QualityRange {
         match mode {
-            Mode::Bitrate => QualityRange::new(100, 30000, 100, true),
+            Mode::Bitrate => QualityRange::stepped(100.0, 30000.0, 100.0, Monotonicity::Increasing),
             Mode::CRF => match self {
-                Self::Aomenc | Self::Vpxenc => QualityRange::new(0, 63, 1, false),
-                Self::Rav1e => QualityRange::new(1, 255, 1, false),
-                Self::SvtAv1 => QualityRange::new(1, 63, 1, false),
-                Self::X264 => QualityRange::new(-10, 51, 4, false),
-                Self::X265 => QualityRange::new(0, 51, 4, false),
+                Self::Aomenc | Self::Vpxenc => {
+                    QualityRange::stepped(0.0, 63.0, 1.0, Monotonicity::Decreasing)
+                }
+                Self::Rav1e => QualityRange::stepped(1.0, 255.0, 1.0, Monotonicity::Decreasing),
+                Self::SvtAv1 => QualityRange::stepped(1.0, 63.0, 1.0, Monotonicity::Decreasing),
+                Self::X264 => QualityRange::stepped(-10.0, 51.0, 0.25, Monotonicity::Decreasing),
+                Self::X265 => QualityRange::stepped(0.0, 51.0, 0.25, Monotonicity::Decreasing),
+                Self::Ffv1 | Self::Prores | Self::Dnxhr => {
+                    QualityRange::from_values(vec![0.0], Monotonicity::Decreasing)
+                }
             },
             Mode::QP => match self {
-                Self::Aomenc | Self::Vpxenc => QualityRange::new(0, 63, 1, false),
-                Self::Rav1e => QualityRange::new(1, 255, 1, false),
-                Self::SvtAv1 => QualityRange::new(1, 63, 1, false),
-                Self::X264 => QualityRange::new(1, 81, 1, false),
-                Self::X265 => QualityRange::new(0, 51, 1, false),
+                Self::Aomenc | Self::Vpxenc => {
+                    QualityRange::stepped(0.0, 63.0, 1.0, Monotonicity::Decreasing)
+                }
+                Self::Rav1e => QualityRange::stepped(1.0, 255.0, 1.0, Monotonicity::Decreasing),
+                Self::SvtAv1 => QualityRange::stepped(1.0, 63.0, 1.0, Monotonicity::Decreasing),
+                Self::X264 => QualityRange::stepped(1.0, 81.0, 1.0, Monotonicity::Decreasing),
+                Self::X265 => QualityRange::stepped(0.0, 51.0, 1.0, Monotonicity::Decreasing),
+                Self::Ffv1 | Self::Prores | Self::Dnxhr => {
+                    QualityRange::from_values(vec![0.0], Monotonicity::Decreasing)
+                }
             },
         }
     }
 
     #[must_use]
     pub const fn passes(&self, config: &Config) -> usize {
+        // Mezzanine encoders take no statistics file, so a second pass would only repeat the first.
+        if self.is_mezzanine() {
+            return 1;
+        }
+
         match config.mode {
             Mode::Bitrate => 2,
             Mode::CRF | Mode::QP => match self {
                 Self::Aomenc | Self::Vpxenc => 2,
-                Self::Rav1e | Self::SvtAv1 | Self::X264 | Self::X265 => 1,
+                Self::Rav1e
+                | Self::SvtAv1
+                | Self::X264
+                | Self::X265
+                | Self::Ffv1
+                | Self::Prores
+                | Self::Dnxhr => 1,
             },
         }
     }
 
-    #[must_use]
-    pub fn base_arguments(&self, preset: &str, key_frame_interval: usize) -> Vec<String> {
+    fn mezzanine_arguments(self, preset: &str, threads: usize) -> Vec<String> {
+        let mut arguments = vec![
+            "-hide_banner".to_owned(),
+            "-nostdin".to_owned(),
+            "-y".to_owned(),
+            "-f".to_owned(),
+            "yuv4mpegpipe".to_owned(),
+            "-i".to_owned(),
+            "-".to_owned(),
+            "-threads".to_owned(),
+            format!("{threads}"),
+        ];
+
         match self {
+            Self::Ffv1 => {
+                arguments.extend(
+                    [
+                        "-c:v",
+                        "ffv1",
+                        "-level",
+                        "3",
+                        "-slicecrc",
+                        "1",
+                        "-slices",
+                        "4",
+                        "-g",
+                        "1",
+                    ]
+                    .map(ToOwned::to_owned),
+                );
+            }
+            // The preset selects the profile, from 0 (proxy) through 5 (4444 XQ).
+            Self::Prores => {
+                let pixel_format = if matches!(preset, "4" | "5" | "4444" | "4444xq") {
+                    "yuv444p10le"
+                } else {
+                    "yuv422p10le"
+                };
+
+                arguments.extend(
+                    [
+                        "-c:v",
+                        "prores_ks",
+                        "-profile:v",
+                        preset,
+                        "-pix_fmt",
+                        pixel_format,
+                    ]
+                    .map(ToOwned::to_owned),
+                );
+            }
+            // The preset selects the profile: dnxhr_lb, dnxhr_sq, dnxhr_hq, dnxhr_hqx or dnxhr_444.
+            Self::Dnxhr => {
+                let pixel_format = match preset {
+                    "dnxhr_444" => "yuv444p10le",
+                    "dnxhr_hqx" => "yuv422p10le",
+                    _ => "yuv422p",
+                };
+
+                arguments.extend(
+                    [
+                        "-c:v",
+                        "dnxhd",
+                        "-profile:v",
+                        preset,
+                        "-pix_fmt",
+                        pixel_format,
+                    ]
+                    .map(ToOwned::to_owned),
+                );
+            }
+            Self::Aomenc | Self::Rav1e | Self::SvtAv1 | Self::Vpxenc | Self::X264 | Self::X265 => {}
+        }
+
+        arguments
+    }
+
+    // Tiles are expressed as counts on the command line but every supported encoder takes their
+    // base two logarithm.
+    #[must_use]
+    pub fn base_arguments(
+        &self,
+        preset: &str,
+        key_frame_interval: usize,
+        threads: usize,
+        tiles: Option<(usize, usize)>,
+        bit_depth: u8,
+    ) -> Vec<String> {
+        let mut arguments = match self {
             Self::Aomenc => vec![
                 format!("--cpu-used={preset}"),
-                "--bit-depth=10".to_owned(),
-                "--threads=1".to_owned(),
+                format!("--bit-depth={bit_depth}"),
+                format!("--threads={threads}"),
                 format!("--kf-max-dist={key_frame_interval}"),
             ],
             Self::Rav1e => vec![
                 "--speed".to_owned(),
                 preset.to_owned(),
                 "--threads".to_owned(),
-                "1".to_owned(),
+                format!("{threads}"),
                 "--keyint".to_owned(),
                 format!("{key_frame_interval}"),
             ],
@@ -242,16 +836,22 @@ impl Encoder {
                 "--keyint".to_owned(),
                 format!("{key_frame_interval}"),
                 "--lp".to_owned(),
-                "1".to_owned(),
+                format!("{threads}"),
                 "--progress".to_owned(),
                 "2".to_owned(),
             ],
             Self::Vpxenc => vec![
                 format!("--cpu-used={preset}"),
                 "--codec=vp9".to_owned(),
-                "--bit-depth=10".to_owned(),
-                "--profile=2".to_owned(),
-                "--threads=1".to_owned(),
+                format!("--bit-depth={bit_depth}"),
+                // VP9 profile 2 is required for high bit depth and profile 0 for 8 bits.
+                if bit_depth > 8 {
+                    "--profile=2"
+                } else {
+                    "--profile=0"
+                }
+                .to_owned(),
+                format!("--threads={threads}"),
                 format!("--kf-max-dist={key_frame_interval}"),
             ],
             Self::X264 => vec![
@@ -261,9 +861,9 @@ impl Encoder {
                 "--preset".to_owned(),
                 preset.to_owned(),
                 "--output-depth".to_owned(),
-                "10".to_owned(),
+                format!("{bit_depth}"),
                 "--threads".to_owned(),
-                "1".to_owned(),
+                format!("{threads}"),
                 "--keyint".to_owned(),
                 format!("{key_frame_interval}"),
             ],
@@ -272,33 +872,274 @@ impl Encoder {
                 "--preset".to_owned(),
                 preset.to_owned(),
                 "--output-depth".to_owned(),
-                "10".to_owned(),
+                format!("{bit_depth}"),
                 "--pools".to_owned(),
-                "1".to_owned(),
+                format!("{threads}"),
                 "-F".to_owned(),
                 "1".to_owned(),
                 "--keyint".to_owned(),
                 format!("{key_frame_interval}"),
             ],
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => self.mezzanine_arguments(preset, threads),
+        };
+
+        if let Some((columns, rows)) = tiles {
+            let (columns, rows) = (columns.trailing_zeros(), rows.trailing_zeros());
+
+            match self {
+                Self::Aomenc | Self::Vpxenc => {
+                    arguments.push(format!("--tile-columns={columns}"));
+                    arguments.push(format!("--tile-rows={rows}"));
+                }
+                Self::Rav1e => {
+                    arguments.extend([
+                        "--tile-cols".to_owned(),
+                        format!("{}", 1_usize << columns),
+                        "--tile-rows".to_owned(),
+                        format!("{}", 1_usize << rows),
+                    ]);
+                }
+                Self::SvtAv1 => {
+                    arguments.extend([
+                        "--tile-columns".to_owned(),
+                        format!("{columns}"),
+                        "--tile-rows".to_owned(),
+                        format!("{rows}"),
+                    ]);
+                }
+                Self::X264 | Self::X265 | Self::Ffv1 | Self::Prores | Self::Dnxhr => {}
+            }
         }
+
+        arguments
     }
 
+    // The block size each encoder pads the frame out to. Crops that break this alignment leave the
+    // encoder coding padding it can smear into the picture edge.
     #[must_use]
-    pub fn tune_arguments(&self, config: &Config) -> Vec<String> {
+    pub const fn block_alignment(&self) -> usize {
+        match self {
+            Self::X264 => 16,
+            Self::Aomenc | Self::Rav1e | Self::SvtAv1 | Self::Vpxenc | Self::X265 => 8,
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => 1,
+        }
+    }
+
+    // The smallest tile, in pixels, each encoder will accept without silently reducing the tile
+    // count. Encoders without tile support return `None`.
+    #[must_use]
+    pub const fn minimum_tile_size(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::Aomenc | Self::Rav1e | Self::SvtAv1 => Some((64, 64)),
+            Self::Vpxenc => Some((256, 64)),
+            Self::X264 | Self::X265 | Self::Ffv1 | Self::Prores | Self::Dnxhr => None,
+        }
+    }
+
+    // The largest lookahead each encoder accepts, in frames. Mezzanine encoders have none.
+    #[must_use]
+    pub const fn maximum_lookahead(&self) -> Option<usize> {
+        match self {
+            Self::Aomenc => Some(48),
+            Self::Vpxenc => Some(25),
+            Self::Rav1e => Some(40),
+            Self::SvtAv1 => Some(120),
+            Self::X264 | Self::X265 => Some(250),
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => None,
+        }
+    }
+
+    // The lookahead passed to the encoder, if any. Only aomenc has always been given one.
+    #[must_use]
+    pub fn lookahead(&self, config: &Config) -> Option<usize> {
+        config
+            .lookahead
+            .or_else(|| (*self == Self::Aomenc).then_some(48))
+    }
+
+    #[must_use]
+    pub fn lookahead_arguments(&self, frames: usize) -> Vec<String> {
+        match self {
+            Self::Aomenc | Self::Vpxenc => vec![format!("--lag-in-frames={frames}")],
+            Self::Rav1e => vec![
+                "--rdo-lookahead-frames".to_owned(),
+                frames.max(1).to_string(),
+            ],
+            Self::SvtAv1 => vec!["--lookahead".to_owned(), frames.to_string()],
+            Self::X264 | Self::X265 => vec!["--rc-lookahead".to_owned(), frames.to_string()],
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => vec![],
+        }
+    }
+
+    // Rough peak memory use of one encoder instance, in bytes per pixel of frame area, including
+    // its lookahead and reference frames at typical presets.
+    #[must_use]
+    pub const fn memory_per_pixel(&self) -> u64 {
+        match self {
+            Self::SvtAv1 => 480,
+            Self::Aomenc | Self::Rav1e => 320,
+            Self::X265 => 160,
+            Self::Vpxenc => 128,
+            Self::X264 => 64,
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => 32,
+        }
+    }
+
+    // Screen content tools favour sharp text and flat regions over film-oriented psychovisual
+    // tuning. Encoders without such tools are left unchanged.
+    #[must_use]
+    pub fn screen_content_arguments(&self) -> Vec<String> {
+        match self {
+            Self::Aomenc => vec![
+                "--tune-content=screen".to_owned(),
+                "--enable-palette=1".to_owned(),
+                "--enable-intrabc=1".to_owned(),
+            ],
+            Self::SvtAv1 => vec!["--scm".to_owned(), "1".to_owned()],
+            Self::Vpxenc => vec!["--tune-content=screen".to_owned()],
+            Self::X264 => vec!["--tune".to_owned(), "stillimage".to_owned()],
+            Self::Rav1e | Self::X265 | Self::Ffv1 | Self::Prores | Self::Dnxhr => vec![],
+        }
+    }
+
+    // Signals a full range source, which would otherwise be flagged as limited range and shown with
+    // crushed blacks and clipped whites. aomenc and vpxenc take the range from the YUV4MPEG header
+    // of their input instead.
+    #[must_use]
+    pub fn full_range_arguments(&self) -> Vec<String> {
+        match self {
+            Self::Rav1e | Self::X265 => vec!["--range".to_owned(), "full".to_owned()],
+            Self::SvtAv1 => vec!["--color-range".to_owned(), "1".to_owned()],
+            Self::X264 => vec![
+                "--input-range".to_owned(),
+                "pc".to_owned(),
+                "--range".to_owned(),
+                "pc".to_owned(),
+            ],
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => {
+                vec!["-color_range".to_owned(), "pc".to_owned()]
+            }
+            Self::Aomenc | Self::Vpxenc => vec![],
+        }
+    }
+
+    // Applied on top of the tune arguments when a finished scene shows banding, shifting bits
+    // towards flat areas. The bit depth is left alone, since every chunk has to share one to be
+    // joined into a single stream.
+    #[must_use]
+    pub fn deband_arguments(&self) -> Vec<String> {
+        match self {
+            Self::Aomenc | Self::Vpxenc => vec!["--aq-mode=1".to_owned()],
+            Self::SvtAv1 => vec![
+                "--enable-variance-boost".to_owned(),
+                "1".to_owned(),
+                "--variance-octile".to_owned(),
+                "4".to_owned(),
+            ],
+            Self::X264 | Self::X265 => vec![
+                "--aq-mode".to_owned(),
+                "3".to_owned(),
+                "--aq-strength".to_owned(),
+                "1.2".to_owned(),
+            ],
+            Self::Rav1e | Self::Ffv1 | Self::Prores | Self::Dnxhr => vec![],
+        }
+    }
+
+    // Applied on top of the tune arguments for animated content. Encoders without a suitable
+    // tuning are left alone.
+    #[must_use]
+    pub fn animation_arguments(&self) -> Vec<String> {
         match self {
+            Self::Aomenc => vec!["--enable-palette=1".to_owned()],
+            Self::X264 | Self::X265 => vec!["--tune".to_owned(), "animation".to_owned()],
+            Self::Rav1e | Self::SvtAv1 | Self::Vpxenc | Self::Ffv1 | Self::Prores | Self::Dnxhr => {
+                vec![]
+            }
+        }
+    }
+
+    // Applied on top of the tune arguments for scenes that the grain analysis flags. The AV1
+    // encoders synthesize grain rather than spend bits preserving it. SVT-AV1 takes a single film
+    // grain level, which with_scene_grain raises instead.
+    #[must_use]
+    pub fn grain_arguments(&self) -> Vec<String> {
+        match self {
+            Self::Aomenc => vec!["--denoise-noise-level=10".to_owned()],
+            Self::Rav1e => vec!["--photon-noise".to_owned(), "8".to_owned()],
+            Self::Vpxenc => vec!["--arnr-strength=0".to_owned()],
+            Self::X264 => [
+                "--aq-strength",
+                "0.5",
+                "--no-dct-decimate",
+                "--deadzone-inter",
+                "6",
+                "--deadzone-intra",
+                "6",
+                "--deblock",
+                "-2:-2",
+                "--ipratio",
+                "1.1",
+                "--pbratio",
+                "1.1",
+                "--psy-rd",
+                "1.0:0.25",
+                "--qcomp",
+                "0.8",
+            ]
+            .map(ToOwned::to_owned)
+            .to_vec(),
+            Self::X265 => [
+                "--aq-mode",
+                "0",
+                "--cbqpoffs",
+                "-2",
+                "--crqpoffs",
+                "-2",
+                "--ipratio",
+                "1.1",
+                "--pbratio",
+                "1.0",
+                "--qcomp",
+                "0.8",
+                "--psy-rd",
+                "4.0",
+                "--psy-rdoq",
+                "10.0",
+                "--rdoq-level",
+                "1",
+                "--no-sao",
+                "--no-strong-intra-smoothing",
+            ]
+            .map(ToOwned::to_owned)
+            .to_vec(),
+            Self::SvtAv1 | Self::Ffv1 | Self::Prores | Self::Dnxhr => vec![],
+        }
+    }
+
+    #[must_use]
+    pub fn tune_arguments(&self, config: &Config) -> Vec<String> {
+        let mut arguments = match self {
             Self::Aomenc => {
-                vec![
-                    "--tune=ssim".to_owned(),
-                    "--enable-qm=1".to_owned(),
-                    "--lag-in-frames=48".to_owned(),
+                let mut arguments = vec!["--tune=ssim".to_owned(), "--enable-qm=1".to_owned()];
+
+                arguments.extend(
+                    self.lookahead(config)
+                        .map(|frames| self.lookahead_arguments(frames))
+                        .unwrap_or_default(),
+                );
+
+                arguments.extend([
                     "--quant-b-adapt=1".to_owned(),
                     "--arnr-strength=1".to_owned(),
                     "--enable-keyframe-filtering=0".to_owned(),
                     "--dist-metric=qm-psnr".to_owned(),
-                ]
+                ]);
+
+                arguments
             }
             Self::SvtAv1 => {
-                if self.passes(config) > 1 {
+                let mut arguments = if self.passes(config) > 1 {
                     vec!["--tune".to_owned(), "0".to_owned()]
                 } else {
                     vec![
@@ -307,38 +1148,37 @@ impl Encoder {
                         "--enable-overlays".to_owned(),
                         "1".to_owned(),
                     ]
-                }
+                };
+
+                arguments.extend(config.svt_arguments());
+                arguments
             }
             Self::Vpxenc => {
                 vec!["--tune=ssim".to_owned()]
             }
-            Self::Rav1e | Self::X264 | Self::X265 => {
+            Self::Rav1e | Self::X264 | Self::X265 | Self::Ffv1 | Self::Prores | Self::Dnxhr => {
                 vec![]
             }
+        };
+
+        // The aomenc lookahead keeps its original place so that existing identifiers are kept.
+        if *self != Self::Aomenc {
+            if let Some(frames) = self.lookahead(config) {
+                arguments.extend(self.lookahead_arguments(frames));
+            }
+        }
+
+        if config.screen_content {
+            arguments.extend(self.screen_content_arguments());
         }
+
+        arguments
     }
 
     #[must_use]
-    #[expect(clippy::too_many_arguments)]
-    #[expect(clippy::too_many_lines)]
-    pub fn arguments(
-        &self,
-        config: &Config,
-        preset: &str,
-        key_frame_interval: usize,
-        pass: Option<usize>,
-        output_file: &Path,
-        stats_file: Option<&PathBuf>,
-        mode: Mode,
-        qp: f64,
-    ) -> Vec<String> {
-        // Base Arguments
-        let mut arguments = self.base_arguments(preset, key_frame_interval);
+    pub fn quality_arguments(&self, mode: Mode, qp: f64) -> Vec<String> {
+        let mut arguments = vec![];
 
-        // Tune Arguments
-        arguments.extend(self.tune_arguments(config));
-
-        // Quality Arguments
         let qp_string = if self.quality_range(&mode).integer() {
             format!("{qp:0}")
         } else {
@@ -413,8 +1253,49 @@ impl Encoder {
 
                 arguments.push(qp_string);
             }
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => {}
         };
 
+        arguments
+    }
+
+    #[must_use]
+    #[expect(clippy::too_many_arguments)]
+    pub fn arguments(
+        &self,
+        config: &Config,
+        preset: &str,
+        key_frame_interval: usize,
+        threads: usize,
+        pass: Option<usize>,
+        output_file: &Path,
+        stats_file: Option<&PathBuf>,
+        mode: Mode,
+        qp: f64,
+    ) -> Vec<String> {
+        // Base Arguments
+        let mut arguments = self.base_arguments(
+            preset,
+            key_frame_interval,
+            threads,
+            config.tiles,
+            config.bit_depth.bits(),
+        );
+
+        // Range Arguments
+        if config.full_range {
+            arguments.extend(self.full_range_arguments());
+        }
+
+        // Tune Arguments
+        arguments.extend(self.tune_arguments(config));
+
+        // Constraint Arguments
+        arguments.extend(levels::arguments(config));
+
+        // Quality Arguments
+        arguments.extend(self.quality_arguments(mode, qp));
+
         // Pass Arguments
         if let Some(pass) = pass {
             if let Some(stats_file) = stats_file {
@@ -438,6 +1319,7 @@ impl Encoder {
                         arguments.push("--stats".to_owned());
                         arguments.push(stats_file.to_string_lossy().to_string());
                     }
+                    Self::Ffv1 | Self::Prores | Self::Dnxhr => {}
                 }
             }
         }
@@ -452,8 +1334,12 @@ impl Encoder {
             Self::SvtAv1 => {
                 arguments.push("-b".to_owned());
                 arguments.push(output_file.to_string_lossy().to_string());
+                // Unlike "-", this spelling of standard input is understood on every platform.
                 arguments.push("-i".to_owned());
-                arguments.push("-".to_owned());
+                arguments.push("stdin".to_owned());
+            }
+            Self::Ffv1 | Self::Prores | Self::Dnxhr => {
+                arguments.push(output_file.to_string_lossy().to_string());
             }
         }
 
@@ -461,8 +1347,148 @@ impl Encoder {
     }
 }
 
-#[derive(Clone, Parser, Debug)]
+// Odd sizes cannot be represented with 4:2:0 chroma.
+fn parse_crop_round(value: &str) -> Result<usize, String> {
+    value
+        .parse::<usize>()
+        .ok()
+        .filter(|round| *round >= 2 && round.is_multiple_of(2))
+        .ok_or_else(|| format!("expected an even number of at least 2 but found '{value}'"))
+}
+
+fn parse_tiles(value: &str) -> Result<(usize, usize), String> {
+    let (columns, rows) = value
+        .split_once('x')
+        .ok_or_else(|| format!("expected COLUMNSxROWS but found '{value}'"))?;
+
+    let parse = |count: &str| -> Result<usize, String> {
+        count
+            .parse::<usize>()
+            .ok()
+            .filter(|count| count.is_power_of_two())
+            .ok_or_else(|| format!("tile counts must be powers of two but found '{count}'"))
+    };
+
+    Ok((parse(columns)?, parse(rows)?))
+}
+
+fn parse_resolution(value: &str) -> Result<(usize, usize), String> {
+    match value.to_ascii_lowercase().as_str() {
+        "480p" => Ok((854, 480)),
+        "720p" => Ok((1280, 720)),
+        "1080p" => Ok((1920, 1080)),
+        "1440p" => Ok((2560, 1440)),
+        "2160p" | "4k" => Ok((3840, 2160)),
+        resolution => {
+            let (width, height) = resolution.split_once('x').ok_or_else(|| {
+                format!("expected a resolution such as 1080p or WIDTHxHEIGHT but found '{value}'")
+            })?;
+
+            let parse = |dimension: &str| -> Result<usize, String> {
+                dimension
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|dimension| *dimension > 0)
+                    .ok_or_else(|| format!("invalid dimension '{dimension}'"))
+            };
+
+            Ok((parse(width)?, parse(height)?))
+        }
+    }
+}
+
+/// A list of scene indexes.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SceneList(pub Vec<usize>);
+
+fn parse_scene_list(value: &str) -> Result<SceneList, String> {
+    let scenes = value
+        .strip_prefix("scenes=")
+        .ok_or_else(|| format!("expected scenes=INDEX,INDEX,... but found '{value}'"))?;
+
+    scenes
+        .split(',')
+        .map(|scene| {
+            scene
+                .trim()
+                .parse::<usize>()
+                .map_err(|_err| format!("invalid scene index '{scene}'"))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(SceneList)
+}
+
+fn parse_scale(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|scale| *scale > 0.0 && *scale <= 1.0)
+        .ok_or_else(|| format!("expected a scale greater than 0 and at most 1 but found '{value}'"))
+}
+
+fn parse_duration(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|duration| duration.is_finite() && *duration > 0.0)
+        .ok_or_else(|| format!("expected a positive number of seconds but found '{value}'"))
+}
+
+fn parse_tool_version(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(tool, version)| (tool.to_owned(), version.to_owned()))
+        .ok_or_else(|| format!("expected TOOL=VERSION but found '{value}'"))
+}
+
+/// Maintenance commands that run instead of an encode.
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Print a completion script for a shell to standard output
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+
+    /// Write manual pages for the program and its commands into a directory
+    Man {
+        /// Directory to write the manual pages into
+        directory: PathBuf,
+    },
+
+    /// Find temporary files and unused probe encodes left behind by interrupted runs
+    Clean {
+        /// Output directory to scan, which must not be in use by a running encode
+        directory: PathBuf,
+
+        /// Delete the files found instead of only listing them
+        #[arg(long, default_value_t = false)]
+        delete: bool,
+    },
+}
+
+/// The full command line, which either describes an encode or names a maintenance command.
+#[derive(Parser)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub config: Option<Config>,
+}
+
+#[derive(Clone, Parser, Debug, Serialize)]
 #[command(author, version, about, long_about = None)]
+#[expect(clippy::struct_excessive_bools)]
 pub struct Config {
     /// Video encoder to use
     #[arg(short, long, value_enum, default_value_t = Encoder::X264)]
@@ -472,18 +1498,100 @@ pub struct Config {
     #[arg(short, long, default_value = "ultrafast")]
     pub preset: String,
 
-    /// Number of workers
+    /// Number of workers (0 chooses based on the cores, memory and source resolution)
     #[arg(short, long, value_parser = clap::value_parser!(usize), default_value_t = 0)]
     pub workers: usize,
 
+    /// Number of threads each encoder instance may use (0 chooses automatically)
+    #[arg(long = "threads-per-worker", value_parser = clap::value_parser!(usize), default_value_t = 0)]
+    pub threads_per_worker: usize,
+
+    /// Keep the thread count fixed instead of growing it as workers run out of scenes
+    #[arg(long, default_value_t = false)]
+    pub fixed_threads: bool,
+
+    /// Encoder profile to enforce, such as high10 for x264 or main for AV1
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// Encoder level to enforce, such as 4.1 or 5.1, validated against the resolution and frame rate
+    /// and held to with a bitrate cap (x264 and x265 in CRF or bitrate mode, SVT-AV1 in CRF mode)
+    #[arg(long)]
+    pub level: Option<String>,
+
+    /// SVT-AV1 fast decode level, from 0 (off) to 2
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub svt_fast_decode: Option<u8>,
+
+    /// Enable SVT-AV1 variance boost
+    #[arg(long, default_value_t = false)]
+    pub svt_variance_boost: bool,
+
+    /// SVT-AV1 sharpness, from -7 to 7
+    #[arg(long, value_parser = clap::value_parser!(i8).range(-7..=7), allow_hyphen_values = true)]
+    pub svt_sharpness: Option<i8>,
+
+    /// SVT-AV1 film grain synthesis level, from 1 to 50
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=50))]
+    pub svt_film_grain: Option<u8>,
+
+    /// Denoise the source when SVT-AV1 film grain synthesis is enabled
+    #[arg(long, default_value_t = false, requires = "svt_film_grain")]
+    pub svt_film_grain_denoise: bool,
+
+    /// Split each frame into tiles, given as columns by rows such as 4x2 (AV1 and VP9 only)
+    #[arg(long, value_parser = parse_tiles)]
+    pub tiles: Option<(usize, usize)>,
+
     /// Quality parameter in the encoder to adjust
     #[arg(short, long, value_enum, default_value_t = Mode::QP)]
     pub mode: Mode,
 
+    /// Maximum number of concurrent libvmaf metric processes and clips measured at once for the final
+    /// metrics (0 uses a quarter of the workers)
+    #[arg(long = "metric-jobs", value_parser = clap::value_parser!(usize), default_value_t = 0)]
+    pub metric_jobs: usize,
+
     /// Quality metric to target
     #[arg(long = "quality-metric", value_enum, default_value_t = Metric::Direct)]
     pub metric: Metric,
 
+    /// Overall metric cost: fast scores VMAF alone on a subset of frames, both for quality search
+    /// probes and the final report, while full measures every metric on every frame
+    #[arg(long, value_enum, default_value_t = MetricsEffort::Full)]
+    pub metrics_effort: MetricsEffort,
+
+    /// Metric to measure on each final scene encode and summarize in place of the fixed quality
+    /// value when the quality metric is direct
+    #[arg(long = "report-metric", value_enum)]
+    pub report_metric: Option<Metric>,
+
+    /// Number of lowest scoring scenes to list per metric after encoding (0 disables the list)
+    #[arg(long = "worst-scenes", value_parser = clap::value_parser!(usize), default_value_t = 10)]
+    pub worst_scenes: usize,
+
+    /// Display resolution to scale both reference and encode to before measuring metrics, such as
+    /// 1080p, 4k or 1920x1080 (defaults to the native resolution)
+    #[arg(long = "metric-resolution", value_parser = parse_resolution)]
+    pub metric_resolution: Option<(usize, usize)>,
+
+    /// Separate reference video to measure metrics against, instead of the source
+    #[arg(long)]
+    pub reference: Option<PathBuf>,
+
+    /// Number of frames by which the reference is ahead of the source (negative if it lags behind)
+    #[arg(
+        long = "reference-offset",
+        allow_hyphen_values = true,
+        default_value_t = 0
+    )]
+    pub reference_offset: i64,
+
+    /// Scale at which SSIMULACRA2 is measured for quality search probes, such as 0.5 for half
+    /// resolution (the final verification is always measured at full resolution)
+    #[arg(long = "probe-metric-scale", value_parser = parse_scale, default_value_t = 1.0)]
+    pub probe_metric_scale: f64,
+
     /// Quality targeting rule
     #[arg(short, long = "quality-rule", value_enum, default_value_t = QualityRule::Minimum)]
     pub rule: QualityRule,
@@ -496,10 +1604,263 @@ pub struct Config {
     #[arg(long = "quality-percentile", value_parser = clap::value_parser!(f64), default_value_t = 0.05)]
     pub percentile: f64,
 
+    /// File of alternate quality targets, one scene index or START-END frame range and target per line
+    #[arg(long = "target-overrides")]
+    pub target_overrides: Option<PathBuf>,
+
+    /// Detect animated content and apply animation tuning to each animated scene or to the whole source
+    #[arg(long = "detect-animation", value_enum, default_value_t = AnimationDetection::Off)]
+    pub animation_detection: AnimationDetection,
+
+    /// Use grain-preserving settings for scenes that score below this PSNR against a denoised copy
+    #[arg(long = "grain-threshold", value_parser = clap::value_parser!(f64))]
+    pub grain_threshold: Option<f64>,
+
+    /// Re-encode scenes whose 95th percentile CAMBI banding score exceeds this with anti-banding
+    /// adaptive quantization settings, at the bit depth of the rest of the encode
+    #[arg(long = "max-banding", value_parser = clap::value_parser!(f64))]
+    pub max_banding: Option<f64>,
+
+    /// Scenes shorter than this many frames skip the quality search and reuse the quality of the
+    /// nearest searched scene, since a percentile over a handful of frames means little
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub short_scene_frames: Option<usize>,
+
+    /// Retry scenes that miss the quality target even at the best quality with up to this many
+    /// progressively slower presets (0 disables)
+    #[arg(long, value_parser = clap::value_parser!(usize), default_value_t = 0)]
+    pub preset_escalation: usize,
+
+    /// Maximum bitrate of each scene in kbps. Scenes that cannot meet the target under it use the
+    /// best quality that stays under it and are reported
+    #[arg(long = "max-bitrate", value_parser = clap::value_parser!(f64))]
+    pub max_bitrate: Option<f64>,
+
+    /// Probe every scene first and choose all scene qualities together, either minimizing bitrate
+    /// while the length-weighted mean metric meets the target, or maximizing that mean within
+    /// --max-bitrate as an average budget
+    #[arg(long = "global-optimization", value_enum)]
+    pub global_objective: Option<GlobalObjective>,
+
+    /// Allowed deviation from the quality target before a scene is corrected
+    #[arg(long, value_parser = clap::value_parser!(f64), default_value_t = 0.0)]
+    pub quality_tolerance: f64,
+
+    /// Maximum number of corrective re-encodes for a scene that misses the quality target
+    #[arg(long, value_parser = clap::value_parser!(usize), default_value_t = 2)]
+    pub max_corrections: usize,
+
+    /// Number of trailing encoder output lines to include in error messages
+    #[arg(long, value_parser = clap::value_parser!(usize), default_value_t = 32)]
+    pub stderr_lines: usize,
+
     /// Quality (QP or CRF) value to pass to the encoder
     #[arg(short, long, value_parser = clap::value_parser!(f64), default_value_t = 23.0)]
     pub quality: f64,
 
+    /// Scene detection method (fast analyzes a downscaled 8-bit decode)
+    #[arg(long = "scene-detect", value_enum, default_value_t = SceneDetection::Accurate)]
+    pub scene_detection: SceneDetection,
+
+    /// Detect scenes on a small proxy of the source, created once, instead of decoding the source
+    /// at full resolution (crop detection and the grain and animation analyses still decode the
+    /// source)
+    #[arg(long, default_value_t = false)]
+    pub proxy: bool,
+
+    /// Treat the source as the JSON index of a directory of already split lossless chunks, each
+    /// encoded as one scene, instead of reading, detecting scenes in and splitting a video
+    #[arg(long, default_value_t = false)]
+    pub pre_split: bool,
+
+    /// Use the scenes in an av1an scenes.json file instead of detecting them
+    #[arg(long)]
+    pub import_scenes: Option<PathBuf>,
+
+    /// Export the detected scenes in an interchange format (may be repeated, and av1an also exports
+    /// the chosen qualities as zones)
+    #[arg(long = "export-scenes", value_enum)]
+    pub scene_exports: Vec<SceneFormat>,
+
+    /// Multiple that detected crop dimensions are rounded down to, which should be even
+    #[arg(long, value_parser = parse_crop_round, default_value_t = 4)]
+    pub crop_round: usize,
+
+    /// Pixels trimmed from every cropped edge beyond the detected black bars, keeping soft bar
+    /// edges out of the picture
+    #[arg(long, default_value_t = 0)]
+    pub crop_margin: usize,
+
+    /// Number of sources whose metadata is kept in memory (0 disables the in-memory cache)
+    #[arg(long, default_value_t = 16)]
+    pub metadata_cache_size: usize,
+
+    /// Shared cache directory for metadata, scenes and metrics, reused across output directories
+    #[arg(long = "cache-dir")]
+    pub cache_directory: Option<PathBuf>,
+
+    /// Directory to work in instead of the output directory, which is then only written to once
+    /// the encode has finished, such as a share mounted read-only until publishing
+    #[arg(long = "staging-dir")]
+    pub staging_directory: Option<PathBuf>,
+
+    // The output directory a staged run publishes to, filled in once staging begins.
+    #[arg(skip)]
+    pub publish_directory: Option<PathBuf>,
+
+    /// Abort instead of warning when frame counts do not line up
+    #[arg(long, default_value_t = false)]
+    pub strict: bool,
+
+    /// Before measuring an encoded scene, check that its first and last frames match the
+    /// reference using a perceptual hash
+    #[arg(long, default_value_t = false)]
+    pub verify_alignment: bool,
+
+    /// After the chunked search, encode the whole video in one x264 or x265 invocation using zones
+    #[arg(long, default_value_t = false)]
+    pub single_encode: bool,
+
+    /// Tune encoding and scene detection for screen captures rather than film
+    #[arg(long, default_value_t = false)]
+    pub screen_content: bool,
+
+    /// Decode scenes on demand from the source instead of splitting them to disk
+    #[arg(long, default_value_t = false)]
+    pub streaming: bool,
+
+    /// FFV1 version used for split scenes (slices and slice CRCs need version 3)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=3), default_value_t = 3)]
+    pub ffv1_level: u8,
+
+    /// Number of slices in each split scene's FFV1 frames, which are encoded in parallel
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub ffv1_slices: Option<usize>,
+
+    /// Store a CRC in every FFV1 slice so that a damaged split scene is detected when decoded
+    #[arg(long, value_parser = clap::value_parser!(bool))]
+    pub ffv1_slicecrc: Option<bool>,
+
+    /// Number of threads used to encode each split scene (0 chooses automatically)
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub ffv1_threads: Option<usize>,
+
+    /// Keep encoder stats files instead of deleting them after each scene, with first passes shared
+    /// by later runs using the same encoder, preset and settings
+    #[arg(long, default_value_t = false)]
+    pub keep_stats: bool,
+
+    /// Rate control lookahead in frames, translated to each encoder's own option and shortened
+    /// to the length of shorter scenes (aomenc defaults to 48)
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub lookahead: Option<usize>,
+
+    /// Merge chunks in batches of this many into intermediate parts first, so an interrupted merge
+    /// resumes from the last complete part (0 merges everything at once)
+    #[arg(long, default_value_t = 0)]
+    pub merge_batch: usize,
+
+    /// Also write the output as GOP-aligned segments of roughly this many seconds
+    #[arg(long = "segment-duration", value_parser = parse_duration)]
+    pub segment_duration: Option<f64>,
+
+    /// Scenes to encode before all others, such as scenes=12,40,41 (the order is kept across
+    /// resumed runs)
+    #[arg(long, value_parser = parse_scene_list)]
+    pub prioritize: Option<SceneList>,
+
+    /// Output bit depth (auto chooses from the source bit depth and its risk of banding)
+    #[arg(long, value_enum, default_value_t = BitDepth::Auto)]
+    pub bit_depth: BitDepth,
+
+    /// Whether split scenes upconvert an 8-bit 4:2:0 source to 10 bits or keep it at 8 bits end
+    /// to end (10-bit 4:2:0 sources are always passed through untouched)
+    #[arg(long, value_enum, default_value_t = SourceDepth::Upconvert)]
+    pub source_depth: SourceDepth,
+
+    /// Encoder to transcode the source audio with while the video encodes, muxing every audio
+    /// track into the output (without this the output contains video only)
+    #[arg(long, value_enum)]
+    pub audio_encoder: Option<AudioEncoder>,
+
+    /// Bitrate of the transcoded audio, such as 128k or 96k
+    #[arg(long, default_value = "128k")]
+    pub audio_bitrate: String,
+
+    /// Source subtitle tracks to copy into the output, as all or a list of mkvmerge track IDs or
+    /// language codes such as 3,4 or eng
+    #[arg(long)]
+    pub subtitles: Option<String>,
+
+    /// Copy the source chapters into the output
+    #[arg(long, default_value_t = false)]
+    pub chapters: bool,
+
+    /// How statistics over scenes are weighted: each scene counting once, each weighted by its
+    /// length in frames, or both side by side
+    #[arg(long, value_enum, default_value_t = SceneWeighting::Both)]
+    pub scene_weighting: SceneWeighting,
+
+    /// Path the final output is also written to, whose extension, if any, must match the container
+    #[arg(long, conflicts_with = "output_template")]
+    pub output_file: Option<PathBuf>,
+
+    /// Template for the path the final output is also written to, relative to the output directory
+    /// unless absolute, with `{encoder}`, `{preset}`, `{quality}`, `{source_stem}` and
+    /// `{identifier}` placeholders
+    #[arg(long)]
+    pub output_template: Option<String>,
+
+    /// Container of the final output instead of MKV (webm needs a VP9 or AV1 encoder)
+    #[arg(long, value_enum)]
+    pub container: Option<Container>,
+
+    /// Also package the merged output for delivery in this container, holding only the video and
+    /// the first audio track
+    #[arg(long, value_enum)]
+    pub delivery: Option<Container>,
+
+    // Why an automatic bit depth resolved the way it did, recorded in the manifest.
+    #[arg(skip)]
+    pub bit_depth_reason: Option<String>,
+
+    // Whether the source signals full range, filled in from its metadata.
+    #[arg(skip)]
+    pub full_range: bool,
+
+    /// Scenes to render review videos of after encoding, such as scenes=3,17
+    #[arg(long, value_parser = parse_scene_list)]
+    pub review: Option<SceneList>,
+
+    /// How review videos compare the source to the encode
+    #[arg(long, value_enum, default_value_t = ReviewMode::SideBySide)]
+    pub review_mode: ReviewMode,
+
+    /// Directory collecting a summary of each title encoded into it, from which a roll-up report
+    /// across the whole batch is regenerated after every encode
+    #[arg(long)]
+    pub batch_report: Option<PathBuf>,
+
+    /// Serve a live status page at this address, such as :8080
+    #[arg(long = "serve-status")]
+    pub status_address: Option<String>,
+
+    /// Append a detailed log of stages, scenes, encoder exits and cache use to this file
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+
+    /// Export stage and scene spans to this OTLP/HTTP traces endpoint (requires the otlp feature)
+    #[arg(long)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Override the minimum accepted version of a tool, as TOOL=VERSION (may be repeated)
+    #[arg(long = "min-tool-version", value_parser = parse_tool_version)]
+    pub minimum_tool_versions: Vec<(String, String)>,
+
+    /// Skip checking external tool versions before starting
+    #[arg(long, default_value_t = false)]
+    pub skip_version_check: bool,
+
     /// Source video file to encode
     pub source: PathBuf,
 
@@ -508,30 +1869,320 @@ pub struct Config {
 }
 
 impl Config {
-    fn encode_arguments_hash(&self) -> String {
+    // A lookahead longer than the scene has nothing to look at, and some encoders reject it.
+    #[must_use]
+    pub fn with_scene_lookahead(&self, frames: usize) -> Self {
+        let mut config = self.clone();
+
+        if let Some(lookahead) = self.encoder.lookahead(self) {
+            config.lookahead = Some(lookahead.min(frames));
+        }
+
+        config
+    }
+
+    // A grain-heavy scene synthesizes more grain than the configured SVT-AV1 level, and one with no
+    // level configured gets a moderate one.
+    #[must_use]
+    pub fn with_scene_grain(&self, grain_heavy: bool) -> Self {
+        let mut config = self.clone();
+
+        if grain_heavy && self.encoder == Encoder::SvtAv1 {
+            config.svt_film_grain = Some(
+                self.svt_film_grain
+                    .map_or(10, |level| level.saturating_add(5).min(50)),
+            );
+        }
+
+        config
+    }
+
+    #[must_use]
+    pub fn encode_arguments_hash(&self) -> String {
         let tune_arguments = self.encoder.tune_arguments(self);
 
         let mut hasher = Sha256::new();
         hasher.update(tune_arguments.join(" "));
+
+        // Tiles change the bitstream, as does the thread count for some encoders.
+        if let Some((columns, rows)) = self.tiles {
+            hasher.update(format!(" tiles={columns}x{rows}"));
+        }
+
+        if self.encoder.thread_dependent() {
+            hasher.update(format!(" threads={}", self.threads_per_worker));
+        }
+
+        // Segmenting caps the key frame interval, so those encodes must not share a cache with
+        // unsegmented ones.
+        if let Some(segment_duration) = self.segment_duration {
+            hasher.update(format!(" segment-duration={segment_duration}"));
+        }
+
+        if self.animation_detection != AnimationDetection::Off {
+            hasher.update(format!(" animation={}", self.animation_detection));
+        }
+
+        // Encodes at the original 10 bits keep their existing identifiers.
+        if self.bit_depth.bits() != 10 {
+            hasher.update(format!(" bit-depth={}", self.bit_depth.bits()));
+        }
+
+        // Scenes kept at 8 bits are different input to the encoder.
+        if self.source_depth == SourceDepth::Keep {
+            hasher.update(format!(" source-depth={}", self.source_depth));
+        }
+
+        // Limited range encodes keep their existing identifiers.
+        if self.full_range {
+            hasher.update(" range=full");
+        }
+
+        // The threshold decides which scenes get the grain arguments.
+        if let Some(grain_threshold) = self.grain_threshold {
+            hasher.update(format!(" grain-threshold={grain_threshold}"));
+        }
+
         let result = hasher.finalize();
 
         encode_string(&result)
     }
 
+    // These become part of the tune arguments, so they are covered by the arguments hash.
+    #[must_use]
+    pub fn svt_arguments(&self) -> Vec<String> {
+        let mut arguments = vec![];
+
+        if let Some(level) = self.svt_fast_decode {
+            arguments.extend(["--fast-decode".to_owned(), format!("{level}")]);
+        }
+
+        if self.svt_variance_boost {
+            arguments.extend(["--enable-variance-boost".to_owned(), "1".to_owned()]);
+        }
+
+        if let Some(sharpness) = self.svt_sharpness {
+            arguments.extend(["--sharpness".to_owned(), format!("{sharpness}")]);
+        }
+
+        if let Some(level) = self.svt_film_grain {
+            arguments.extend([
+                "--film-grain".to_owned(),
+                format!("{level}"),
+                "--film-grain-denoise".to_owned(),
+                if self.svt_film_grain_denoise {
+                    "1"
+                } else {
+                    "0"
+                }
+                .to_owned(),
+            ]);
+        }
+
+        arguments
+    }
+
+    // Without an explicit worker count, larger frames get fewer encoders with more threads each,
+    // and the encoders together must fit in the available memory.
+    #[expect(clippy::integer_division)]
+    #[expect(clippy::integer_division_remainder_used)]
+    #[must_use]
+    pub fn with_automatic_threads(&self, metadata: &Metadata) -> Self {
+        let mut config = self.clone();
+
+        if self.workers != 0 {
+            config.threads_per_worker = self.threads_per_worker.max(1);
+            return config;
+        }
+
+        let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let pixels = metadata
+            .dimensions()
+            .map_or(1920 * 1080, |(width, height)| width * height);
+
+        let threads = match (self.threads_per_worker, pixels) {
+            (0, 0..=921_600) => 1,
+            (0, 921_601..=2_359_296) => 2,
+            (0, _) => 4,
+            (threads, _) => threads,
+        };
+
+        let mut workers = (cores / threads).max(1);
+
+        if let Some(memory) = available_memory() {
+            // Decoders, pipes and metric processes take roughly another 64 bytes per pixel for each
+            // worker, and a fifth of the memory is left for everything else.
+            let worker_memory = u64::try_from(pixels)
+                .unwrap_or(u64::MAX)
+                .saturating_mul(self.encoder.memory_per_pixel() + 64)
+                .max(1);
+
+            let memory_workers =
+                usize::try_from(memory / 5 * 4 / worker_memory).unwrap_or(usize::MAX);
+
+            workers = workers.min(memory_workers).max(1);
+        }
+
+        config.workers = workers;
+        config.threads_per_worker = threads;
+
+        config
+    }
+
+    // The range is probed from the source rather than given on the command line, and a source
+    // without range information is taken to be limited range like most video.
+    #[must_use]
+    pub fn with_color_range(&self, metadata: &Metadata) -> Self {
+        let mut config = self.clone();
+        config.full_range = metadata.full_range.unwrap_or(false);
+        config
+    }
+
+    // The requested path for the final output, if any. Without an extension, the one of the output
+    // itself is used, and any other extension is rejected since the output is not converted.
+    #[expect(clippy::literal_string_with_formatting_args)]
+    pub fn output_file_path(&self, extension: &str) -> anyhow::Result<Option<PathBuf>> {
+        let (path, requested) = if let Some(output_file) = &self.output_file {
+            (output_file.clone(), output_file.as_os_str().to_owned())
+        } else if let Some(template) = &self.output_template {
+            let source_stem = self
+                .source
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            let substitutions = [
+                ("{encoder}", self.encoder.to_string()),
+                ("{preset}", self.preset.clone()),
+                ("{quality}", self.quality.to_string()),
+                ("{source_stem}", source_stem),
+                ("{identifier}", self.encode_identifier(true)),
+            ];
+
+            // The template is checked before substituting, since a source stem may contain braces.
+            let unknown = substitutions
+                .iter()
+                .fold(template.clone(), |unknown, (placeholder, _)| {
+                    unknown.replace(placeholder, "")
+                });
+
+            if unknown.contains(['{', '}']) {
+                return Err(anyhow!(
+                    "Output template {template:?} has an unknown placeholder"
+                ));
+            }
+
+            let path = substitutions
+                .iter()
+                .fold(template.clone(), |path, (placeholder, value)| {
+                    path.replace(placeholder, value)
+                });
+
+            (
+                self.publish_directory
+                    .as_ref()
+                    .unwrap_or(&self.output_directory)
+                    .join(path),
+                template.into(),
+            )
+        } else {
+            return Ok(None);
+        };
+
+        match Path::new(&requested).extension() {
+            Some(requested) if !requested.eq_ignore_ascii_case(extension) => Err(anyhow!(
+                "Output file extension {requested:?} does not match the {extension} output"
+            )),
+            Some(_) => Ok(Some(path)),
+            None => {
+                let mut path = path.into_os_string();
+                path.push(format!(".{extension}"));
+                Ok(Some(path.into()))
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn config_directory(&self) -> PathBuf {
+        self.output_directory.join("config")
+    }
+
+    // Every artifact is written to the staging directory, which stands in for the output directory
+    // until the finished output is published to it.
+    #[must_use]
+    pub fn with_staging(&self) -> Self {
+        let mut config = self.clone();
+
+        if let Some(staging_directory) = &self.staging_directory {
+            config.output_directory.clone_from(staging_directory);
+            config.publish_directory = Some(self.output_directory.clone());
+        }
+
+        config
+    }
+
+    #[must_use]
+    pub fn metric_jobs(&self) -> usize {
+        if self.metric_jobs == 0 {
+            (self.workers / 4).max(1)
+        } else {
+            self.metric_jobs
+        }
+    }
+
     #[must_use]
     pub const fn passes(&self) -> usize {
         self.encoder.passes(self)
     }
 
+    // The metric along with anything that changes its values, such as the display resolution or an
+    // external reference.
+    #[must_use]
+    pub fn metric_identifier(&self) -> String {
+        let mut metric = self.metric_resolution.map_or_else(
+            || self.metric.to_string(),
+            |(width, height)| format!("{}@{width}x{height}", self.metric),
+        );
+
+        // Fast VMAF scores differ slightly, so searches on them must not share results.
+        if self.metric == Metric::VMAF && self.metrics_effort == MetricsEffort::Fast {
+            metric = format!("{metric}-fast");
+        }
+
+        if let Some(reference) = &self.reference {
+            let reference = hash_string(&format!(
+                "{}@{}",
+                reference.to_string_lossy(),
+                self.reference_offset
+            ));
+
+            metric = format!("{metric}-ref{}", &reference[..8]);
+        }
+
+        metric
+    }
+
     #[must_use]
     pub fn encode_identifier(&self, include_quality: bool) -> String {
         let encoder = self.encoder.to_string();
         let preset = self.preset.clone();
         let mode = self.mode.to_string();
-        let metric = self.metric.to_string();
+        let metric = self.metric_identifier();
         let quality = self.quality;
-        let rule = self.rule.to_string();
-        let constraint = "unconstrained";
+        let rule = self.max_bitrate.map_or_else(
+            || self.rule.to_string(),
+            |max_bitrate| format!("{}-max{max_bitrate}", self.rule),
+        );
+        let rule = self.global_objective.map_or_else(
+            || rule.clone(),
+            |objective| format!("{rule}-global{objective}"),
+        );
+        let constraint = match (&self.profile, &self.level) {
+            (None, None) => "unconstrained".to_owned(),
+            (Some(profile), None) => profile.clone(),
+            (None, Some(level)) => format!("level{level}"),
+            (Some(profile), Some(level)) => format!("{profile}@{level}"),
+        };
         let hash = self.encode_arguments_hash();
 
         let percentile = if self.use_mean {
@@ -541,10 +2192,17 @@ impl Config {
         };
 
         if include_quality {
-            if self.metric == Metric::Direct {
+            let identifier = if self.metric == Metric::Direct {
                 format!("{encoder}-{preset}-{mode}-{quality}-{constraint}-{hash}")
             } else {
                 format!("{encoder}-{preset}-{mode}-{metric}-{rule}-{quality}-{percentile}-{constraint}-{hash}")
+            };
+
+            // A whole video encode reuses the chunked search but must not overwrite its output.
+            if self.single_encode {
+                format!("{identifier}-single")
+            } else {
+                identifier
             }
         } else {
             format!("{encoder}-{preset}-{mode}-{constraint}-{hash}")
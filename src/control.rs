@@ -0,0 +1,247 @@
+use std::collections::BTreeSet;
+use std::io::{self, Read};
+use std::process::{Child, ExitStatus, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+#[cfg(unix)]
+use crate::{exit, util};
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static CHILDREN: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+static PAUSED_TIME: Mutex<(Duration, Option<Instant>)> = Mutex::new((Duration::ZERO, None));
+
+fn children() -> MutexGuard<'static, BTreeSet<u32>> {
+    CHILDREN
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Registration of a subprocess that is suspended and resumed along with the encode.
+pub struct TrackedChild(u32);
+
+impl TrackedChild {
+    // The process ID is released before the child is reaped, so a pause or interrupt can never
+    // signal an unrelated process that has since been given the same ID.
+    pub fn wait(self, child: &mut Child) -> io::Result<ExitStatus> {
+        wait_for_exit(self.0);
+        drop(self);
+
+        child.wait()
+    }
+
+    // Like Child::wait_with_output, but reaping the child through the tracker.
+    pub fn wait_with_output(self, mut child: Child) -> io::Result<Output> {
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        let (stdout, stderr) = thread::scope(|scope| {
+            let stdout = scope.spawn(|| read_pipe(stdout));
+            let stderr = read_pipe(stderr);
+
+            (
+                stdout
+                    .join()
+                    .unwrap_or_else(|error| std::panic::resume_unwind(error)),
+                stderr,
+            )
+        });
+
+        let status = self.wait(&mut child)?;
+
+        Ok(Output {
+            status,
+            stdout: stdout?,
+            stderr: stderr?,
+        })
+    }
+}
+
+impl Drop for TrackedChild {
+    fn drop(&mut self) {
+        children().remove(&self.0);
+    }
+}
+
+// A child spawned while paused is stopped straight away, so it cannot race the pause request.
+pub fn track(child: &Child) -> TrackedChild {
+    let pid = child.id();
+    let mut children = children();
+
+    children.insert(pid);
+
+    if PAUSED.load(Ordering::SeqCst) {
+        signal_child(pid, false);
+    }
+
+    drop(children);
+
+    TrackedChild(pid)
+}
+
+fn read_pipe(pipe: Option<impl Read>) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+
+    if let Some(mut pipe) = pipe {
+        pipe.read_to_end(&mut buffer)?;
+    }
+
+    Ok(buffer)
+}
+
+// Waits for the child to exit but leaves it a zombie, so its process ID stays reserved until
+// Child::wait reaps it. Any failure is left for Child::wait to report.
+#[cfg(unix)]
+fn wait_for_exit(pid: u32) {
+    loop {
+        // SAFETY: A zeroed siginfo_t is a valid value, and waitid only writes to it.
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+
+        // SAFETY: The pointer is to a live siginfo_t, and WNOWAIT leaves the child unreaped.
+        let result = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid,
+                &raw mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+
+        if result == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+const fn wait_for_exit(_pid: u32) {}
+
+// The total time spent paused so far, including a pause still in progress, so estimates based on
+// elapsed time can leave it out.
+pub fn paused_time() -> Duration {
+    let (total, since) = *PAUSED_TIME
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    total + since.map_or(Duration::ZERO, |since| since.elapsed())
+}
+
+// Scenes already in flight are suspended rather than abandoned, so only new work waits here.
+pub fn wait_while_paused() {
+    while PAUSED.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+#[cfg(unix)]
+fn signal_child(pid: u32, resume: bool) {
+    send_signal(pid, if resume { libc::SIGCONT } else { libc::SIGSTOP });
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: libc::c_int) {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return;
+    };
+
+    // SAFETY: Sending a signal has no memory safety implications. Callers hold the CHILDREN lock,
+    // and TrackedChild::wait only reaps a child after removing it under that lock, so the process
+    // ID still belongs to the child (or its zombie) and cannot have been reused.
+    if unsafe { libc::kill(pid, signal) } != 0 {
+        warn!("Unable to send signal {signal} to subprocess {pid}");
+    }
+}
+
+#[cfg(not(unix))]
+const fn signal_child(_pid: u32, _resume: bool) {}
+
+#[cfg(unix)]
+fn set_paused(paused: bool) {
+    let children = children();
+
+    if PAUSED.swap(paused, Ordering::SeqCst) == paused {
+        return;
+    }
+
+    for &pid in children.iter() {
+        signal_child(pid, !paused);
+    }
+
+    let count = children.len();
+    drop(children);
+
+    let mut paused_time = PAUSED_TIME
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    if paused {
+        paused_time.1 = Some(Instant::now());
+    } else if let Some(since) = paused_time.1.take() {
+        paused_time.0 += since.elapsed();
+    }
+
+    drop(paused_time);
+
+    if paused {
+        warn!("Pausing encode and suspending {count} subprocess(es)");
+    } else {
+        warn!("Resuming encode and {count} subprocess(es)");
+    }
+}
+
+// Subprocesses only share a terminal's SIGINT, not a SIGTERM sent to this process, and a stopped
+// subprocess ignores SIGTERM until it is resumed. Not every file is written atomically, so this can
+// leave partial split scenes, statistics or merge parts behind, just as a crash would.
+#[cfg(unix)]
+fn interrupt() -> ! {
+    let children = children();
+
+    warn!("Interrupted, stopping {} subprocess(es)", children.len());
+
+    for &pid in children.iter() {
+        send_signal(pid, libc::SIGTERM);
+        send_signal(pid, libc::SIGCONT);
+    }
+
+    drop(children);
+
+    util::shutdown_tracing();
+
+    std::process::exit(exit::INTERRUPTED.into())
+}
+
+// SIGUSR1 pauses and SIGUSR2 resumes. SIGINT and SIGTERM end the run with a distinct exit code.
+// Exiting from a handler would take signals away from any process embedding the library, so only
+// the command line installs these.
+#[cfg(unix)]
+pub fn install() -> anyhow::Result<()> {
+    use anyhow::Context;
+    use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGUSR1, SIGUSR2, SIGINT, SIGTERM])
+        .context("Unable to register control signal handlers")?;
+
+    thread::Builder::new()
+        .name("pause-control".to_owned())
+        .spawn(move || {
+            for signal in signals.forever() {
+                match signal {
+                    SIGUSR1 | SIGUSR2 => set_paused(signal == SIGUSR1),
+                    _ => interrupt(),
+                }
+            }
+        })
+        .context("Unable to spawn pause control thread")?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub const fn install() -> anyhow::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::cmp;
+
+use anyhow::{anyhow, Context};
+
+use crate::animation;
+use crate::config::{BitDepth, Config};
+use crate::ffmpeg::{self, Metadata};
+use crate::presplit;
+use crate::scenes::{get, split_key, split_pixel_format};
+
+// Enough of the longest scenes to judge the source as a whole without decoding all of it.
+const SAMPLE_SCENES: usize = 8;
+
+// Fraction of flat luma steps above which smooth gradients make an 8-bit encode likely to band.
+const MINIMUM_FLAT_FRACTION: f64 = 0.5;
+
+// High bit depth sources keep their precision. An 8-bit source only gains from 10 bits where smooth
+// gradients would otherwise band, while screen content and busy textures gain nothing but size.
+fn choose(config: &Config, metadata: &Metadata) -> anyhow::Result<(BitDepth, String)> {
+    if config.encoder.is_mezzanine() {
+        return Ok((
+            BitDepth::Ten,
+            "mezzanine encoders choose their own depth".to_owned(),
+        ));
+    }
+
+    // Pre-split chunks share one format, so the first stands in for the source.
+    let source = if config.pre_split {
+        presplit::chunk(config, 0)?
+    } else {
+        config.source.clone()
+    };
+
+    let source_depth = ffmpeg::bit_depth(&source)
+        .with_context(|| format!("Unable to determine bit depth of {source:?}"))?;
+
+    if source_depth > 8 {
+        return Ok((BitDepth::Ten, format!("source is {source_depth}-bit")));
+    }
+
+    if split_pixel_format(config, metadata) == "yuv420p" {
+        return Ok((BitDepth::Eight, "8-bit source kept at 8 bits".to_owned()));
+    }
+
+    if config.screen_content {
+        return Ok((BitDepth::Eight, "8-bit screen content".to_owned()));
+    }
+
+    let mut scenes = get(config).context("Unable to fetch scene data")?;
+    scenes.sort_by_key(|scene| cmp::Reverse(scene.length()));
+
+    // The analysis is cached where animation detection looks for it, so neither repeats the other.
+    let analysis_directory = config
+        .output_directory
+        .join("analysis")
+        .join(split_key(config, metadata).context("Unable to calculate split key")?);
+
+    let chunks = if config.pre_split {
+        presplit::load(config).context("Unable to load chunk index")?
+    } else {
+        vec![]
+    };
+
+    let samples = scenes
+        .iter()
+        .take(SAMPLE_SCENES)
+        .map(|scene| -> anyhow::Result<(f64, f64)> {
+            let (path, filter, range) = if config.pre_split {
+                let chunk = chunks.get(scene.index()).ok_or_else(|| {
+                    anyhow!("Chunk index has no chunk for scene {}", scene.index())
+                })?;
+
+                (chunk.file.clone(), None, None)
+            } else {
+                (
+                    config.source.clone(),
+                    metadata.crop_filter.as_deref(),
+                    Some(scene.source_range(metadata)),
+                )
+            };
+
+            let statistics = animation::analyze(
+                &path,
+                filter,
+                range,
+                &analysis_directory.join(format!("scene-{:05}.animation.json", scene.index())),
+            )
+            .with_context(|| format!("Unable to analyze scene {:05}", scene.index()))?;
+
+            #[expect(clippy::as_conversions)]
+            #[expect(clippy::cast_precision_loss)]
+            let length = scene.length() as f64;
+
+            Ok((statistics.flat_fraction * length, length))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let (weighted, frames) = samples
+        .iter()
+        .fold((0.0, 0.0), |(weighted, frames), (sample, length)| {
+            (weighted + sample, frames + length)
+        });
+
+    let flat_fraction = if frames > 0.0 { weighted / frames } else { 0.0 };
+
+    Ok(if flat_fraction >= MINIMUM_FLAT_FRACTION {
+        (
+            BitDepth::Ten,
+            format!("8-bit source with smooth gradients ({flat_fraction:.2} flat)"),
+        )
+    } else {
+        (
+            BitDepth::Eight,
+            format!("8-bit source with little banding risk ({flat_fraction:.2} flat)"),
+        )
+    })
+}
+
+#[expect(clippy::print_stdout)]
+pub fn resolve(config: &Config, metadata: &Metadata) -> anyhow::Result<Config> {
+    let mut config = config.clone();
+
+    if config.bit_depth != BitDepth::Auto {
+        return Ok(config);
+    }
+
+    let (bit_depth, reason) =
+        choose(&config, metadata).context("Unable to choose an output bit depth")?;
+
+    println!("Encoding at {bit_depth}-bit: {reason}");
+
+    config.bit_depth = bit_depth;
+    config.bit_depth_reason = Some(reason);
+
+    Ok(config)
+}
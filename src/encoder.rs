@@ -1,570 +1,3812 @@
-use std::cmp;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ffi::OsString;
 use std::fs;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::str;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context};
 use crossbeam_queue::ArrayQueue;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressState, ProgressStyle};
+use prettytable::{format::consts, row, Table};
+use serde::Serialize;
 use statrs::statistics::{Data, Distribution, OrderStatistics};
+use tracing::{debug, error, field, info_span, warn};
 
-use crate::config::{Config, Metric, Mode, QualityRule};
-use crate::ffmpeg::{create_child_read, get_metadata, Metadata};
-use crate::metrics::ClipMetrics;
-use crate::scenes::{get, Scene};
+use crate::alignment;
+use crate::allocation::{self, Allocation, RatePoint, SceneModel};
+use crate::animation::{self, AnimationDecision};
+use crate::audio;
+use crate::cache::shared_directory;
+use crate::config::{
+    self, AnimationDetection, Config, Container, GlobalObjective, Metric, MetricsEffort, Mode,
+    Monotonicity, QualityRule, SceneFormat,
+};
+use crate::control::{paused_time, track, wait_while_paused};
+use crate::ffmpeg::{
+    create_child_read, dimensions, frame_rate, get_metadata, Metadata, SourceRange,
+};
+use crate::grain::{self, GrainDecision};
+use crate::history::FpsHistory;
+use crate::levels;
+use crate::manifest::previous_scene_qualities;
+use crate::metrics::{ClipMetrics, MetricContext};
+use crate::package;
+use crate::pipe::{self, RelayFailure};
+use crate::pool::WorkerPool;
+use crate::probes::{ProbeIndex, SearchStep, SearchTimeline};
+use crate::report::{self, EncoderReport};
+use crate::review;
+use crate::scenes::{format_scenes, get, split_key, Scene};
+use crate::schedule::{SceneSchedule, SceneStatus};
+use crate::status::{set_bitrate, set_worker_message};
+use crate::tools::{command, versions};
 use crate::util::{
-    create_progress_style, print_histogram, print_stats, verify_directory, HumanBitrate,
+    create_progress_style, hash_string, notify_progress, print_histogram, print_stats, read_json,
+    verify_directory, weight_by_length, write_json, HumanBitrate,
 };
 
+// Ten seconds at 300 FPS. A longer interval can only come from a broken duration or frame count.
+const MAXIMUM_KEY_FRAME_INTERVAL: usize = 3000;
+
+// An encoder that takes this long to accept more input is probably stuck rather than just slow.
+const STALL_WARNING: Duration = Duration::from_mins(1);
+
+// Encoders round their reported bitrate and count a little container-level data differently.
+const MAXIMUM_BITRATE_DISCREPANCY: f64 = 0.05;
+
+// Global optimization probes each scene at its expected quality and a step either side, with the
+// step a sixth of the quality range so the probes span most of the useful trade-offs.
+const GLOBAL_PROBE_STEPS: usize = 6;
+
 fn update_worker_message(progress_bar: &ProgressBar, scene_index: usize, message: &str) {
-    progress_bar.set_message(format!("[Scene {scene_index:05}] {message}"));
+    let message = format!("[Scene {scene_index:05}] {message}");
+    set_worker_message(&message);
+    progress_bar.set_message(message);
 }
 
 fn clear_worker_message(progress_bar: &ProgressBar) {
+    set_worker_message("[Idle       ]");
     progress_bar.set_message("[Idle       ]");
 }
 
-pub struct EncodeStatistics {
-    config: Config,
-    scene_lengths: Vec<f64>,
-    qualities: Vec<f64>,
+// A finished scene's index, quality, reported metric value, target miss and final chunk.
+type SceneResult = (usize, f64, Option<f64>, Option<TargetMiss>, PathBuf);
+
+// The most encoder output kept for the failure log and the encode summary, both of which only need
+// its end. A long encode at a high log level can otherwise write hundreds of megabytes.
+const OUTPUT_LIMIT: usize = 1 << 20;
+
+// Keeps the last few lines of encoder output for error messages, along with the end of everything
+// written so far for the failure log.
+struct EncoderOutput {
+    recent: VecDeque<String>,
+    depth: usize,
+    tail: VecDeque<u8>,
+    truncated: bool,
+    command_line: Vec<String>,
 }
 
-impl EncodeStatistics {
-    #[must_use]
-    pub fn new(config: &Config) -> Self {
+impl EncoderOutput {
+    fn new(depth: usize) -> Self {
         Self {
-            config: config.clone(),
-            scene_lengths: vec![],
-            qualities: vec![],
+            recent: VecDeque::with_capacity(depth),
+            depth,
+            tail: VecDeque::new(),
+            truncated: false,
+            command_line: vec![],
         }
     }
 
-    pub fn print_quality_stats(&self) -> anyhow::Result<()> {
-        println!("{} Statistics", self.config.mode_description());
-        println!();
-        print_histogram(&self.qualities).with_context(|| {
-            format!(
-                "Unable to output {} histogram",
-                self.config.mode_description()
-            )
-        })?;
-        println!();
+    fn push(&mut self, chunk: &[u8]) {
+        self.tail.extend(chunk);
 
-        print_stats(&mut vec![
-            ("Scene Length".to_owned(), self.scene_lengths.clone()),
-            (self.config.mode_description(), self.qualities.clone()),
-        ])
-        .with_context(|| {
-            format!(
-                "Unable to output {} statistics",
-                self.config.mode_description()
-            )
-        })?;
+        if self.tail.len() > OUTPUT_LIMIT {
+            self.tail.drain(..self.tail.len() - OUTPUT_LIMIT);
+            self.truncated = true;
+        }
 
-        Ok(())
+        if let Ok(line) = str::from_utf8(chunk) {
+            self.recent.push_back(line.to_owned());
+        }
+
+        while self.recent.len() > self.depth {
+            self.recent.pop_front();
+        }
+    }
+
+    fn text(&self) -> String {
+        let tail = self.tail.iter().copied().collect::<Vec<_>>();
+        let text = String::from_utf8_lossy(&tail).into_owned();
+
+        if self.truncated {
+            format!("[Earlier output omitted]\n{text}")
+        } else {
+            text
+        }
     }
 }
 
-pub struct Encoder {
-    config: Config,
-    scenes: Vec<Scene>,
-    metadata: Metadata,
-    encode_directory: PathBuf,
-    active_workers: AtomicUsize,
+/// A scene that was re-encoded with anti-banding settings, with its CAMBI scores before and after.
+#[derive(Clone, Copy, Serialize)]
+pub struct BandingIntervention {
+    cambi: f64,
+    deband_cambi: Option<f64>,
 }
 
-impl Encoder {
-    pub fn new(config: &Config) -> anyhow::Result<Self> {
-        let mut scenes = get(config).context("Unable to fetch scene data")?;
-        scenes.sort_by_key(|x| cmp::Reverse(x.length()));
+/// A scene whose final encode still missed the quality target.
+#[derive(Clone, Copy, Serialize)]
+pub struct TargetMiss {
+    metric_value: f64,
+    gap: f64,
+    target: f64,
+    quality: f64,
+    range_boundary: bool,
+    bitrate: Option<f64>,
+}
 
-        let encode_directory = config
-            .output_directory
-            .join("encode")
-            .join(config.encode_identifier(false));
+/// A scene that missed the quality target at the best quality and was re-encoded with a slower
+/// preset, with its metric value before and after.
+#[derive(Clone, Serialize)]
+pub struct PresetEscalation {
+    preset: String,
+    metric_value: f64,
+    escalated_metric_value: Option<f64>,
+}
 
-        Ok(Self {
-            config: config.clone(),
-            scenes,
-            metadata: get_metadata(config).with_context(|| {
-                format!("Unable to fetch video metadata for {:?}", &config.source)
-            })?,
-            encode_directory,
-            active_workers: config.workers.into(),
-        })
+/// Whether an encode was a quality search probe, produced the scene that was kept, or encoded the
+/// whole video at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodeKind {
+    Probe,
+    Final,
+    Whole,
+}
+
+/// Time spent in one encoder pass, summed over every encode of one kind.
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct PassTiming {
+    encodes: usize,
+    frames: usize,
+    seconds: f64,
+}
+
+impl PassTiming {
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    fn fps(&self) -> f64 {
+        self.frames as f64 / self.seconds
     }
+}
 
-    #[expect(clippy::too_many_lines)]
-    pub fn encode(&self) -> anyhow::Result<(PathBuf, Vec<ClipMetrics>, EncodeStatistics)> {
-        let mut statistics = EncodeStatistics::new(&self.config);
+pub struct EncodeStatistics {
+    config: Config,
+    scene_lengths: BTreeMap<usize, usize>,
+    qualities: BTreeMap<usize, f64>,
+    scene_qualities: BTreeMap<usize, f64>,
+    scene_clips: BTreeMap<usize, PathBuf>,
+    target_misses: BTreeMap<usize, TargetMiss>,
+    animation_decisions: BTreeMap<usize, AnimationDecision>,
+    grain_decisions: BTreeMap<usize, GrainDecision>,
+    banding_interventions: BTreeMap<usize, BandingIntervention>,
+    preset_escalations: BTreeMap<usize, PresetEscalation>,
+    pass_timings: BTreeMap<EncodeKind, BTreeMap<usize, PassTiming>>,
+    encoder_reports: BTreeMap<usize, EncoderReport>,
+    global_allocation: Option<Allocation>,
+    short_scenes: BTreeMap<usize, usize>,
+}
 
-        let scene_queue: ArrayQueue<Scene> = ArrayQueue::new(self.scenes.len());
-        let result_queue: ArrayQueue<ClipMetrics> = ArrayQueue::new(self.scenes.len());
-        let quality_queue: ArrayQueue<f64> = ArrayQueue::new(self.scenes.len());
+impl EncodeStatistics {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            scene_lengths: BTreeMap::new(),
+            qualities: BTreeMap::new(),
+            scene_qualities: BTreeMap::new(),
+            scene_clips: BTreeMap::new(),
+            target_misses: BTreeMap::new(),
+            animation_decisions: BTreeMap::new(),
+            grain_decisions: BTreeMap::new(),
+            banding_interventions: BTreeMap::new(),
+            preset_escalations: BTreeMap::new(),
+            pass_timings: BTreeMap::new(),
+            encoder_reports: BTreeMap::new(),
+            global_allocation: None,
+            short_scenes: BTreeMap::new(),
+        }
+    }
 
-        for scene in &self.scenes {
-            #[expect(clippy::as_conversions)]
-            #[expect(clippy::cast_precision_loss)]
-            statistics.scene_lengths.push(scene.length() as f64);
+    #[must_use]
+    pub const fn pass_timings(&self) -> &BTreeMap<EncodeKind, BTreeMap<usize, PassTiming>> {
+        &self.pass_timings
+    }
 
-            if scene_queue.push(*scene).is_err() {
-                return Err(anyhow!("Encoding worker queue was unexpectedly full"));
-            }
+    // Only encodes that actually ran are counted, so a resumed run reports just its own work.
+    #[expect(clippy::print_stdout)]
+    pub fn print_pass_timings(&self) {
+        if self.pass_timings.is_empty() {
+            return;
         }
 
-        let multi_progress = MultiProgress::new();
+        let mut table = Table::new();
+        table.set_format(*consts::FORMAT_BOX_CHARS);
+        table.add_row(row!["Encode", "Pass", "Encodes", "Frames", "Time", "FPS"]);
 
-        let worker_progress_style = ProgressStyle::with_template("{msg}")
-            .context("Unable to create worker progress style")?;
+        for (kind, passes) in &self.pass_timings {
+            for (pass, timing) in passes {
+                table.add_row(row![
+                    format!("{kind:?}"),
+                    pass,
+                    timing.encodes,
+                    timing.frames,
+                    HumanDuration(Duration::from_secs_f64(timing.seconds)),
+                    format!("{:8.2}", timing.fps()),
+                ]);
+            }
+        }
 
-        let worker_progress_bars = (0..rayon::current_num_threads())
-            .map(|_thread_index| {
-                multi_progress.add(ProgressBar::new(1).with_style(worker_progress_style.clone()))
-            })
-            .collect::<Vec<_>>();
+        println!("Encoding time by pass:");
+        table.printstd();
+        println!();
+    }
 
-        let progress_bar =
-            ProgressBar::new(self.metadata.frame_count.try_into().unwrap_or(u64::MAX));
+    #[must_use]
+    pub const fn encoder_reports(&self) -> &BTreeMap<usize, EncoderReport> {
+        &self.encoder_reports
+    }
 
-        progress_bar.set_style(
-            create_progress_style(
-                "{spinner:.green} [{elapsed_precise}] Encoding scenes...         [{wide_bar:.cyan/blue}] {percent:>3}% {human_pos:>8}/{human_len:>8} ({smooth_per_sec:>6} FPS, {msg:>12}, ETA: {smooth_eta:>3})"
-            ).context("Unable to create encoding progress bar style")?
-        );
+    #[must_use]
+    pub const fn scene_qualities(&self) -> &BTreeMap<usize, f64> {
+        &self.scene_qualities
+    }
 
-        let progress_bar = multi_progress.add(progress_bar);
-        progress_bar.reset();
-        progress_bar.enable_steady_tick(Duration::from_secs(1));
+    #[must_use]
+    pub const fn target_misses(&self) -> &BTreeMap<usize, TargetMiss> {
+        &self.target_misses
+    }
 
-        let mut clips: Vec<ClipMetrics> = vec![];
+    #[must_use]
+    pub const fn animation_decisions(&self) -> &BTreeMap<usize, AnimationDecision> {
+        &self.animation_decisions
+    }
 
-        thread::scope(|scope| -> anyhow::Result<()> {
-            let threads = (0..self.config.workers)
-                .map(|thread_index| -> anyhow::Result<_> {
-                    let worker_progress_bar = worker_progress_bars
-                        .get(thread_index)
-                        .ok_or_else(|| anyhow!("Unable to access encoding worker progress bar"))?;
+    pub fn print_animation_decisions(&self) {
+        if self.animation_decisions.is_empty() {
+            return;
+        }
 
-                    clear_worker_message(worker_progress_bar);
+        let animated = self
+            .animation_decisions
+            .iter()
+            .filter(|(_, decision)| decision.animated)
+            .map(|(scene_index, _)| format!("{scene_index:05}"))
+            .collect::<Vec<_>>();
 
-                    Ok(scope.spawn(|| -> anyhow::Result<()> {
-                        while let Some(scene) = &scene_queue.pop() {
-                            let (result, quality) =
-                                self.encode_scene(scene, worker_progress_bar).with_context(
-                                    || format!("Unable to encode scene {}", scene.index()),
-                                )?;
-
-                            let input_filename = self
-                                .config
-                                .output_directory
-                                .join("source")
-                                .join(format!("scene-{:05}.mkv", scene.index()));
-
-                            let metrics = ClipMetrics::new(&result, &input_filename, None)
-                                .with_context(|| {
-                                    format!(
-                                        "Unable to calculate metrics for scene {}",
-                                        scene.index()
-                                    )
-                                })?;
+        println!(
+            "{} of {} scene(s) used animation tuning{}{}",
+            animated.len(),
+            self.animation_decisions.len(),
+            if animated.is_empty() { "" } else { ": " },
+            animated.join(", ")
+        );
+        println!();
+    }
 
-                            if result_queue.push(metrics).is_err() {
-                                return Err(anyhow!("Encoding result queue was unexpectedly full"));
-                            }
+    #[must_use]
+    pub const fn grain_decisions(&self) -> &BTreeMap<usize, GrainDecision> {
+        &self.grain_decisions
+    }
 
-                            if quality_queue.push(quality).is_err() {
-                                return Err(anyhow!(
-                                    "Encoding quality result queue was unexpectedly full"
-                                ));
-                            }
+    pub fn print_grain_decisions(&self) {
+        if self.grain_decisions.is_empty() {
+            return;
+        }
 
-                            clear_worker_message(worker_progress_bar);
-                        }
+        let grain_heavy = self
+            .grain_decisions
+            .iter()
+            .filter(|(_, decision)| decision.grain_heavy)
+            .map(|(scene_index, decision)| format!("{scene_index:05} ({:0.2} dB)", decision.score))
+            .collect::<Vec<_>>();
 
-                        worker_progress_bar.finish();
-                        self.active_workers.fetch_sub(1, Ordering::Relaxed);
+        println!(
+            "{} of {} scene(s) used grain-preserving settings{}{}",
+            grain_heavy.len(),
+            self.grain_decisions.len(),
+            if grain_heavy.is_empty() { "" } else { ": " },
+            grain_heavy.join(", ")
+        );
+        println!();
+    }
 
-                        Ok(())
-                    }))
-                })
-                .collect::<Result<Vec<_>, _>>()
-                .context("Unable to spawn encoding workers")?;
+    #[must_use]
+    pub const fn banding_interventions(&self) -> &BTreeMap<usize, BandingIntervention> {
+        &self.banding_interventions
+    }
 
-            let mut current_bytes = 0;
-            let mut current_duration = 0.0_f64;
+    pub fn print_banding_interventions(&self) {
+        if self.banding_interventions.is_empty() {
+            return;
+        }
 
-            while threads
-                .iter()
-                .any(|thread| -> bool { !thread.is_finished() })
-                || !result_queue.is_empty()
-            {
-                while let Some(mut clip) = result_queue.pop() {
-                    current_bytes += clip
-                        .sizes()
-                        .context("Unable to read clip size")?
-                        .iter()
-                        .sum::<usize>();
+        println!(
+            "{} scene(s) were re-encoded to reduce banding:",
+            self.banding_interventions.len()
+        );
+        println!();
 
-                    current_duration += clip.duration().context("Unable to read clip duration")?;
+        for (scene_index, intervention) in &self.banding_interventions {
+            println!(
+                "Scene {scene_index:05}: CAMBI {:0.3} => {}",
+                intervention.cambi,
+                intervention
+                    .deband_cambi
+                    .map_or_else(|| "N/A".to_owned(), |cambi| format!("{cambi:0.3}"))
+            );
+        }
 
-                    #[expect(clippy::as_conversions)]
-                    #[expect(clippy::cast_precision_loss)]
-                    progress_bar.set_message(format!(
-                        "{}",
-                        HumanBitrate(current_bytes as f64 * 8.0 / current_duration)
-                    ));
+        println!();
+    }
 
-                    progress_bar.inc(
-                        (clip.frames().context("Unable to read clip frame count")?)
-                            .try_into()
-                            .unwrap_or(u64::MAX),
-                    );
+    #[must_use]
+    pub const fn short_scenes(&self) -> &BTreeMap<usize, usize> {
+        &self.short_scenes
+    }
 
-                    clips.push(clip);
-                }
+    pub fn print_short_scenes(&self) {
+        if self.short_scenes.is_empty() {
+            return;
+        }
 
-                while let Some(quality) = quality_queue.pop() {
-                    statistics.qualities.push(quality);
-                }
-            }
+        println!(
+            "{} short scene(s) reused the quality of a nearby scene:",
+            self.short_scenes.len()
+        );
+        println!();
 
-            for thread in threads {
-                let result = thread.join();
+        for (scene_index, source_index) in &self.short_scenes {
+            println!(
+                "Scene {scene_index:05}: {} from scene {source_index:05}",
+                self.scene_qualities
+                    .get(scene_index)
+                    .map_or_else(|| "N/A".to_owned(), ToString::to_string)
+            );
+        }
 
-                match result {
-                    Ok(result) => {
-                        result.context("Unable to encode scene")?;
-                    }
-                    Err(error) => {
-                        return Err(anyhow!("Encoding worker panicked: {:?}", error));
-                    }
-                }
-            }
+        println!();
+    }
 
-            progress_bar.finish();
+    #[must_use]
+    pub const fn preset_escalations(&self) -> &BTreeMap<usize, PresetEscalation> {
+        &self.preset_escalations
+    }
 
-            if !result_queue.is_empty() {
-                return Err(anyhow!(
-                    "BUG: Result queue was not empty after joining encoding threads"
-                ));
-            }
+    pub fn print_preset_escalations(&self) {
+        if self.preset_escalations.is_empty() {
+            return;
+        }
 
-            Ok(())
-        })
-        .context("Unable to execute encoding workers")?;
+        println!(
+            "{} scene(s) were re-encoded with a slower preset:",
+            self.preset_escalations.len()
+        );
+        println!();
 
-        clips.sort_by(|x, y| x.path().cmp(y.path()));
+        for (scene_index, escalation) in &self.preset_escalations {
+            println!(
+                "Scene {scene_index:05}: preset {} => {:0.3} from {:0.3}",
+                escalation.preset,
+                escalation.escalated_metric_value.unwrap_or(f64::NAN),
+                escalation.metric_value
+            );
+        }
 
-        let output_path = self
-            .merge_scenes(&clips)
-            .context("Unable to merge scenes")?;
+        println!();
+    }
 
-        Ok((output_path, clips, statistics))
+    #[must_use]
+    pub const fn global_allocation(&self) -> Option<&Allocation> {
+        self.global_allocation.as_ref()
     }
 
-    fn merge_scenes(&self, files: &[ClipMetrics]) -> anyhow::Result<PathBuf> {
-        let output_path = self.config.output_directory.join("output");
+    pub fn print_global_allocation(&self) {
+        let Some(allocation) = &self.global_allocation else {
+            return;
+        };
 
-        verify_directory(&output_path).with_context(|| {
-            format!("Unable to verify merging output directory {output_path:?}")
-        })?;
+        let (objective, constraint) = match self.config.global_objective {
+            Some(GlobalObjective::Quality) => (
+                "maximize quality",
+                format!(
+                    "average bitrate at most {} kbps",
+                    self.config.max_bitrate.unwrap_or(f64::NAN)
+                ),
+            ),
+            _ => (
+                "minimize bitrate",
+                format!("mean metric at least {}", self.config.quality),
+            ),
+        };
 
-        let temporary_output_path =
-            output_path.join(format!("{}.tmp.mkv", self.config.encode_identifier(true)));
+        println!("Global optimization ({objective} with {constraint}):");
+        println!();
+        println!(
+            "Predicted mean metric {:0.3} at {:0.1} kbps over {} scene(s){}",
+            allocation.metric,
+            allocation.bitrate,
+            allocation.qualities.len(),
+            if allocation.satisfied {
+                ""
+            } else {
+                " (constraint could not be met)"
+            }
+        );
+        println!();
+    }
 
-        let output_path = output_path.join(format!("{}.mkv", self.config.encode_identifier(true)));
+    pub fn print_target_misses(&self) {
+        if self.target_misses.is_empty() {
+            return;
+        }
 
-        let progress_bar = ProgressBar::new_spinner();
-        progress_bar.enable_steady_tick(Duration::from_millis(120));
-        progress_bar.set_style(
-            create_progress_style("{spinner:.green} [{elapsed_precise}] {msg}")
-                .context("Unable to create scene merging progress bar style")?,
+        println!(
+            "{} scene(s) missed the quality target:",
+            self.target_misses.len()
         );
-        progress_bar.set_message("Merging scenes...");
+        println!();
 
-        if !output_path.exists() {
-            let file_args = files
-                .iter()
-                .enumerate()
-                .map(|(index, metrics)| {
-                    if index > 0 {
-                        format!("+{}", metrics.path().to_string_lossy())
-                    } else {
-                        metrics.path().to_string_lossy().to_string()
-                    }
-                })
-                .collect::<Vec<_>>();
+        for (scene_index, miss) in &self.target_misses {
+            // With a bitrate ceiling, a scene is listed either because the ceiling kept it below
+            // the target or because even the lowest quality exceeded the ceiling.
+            let limit = match (miss.bitrate, miss.range_boundary) {
+                (Some(bitrate), _) => {
+                    format!(" (conflicts with the bitrate ceiling at {bitrate:0.0} kbps)")
+                }
+                (None, true) => " (limited by the quality range)".to_owned(),
+                (None, false) => String::new(),
+            };
 
-            let merge_pipe = Command::new("mkvmerge")
-                .arg("-o")
-                .arg(&temporary_output_path)
-                .args(file_args)
-                .stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .context("Unable to spawn mkvmerge")?;
+            println!(
+                "Scene {scene_index:05}: {:0.3} of {} (gap {:+0.3}) at {} {}{limit}",
+                miss.metric_value,
+                miss.target,
+                miss.gap,
+                self.config.mode_description(),
+                miss.quality,
+            );
+        }
 
-            let result = merge_pipe
-                .wait_with_output()
-                .context("Unable to wait for mkvmerge to finish")?;
+        println!();
+    }
 
-            if !result.status.success() {
-                progress_bar.set_message("Merging scenes...failed!");
-                progress_bar.finish();
+    // Direct encodes all share the configured quality, so a reported metric says far more about
+    // how the scenes turned out.
+    fn quality_description(&self) -> String {
+        match self.config.report_metric {
+            Some(metric) if self.config.metric == Metric::Direct => metric.to_string(),
+            _ => self.config.mode_description(),
+        }
+    }
+
+    // The histogram counts every scene once, and the statistics show each scene counting once,
+    // weighted by its length, or both, as configured.
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn print_quality_stats(&self) -> anyhow::Result<()> {
+        let description = self.quality_description();
+        let qualities = self.qualities.values().copied().collect::<Vec<_>>();
+        let weighting = self.config.scene_weighting;
+
+        println!("{description} Statistics");
+        println!();
+        print_histogram(&qualities)
+            .with_context(|| format!("Unable to output {description} histogram"))?;
+        println!();
+
+        let mut stats = vec![(
+            "Scene Length".to_owned(),
+            self.scene_lengths
+                .values()
+                .map(|length| *length as f64)
+                .collect(),
+        )];
+
+        if weighting.per_scene() {
+            stats.push((format!("{description} (per scene)"), qualities));
+        }
+
+        if weighting.by_length() {
+            stats.push((
+                format!("{description} (length-weighted)"),
+                weight_by_length(self.qualities.iter().map(|(scene_index, quality)| {
+                    (
+                        *quality,
+                        self.scene_lengths
+                            .get(scene_index)
+                            .copied()
+                            .unwrap_or_default(),
+                    )
+                })),
+            ));
+        }
+
+        print_stats(&mut stats)
+            .with_context(|| format!("Unable to output {description} statistics"))?;
+
+        Ok(())
+    }
+}
+
+pub struct Encoder {
+    config: Config,
+    scenes: Vec<Scene>,
+    metadata: Metadata,
+    encode_directory: PathBuf,
+    shared_metrics_directory: Option<PathBuf>,
+    active_workers: AtomicUsize,
+    worker_pool: Arc<WorkerPool>,
+    previous_qualities: Option<BTreeMap<usize, f64>>,
+    previous_quality_delta: Mutex<(f64, usize)>,
+    target_overrides: BTreeMap<usize, f64>,
+    analysis_directory: PathBuf,
+    first_pass_directory: PathBuf,
+    animation_decisions: Mutex<BTreeMap<usize, AnimationDecision>>,
+    grain_decisions: Mutex<BTreeMap<usize, GrainDecision>>,
+    banding_interventions: Mutex<BTreeMap<usize, BandingIntervention>>,
+    preset_escalations: Mutex<BTreeMap<usize, PresetEscalation>>,
+    scene_reports: Mutex<BTreeMap<usize, EncoderReport>>,
+    global_allocation: Mutex<Option<Allocation>>,
+    searched_qualities: Mutex<BTreeMap<usize, f64>>,
+    pending_searches: Mutex<usize>,
+    searches_finished: Condvar,
+    short_scenes: Mutex<BTreeMap<usize, usize>>,
+    aligned_clips: Mutex<BTreeSet<PathBuf>>,
+    reference_frame_rate: Option<f64>,
+    schedule: SceneSchedule,
+    fps_history_path: PathBuf,
+    fps_history: Mutex<FpsHistory>,
+    pass_timings: Mutex<BTreeMap<EncodeKind, BTreeMap<usize, PassTiming>>>,
+    metric_context: MetricContext,
+}
+
+impl Encoder {
+    #[expect(clippy::too_many_lines)]
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let mut scenes = get(config).context("Unable to fetch scene data")?;
+
+        // Slow scenes are started first so that none is left running alone at the end. Encoding
+        // speed from earlier runs predicts this far better than length for grainy scenes.
+        let fps_history_path = config.output_directory.join("fps-history.json");
+        let fps_history =
+            FpsHistory::load(&fps_history_path).context("Unable to load FPS history")?;
+        let history_key = format!("{}-{}", config.encoder, config.preset);
+
+        let costs = fps_history.costs(&history_key, &scenes);
+
+        scenes.sort_by(|a, b| costs[&b.index()].total_cmp(&costs[&a.index()]));
+
+        let metadata = get_metadata(config)
+            .with_context(|| format!("Unable to fetch video metadata for {:?}", &config.source))?;
+
+        let metric_context =
+            MetricContext::new(config, &metadata).context("Unable to set up metric context")?;
+
+        // Encodes are grouped by the split scenes they were made from, so that a stale split only
+        // takes its own encodes with it.
+        let encode_directory = config
+            .output_directory
+            .join("encode")
+            .join(split_key(config, &metadata).context("Unable to calculate split key")?)
+            .join(config.encode_identifier(false));
+
+        if config.single_encode
+            && !matches!(
+                config.encoder,
+                config::Encoder::X264 | config::Encoder::X265
+            )
+        {
+            return Err(anyhow!(
+                "Whole video encoding requires x264 or x265, which support zones"
+            ));
+        }
+
+        if config.encoder == config::Encoder::Rav1e && config.mode == Mode::CRF {
+            return Err(anyhow!(
+                "rav1e does not currently support CRF mode. Use QP mode instead."
+            ));
+        }
 
+        // x264 takes a single psychovisual tune, and a second --tune would silently replace the
+        // first.
+        if config.encoder == config::Encoder::X264
+            && config.screen_content
+            && config.animation_detection != AnimationDetection::Off
+        {
+            return Err(anyhow!(
+                "x264 cannot combine the screen content and animation tunes. Disable animation detection or screen content tuning."
+            ));
+        }
+
+        if config.encoder != config::Encoder::SvtAv1 && !config.svt_arguments().is_empty() {
+            return Err(anyhow!("SVT-AV1 options require the svt-av1 encoder"));
+        }
+
+        if config.max_bitrate.is_some()
+            && (config.rule != QualityRule::Minimum
+                || matches!(config.metric, Metric::Direct | Metric::Bitrate))
+        {
+            return Err(anyhow!(
+                "A bitrate ceiling requires the minimum quality rule and a quality metric other than bitrate"
+            ));
+        }
+
+        levels::validate(config, &metadata).context("Unable to satisfy profile and level")?;
+
+        if !config.pre_split {
+            warn_crop_alignment(config, &metadata).context("Unable to check crop alignment")?;
+        }
+
+        let interval = key_frame_interval(config, &metadata);
+
+        if !(1..=MAXIMUM_KEY_FRAME_INTERVAL).contains(&interval) {
+            return Err(anyhow!(
+                "The source reports {} frames over {:0.3} seconds, which gives a key frame interval of {interval} frames, outside the supported range of 1 to {MAXIMUM_KEY_FRAME_INTERVAL}",
+                metadata.frame_count,
+                metadata.duration
+            ));
+        }
+
+        if let Some((columns, rows)) = config.tiles {
+            let (minimum_width, minimum_height) = config
+                .encoder
+                .minimum_tile_size()
+                .ok_or_else(|| anyhow!("The {} encoder does not support tiles", config.encoder))?;
+
+            if let Some((width, height)) = metadata.dimensions() {
+                if width / columns < minimum_width || height / rows < minimum_height {
+                    return Err(anyhow!(
+                        "{columns}x{rows} tiles are too many for a {width}x{height} frame, as {} requires tiles of at least {minimum_width}x{minimum_height} pixels",
+                        config.encoder
+                    ));
+                }
+            }
+        }
+
+        let shared_metrics_directory = shared_directory(config)
+            .context("Unable to determine shared cache directory")?
+            .map(|directory| -> anyhow::Result<PathBuf> {
+                Ok(directory
+                    .join("metrics")
+                    .join(split_key(config, &metadata).context("Unable to calculate split key")?)
+                    .join(config.encode_identifier(false)))
+            })
+            .transpose()?;
+
+        if let Some(lookahead) = config.lookahead {
+            let maximum = config.encoder.maximum_lookahead().ok_or_else(|| {
+                anyhow!("The {} encoder has no lookahead setting", config.encoder)
+            })?;
+
+            if lookahead > maximum {
                 return Err(anyhow!(
-                    "mkvmerge returned error code {} and the following output:\n{}\n{}",
-                    result.status,
-                    str::from_utf8(&result.stdout)
-                        .context("Unable to parse mkvmerge output as UTF-8")?,
-                    str::from_utf8(&result.stderr)
-                        .context("Unable to parse mkvmerge output as UTF-8")?
+                    "A lookahead of {lookahead} frames is longer than the {maximum} frames {} accepts",
+                    config.encoder
                 ));
             }
         }
 
-        if temporary_output_path.exists() {
-            fs::rename(&temporary_output_path, &output_path).with_context(|| {
-                format!("Unable to rename {temporary_output_path:?} to {output_path:?}")
+        if config.report_metric.is_some() && config.metric != Metric::Direct {
+            return Err(anyhow!(
+                "A report metric only applies to direct encodes, which otherwise report the metric they target"
+            ));
+        }
+
+        if let Some(container) = config.delivery {
+            package::verify(config, container).context("Unable to package delivery output")?;
+        }
+
+        config
+            .output_file_path(config.container.map_or("mkv", Container::extension))
+            .context("Unable to determine output file path")?;
+
+        if let Some(container) = config.container {
+            package::verify(config, container).context("Unable to package output")?;
+
+            if config.subtitles.is_some() {
+                return Err(anyhow!("Subtitles can only be kept in MKV output"));
+            }
+        }
+
+        if config.report_metric == Some(Metric::Direct) {
+            return Err(anyhow!("The direct metric cannot be reported"));
+        }
+
+        if config.single_encode && config.animation_detection == AnimationDetection::Scene {
+            return Err(anyhow!(
+                "Per-scene animation tuning cannot be applied to whole video encoding, so use source detection instead"
+            ));
+        }
+
+        if config.single_encode && config.grain_threshold.is_some() {
+            return Err(anyhow!(
+                "Grain handling changes encoder settings per scene, which whole video encoding cannot do"
+            ));
+        }
+
+        if let Some(objective) = config.global_objective {
+            if matches!(config.metric, Metric::Direct | Metric::Bitrate)
+                || config.encoder.is_mezzanine()
+            {
+                return Err(anyhow!(
+                    "Global optimization needs a quality metric and an encoder with a quality search"
+                ));
+            }
+
+            if config.rule != QualityRule::Minimum || config.single_encode {
+                return Err(anyhow!(
+                    "Global optimization chooses scene qualities itself, so it requires the minimum quality rule and scene encoding"
+                ));
+            }
+
+            if objective == GlobalObjective::Quality && config.max_bitrate.is_none() {
+                return Err(anyhow!(
+                    "Optimizing for quality needs an average bitrate budget from --max-bitrate"
+                ));
+            }
+        }
+
+        let analysis_directory = config
+            .output_directory
+            .join("analysis")
+            .join(split_key(config, &metadata).context("Unable to calculate split key")?);
+
+        // Kept first passes only depend on the scenes and the encoder settings, not on the quality
+        // target or metric that decide the encode identifier.
+        let first_pass_directory = if config.keep_stats {
+            config
+                .output_directory
+                .join("first-pass")
+                .join(split_key(config, &metadata).context("Unable to calculate split key")?)
+                .join(format!(
+                    "{}-{}-{}",
+                    config.encoder,
+                    config.preset,
+                    config.encode_arguments_hash()
+                ))
+        } else {
+            encode_directory.clone()
+        };
+
+        let target_overrides = config
+            .target_overrides
+            .as_ref()
+            .map(|path| {
+                load_target_overrides(path, &scenes)
+                    .with_context(|| format!("Unable to load target overrides from {path:?}"))
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        let previous_qualities = previous_scene_qualities(config)
+            .context("Unable to load qualities from a previous run")?;
+
+        let reference_frame_rate = config
+            .reference
+            .as_deref()
+            .map(|reference| {
+                frame_rate(reference).with_context(|| {
+                    format!("Unable to determine frame rate of reference {reference:?}")
+                })
+            })
+            .transpose()?
+            .map(|(numerator, denominator)| f64::from(numerator) / f64::from(denominator));
+
+        // The reference is cropped just like the source, which only lines up if both have the same
+        // geometry.
+        if let Some(reference) = &config.reference {
+            let reference_dimensions = dimensions(reference).with_context(|| {
+                format!("Unable to determine dimensions of reference {reference:?}")
+            })?;
+            let source_dimensions = dimensions(&config.source).with_context(|| {
+                format!(
+                    "Unable to determine dimensions of source {:?}",
+                    &config.source
+                )
             })?;
+
+            if reference_dimensions != source_dimensions {
+                return Err(anyhow!(
+                    "Reference {reference:?} is {}x{}, but the source is {}x{}",
+                    reference_dimensions.0,
+                    reference_dimensions.1,
+                    source_dimensions.0,
+                    source_dimensions.1
+                ));
+            }
         }
 
-        progress_bar.set_message("Merging scenes...done!");
-        progress_bar.finish();
+        let schedule = SceneSchedule::load(
+            &encode_directory.join("schedule.json"),
+            &scenes,
+            config
+                .prioritize
+                .as_ref()
+                .map_or(&[], |prioritize| prioritize.0.as_slice()),
+        )
+        .context("Unable to load scene schedule")?;
 
-        Ok(output_path)
+        Ok(Self {
+            config: config.clone(),
+            scenes,
+            metadata,
+            encode_directory,
+            shared_metrics_directory,
+            active_workers: config.workers.into(),
+            worker_pool: Arc::new(WorkerPool::new(config.workers)),
+            previous_qualities,
+            previous_quality_delta: Mutex::new((0.0, 0)),
+            target_overrides,
+            analysis_directory,
+            first_pass_directory,
+            animation_decisions: Mutex::new(BTreeMap::new()),
+            grain_decisions: Mutex::new(BTreeMap::new()),
+            banding_interventions: Mutex::new(BTreeMap::new()),
+            preset_escalations: Mutex::new(BTreeMap::new()),
+            scene_reports: Mutex::new(BTreeMap::new()),
+            global_allocation: Mutex::new(None),
+            searched_qualities: Mutex::new(BTreeMap::new()),
+            pending_searches: Mutex::new(0),
+            searches_finished: Condvar::new(),
+            short_scenes: Mutex::new(BTreeMap::new()),
+            aligned_clips: Mutex::new(BTreeSet::new()),
+            reference_frame_rate,
+            schedule,
+            fps_history_path,
+            fps_history: Mutex::new(fps_history),
+            pass_timings: Mutex::new(BTreeMap::new()),
+            metric_context,
+        })
+    }
+
+    fn history_key(&self) -> String {
+        format!("{}-{}", self.config.encoder, self.config.preset)
+    }
+
+    fn record_pass_timing(&self, kind: EncodeKind, pass: usize, frames: usize, elapsed: Duration) {
+        let mut pass_timings = self
+            .pass_timings
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        let timing = pass_timings
+            .entry(kind)
+            .or_default()
+            .entry(pass)
+            .or_default();
+
+        timing.encodes += 1;
+        timing.frames += frames;
+        timing.seconds += elapsed.as_secs_f64();
+
+        drop(pass_timings);
+    }
+
+    fn record_fps(&self, scene: &Scene, elapsed: Duration) -> anyhow::Result<()> {
+        #[expect(clippy::as_conversions)]
+        #[expect(clippy::cast_precision_loss)]
+        let fps = scene.length() as f64 / elapsed.as_secs_f64();
+
+        let mut fps_history = self
+            .fps_history
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        fps_history.record(&self.history_key(), scene.index(), fps);
+
+        let result = fps_history.write(&self.fps_history_path);
+        drop(fps_history);
+
+        result
     }
 
+    fn shared_metrics_path(&self, path: &Path) -> Option<PathBuf> {
+        self.shared_metrics_directory
+            .as_ref()
+            .and_then(|directory| {
+                path.strip_prefix(&self.encode_directory)
+                    .ok()
+                    .map(|relative| directory.join(relative).with_extension("metrics.json"))
+            })
+    }
+
+    // The previous run's quality is shifted by the mean difference observed so far in this run,
+    // which absorbs the systematic offset from a preset or encoder version change.
     #[expect(clippy::as_conversions)]
     #[expect(clippy::cast_precision_loss)]
-    #[expect(clippy::too_many_lines)]
-    fn encode_scene(
+    fn quality_hint(&self, scene: &Scene) -> Option<f64> {
+        let previous = self.previous_qualities.as_ref()?.get(&scene.index())?;
+
+        let (sum, count) = *self
+            .previous_quality_delta
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        Some(if count == 0 {
+            *previous
+        } else {
+            previous + sum / count as f64
+        })
+    }
+
+    fn record_quality(&self, scene: &Scene, quality: f64) {
+        if let Some(previous) = self
+            .previous_qualities
+            .as_ref()
+            .and_then(|qualities| qualities.get(&scene.index()))
+        {
+            let mut delta = self
+                .previous_quality_delta
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+            delta.0 += quality - previous;
+            delta.1 += 1;
+        }
+    }
+
+    // Once workers run out of scenes, their share of the machine is handed to the scenes that start
+    // afterwards. Tiles are left alone, since they would change the output, and so is the thread
+    // count of encoders whose output depends on it.
+    #[expect(clippy::integer_division)]
+    #[expect(clippy::integer_division_remainder_used)]
+    fn encoder_threads(&self) -> usize {
+        let threads = self.config.threads_per_worker.max(1);
+
+        if self.config.fixed_threads || self.config.encoder.thread_dependent() {
+            return threads;
+        }
+
+        let active_workers = self.active_workers.load(Ordering::Relaxed).max(1);
+
+        (self.config.workers * threads / active_workers).max(threads)
+    }
+
+    // Anti-banding settings change the first pass as well, so those scenes keep a separate one.
+    fn first_pass_stats_path(&self, scene: &Scene) -> PathBuf {
+        let name = if self.scene_debanded(scene) {
+            "first-pass-deband.stats.log"
+        } else {
+            "first-pass.stats.log"
+        };
+
+        self.first_pass_directory
+            .join(format!("scene-{:05}", scene.index()))
+            .join(name)
+    }
+
+    // In streaming mode, scenes are decoded straight from the source rather than from the split
+    // lossless intermediates, so the crop has to be applied on the fly.
+    fn scene_input(&self, scene: &Scene) -> (PathBuf, Option<&str>, Option<SourceRange>) {
+        if self.config.streaming {
+            (
+                self.config.source.clone(),
+                self.metadata.crop_filter.as_deref(),
+                Some(scene.source_range(&self.metadata)),
+            )
+        } else {
+            (
+                self.config
+                    .output_directory
+                    .join("source")
+                    .join(format!("scene-{:05}.mkv", scene.index())),
+                None,
+                None,
+            )
+        }
+    }
+
+    // Metrics are measured against the external reference when one is given, cropped like the
+    // source, and otherwise against the same input the encoder was given.
+    fn scene_reference(
         &self,
         scene: &Scene,
-        progress_bar: &ProgressBar,
-    ) -> anyhow::Result<(PathBuf, f64)> {
-        let quality = if self.config.metric == Metric::Direct {
-            self.config.quality
+    ) -> anyhow::Result<(PathBuf, Option<&str>, Option<SourceRange>)> {
+        let Some(reference) = &self.config.reference else {
+            return Ok(self.scene_input(scene));
+        };
+
+        Ok((
+            reference.clone(),
+            self.metadata.crop_filter.as_deref(),
+            Some(self.reference_range(scene.start_frame(), scene.length())?),
+        ))
+    }
+
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    fn reference_range(&self, start_frame: usize, frames: usize) -> anyhow::Result<SourceRange> {
+        let frame_rate = self
+            .reference_frame_rate
+            .ok_or_else(|| anyhow!("Reference frame rate is unknown"))?;
+
+        let start_frame = i64::try_from(start_frame)
+            .ok()
+            .and_then(|start_frame| start_frame.checked_add(self.config.reference_offset))
+            .and_then(|start_frame| usize::try_from(start_frame).ok())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Reference offset {} moves frame {start_frame} before the start of the reference",
+                    self.config.reference_offset
+                )
+            })?;
+
+        // As with the source, seeking half a frame early keeps the seek frame-accurate.
+        let start_time = if start_frame == 0 {
+            0.0
         } else {
-            let mut quality_range = self.config.encoder.quality_range(&self.config.mode);
+            (start_frame as f64 - 0.5) / frame_rate
+        };
+
+        Ok(SourceRange { start_time, frames })
+    }
+
+    #[expect(clippy::too_many_lines)]
+    pub fn encode(&self) -> anyhow::Result<(PathBuf, Vec<ClipMetrics>, EncodeStatistics)> {
+        let mut statistics = EncodeStatistics::new(&self.config);
+
+        // Audio only depends on the source, so it transcodes alongside the whole video encode.
+        let audio_transcode = audio::Transcode::spawn(&self.config);
+
+        self.analyze_animation()
+            .context("Unable to detect animated content")?;
+
+        self.optimize_globally()
+            .context("Unable to optimize scene qualities globally")?;
+
+        let scene_queue: ArrayQueue<Scene> = ArrayQueue::new(self.scenes.len());
+        let result_queue: ArrayQueue<ClipMetrics> = ArrayQueue::new(self.scenes.len());
+        let quality_queue: ArrayQueue<SceneResult> = ArrayQueue::new(self.scenes.len());
+
+        for scene in &self.scenes {
+            statistics
+                .scene_lengths
+                .insert(scene.index(), scene.length());
+        }
+
+        // Scenes are handed out in the persisted schedule order so that a resumed encode picks up
+        // the same work first, including any scenes prioritized on the command line. Short scenes
+        // go last and wait for every regular scene to be searched before they are encoded.
+        let scheduled = self
+            .schedule
+            .order()
+            .into_iter()
+            .filter_map(|scene_index| {
+                self.scenes
+                    .iter()
+                    .find(|scene| scene.index() == scene_index)
+            })
+            .collect::<Vec<_>>();
+
+        let (short, regular): (Vec<&Scene>, Vec<&Scene>) =
+            scheduled.into_iter().partition(|scene| self.short(scene));
+
+        *self
+            .pending_searches
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = regular.len();
+
+        for scene in regular.into_iter().chain(short) {
+            if scene_queue.push(*scene).is_err() {
+                return Err(anyhow!("Encoding worker queue was unexpectedly full"));
+            }
+        }
+
+        let multi_progress = MultiProgress::new();
+
+        let worker_progress_style = ProgressStyle::with_template("{msg}")
+            .context("Unable to create worker progress style")?;
+
+        let worker_progress_bars = (0..self.config.workers)
+            .map(|_thread_index| {
+                multi_progress.add(ProgressBar::new(1).with_style(worker_progress_style.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        let progress_bar =
+            ProgressBar::new(self.metadata.frame_count.try_into().unwrap_or(u64::MAX));
+
+        progress_bar.set_style(
+            create_progress_style(
+                "{spinner:.green} [{elapsed_precise}] Encoding scenes...         [{wide_bar:.cyan/blue}] {percent:>3}% {human_pos:>8}/{human_len:>8} ({smooth_per_sec:>6} FPS, {msg:>12}, ETA: {smooth_eta:>3})"
+            ).context("Unable to create encoding progress bar style")?
+        );
+
+        // The remaining time is extrapolated from the expected cost of the finished scenes rather
+        // than their frame count, so a few slow scenes do not make the estimate swing.
+        let (scene_costs, total_cost) = {
+            let fps_history = self
+                .fps_history
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+            let scene_costs = fps_history.costs(&self.history_key(), &self.scenes);
+
+            drop(fps_history);
+
+            let total_cost: f64 = scene_costs.values().sum();
+            (scene_costs, total_cost)
+        };
+
+        let completed_cost = Arc::new(Mutex::new(0.0_f64));
+        let eta_completed_cost = Arc::clone(&completed_cost);
+
+        // Time spent paused says nothing about encoding speed, so it is left out of the estimate.
+        let paused_before = paused_time();
+
+        progress_bar.set_style(progress_bar.style().with_key(
+            "smooth_eta",
+            move |state: &ProgressState, writer: &mut dyn std::fmt::Write| {
+                let completed = *eta_completed_cost
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+                let eta = if completed > 0.0 {
+                    let encoding = state
+                        .elapsed()
+                        .saturating_sub(paused_time().saturating_sub(paused_before));
+                    let remaining = encoding.as_secs_f64() * (total_cost - completed) / completed;
+
+                    format!(
+                        "{:#}",
+                        HumanDuration(Duration::from_secs_f64(remaining.max(0.0)))
+                    )
+                } else {
+                    "-".to_owned()
+                };
+
+                writer.write_str(&eta).unwrap_or_else(|err| {
+                    error!("Unexpected error while formatting smooth_eta in progress bar: {err}");
+                });
+            },
+        ));
+
+        let progress_bar = multi_progress.add(progress_bar);
+        progress_bar.reset();
+        progress_bar.enable_steady_tick(Duration::from_secs(1));
+
+        let mut clips: Vec<ClipMetrics> = vec![];
+
+        thread::scope(|scope| -> anyhow::Result<()> {
+            let threads = (0..self.config.workers)
+                .map(|thread_index| -> anyhow::Result<_> {
+                    let worker_progress_bar = worker_progress_bars
+                        .get(thread_index)
+                        .ok_or_else(|| anyhow!("Unable to access encoding worker progress bar"))?;
+
+                    clear_worker_message(worker_progress_bar);
+
+                    Ok(scope.spawn(|| -> anyhow::Result<()> {
+                        loop {
+                            wait_while_paused();
+
+                            let Some(scene) = &scene_queue.pop() else {
+                                break;
+                            };
+
+                            let encoded = self.encode_scene(scene, worker_progress_bar);
+
+                            if !self.short(scene) {
+                                self.finish_search();
+                            }
+
+                            if encoded.is_err() {
+                                self.schedule
+                                    .set(scene.index(), SceneStatus::Failed)
+                                    .context("Unable to update scene schedule")?;
+                            }
+
+                            let (result, quality, target_miss) = encoded.with_context(|| {
+                                format!("Unable to encode scene {}", scene.index())
+                            })?;
+
+                            let reported_value = self
+                                .measure_report_metric(scene, &result, worker_progress_bar)
+                                .with_context(|| {
+                                    format!(
+                                        "Unable to measure report metric for scene {}",
+                                        scene.index()
+                                    )
+                                })?;
+
+                            let (reference_filename, reference_filter, reference_range) =
+                                self.scene_reference(scene)?;
+
+                            let mut metrics = ClipMetrics::new(
+                                &result,
+                                &reference_filename,
+                                reference_filter,
+                                reference_range,
+                                self.shared_metrics_path(&result),
+                                &self.metric_context,
+                            )
+                            .with_context(|| {
+                                    format!(
+                                        "Unable to calculate metrics for scene {}",
+                                        scene.index()
+                                    )
+                                })?;
+
+                            let frames = metrics.frames().with_context(|| {
+                                format!("Unable to read frame count for scene {}", scene.index())
+                            })?;
+
+                            if frames != scene.length() {
+                                if self.config.strict {
+                                    return Err(anyhow!(
+                                        "Scene {} should contain {} frames but the encoded clip \
+                                        contains {frames}. The scene was decoded short or the \
+                                        encoder dropped frames, so every later frame in the \
+                                        output would be shifted relative to the source.",
+                                        scene.index(),
+                                        scene.length()
+                                    ));
+                                }
+
+                                warn!(
+                                    "Scene {} should contain {} frames but the encoded clip contains {frames}.",
+                                    scene.index(),
+                                    scene.length()
+                                );
+                            }
+
+                            // A chunk whose duration disagrees with its frame count by more
+                            // than a frame would skew every bitrate figure.
+                            let duration = metrics.duration().with_context(|| {
+                                format!("Unable to read duration for scene {}", scene.index())
+                            })?;
+
+                            let expected_duration = scene.duration(&self.metadata);
+                            #[expect(clippy::as_conversions)]
+                            #[expect(clippy::cast_precision_loss)]
+                            let frame_duration = expected_duration / scene.length() as f64;
+
+                            if (duration - expected_duration).abs() > frame_duration {
+                                warn!(
+                                    "Scene {} should last {expected_duration:.3} seconds but the encoded clip lasts {duration:.3} seconds.",
+                                    scene.index()
+                                );
+                            }
+
+                            if result_queue.push(metrics).is_err() {
+                                return Err(anyhow!("Encoding result queue was unexpectedly full"));
+                            }
+
+                            if quality_queue
+                                .push((
+                                    scene.index(),
+                                    quality,
+                                    reported_value,
+                                    target_miss,
+                                    result.clone(),
+                                ))
+                                .is_err() {
+                                return Err(anyhow!(
+                                    "Encoding quality result queue was unexpectedly full"
+                                ));
+                            }
+
+                            self.schedule
+                                .set(scene.index(), SceneStatus::Done)
+                                .context("Unable to update scene schedule")?;
+
+                            clear_worker_message(worker_progress_bar);
+                        }
+
+                        worker_progress_bar.finish();
+                        self.active_workers.fetch_sub(1, Ordering::Relaxed);
+
+                        Ok(())
+                    }))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .context("Unable to spawn encoding workers")?;
+
+            let mut current_bytes = 0;
+            let mut current_duration = 0.0_f64;
+
+            while threads
+                .iter()
+                .any(|thread| -> bool { !thread.is_finished() })
+                || !result_queue.is_empty()
+            {
+                while let Some(mut clip) = result_queue.pop() {
+                    current_bytes += clip
+                        .sizes()
+                        .context("Unable to read clip size")?
+                        .iter()
+                        .sum::<usize>();
+
+                    current_duration += clip.duration().context("Unable to read clip duration")?;
+
+                    #[expect(clippy::as_conversions)]
+                    #[expect(clippy::cast_precision_loss)]
+                    let bitrate = format!(
+                        "{}",
+                        HumanBitrate(current_bytes as f64 * 8.0 / current_duration)
+                    );
+
+                    set_bitrate(bitrate.clone());
+                    progress_bar.set_message(bitrate);
+
+                    progress_bar.inc(
+                        (clip.frames().context("Unable to read clip frame count")?)
+                            .try_into()
+                            .unwrap_or(u64::MAX),
+                    );
+
+                    notify_progress("encode", &progress_bar);
+
+                    clips.push(clip);
+                }
+
+                while let Some((scene_index, quality, reported_value, target_miss, clip_path)) =
+                    quality_queue.pop()
+                {
+                    *completed_cost
+                        .lock()
+                        .unwrap_or_else(std::sync::PoisonError::into_inner) +=
+                        scene_costs.get(&scene_index).copied().unwrap_or_default();
+
+                    // Scene qualities still record the quality used, which whole video encoding
+                    // and later runs depend on.
+                    statistics
+                        .qualities
+                        .insert(scene_index, reported_value.unwrap_or(quality));
+                    statistics.scene_qualities.insert(scene_index, quality);
+                    statistics.scene_clips.insert(scene_index, clip_path);
+
+                    if let Some(target_miss) = target_miss {
+                        statistics.target_misses.insert(scene_index, target_miss);
+                    }
+                }
+            }
+
+            for thread in threads {
+                let result = thread.join();
+
+                match result {
+                    Ok(result) => {
+                        result.context("Unable to encode scene")?;
+                    }
+                    Err(error) => {
+                        return Err(anyhow!("Encoding worker panicked: {:?}", error));
+                    }
+                }
+            }
+
+            progress_bar.finish();
+
+            if !result_queue.is_empty() {
+                return Err(anyhow!(
+                    "BUG: Result queue was not empty after joining encoding threads"
+                ));
+            }
+
+            Ok(())
+        })
+        .context("Unable to execute encoding workers")?;
+
+        clips.sort_by(|x, y| x.path().cmp(y.path()));
+
+        statistics.animation_decisions = self
+            .animation_decisions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+
+        statistics.banding_interventions = self
+            .banding_interventions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+
+        statistics.preset_escalations = self
+            .preset_escalations
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+
+        statistics.grain_decisions = self
+            .grain_decisions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+
+        statistics.encoder_reports = self
+            .scene_reports
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+
+        statistics.short_scenes = self
+            .short_scenes
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+
+        statistics.global_allocation.clone_from(
+            &self
+                .global_allocation
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        );
+
+        if self.config.single_encode {
+            let single_path = self
+                .encode_single(&statistics)
+                .context("Unable to encode whole video")?;
+
+            statistics.pass_timings = self
+                .pass_timings
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .clone();
+
+            let audio_path = audio_transcode.join()?;
+
+            let output_path = self
+                .merge_scenes(&[single_path], audio_path.as_deref())
+                .context("Unable to mux whole video encode")?;
+
+            // The chunked encodes only served to pick qualities, so metrics describe the final
+            // continuous encode instead.
+            let clip = if let Some(reference) = &self.config.reference {
+                ClipMetrics::new(
+                    &output_path,
+                    reference,
+                    self.metadata.crop_filter.as_deref(),
+                    Some(self.reference_range(0, self.metadata.frame_count)?),
+                    None,
+                    &self.metric_context,
+                )
+            } else {
+                ClipMetrics::new(
+                    &output_path,
+                    &self.config.source,
+                    self.metadata.crop_filter.as_deref(),
+                    None,
+                    None,
+                    &self.metric_context,
+                )
+            }
+            .context("Unable to calculate metrics for whole video encode")?;
+
+            return Ok((output_path, vec![clip], statistics));
+        }
+
+        self.validate_chunks(&mut clips, &statistics)
+            .context("Unable to validate scene chunks")?;
+
+        let audio_path = audio_transcode.join()?;
+
+        let output_path = self
+            .merge_scenes(
+                &clips
+                    .iter()
+                    .map(|clip| clip.path().clone())
+                    .collect::<Vec<_>>(),
+                audio_path.as_deref(),
+            )
+            .context("Unable to merge scenes")?;
+
+        statistics.pass_timings = self
+            .pass_timings
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone();
+
+        Ok((output_path, clips, statistics))
+    }
+
+    // Review videos compare each selected scene's final encode against the same reference its
+    // metrics were measured against.
+    pub fn review(&self, statistics: &EncodeStatistics) -> anyhow::Result<()> {
+        let Some(review) = &self.config.review else {
+            return Ok(());
+        };
+
+        let review_directory = self.config.output_directory.join("output").join("review");
+        let identifier = self.config.encode_identifier(true);
+
+        for scene_index in &review.0 {
+            let Some(scene) = self
+                .scenes
+                .iter()
+                .find(|scene| scene.index() == *scene_index)
+            else {
+                warn!("Unable to review scene {scene_index}, which does not exist");
+                continue;
+            };
+
+            let Some(clip_path) = statistics.scene_clips.get(scene_index) else {
+                warn!("Unable to review scene {scene_index}, which was not encoded");
+                continue;
+            };
+
+            let (reference_filename, reference_filter, reference_range) =
+                self.scene_reference(scene)?;
+
+            let output_path = review_directory.join(format!(
+                "{identifier}-scene-{scene_index:05}-{}.mkv",
+                self.config.review_mode
+            ));
+
+            review::render(
+                self.config.review_mode,
+                &reference_filename,
+                reference_filter,
+                reference_range,
+                clip_path,
+                self.metric_context.display_filter().as_deref(),
+                &output_path,
+            )
+            .with_context(|| format!("Unable to render review video for scene {scene_index}"))?;
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn key_frame_interval(&self) -> usize {
+        key_frame_interval(&self.config, &self.metadata)
+    }
+
+    // Every quality handed to the encoder is checked first, since most encoders either reject an
+    // out-of-range value with an unhelpful message or silently clamp it.
+    fn check_quality(&self, qp: f64) -> anyhow::Result<()> {
+        let quality_range = self.config.encoder.quality_range(&self.config.mode);
+        let (minimum, maximum) = (quality_range.minimum(), quality_range.maximum());
+
+        if !qp.is_finite() || qp < minimum - f64::EPSILON || qp > maximum + f64::EPSILON {
+            return Err(anyhow!(
+                "Quality {qp} is outside the range {minimum} to {maximum} accepted by {} in {} mode",
+                self.config.encoder,
+                self.config.mode
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn write_tags(&self, tags_path: &Path) -> anyhow::Result<()> {
+        let mut encoder_settings = self.config.encoder.base_arguments(
+            &self.config.preset,
+            self.key_frame_interval(),
+            self.config.threads_per_worker.max(1),
+            self.config.tiles,
+            self.config.bit_depth.bits(),
+        );
+        encoder_settings.extend(self.config.encoder.tune_arguments(&self.config));
+        encoder_settings.extend(levels::arguments(&self.config));
+
+        let mut tags = vec![
+            (
+                "ENCODE_IDENTIFIER".to_owned(),
+                self.config.encode_identifier(true),
+            ),
+            ("ENCODER_SETTINGS".to_owned(), encoder_settings.join(" ")),
+            (
+                "VIDEO_ENCODING_WRAPPER_VERSION".to_owned(),
+                env!("CARGO_PKG_VERSION").to_owned(),
+            ),
+        ];
+
+        for (command, version) in
+            versions(&self.config).context("Unable to determine tool versions")?
+        {
+            tags.push((format!("{}_VERSION", command.to_uppercase()), version));
+        }
+
+        let simple_tags = tags
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "    <Simple>\n      <Name>{}</Name>\n      <String>{}</String>\n    </Simple>\n",
+                    escape_xml(name),
+                    escape_xml(value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .concat();
+
+        let xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE Tags SYSTEM \"matroskatags.dtd\">\n<Tags>\n  <Tag>\n    <Targets />\n{simple_tags}  </Tag>\n</Tags>\n");
+
+        fs::write(tags_path, xml)
+            .with_context(|| format!("Unable to write output tags to {tags_path:?}"))?;
+
+        Ok(())
+    }
+
+    // Zones cover every frame, so the base quality only matters for bitrate mode, where each zone
+    // is a multiplier of the overall target.
+    fn zone_arguments(
+        &self,
+        zones: &[(usize, usize, f64)],
+        base_quality: f64,
+        zone_file: &Path,
+    ) -> anyhow::Result<Vec<String>> {
+        if self.config.encoder == config::Encoder::X265 && self.config.mode == Mode::CRF {
+            // x265 zones only accept QPs and bitrate multipliers, but a zone file accepts any option.
+            let contents = zones
+                .iter()
+                .map(|(start, _end, quality)| format!("{start} --crf {quality:0.2}\n"))
+                .collect::<Vec<_>>()
+                .concat();
+
+            fs::write(zone_file, contents)
+                .with_context(|| format!("Unable to write zone file {zone_file:?}"))?;
+
+            return Ok(vec![
+                "--zonefile".to_owned(),
+                zone_file.to_string_lossy().to_string(),
+            ]);
+        }
+
+        let zones = zones
+            .iter()
+            .map(|(start, end, quality)| match self.config.mode {
+                Mode::Bitrate => format!("{start},{end},b={:0.3}", quality / base_quality),
+                Mode::CRF => format!("{start},{end},crf={quality:0.2}"),
+                Mode::QP => format!("{start},{end},q={quality:0.0}"),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        Ok(vec!["--zones".to_owned(), zones])
+    }
+
+    // Some delivery specifications require one continuous encode, so the per-scene qualities found
+    // by the chunked search are replayed as zones in a single invocation over the whole source.
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    #[expect(clippy::too_many_lines)]
+    fn encode_single(&self, statistics: &EncodeStatistics) -> anyhow::Result<PathBuf> {
+        let output_directory = self.config.output_directory.join("output");
+        let identifier = self.config.encode_identifier(true);
+        let extension = self.config.encoder.extension();
+
+        let output_path = output_directory.join(format!("{identifier}.single.{extension}"));
+
+        if output_path.exists() {
+            return Ok(output_path);
+        }
+
+        let temporary_output_path =
+            output_directory.join(format!("{identifier}.single.tmp.{extension}"));
+        let stats_path = output_directory.join(format!("{identifier}.single.stats.log"));
+        let qpfile_path = output_directory.join(format!("{identifier}.single.qpfile"));
+        let zone_file_path = output_directory.join(format!("{identifier}.single.zones"));
+
+        let scenes = get(&self.config).context("Unable to fetch scene data")?;
+
+        let zones = scenes
+            .iter()
+            .map(|scene| -> anyhow::Result<(usize, usize, f64)> {
+                let quality = statistics
+                    .scene_qualities()
+                    .get(&scene.index())
+                    .ok_or_else(|| anyhow!("No quality was chosen for scene {}", scene.index()))?;
+
+                Ok((
+                    scene.start_frame(),
+                    scene.start_frame() + scene.length() - 1,
+                    *quality,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let base_quality = zones
+            .iter()
+            .map(|(start, end, quality)| (end - start + 1) as f64 * quality)
+            .sum::<f64>()
+            / self.metadata.frame_count as f64;
+
+        self.check_quality(base_quality)
+            .context("Unable to choose a base quality for the whole video")?;
+
+        // Scene starts are forced to be key frames, matching the chunked output.
+        fs::write(
+            &qpfile_path,
+            format_scenes(SceneFormat::Qpfile, &scenes, &self.metadata)
+                .context("Unable to format scenes as a qpfile")?,
+        )
+        .with_context(|| format!("Unable to write qpfile {qpfile_path:?}"))?;
+
+        let zone_arguments = self
+            .zone_arguments(&zones, base_quality, &zone_file_path)
+            .context("Unable to generate zone arguments")?;
+
+        let progress_bar = ProgressBar::new_spinner();
+        progress_bar.enable_steady_tick(Duration::from_millis(120));
+        progress_bar.set_style(
+            create_progress_style("{spinner:.green} [{elapsed_precise}] {msg}")
+                .context("Unable to create whole video encoding progress bar style")?,
+        );
+
+        let passes = self.config.passes();
+
+        for pass in 1..=passes {
+            let prefix = format!("Encoding whole video (pass {pass} of {passes})... ");
+            progress_bar.set_message(prefix.clone());
+
+            let pass_start = Instant::now();
+
+            let mut decoder_pipe = create_child_read(
+                &self.config.source,
+                self.metadata.crop_filter.as_deref(),
+                self.config.bit_depth.pixel_format(),
+                None,
+                Stdio::null(),
+                Stdio::piped(),
+                Stdio::null(),
+            )
+            .context("Unable to spawn whole video decoder subprocess")?;
+
+            let _decoder_tracker = track(&decoder_pipe);
+
+            let decoder_stdout = decoder_pipe.stdout.take().ok_or_else(|| {
+                anyhow!("Unable to access stdout for whole video decoder subprocess")
+            })?;
+
+            let mut arguments = self.config.encoder.arguments(
+                &self.config,
+                &self.config.preset,
+                self.key_frame_interval(),
+                self.config.workers.max(1),
+                (passes > 1).then_some(pass),
+                &temporary_output_path,
+                Some(&stats_path),
+                self.config.mode,
+                base_quality,
+            );
+
+            // Only source-wide animation detection is allowed here, so any animated scene means
+            // the whole video is.
+            if self.animated() {
+                arguments.extend(self.config.encoder.animation_arguments());
+            }
+
+            arguments.push("--qpfile".to_owned());
+            arguments.push(qpfile_path.to_string_lossy().to_string());
+            arguments.extend(zone_arguments.iter().cloned());
+
+            let mut encoder_pipe = command(&self.config.encoder.command())
+                .args(&arguments)
+                .stdin(decoder_stdout)
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .context("Unable to spawn whole video encoding subprocess")?;
+
+            let encoder_tracker = track(&encoder_pipe);
+
+            let mut encoder_stderr =
+                BufReader::new(encoder_pipe.stderr.take().ok_or_else(|| {
+                    anyhow!("Unable to access stderr for whole video encoding subprocess")
+                })?);
+
+            let mut buffer = Vec::with_capacity(256);
+            let mut output = EncoderOutput::new(self.config.stderr_lines);
+
+            while let Ok(bytes) = encoder_stderr.read_until(b'\r', &mut buffer) {
+                if bytes == 0 {
+                    break;
+                }
+
+                if let Ok(line) = str::from_utf8(&buffer) {
+                    if !line.contains('\n') {
+                        progress_bar.set_message(format!("{prefix}{line}"));
+                    }
+                }
+
+                output.push(&buffer);
+                buffer.clear();
+            }
+
+            let result = encoder_tracker
+                .wait(&mut encoder_pipe)
+                .context("Unable to wait for whole video encoding subprocess")?;
+
+            if !result.success() {
+                progress_bar.set_message("Encoding whole video...failed!");
+                progress_bar.finish();
+
+                let log_path = self
+                    .write_failure_log(&format!("whole-video-pass{pass}"), &arguments, &output)
+                    .context("Unable to write encoder failure log")?;
+
+                return Err(anyhow!(
+                    "Encoder process exited with status {} (full log in {log_path:?}) and output {:#?}",
+                    result,
+                    &output.recent
+                ));
+            }
+
+            self.record_pass_timing(
+                EncodeKind::Whole,
+                pass,
+                self.metadata.frame_count,
+                pass_start.elapsed(),
+            );
+        }
+
+        fs::rename(&temporary_output_path, &output_path).with_context(|| {
+            format!("Unable to rename {temporary_output_path:?} to {output_path:?}")
+        })?;
+
+        for path in [&stats_path, &qpfile_path, &zone_file_path] {
+            if path.exists() {
+                fs::remove_file(path).with_context(|| format!("Unable to remove {path:?}"))?;
+            }
+        }
+
+        progress_bar.set_message("Encoding whole video...done!");
+        progress_bar.finish();
+
+        Ok(output_path)
+    }
+
+    // mkvmerge happily appends a truncated or empty chunk, which leaves the merged file broken in
+    // ways that are easy to miss, so every chunk is checked first and all problems are reported.
+    fn validate_chunks(
+        &self,
+        clips: &mut [ClipMetrics],
+        statistics: &EncodeStatistics,
+    ) -> anyhow::Result<()> {
+        let mut problems = vec![];
+
+        for scene in &self.scenes {
+            let Some(clip_path) = statistics.scene_clips.get(&scene.index()) else {
+                problems.push(format!("Scene {:05}: no chunk was encoded", scene.index()));
+                continue;
+            };
+
+            let size = fs::metadata(clip_path).map_or(0, |metadata| metadata.len());
+
+            if size == 0 {
+                problems.push(format!(
+                    "Scene {:05}: {clip_path:?} is missing or empty",
+                    scene.index()
+                ));
+                continue;
+            }
+
+            if let Err(error) = frame_rate(clip_path) {
+                problems.push(format!(
+                    "Scene {:05}: {clip_path:?} has an unreadable header: {error:#}",
+                    scene.index()
+                ));
+                continue;
+            }
+
+            let Some(clip) = clips.iter_mut().find(|clip| clip.path() == clip_path) else {
+                problems.push(format!(
+                    "Scene {:05}: {clip_path:?} has no metrics",
+                    scene.index()
+                ));
+                continue;
+            };
+
+            match clip.frames() {
+                Ok(frames) if frames == scene.length() => {}
+                Ok(frames) => problems.push(format!(
+                    "Scene {:05}: {clip_path:?} has {frames} frames instead of {}",
+                    scene.index(),
+                    scene.length()
+                )),
+                Err(error) => problems.push(format!(
+                    "Scene {:05}: {clip_path:?} could not be read: {error:#}",
+                    scene.index()
+                )),
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Refusing to merge {} invalid scene chunk(s):\n{}",
+                problems.len(),
+                problems.join("\n")
+            ))
+        }
+    }
+
+    fn merge_scenes(
+        &self,
+        files: &[PathBuf],
+        audio_path: Option<&Path>,
+    ) -> anyhow::Result<PathBuf> {
+        let output_path = self.config.output_directory.join("output");
+
+        verify_directory(&output_path).with_context(|| {
+            format!("Unable to verify merging output directory {output_path:?}")
+        })?;
+
+        // Output with audio, subtitles or chapters is named apart from video-only output, so adding
+        // them to a finished encode only repeats the merge.
+        let mut output_name = self.config.encode_identifier(true);
+
+        if let (Some(audio_encoder), Some(_)) = (self.config.audio_encoder, audio_path) {
+            output_name = format!("{output_name}-{audio_encoder}{}", self.config.audio_bitrate);
+        }
+
+        // The track selection is hashed into the name, so choosing other tracks merges again.
+        if let Some(subtitles) = &self.config.subtitles {
+            output_name = format!("{output_name}-subtitles-{}", &hash_string(subtitles)[..8]);
+        }
+
+        if self.config.chapters {
+            output_name.push_str("-chapters");
+        }
+
+        // WebM is a subset of Matroska that mkvmerge writes directly, while MP4 is remuxed from the
+        // merged MKV afterwards.
+        let webm = self.config.container == Some(Container::Webm);
+        let extension = if webm { "webm" } else { "mkv" };
+
+        let temporary_output_path = output_path.join(format!("{output_name}.tmp.{extension}"));
+
+        let tags_path =
+            output_path.join(format!("{}.tags.xml", self.config.encode_identifier(true)));
+
+        let parts_directory =
+            output_path.join(format!("{}.parts", self.config.encode_identifier(true)));
+
+        let output_path = output_path.join(format!("{output_name}.{extension}"));
+
+        if let Some(packaged_path) = self
+            .config
+            .container
+            .map(|container| output_path.with_extension(container.extension()))
+            .filter(|packaged_path| packaged_path.exists())
+        {
+            return Ok(packaged_path);
+        }
+
+        let progress_bar = ProgressBar::new(100);
+        progress_bar.enable_steady_tick(Duration::from_millis(120));
+        progress_bar.set_style(
+            create_progress_style(
+                "{spinner:.green} [{elapsed_precise}] {msg} [{wide_bar:.cyan/blue}] {percent:>3}%",
+            )
+            .context("Unable to create scene merging progress bar style")?,
+        );
+        progress_bar.set_message("Merging scenes...");
+
+        if !output_path.exists() {
+            // WebM only allows a few of the Matroska tags, so the encode tags are left out of it.
+            let options: Vec<OsString> = if webm {
+                vec!["--webm".into()]
+            } else {
+                self.write_tags(&tags_path)
+                    .context("Unable to write output tags")?;
+
+                vec!["--global-tags".into(), tags_path.into()]
+            };
+
+            let batched = self.config.merge_batch > 0 && files.len() > self.config.merge_batch;
+
+            // Batches are merged into parts first, taking the first half of the progress bar.
+            let (parts, offset) = if batched {
+                (
+                    self.merge_parts(&parts_directory, files, &progress_bar)
+                        .context("Unable to merge scene batches")?,
+                    50,
+                )
+            } else {
+                (files.to_vec(), 0)
+            };
+
+            if let Err(error) = run_mkvmerge(
+                &temporary_output_path,
+                &options,
+                &parts,
+                &self.passthrough_inputs(audio_path),
+                &progress_bar,
+                (offset, 100 - offset),
+            ) {
+                progress_bar.set_message("Merging scenes...failed!");
+                progress_bar.finish();
+
+                return Err(error);
+            }
+        }
+        if temporary_output_path.exists() {
+            fs::rename(&temporary_output_path, &output_path).with_context(|| {
+                format!("Unable to rename {temporary_output_path:?} to {output_path:?}")
+            })?;
+        }
+
+        if parts_directory.exists() {
+            fs::remove_dir_all(&parts_directory).with_context(|| {
+                format!("Unable to remove merge parts directory {parts_directory:?}")
+            })?;
+        }
+
+        progress_bar.set_message("Merging scenes...done!");
+        progress_bar.set_position(100);
+        progress_bar.finish();
+
+        if self.config.container == Some(Container::Mp4) {
+            return package::convert(&output_path, self.config.encoder, Container::Mp4)
+                .context("Unable to package output");
+        }
+
+        Ok(output_path)
+    }
+
+    // The transcoded audio and anything kept from the source are added as inputs of their own after
+    // the appended video chunks, so their tracks sit beside the video. Only the selected subtitle
+    // tracks and chapters are taken from the source.
+    fn passthrough_inputs(&self, audio_path: Option<&Path>) -> Vec<OsString> {
+        let mut inputs: Vec<OsString> = audio_path.map(Into::into).into_iter().collect();
+
+        if self.config.subtitles.is_none() && !self.config.chapters {
+            return inputs;
+        }
+
+        inputs.extend(
+            [
+                "--no-video",
+                "--no-audio",
+                "--no-buttons",
+                "--no-attachments",
+                "--no-track-tags",
+                "--no-global-tags",
+            ]
+            .map(Into::into),
+        );
+
+        match self.config.subtitles.as_deref() {
+            None => inputs.push("--no-subtitles".into()),
+            Some("all") => {}
+            Some(tracks) => {
+                inputs.push("--subtitle-tracks".into());
+                inputs.push(tracks.into());
+            }
+        }
+
+        if !self.config.chapters {
+            inputs.push("--no-chapters".into());
+        }
+
+        inputs.push(self.config.source.clone().into());
+
+        inputs
+    }
+
+    // Each batch of chunks is merged into a part named after the chunks it holds, so an interrupted
+    // merge resumes after the last complete part instead of starting over. The parts are only
+    // needed until the final merge succeeds.
+    #[expect(clippy::as_conversions)]
+    fn merge_parts(
+        &self,
+        parts_directory: &Path,
+        files: &[PathBuf],
+        progress_bar: &ProgressBar,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        verify_directory(parts_directory).with_context(|| {
+            format!("Unable to verify merge parts directory {parts_directory:?}")
+        })?;
+
+        let batches = files.chunks(self.config.merge_batch).collect::<Vec<_>>();
+        let share = 50 / batches.len().max(1) as u64;
+        let mut parts = vec![];
+
+        for (index, batch) in batches.iter().enumerate() {
+            let key = batch
+                .iter()
+                .map(|path| {
+                    let size = fs::metadata(path).map_or(0, |metadata| metadata.len());
+                    format!("{}:{size}", path.to_string_lossy())
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            let part_path =
+                parts_directory.join(format!("part-{index:05}-{}.mkv", hash_string(&key)));
+
+            if !part_path.exists() {
+                let temporary_part_path = part_path.with_extension("tmp.mkv");
+
+                run_mkvmerge(
+                    &temporary_part_path,
+                    &[],
+                    batch,
+                    &[],
+                    progress_bar,
+                    (index as u64 * share, share),
+                )
+                .with_context(|| format!("Unable to merge scene batch {index}"))?;
+
+                fs::rename(&temporary_part_path, &part_path).with_context(|| {
+                    format!("Unable to rename {temporary_part_path:?} to {part_path:?}")
+                })?;
+            }
+
+            progress_bar.set_position((index as u64 + 1) * share);
+            parts.push(part_path);
+        }
+
+        Ok(parts)
+    }
+
+    fn measure_quality(
+        &self,
+        scene: &Scene,
+        output_filename: &Path,
+        progress_bar: &ProgressBar,
+        description: &str,
+        probe: bool,
+    ) -> anyhow::Result<f64> {
+        self.measure_metric(
+            self.config.metric,
+            scene,
+            output_filename,
+            progress_bar,
+            description,
+            probe,
+        )
+    }
+
+    // Scores against a reference that is off by even a frame look like a loss of quality rather
+    // than the chunking problem they are, so nothing is measured until the clip lines up. The
+    // frame hashes need a decode of both clips, so each clip is only checked once.
+    fn verify_alignment(
+        &self,
+        scene: &Scene,
+        metrics: &mut ClipMetrics,
+        (reference_filename, reference_filter, reference_range): (
+            &Path,
+            Option<&str>,
+            Option<SourceRange>,
+        ),
+        progress_bar: &ProgressBar,
+        description: &str,
+    ) -> anyhow::Result<()> {
+        let frames = metrics
+            .frames()
+            .context("Unable to read encoded clip frame count")?;
+
+        if frames != scene.length() {
+            if self.config.strict {
+                return Err(anyhow!(
+                    "Scene {} should contain {} frames but the encoded clip contains {frames}, so its scores would be misaligned",
+                    scene.index(),
+                    scene.length()
+                ));
+            }
+
+            warn!(
+                "Scene {} should contain {} frames but the encoded clip contains {frames}, so its scores may be misaligned.",
+                scene.index(),
+                scene.length()
+            );
+        }
+
+        if !self.config.verify_alignment
+            || self
+                .aligned_clips
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .contains(metrics.path())
+        {
+            return Ok(());
+        }
+
+        update_worker_message(
+            progress_bar,
+            scene.index(),
+            &format!("{description}Verifying alignment..."),
+        );
+
+        alignment::verify(
+            metrics.path(),
+            reference_filename,
+            reference_filter,
+            reference_range,
+        )?;
+
+        self.aligned_clips
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(metrics.path().clone());
+
+        Ok(())
+    }
+
+    fn measure_report_metric(
+        &self,
+        scene: &Scene,
+        output_filename: &Path,
+        progress_bar: &ProgressBar,
+    ) -> anyhow::Result<Option<f64>> {
+        match self.config.report_metric {
+            Some(metric) if self.config.metric == Metric::Direct => self
+                .measure_metric(
+                    metric,
+                    scene,
+                    output_filename,
+                    progress_bar,
+                    "Report :: ",
+                    false,
+                )
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    fn measure_metric(
+        &self,
+        metric: Metric,
+        scene: &Scene,
+        output_filename: &Path,
+        progress_bar: &ProgressBar,
+        description: &str,
+        probe: bool,
+    ) -> anyhow::Result<f64> {
+        update_worker_message(
+            progress_bar,
+            scene.index(),
+            &format!("{description}Calculating metric..."),
+        );
+
+        let (reference_filename, reference_filter, reference_range) =
+            self.scene_reference(scene)?;
+
+        let mut metrics = ClipMetrics::new(
+            output_filename,
+            &reference_filename,
+            reference_filter,
+            reference_range,
+            self.shared_metrics_path(output_filename),
+            &self.metric_context,
+        )
+        .with_context(|| format!("Unable to calculate metrics for scene {:05}", scene.index()))?;
+
+        self.verify_alignment(
+            scene,
+            &mut metrics,
+            (&reference_filename, reference_filter, reference_range),
+            progress_bar,
+            description,
+        )
+        .with_context(|| {
+            format!(
+                "Unable to align scene {:05} with its reference",
+                scene.index()
+            )
+        })?;
+
+        // Metric passes on long scenes can take minutes, so the frames scored so far are shown.
+        let scored = AtomicU64::new(0);
+        let worker_progress_bar = progress_bar.clone();
+        let scene_index = scene.index();
+        let scene_length = scene.length();
+        let message = format!("{description}Calculating metric...");
+
+        metrics.set_progress(Arc::new(move |frames| {
+            let scored = scored.fetch_add(frames, Ordering::Relaxed) + frames;
+
+            update_worker_message(
+                &worker_progress_bar,
+                scene_index,
+                &format!("{message} {scored}/{scene_length} frames"),
+            );
+        }));
+
+        #[expect(clippy::integer_division)]
+        #[expect(clippy::integer_division_remainder_used)]
+        let threads = self.config.workers / self.active_workers.load(Ordering::Relaxed);
+
+        // SSIMULACRA2 borrows the threads of idle workers as it goes, and gives them back as soon
+        // as those workers start encoding again.
+        let _measuring = self.worker_pool.measuring();
+
+        if metric == Metric::SSIMULACRA2 {
+            metrics.set_thread_budget(self.worker_pool.budget());
+        }
+
+        let metric_values = match metric {
+            Metric::Direct => vec![0.0_f64],
+            Metric::PSNR => metrics
+                .psnr(threads)
+                .context("Unable to calculate PSNR values")?
+                .clone(),
+            Metric::SSIM => metrics
+                .ssim(threads)
+                .context("Unable to calculate SSIM values")?
+                .clone(),
+            Metric::VMAF if self.config.metrics_effort == MetricsEffort::Fast => metrics
+                .fast_vmaf(threads)
+                .context("Unable to calculate fast VMAF values")?
+                .clone(),
+            Metric::VMAF => metrics
+                .vmaf(threads)
+                .context("Unable to calculate VMAF values")?
+                .clone(),
+            Metric::SSIMULACRA2 => metrics
+                .scaled_ssimulacra2(self.config.workers, self.metric_scale(probe))
+                .context("Unable to calculate SSIMULACRA2 values")?
+                .clone(),
+            Metric::Bitrate => {
+                let duration = metrics.duration().context("Unable to calculate duration")?;
+
+                let frames = metrics
+                    .frames()
+                    .context("Unable to determine frame count")?;
+
+                let frame_duration = duration / frames as f64;
+
+                metrics
+                    .sizes()
+                    .context("Unable to calculate frame sizes")?
+                    .iter()
+                    .map(|x| *x as f64 * 8.0_f64 / frame_duration)
+                    .collect()
+            }
+        };
+
+        let metric_value = if self.config.use_mean {
+            Data::new(metric_values)
+                .mean()
+                .ok_or_else(|| anyhow!("Unable to calculate mean value of metric data"))?
+        } else {
+            Data::new(metric_values).quantile(self.config.percentile)
+        };
+
+        Ok(metric_value)
+    }
+
+    // The hash in the encode identifier cannot be reversed, so the exact command line is kept with
+    // the chunk's scores for later investigation.
+    fn record_encoder_arguments(
+        &self,
+        scene: &Scene,
+        output_filename: &Path,
+        command_line: Vec<String>,
+    ) -> anyhow::Result<()> {
+        let (reference_filename, reference_filter, reference_range) =
+            self.scene_reference(scene)?;
+
+        ClipMetrics::new(
+            output_filename,
+            &reference_filename,
+            reference_filter,
+            reference_range,
+            self.shared_metrics_path(output_filename),
+            &self.metric_context,
+        )
+        .with_context(|| format!("Unable to open metrics for scene {:05}", scene.index()))?
+        .set_encoder_arguments(command_line)
+    }
+
+    // The encoder's own bitrate should agree with the size of the chunk it produced. A mismatch means
+    // frames were lost or duplicated between the encoder and the container.
+    fn record_encoder_report(&self, scene: &Scene, output_filename: &Path) -> anyhow::Result<()> {
+        let report_path = output_filename.with_extension("report.json");
+
+        let Some(mut report) = read_json::<EncoderReport>(&report_path)
+            .with_context(|| format!("Unable to read encoder report {report_path:?}"))?
+        else {
+            return Ok(());
+        };
+
+        if let Some(bitrate) = report.bitrate {
+            let measured_bitrate = self
+                .measure_bitrate(scene, output_filename)
+                .context("Unable to measure scene bitrate")?;
+
+            if (measured_bitrate - bitrate).abs() > bitrate * MAXIMUM_BITRATE_DISCREPANCY {
+                warn!(
+                    "Scene {} was reported by the encoder at {bitrate:0.1} kbps but measures {measured_bitrate:0.1} kbps",
+                    scene.index()
+                );
+            }
+
+            report.measured_bitrate = Some(measured_bitrate);
+        }
+
+        self.scene_reports
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(scene.index(), report);
+
+        Ok(())
+    }
+
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    fn measure_bitrate(&self, scene: &Scene, output_filename: &Path) -> anyhow::Result<f64> {
+        let (reference_filename, reference_filter, reference_range) =
+            self.scene_reference(scene)?;
+
+        let mut metrics = ClipMetrics::new(
+            output_filename,
+            &reference_filename,
+            reference_filter,
+            reference_range,
+            self.shared_metrics_path(output_filename),
+            &self.metric_context,
+        )
+        .with_context(|| format!("Unable to calculate metrics for scene {:05}", scene.index()))?;
+
+        let duration = metrics.duration().context("Unable to calculate duration")?;
+        let size: usize = metrics
+            .sizes()
+            .context("Unable to calculate frame sizes")?
+            .iter()
+            .sum();
+
+        Ok(size as f64 * 8.0 / duration / 1000.0)
+    }
+
+    // Every scene is analyzed up front, since a decision for the whole source needs all of them.
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    fn analyze_animation(&self) -> anyhow::Result<()> {
+        if self.config.animation_detection == AnimationDetection::Off {
+            return Ok(());
+        }
+
+        let scene_queue: ArrayQueue<Scene> = ArrayQueue::new(self.scenes.len().max(1));
+
+        for scene in &self.scenes {
+            if scene_queue.push(*scene).is_err() {
+                return Err(anyhow!("Animation analysis queue was unexpectedly full"));
+            }
+        }
+
+        let progress_bar =
+            ProgressBar::new(self.metadata.frame_count.try_into().unwrap_or(u64::MAX));
+
+        progress_bar.set_style(
+            create_progress_style(
+                "{spinner:.green} [{elapsed_precise}] Detecting animation...     [{wide_bar:.cyan/blue}] {percent:>3}% {human_pos:>8}/{human_len:>8} ({smooth_per_sec:>6} FPS, ETA: {smooth_eta:>3})"
+            ).context("Unable to create animation analysis progress bar style")?
+        );
+
+        thread::scope(|scope| -> anyhow::Result<()> {
+            let threads = (0..self.config.workers)
+                .map(|_thread_index| {
+                    scope.spawn(|| -> anyhow::Result<()> {
+                        while let Some(scene) = scene_queue.pop() {
+                            wait_while_paused();
+
+                            let (input_filename, input_filter, input_range) =
+                                self.scene_input(&scene);
+
+                            let statistics = animation::analyze(
+                                &input_filename,
+                                input_filter,
+                                input_range,
+                                &self
+                                    .analysis_directory
+                                    .join(format!("scene-{:05}.animation.json", scene.index())),
+                            )
+                            .with_context(|| {
+                                format!("Unable to analyze animation in scene {:05}", scene.index())
+                            })?;
+
+                            self.animation_decisions
+                                .lock()
+                                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                                .insert(
+                                    scene.index(),
+                                    AnimationDecision {
+                                        statistics,
+                                        animated: statistics.animated(),
+                                    },
+                                );
+
+                            progress_bar.inc(scene.length().try_into().unwrap_or(u64::MAX));
+                            notify_progress("animation", &progress_bar);
+                        }
+
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for thread in threads {
+                thread
+                    .join()
+                    .map_err(|error| anyhow!("Animation analysis worker panicked: {error:?}"))??;
+            }
+
+            Ok(())
+        })?;
+
+        progress_bar.finish();
+
+        // The whole source is treated as animation when most of its running time is.
+        if self.config.animation_detection == AnimationDetection::Source {
+            let mut decisions = self
+                .animation_decisions
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+            let animated_frames: usize = self
+                .scenes
+                .iter()
+                .filter(|scene| {
+                    decisions
+                        .get(&scene.index())
+                        .is_some_and(|decision| decision.animated)
+                })
+                .map(Scene::length)
+                .sum();
+
+            let animated = animated_frames as f64 * 2.0 >= self.metadata.frame_count as f64;
+
+            for decision in decisions.values_mut() {
+                decision.animated = animated;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Probes a scene at a few qualities around where its own search would likely end, measuring the
+    // metric and bitrate of each. The probes are kept in the scene's probe index and encoded exactly
+    // as a search would encode them, so later runs and the final encodes reuse them.
+    fn model_scene(&self, scene: &Scene, progress_bar: &ProgressBar) -> anyhow::Result<SceneModel> {
+        self.analyze_grain(scene, progress_bar)?;
+
+        let quality_range = self.config.encoder.quality_range(&self.config.mode);
+        let values = quality_range.values();
+
+        let probe_index_path = self.probe_index_path(scene);
+        let probe_metric = self.probe_metric();
+        let mut probe_index =
+            ProbeIndex::load(&probe_index_path).context("Unable to load scene probe index")?;
+
+        let center = self
+            .quality_hint(scene)
+            .or_else(|| probe_index.closest(&probe_metric, self.scene_target(scene)))
+            .and_then(|hint| quality_range.snap(hint))
+            .and_then(|hint| {
+                values
+                    .iter()
+                    .position(|value| (value - hint).abs() < f64::EPSILON)
+            })
+            .unwrap_or(values.len() / 2);
+
+        let step = (values.len() / GLOBAL_PROBE_STEPS).max(1);
+
+        let mut indices = vec![center.saturating_sub(step), center, center + step];
+        indices.retain(|index| *index < values.len());
+        indices.dedup();
+
+        let mut points = vec![];
+
+        for index in indices {
+            let Some(&quality) = values.get(index) else {
+                continue;
+            };
+
+            let description = format!("Global Probe :: Quality {quality} :: ");
+
+            let output_filename = self
+                .encode_scene_single(
+                    scene,
+                    progress_bar,
+                    &description,
+                    EncodeKind::Probe,
+                    self.config.passes(),
+                    quality,
+                )
+                .context("Unable to encode scene")?;
+
+            let metric = self
+                .measure_quality(scene, &output_filename, progress_bar, &description, true)
+                .context("Unable to measure scene quality")?;
+
+            let bitrate = self
+                .measure_bitrate(scene, &output_filename)
+                .context("Unable to measure scene bitrate")?;
+
+            if probe_index.record(quality, &output_filename, &probe_metric, metric) {
+                probe_index
+                    .write(&probe_index_path)
+                    .context("Unable to update scene probe index")?;
+            }
+
+            points.push(RatePoint {
+                quality,
+                metric,
+                bitrate,
+            });
+        }
+
+        #[expect(clippy::as_conversions)]
+        #[expect(clippy::cast_precision_loss)]
+        let frames = scene.length() as f64;
+
+        Ok(SceneModel {
+            index: scene.index(),
+            frames,
+            points,
+            floor: self.target_overrides.get(&scene.index()).copied(),
+        })
+    }
+
+    // Every scene is probed before any is encoded, so that quality can be spent where it is cheapest
+    // across the whole source rather than meeting the same target in every scene.
+    fn optimize_globally(&self) -> anyhow::Result<()> {
+        let Some(objective) = self.config.global_objective else {
+            return Ok(());
+        };
+
+        let scene_queue: ArrayQueue<Scene> = ArrayQueue::new(self.scenes.len().max(1));
+
+        for scene in &self.scenes {
+            if scene_queue.push(*scene).is_err() {
+                return Err(anyhow!("Global optimization queue was unexpectedly full"));
+            }
+        }
+
+        let progress_bar =
+            ProgressBar::new(self.metadata.frame_count.try_into().unwrap_or(u64::MAX));
+
+        progress_bar.set_style(
+            create_progress_style(
+                "{spinner:.green} [{elapsed_precise}] Probing scenes...         [{wide_bar:.cyan/blue}] {percent:>3}% {human_pos:>8}/{human_len:>8} ({smooth_per_sec:>6} FPS, ETA: {smooth_eta:>3})"
+            ).context("Unable to create global optimization progress bar style")?
+        );
+
+        let models = Mutex::new(vec![]);
+
+        thread::scope(|scope| -> anyhow::Result<()> {
+            let threads = (0..self.config.workers)
+                .map(|_thread_index| {
+                    scope.spawn(|| -> anyhow::Result<()> {
+                        while let Some(scene) = scene_queue.pop() {
+                            wait_while_paused();
+
+                            let model =
+                                self.model_scene(&scene, &progress_bar).with_context(|| {
+                                    format!("Unable to probe scene {:05}", scene.index())
+                                })?;
+
+                            models
+                                .lock()
+                                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                                .push(model);
+
+                            progress_bar.inc(scene.length().try_into().unwrap_or(u64::MAX));
+                            notify_progress("optimization", &progress_bar);
+                        }
+
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for thread in threads {
+                thread
+                    .join()
+                    .map_err(|error| anyhow!("Global optimization worker panicked: {error:?}"))??;
+            }
+
+            Ok(())
+        })?;
+
+        progress_bar.finish();
+
+        let limit = match objective {
+            GlobalObjective::Bitrate => self.config.quality,
+            GlobalObjective::Quality => self.config.max_bitrate.unwrap_or(f64::INFINITY),
+        };
+
+        let models = models
+            .into_inner()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        let allocation = allocation::allocate(
+            &models,
+            self.config
+                .encoder
+                .quality_range(&self.config.mode)
+                .values(),
+            objective,
+            limit,
+        );
+
+        if !allocation.satisfied {
+            warn!(
+                "No combination of probed scene qualities meets the global {objective} constraint, so the closest one is used"
+            );
+        }
+
+        *self
+            .global_allocation
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(allocation);
+
+        Ok(())
+    }
+
+    fn short(&self, scene: &Scene) -> bool {
+        self.config
+            .short_scene_frames
+            .is_some_and(|frames| scene.length() < frames)
+    }
+
+    // Counts down the regular scenes, whether their encode succeeded or not, so that short scenes
+    // never wait on a scene that is no longer being worked on.
+    fn finish_search(&self) {
+        let mut pending = self
+            .pending_searches
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        *pending = pending.saturating_sub(1);
+        drop(pending);
+
+        self.searches_finished.notify_all();
+    }
+
+    // The closest scene whose quality was actually searched, by index. Every regular scene is
+    // searched first, so the neighbor does not depend on which workers happened to finish early.
+    fn inherited_quality(&self, scene: &Scene) -> Option<f64> {
+        if !self.short(scene) {
+            return None;
+        }
+
+        let mut pending = self
+            .pending_searches
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        while *pending > 0 {
+            pending = self
+                .searches_finished
+                .wait(pending)
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+        }
+
+        drop(pending);
+
+        let (source_index, quality) = self
+            .searched_qualities
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .iter()
+            .min_by_key(|(index, _)| index.abs_diff(scene.index()))
+            .map(|(index, quality)| (*index, *quality))?;
+
+        self.short_scenes
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(scene.index(), source_index);
+
+        Some(quality)
+    }
+
+    fn allocated_quality(&self, scene: &Scene) -> Option<f64> {
+        self.global_allocation
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .as_ref()
+            .and_then(|allocation| allocation.qualities.get(&scene.index()).copied())
+    }
+
+    fn animated(&self) -> bool {
+        self.animation_decisions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .values()
+            .any(|decision| decision.animated)
+    }
+
+    fn scene_animated(&self, scene: &Scene) -> bool {
+        self.animation_decisions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(&scene.index())
+            .is_some_and(|decision| decision.animated)
+    }
+
+    fn analyze_grain(&self, scene: &Scene, progress_bar: &ProgressBar) -> anyhow::Result<()> {
+        let Some(threshold) = self.config.grain_threshold else {
+            return Ok(());
+        };
+
+        update_worker_message(progress_bar, scene.index(), "Analyzing grain...");
+
+        let (input_filename, input_filter, input_range) = self.scene_input(scene);
+
+        let score = grain::analyze(
+            &input_filename,
+            input_filter,
+            input_range,
+            &self
+                .analysis_directory
+                .join(format!("scene-{:05}.grain.json", scene.index())),
+        )
+        .with_context(|| format!("Unable to analyze grain in scene {:05}", scene.index()))?;
+
+        self.grain_decisions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(
+                scene.index(),
+                GrainDecision {
+                    score,
+                    grain_heavy: score < threshold,
+                },
+            );
+
+        Ok(())
+    }
+
+    fn grain_heavy(&self, scene: &Scene) -> bool {
+        self.grain_decisions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(&scene.index())
+            .is_some_and(|decision| decision.grain_heavy)
+    }
+
+    fn measure_banding(&self, scene: &Scene, output_filename: &Path) -> anyhow::Result<f64> {
+        let (reference_filename, reference_filter, reference_range) =
+            self.scene_reference(scene)?;
+
+        let mut metrics = ClipMetrics::new(
+            output_filename,
+            &reference_filename,
+            reference_filter,
+            reference_range,
+            self.shared_metrics_path(output_filename),
+            &self.metric_context,
+        )
+        .with_context(|| format!("Unable to calculate metrics for scene {:05}", scene.index()))?;
+
+        #[expect(clippy::integer_division)]
+        #[expect(clippy::integer_division_remainder_used)]
+        let threads = self.config.workers / self.active_workers.load(Ordering::Relaxed).max(1);
+
+        let cambi = metrics
+            .cambi(threads)
+            .context("Unable to calculate CAMBI values")?
+            .clone();
+
+        Ok(Data::new(cambi).quantile(0.95))
+    }
+
+    fn scene_debanded(&self, scene: &Scene) -> bool {
+        self.banding_interventions
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .contains_key(&scene.index())
+    }
+
+    fn scene_preset(&self, scene: &Scene) -> Option<String> {
+        self.preset_escalations
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(&scene.index())
+            .map(|escalation| escalation.preset.clone())
+    }
+
+    // Scenes that miss the target even at the best quality may only need more encoder effort, so
+    // slower presets are tried at that quality until one meets the target or the limit is reached.
+    fn escalate_preset(
+        &self,
+        scene: &Scene,
+        progress_bar: &ProgressBar,
+        quality: f64,
+        output_filename: PathBuf,
+        metric_value: f64,
+    ) -> anyhow::Result<(PathBuf, f64)> {
+        let target = self.scene_target(scene);
+        let mut preset = self.config.preset.clone();
+        let (mut output_filename, mut escalated_metric_value) = (output_filename, metric_value);
+
+        for _ in 0..self.config.preset_escalation {
+            let Some(slower_preset) = self.config.encoder.slower_preset(&preset) else {
+                break;
+            };
+
+            preset = slower_preset;
+
+            warn!(
+                "Scene {} missed the quality target at the best quality with a value of {escalated_metric_value:0.3}. Re-encoding with preset {preset}.",
+                scene.index()
+            );
+
+            self.preset_escalations
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .insert(
+                    scene.index(),
+                    PresetEscalation {
+                        preset: preset.clone(),
+                        metric_value,
+                        escalated_metric_value: None,
+                    },
+                );
+
+            let description = format!("Preset {preset} :: ");
+
+            output_filename = self
+                .encode_scene_single(
+                    scene,
+                    progress_bar,
+                    &description,
+                    EncodeKind::Final,
+                    self.config.passes(),
+                    quality,
+                )
+                .with_context(|| {
+                    format!(
+                        "Unable to re-encode scene {:05} with preset {preset}",
+                        scene.index()
+                    )
+                })?;
+
+            escalated_metric_value = self
+                .measure_quality(scene, &output_filename, progress_bar, &description, false)
+                .context("Unable to verify scene quality")?;
+
+            if let Some(escalation) = self
+                .preset_escalations
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .get_mut(&scene.index())
+            {
+                escalation.escalated_metric_value = Some(escalated_metric_value);
+            }
+
+            if self.target_miss(escalated_metric_value, target).is_none() {
+                break;
+            }
+        }
+
+        Ok((output_filename, escalated_metric_value))
+    }
+
+    fn probe_index_path(&self, scene: &Scene) -> PathBuf {
+        self.encode_directory
+            .join(format!("scene-{:05}", scene.index()))
+            .join("probes.json")
+    }
+
+    // Only SSIMULACRA2 probes are measured at a reduced scale.
+    fn metric_scale(&self, probe: bool) -> f64 {
+        if probe && self.config.metric == Metric::SSIMULACRA2 {
+            self.config.probe_metric_scale
+        } else {
+            1.0
+        }
+    }
+
+    // Probe scores are only comparable when measured with the same metric and aggregation.
+    fn probe_metric(&self) -> String {
+        let scale = self.metric_scale(true);
+
+        let metric = if scale < 1.0 {
+            format!("{}-x{scale}", self.config.metric_identifier())
+        } else {
+            self.config.metric_identifier()
+        };
+
+        if self.config.use_mean {
+            format!("{metric}-mean")
+        } else {
+            format!("{metric}-{}", self.config.percentile)
+        }
+    }
+
+    fn scene_target(&self, scene: &Scene) -> f64 {
+        self.target_overrides
+            .get(&scene.index())
+            .copied()
+            .unwrap_or(self.config.quality)
+    }
+
+    // Returns the direction the metric has to move in, if the value misses the target by more than
+    // the tolerance. The target rule is only checked when a tolerance has been given.
+    fn target_miss(&self, metric_value: f64, target: f64) -> Option<f64> {
+        let tolerance = self.config.quality_tolerance;
+
+        let too_low = metric_value < target - tolerance;
+        let too_high = metric_value > target + tolerance;
+
+        match self.config.rule {
+            QualityRule::Minimum => too_low.then_some(1.0),
+            QualityRule::Maximum => too_high.then_some(-1.0),
+            QualityRule::Target if tolerance > 0.0 => {
+                if too_low {
+                    Some(1.0)
+                } else if too_high {
+                    Some(-1.0)
+                } else {
+                    None
+                }
+            }
+            QualityRule::Target => None,
+        }
+    }
+
+    #[expect(clippy::too_many_lines)]
+    fn encode_scene(
+        &self,
+        scene: &Scene,
+        progress_bar: &ProgressBar,
+    ) -> anyhow::Result<(PathBuf, f64, Option<TargetMiss>)> {
+        // The outcome is attached to the span so that traces show it without the manifest.
+        let scene_span = info_span!(
+            "scene",
+            index = scene.index(),
+            quality = field::Empty,
+            probes = field::Empty
+        )
+        .entered();
+
+        // A globally allocated quality replaces the scene's own search and is deliberately allowed
+        // to miss the per-scene target, so it is not verified against it either.
+        // Short scenes likewise take their quality from a searched neighbor.
+        let allocated_quality = self.allocated_quality(scene);
+        let searchable = self.config.metric != Metric::Direct
+            && !self.config.encoder.is_mezzanine()
+            && allocated_quality.is_none();
+        let inherited_quality = searchable.then(|| self.inherited_quality(scene)).flatten();
+        let searched = searchable && inherited_quality.is_none();
+        let target = self.scene_target(scene);
+
+        self.analyze_grain(scene, progress_bar)?;
+
+        let quality = if let Some(quality) = allocated_quality.or(inherited_quality) {
+            quality
+        } else if searched {
+            self.schedule
+                .set(scene.index(), SceneStatus::Probing)
+                .context("Unable to update scene schedule")?;
+
+            let mut quality_range = self.config.encoder.quality_range(&self.config.mode);
+
+            let probe_index_path = self.probe_index_path(scene);
+            let probe_metric = self.probe_metric();
+            let mut probe_index =
+                ProbeIndex::load(&probe_index_path).context("Unable to load scene probe index")?;
+
+            // Probes from earlier runs at other targets bracket the answer nearly as well as the
+            // previous run's quality does.
+            if let Some(hint) = self
+                .quality_hint(scene)
+                .or_else(|| probe_index.closest(&probe_metric, target))
+            {
+                quality_range = quality_range.with_hint(hint);
+            }
+
+            // Without a probe under the bitrate ceiling, the worst quality is the safest choice.
+            let maximum_rule =
+                self.config.rule == QualityRule::Maximum || self.config.max_bitrate.is_some();
+
+            let mut best_quality = if maximum_rule {
+                quality_range.worst()
+            } else {
+                quality_range.best()
+            };
+
+            let mut best_score = f64::MIN;
+            let mut capped_best: Option<(f64, f64)> = None;
+            let mut probes = 0_usize;
+            let mut steps = vec![];
+            let search_start = Instant::now();
+
+            while let Some(current_quality) = quality_range.current() {
+                let true_minimum = quality_range.minimum().min(best_quality);
+                let true_maximum = quality_range.maximum().max(best_quality);
+
+                let best_score_text = if best_score < -10_000.0_f64 {
+                    "N/A".to_owned()
+                } else {
+                    format!("{best_score:0.3}")
+                };
+
+                let search_description = if quality_range.integer() {
+                    let digits = if self.config.mode == Mode::Bitrate {
+                        6
+                    } else {
+                        4
+                    };
+
+                    format!(
+                        "Quality Search :: Current Range: {true_minimum:digits$} - {true_maximum:digits$} ({current_quality:digits$}) :: Current Best: {best_quality:digits$} => {best_score_text:9} :: ",
+                    )
+                } else {
+                    format!(
+                        "Quality Search :: Current Range: {true_minimum:7.2} - {true_maximum:0.2} ({current_quality:7.2}) :: Current Best: (Current Best: {best_quality} => {best_score_text}) :: ",
+                    )
+                };
+
+                let output_filename = self
+                    .encode_scene_single(
+                        scene,
+                        progress_bar,
+                        &search_description,
+                        EncodeKind::Probe,
+                        self.config.passes(),
+                        current_quality,
+                    )
+                    .context("Unable to encode scene")?;
+
+                probes += 1;
+
+                let metric_value = self
+                    .measure_quality(
+                        scene,
+                        &output_filename,
+                        progress_bar,
+                        &search_description,
+                        true,
+                    )
+                    .context("Unable to measure scene quality")?;
+
+                if probe_index.record(
+                    current_quality,
+                    &output_filename,
+                    &probe_metric,
+                    metric_value,
+                ) {
+                    probe_index
+                        .write(&probe_index_path)
+                        .context("Unable to update scene probe index")?;
+                }
+
+                let bitrate = self
+                    .measure_bitrate(scene, &output_filename)
+                    .context("Unable to measure scene bitrate")?;
+
+                steps.push(SearchStep {
+                    probe: probes,
+                    minimum: true_minimum,
+                    maximum: true_maximum,
+                    quality: current_quality,
+                    score: metric_value,
+                    bitrate,
+                    elapsed_seconds: search_start.elapsed().as_secs_f64(),
+                });
+
+                // A probe over the bitrate ceiling is never chosen, and only worse qualities can
+                // get under it. Below it, the search follows the quality target as usual.
+                if self
+                    .config
+                    .max_bitrate
+                    .is_some_and(|max_bitrate| bitrate > max_bitrate)
+                {
+                    quality_range.worse();
+                    continue;
+                }
+
+                // Should the floor be out of reach under the ceiling, the best quality that stays
+                // under it is used instead.
+                if self.config.max_bitrate.is_some()
+                    && metric_value < target
+                    && capped_best.is_none_or(|(quality, _)| {
+                        quality_range.is_better(current_quality, quality)
+                    })
+                {
+                    capped_best = Some((current_quality, metric_value));
+                }
+
+                match self.config.rule {
+                    QualityRule::Maximum => {
+                        if metric_value <= target {
+                            if quality_range.is_better(current_quality, best_quality) {
+                                best_quality = current_quality;
+                                best_score = metric_value;
+                            }
+
+                            quality_range.better();
+                        } else {
+                            quality_range.worse();
+                        }
+                    }
+                    QualityRule::Minimum => {
+                        if metric_value >= target {
+                            if quality_range.is_better(best_quality, current_quality) {
+                                best_quality = current_quality;
+                                best_score = metric_value;
+                            }
+
+                            quality_range.worse();
+                        } else {
+                            quality_range.better();
+                        }
+                    }
+                    QualityRule::Target => {
+                        let current_delta = (target - best_score).abs();
+                        let new_delta = (target - metric_value).abs();
+
+                        if new_delta < current_delta {
+                            best_quality = current_quality;
+                            best_score = metric_value;
+                        }
+
+                        let too_low = match quality_range.monotonicity() {
+                            Monotonicity::Increasing => metric_value <= target,
+                            Monotonicity::Decreasing => metric_value < target,
+                        };
+
+                        if too_low {
+                            quality_range.better();
+                        } else {
+                            quality_range.worse();
+                        }
+                    }
+                }
+            }
+
+            if best_score <= f64::MIN {
+                if let Some((quality, score)) = capped_best {
+                    best_quality = quality;
+                    best_score = score;
+                }
+            }
+
+            SearchTimeline {
+                metric: probe_metric,
+                target,
+                steps,
+                quality: best_quality,
+                score: best_score,
+            }
+            .write(&probe_index_path.with_file_name("search.json"))
+            .context("Unable to write scene search timeline")?;
+
+            self.searched_qualities
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .insert(scene.index(), best_quality);
+            scene_span.record("probes", probes);
+
+            best_quality
+        } else {
+            target
+        };
+
+        let mut quality = quality;
+
+        self.schedule
+            .set(scene.index(), SceneStatus::Encoding)
+            .context("Unable to update scene schedule")?;
+
+        let mut output_filename = self
+            .encode_scene_single(
+                scene,
+                progress_bar,
+                "",
+                EncodeKind::Final,
+                self.config.passes(),
+                quality,
+            )
+            .with_context(|| {
+                format!(
+                    "Unable to encode scene {:05} at quality {quality}",
+                    scene.index()
+                )
+            })?;
+
+        let mut target_miss = None;
+
+        // The final encode is checked again and nudged one quality step at a time if it still
+        // misses, stopping early at the edge of the quality range. A correction that breaks the
+        // bitrate ceiling is discarded, leaving the scene to be reported.
+        if searched {
+            let quality_range = self.config.encoder.quality_range(&self.config.mode);
+
+            let mut metric_value = self
+                .measure_quality(
+                    scene,
+                    &output_filename,
+                    progress_bar,
+                    "Verification :: ",
+                    false,
+                )
+                .context("Unable to verify scene quality")?;
+
+            for correction in 1..=self.config.max_corrections {
+                let description = format!("Verification {correction} :: ");
+
+                let Some(direction) = self.target_miss(metric_value, target) else {
+                    break;
+                };
+
+                let corrected_quality = quality_range.adjacent(quality, direction);
+
+                if (corrected_quality - quality).abs() < f64::EPSILON {
+                    break;
+                }
+
+                warn!(
+                    "Scene {} missed the quality target with a value of {metric_value:0.3}. Re-encoding at {corrected_quality} instead of {quality}.",
+                    scene.index()
+                );
+
+                let corrected_filename = self
+                    .encode_scene_single(
+                        scene,
+                        progress_bar,
+                        &description,
+                        EncodeKind::Final,
+                        self.config.passes(),
+                        corrected_quality,
+                    )
+                    .with_context(|| {
+                        format!(
+                            "Unable to re-encode scene {:05} at quality {corrected_quality}",
+                            scene.index()
+                        )
+                    })?;
+
+                if let Some(max_bitrate) = self.config.max_bitrate {
+                    let bitrate = self
+                        .measure_bitrate(scene, &corrected_filename)
+                        .context("Unable to measure scene bitrate")?;
+
+                    if bitrate > max_bitrate {
+                        break;
+                    }
+                }
+
+                quality = corrected_quality;
+                output_filename = corrected_filename;
+
+                metric_value = self
+                    .measure_quality(scene, &output_filename, progress_bar, &description, false)
+                    .context("Unable to verify scene quality")?;
+            }
+
+            // Comparisons with a previous run are made against the quality actually used.
+            self.record_quality(scene, quality);
+
+            // A bitrate ceiling is what kept the quality down, which a slower preset cannot help.
+            if self.config.preset_escalation > 0
+                && self.config.max_bitrate.is_none()
+                && (quality - quality_range.best()).abs() < f64::EPSILON
+                && self
+                    .target_miss(metric_value, target)
+                    .is_some_and(|direction| direction > 0.0)
+            {
+                (output_filename, metric_value) = self
+                    .escalate_preset(scene, progress_bar, quality, output_filename, metric_value)
+                    .context("Unable to escalate scene preset")?;
+            }
+
+            let bitrate = self
+                .config
+                .max_bitrate
+                .map(|_| self.measure_bitrate(scene, &output_filename))
+                .transpose()
+                .context("Unable to measure scene bitrate")?;
+
+            let bitrate_conflict = bitrate
+                .zip(self.config.max_bitrate)
+                .is_some_and(|(bitrate, max_bitrate)| bitrate > max_bitrate);
+
+            if self.target_miss(metric_value, target).is_some() || bitrate_conflict {
+                target_miss = Some(TargetMiss {
+                    metric_value,
+                    target,
+                    gap: metric_value - target,
+                    quality,
+                    range_boundary: (quality - quality_range.minimum()).abs() < f64::EPSILON
+                        || (quality - quality_range.maximum()).abs() < f64::EPSILON,
+                    bitrate,
+                });
+            }
+        }
+
+        // Banding is invisible to the usual metrics, so a finished scene is checked separately and
+        // re-encoded once with the anti-banding settings if needed.
+        if let Some(max_banding) = self
+            .config
+            .max_banding
+            .filter(|_| !self.config.encoder.is_mezzanine())
+        {
+            update_worker_message(progress_bar, scene.index(), "Measuring banding...");
+
+            let cambi = self
+                .measure_banding(scene, &output_filename)
+                .context("Unable to measure scene banding")?;
+
+            if cambi > max_banding {
+                warn!(
+                    "Scene {} has a CAMBI score of {cambi:0.3}. Re-encoding with anti-banding settings.",
+                    scene.index()
+                );
+
+                self.banding_interventions
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .insert(
+                        scene.index(),
+                        BandingIntervention {
+                            cambi,
+                            deband_cambi: None,
+                        },
+                    );
+
+                output_filename = self
+                    .encode_scene_single(
+                        scene,
+                        progress_bar,
+                        "Debanding :: ",
+                        EncodeKind::Final,
+                        self.config.passes(),
+                        quality,
+                    )
+                    .with_context(|| {
+                        format!(
+                            "Unable to re-encode scene {:05} with anti-banding settings",
+                            scene.index()
+                        )
+                    })?;
+
+                let deband_cambi = self
+                    .measure_banding(scene, &output_filename)
+                    .context("Unable to measure scene banding")?;
+
+                if let Some(intervention) = self
+                    .banding_interventions
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .get_mut(&scene.index())
+                {
+                    intervention.deband_cambi = Some(deband_cambi);
+                }
+            }
+        }
+
+        self.record_encoder_report(scene, &output_filename)
+            .context("Unable to record encoder report")?;
+
+        scene_span.record("quality", quality);
+
+        let first_pass_stats_filename = self.first_pass_stats_path(scene);
+
+        if first_pass_stats_filename.exists() && !self.config.keep_stats {
+            fs::remove_file(&first_pass_stats_filename)
+                .context("Unable to remove first pass stats file")?;
+        }
+
+        Ok((output_filename, quality, target_miss))
+    }
+
+    #[expect(clippy::too_many_arguments)]
+    #[expect(clippy::too_many_lines)]
+    fn encode_scene_attempt(
+        &self,
+        scene: &Scene,
+        progress_bar: &ProgressBar,
+        progress_prefix: &str,
+        passes: usize,
+        qp: f64,
+        temporary_output_filename: &Path,
+        pass_stats_filename: &PathBuf,
+    ) -> anyhow::Result<EncoderOutput> {
+        let _encoding = self.worker_pool.encoding();
+
+        let (input_filename, input_filter, input_range) = self.scene_input(scene);
+
+        let mut decoder_pipe = create_child_read(
+            &input_filename,
+            input_filter,
+            self.config.bit_depth.pixel_format(),
+            input_range,
+            Stdio::null(),
+            Stdio::piped(),
+            Stdio::piped(),
+        )
+        .context("Unable to spawn encoding video decoder subprocess")?;
+
+        let decoder_tracker = track(&decoder_pipe);
+
+        let decoder_stdout = decoder_pipe.stdout.take().ok_or_else(|| {
+            anyhow!("Unable to access stdout for encoding video decoder subprocess")
+        })?;
+
+        let decoder_stderr = decoder_pipe.stderr.take().ok_or_else(|| {
+            anyhow!("Unable to access stderr for encoding video decoder subprocess")
+        })?;
+
+        // The decoder's output is kept in case it, rather than the encoder, turns out to have failed.
+        let stderr_lines = self.config.stderr_lines;
+        let decoder_output = thread::spawn(move || {
+            let mut decoder_stderr = BufReader::new(decoder_stderr);
+            let mut buffer = Vec::with_capacity(256);
+            let mut output = EncoderOutput::new(stderr_lines);
+
+            while let Ok(bytes) = decoder_stderr.read_until(b'\n', &mut buffer) {
+                if bytes == 0 {
+                    break;
+                }
+
+                output.push(&buffer);
+                buffer.clear();
+            }
+
+            output
+        });
+
+        update_worker_message(
+            progress_bar,
+            scene.index(),
+            &format!("{progress_prefix}Beginning encode..."),
+        );
+
+        self.check_quality(qp)
+            .with_context(|| format!("Unable to encode scene {:05}", scene.index()))?;
+
+        let key_frame_interval = self.key_frame_interval();
+        let threads = self.encoder_threads();
+        let preset = self
+            .scene_preset(scene)
+            .unwrap_or_else(|| self.config.preset.clone());
+
+        let grain_heavy = self.grain_heavy(scene);
+
+        let mut arguments = self.config.encoder.arguments(
+            &self
+                .config
+                .with_scene_lookahead(scene.length())
+                .with_scene_grain(grain_heavy),
+            &preset,
+            key_frame_interval,
+            threads,
+            (self.config.passes() > 1).then_some(passes),
+            temporary_output_filename,
+            Some(pass_stats_filename),
+            self.config.mode,
+            qp,
+        );
+
+        if self.scene_animated(scene) {
+            arguments.extend(self.config.encoder.animation_arguments());
+        }
+
+        if grain_heavy {
+            arguments.extend(self.config.encoder.grain_arguments());
+        }
+
+        if self.scene_debanded(scene) {
+            arguments.extend(self.config.encoder.deband_arguments());
+        }
+
+        let mut encoder_pipe = command(&self.config.encoder.command())
+            .args(&arguments)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Unable to spawn video encoding subprocess")?;
+
+        let encoder_tracker = track(&encoder_pipe);
+
+        let encoder_stdin = encoder_pipe
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("Unable to access stdin for video encoder subprocess"))?;
+
+        let relay = thread::spawn(move || pipe::relay(decoder_stdout, encoder_stdin));
+
+        let mut encoder_stderr = BufReader::new(
+            encoder_pipe
+                .stderr
+                .take()
+                .ok_or_else(|| anyhow!("Unable to access stderr for video encoder subprocess"))?,
+        );
+
+        let mut buffer = Vec::with_capacity(256);
+        let mut output = EncoderOutput::new(self.config.stderr_lines);
+
+        while let Ok(bytes) = encoder_stderr.read_until(b'\r', &mut buffer) {
+            if bytes == 0 {
+                break;
+            }
+
+            if let Ok(line) = str::from_utf8(&buffer) {
+                if !line.contains('\n') {
+                    update_worker_message(
+                        progress_bar,
+                        scene.index(),
+                        &format!("{progress_prefix}{line}"),
+                    );
+                }
+            }
+
+            output.push(&buffer);
+            buffer.clear();
+        }
+
+        let result = encoder_tracker
+            .wait(&mut encoder_pipe)
+            .context("Unable to wait for video encoder subprocess")?;
+
+        let relay = relay
+            .join()
+            .map_err(|error| anyhow!("Encoder input relay panicked: {error:?}"))?;
+
+        let decoder_result = decoder_tracker
+            .wait(&mut decoder_pipe)
+            .context("Unable to wait for encoding video decoder subprocess")?;
 
-            let mut best_quality = match self.config.mode {
-                Mode::Bitrate => {
-                    if self.config.rule == QualityRule::Maximum {
-                        quality_range.minimum()
-                    } else {
-                        quality_range.maximum()
-                    }
-                }
-                Mode::CRF | Mode::QP => {
-                    if self.config.rule == QualityRule::Maximum {
-                        quality_range.maximum()
-                    } else {
-                        quality_range.minimum()
-                    }
-                }
-            };
+        let decoder_output = decoder_output
+            .join()
+            .map_err(|error| anyhow!("Decoder output reader panicked: {error:?}"))?;
 
-            let mut best_score = f64::MIN;
+        debug!(
+            scene = scene.index(),
+            qp,
+            pass = passes,
+            status = %result,
+            decoder_status = %decoder_result,
+            bytes = relay.bytes,
+            read_wait = ?relay.read_wait,
+            write_wait = ?relay.write_wait,
+            bound = if relay.encoder_bound() { "encoder" } else { "decoder" },
+            "Encoder exited"
+        );
 
-            while let Some(current_quality) = quality_range.current() {
-                let true_minimum = quality_range.minimum().min(best_quality);
-                let true_maximum = quality_range.maximum().max(best_quality);
+        if relay.longest_write >= STALL_WARNING {
+            warn!(
+                "Scene {} encoder stopped accepting input for {} at one point",
+                scene.index(),
+                HumanDuration(relay.longest_write)
+            );
+        }
 
-                let best_score_text = if best_score < -10_000.0_f64 {
-                    "N/A".to_owned()
-                } else {
-                    format!("{best_score:0.3}")
-                };
+        // An input that ended cleanly while the decoder failed means the decoder died first, and
+        // the encoder only saw a truncated stream. If the encoder stopped reading first instead,
+        // the decoder failing afterwards is just a consequence.
+        let encoder_stopped_reading = matches!(relay.failure, Some(RelayFailure::Write(_)));
 
-                let search_description = if quality_range.integer() {
-                    let digits = if self.config.mode == Mode::Bitrate {
-                        6
-                    } else {
-                        4
-                    };
+        if !decoder_result.success() && !encoder_stopped_reading {
+            return Err(anyhow!(
+                "Decoder process exited with status {} after passing {} MiB to the encoder{} and output {:#?}",
+                decoder_result,
+                relay.bytes >> 20,
+                match &relay.failure {
+                    Some(RelayFailure::Read(error)) => format!(" (read failed: {error})"),
+                    _ => String::new(),
+                },
+                &decoder_output.recent
+            ));
+        }
+
+        if !result.success() {
+            let log_path = self
+                .write_failure_log(
+                    &format!(
+                        "scene-{:05}-{}-{qp}-pass{passes}",
+                        scene.index(),
+                        self.config.mode
+                    ),
+                    &arguments,
+                    &output,
+                )
+                .context("Unable to write encoder failure log")?;
 
+            return Err(anyhow!(
+                "Encoder process exited with status {}{} (full log in {log_path:?}) and output {:#?}",
+                result,
+                if encoder_stopped_reading {
                     format!(
-                        "Quality Search :: Current Range: {true_minimum:digits$} - {true_maximum:digits$} ({current_quality:digits$}) :: Current Best: {best_quality:digits$} => {best_score_text:9} :: ",
+                        " after it stopped reading its input at {} MiB",
+                        relay.bytes >> 20
                     )
                 } else {
-                    format!(
-                        "Quality Search :: Current Range: {true_minimum:7.2} - {true_maximum:0.2} ({current_quality:7.2}) :: Current Best: (Current Best: {best_quality} => {best_score_text}) :: ",
-                    )
-                };
+                    String::new()
+                },
+                &output.recent
+            ));
+        }
 
-                let input_filename = self
-                    .config
-                    .output_directory
-                    .join("source")
-                    .join(format!("scene-{:05}.mkv", scene.index()));
+        output.command_line = std::iter::once(self.config.encoder.command())
+            .chain(arguments)
+            .collect();
 
-                let output_filename = self
-                    .encode_scene_single(
-                        scene,
-                        progress_bar,
-                        &search_description,
-                        self.config.passes(),
-                        current_quality,
-                    )
-                    .context("Unable to encode scene")?;
+        Ok(output)
+    }
 
-                update_worker_message(
-                    progress_bar,
-                    scene.index(),
-                    &format!("{search_description}Calculating metric..."),
-                );
+    // Elementary streams have no timing of their own, so the frame rate of the scene input is
+    // applied explicitly. The stream is removed once it has been wrapped.
+    fn wrap_elementary_stream(
+        &self,
+        scene: &Scene,
+        input: &Path,
+        output: &Path,
+    ) -> anyhow::Result<()> {
+        let (input_filename, _, _) = self.scene_input(scene);
 
-                let mut metrics = ClipMetrics::new(&output_filename, &input_filename, None)
-                    .with_context(|| {
-                        format!("Unable to calculate metrics for scene {:05}", scene.index())
-                    })?;
+        let (numerator, denominator) = frame_rate(&input_filename)
+            .with_context(|| format!("Unable to determine frame rate of {input_filename:?}"))?;
 
-                #[expect(clippy::integer_division)]
-                #[expect(clippy::integer_division_remainder_used)]
-                let threads = self.config.workers / self.active_workers.load(Ordering::Relaxed);
-
-                let metric_values = match self.config.metric {
-                    Metric::Direct => vec![0.0_f64],
-                    Metric::PSNR => metrics
-                        .psnr(threads)
-                        .context("Unable to calculate PSNR values")?
-                        .clone(),
-                    Metric::SSIM => metrics
-                        .ssim(threads)
-                        .context("Unable to calculate SSIM values")?
-                        .clone(),
-                    Metric::VMAF => metrics
-                        .vmaf(threads)
-                        .context("Unable to calculate VMAF values")?
-                        .clone(),
-                    Metric::SSIMULACRA2 => metrics
-                        .ssimulacra2(threads)
-                        .context("Unable to calculate SSIMULACRA2 values")?
-                        .clone(),
-                    Metric::Bitrate => {
-                        let duration =
-                            metrics.duration().context("Unable to calculate duration")?;
-
-                        let frames = metrics
-                            .frames()
-                            .context("Unable to determine frame count")?;
-
-                        let frame_duration = duration / frames as f64;
-
-                        metrics
-                            .sizes()
-                            .context("Unable to calculate frame sizes")?
-                            .iter()
-                            .map(|x| *x as f64 * 8.0_f64 / frame_duration)
-                            .collect()
-                    }
-                };
+        let temporary_output = output.with_extension("wrap.tmp.mkv");
 
-                let metric_value = if self.config.use_mean {
-                    Data::new(metric_values)
-                        .mean()
-                        .ok_or_else(|| anyhow!("Unable to calculate mean value of metric data"))?
-                } else {
-                    Data::new(metric_values).quantile(self.config.percentile)
-                };
+        let result = command("mkvmerge")
+            .arg("-o")
+            .arg(&temporary_output)
+            .arg("--default-duration")
+            .arg(format!("0:{numerator}/{denominator}fps"))
+            .arg(input)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .context("Unable to run mkvmerge")?;
 
-                match self.config.rule {
-                    QualityRule::Maximum => match self.config.mode {
-                        Mode::Bitrate => {
-                            if metric_value <= self.config.quality {
-                                if current_quality > best_quality {
-                                    best_quality = current_quality;
-                                    best_score = metric_value;
-                                }
+        if !result.status.success() {
+            return Err(anyhow!(
+                "mkvmerge returned error code {} and the following output:\n{}\n{}",
+                result.status,
+                String::from_utf8_lossy(&result.stdout),
+                String::from_utf8_lossy(&result.stderr)
+            ));
+        }
 
-                                quality_range.higher();
-                            } else {
-                                quality_range.lower();
-                            }
-                        }
-                        Mode::CRF | Mode::QP => {
-                            if metric_value <= self.config.quality {
-                                if current_quality < best_quality {
-                                    best_quality = current_quality;
-                                    best_score = metric_value;
-                                }
+        fs::rename(&temporary_output, output)
+            .with_context(|| format!("Unable to rename {temporary_output:?} to {output:?}"))?;
 
-                                quality_range.lower();
-                            } else {
-                                quality_range.higher();
-                            }
-                        }
-                    },
-                    QualityRule::Minimum => match self.config.mode {
-                        Mode::Bitrate => {
-                            if metric_value >= self.config.quality {
-                                if current_quality < best_quality {
-                                    best_quality = current_quality;
-                                    best_score = metric_value;
-                                }
+        fs::remove_file(input).with_context(|| format!("Unable to remove {input:?}"))?;
 
-                                quality_range.lower();
-                            } else {
-                                quality_range.higher();
-                            }
-                        }
-                        Mode::CRF | Mode::QP => {
-                            if metric_value >= self.config.quality {
-                                if current_quality > best_quality {
-                                    best_quality = current_quality;
-                                    best_score = metric_value;
-                                }
+        Ok(())
+    }
 
-                                quality_range.higher();
-                            } else {
-                                quality_range.lower();
-                            }
-                        }
-                    },
-                    QualityRule::Target => {
-                        let current_delta = (self.config.quality - best_score).abs();
-                        let new_delta = (self.config.quality - metric_value).abs();
+    // Failed encodes are kept with their full command line, since the tail of the output rarely
+    // says enough for a bug report.
+    fn write_failure_log(
+        &self,
+        name: &str,
+        arguments: &[String],
+        output: &EncoderOutput,
+    ) -> anyhow::Result<PathBuf> {
+        let failed_path = self.config.output_directory.join("failed");
 
-                        if new_delta < current_delta {
-                            best_quality = current_quality;
-                            best_score = metric_value;
-                        }
+        verify_directory(&failed_path)
+            .with_context(|| format!("Unable to verify failure log directory {failed_path:?}"))?;
 
-                        if (self.config.mode == Mode::Bitrate
-                            && metric_value <= self.config.quality)
-                            || (self.config.mode != Mode::Bitrate
-                                && metric_value >= self.config.quality)
-                        {
-                            quality_range.higher();
-                        } else {
-                            quality_range.lower();
-                        }
-                    }
+        let log_path = failed_path.join(format!("{name}.log"));
+
+        let command_line = std::iter::once(self.config.encoder.command())
+            .chain(arguments.iter().map(|argument| {
+                if argument.contains(char::is_whitespace) {
+                    format!("'{argument}'")
+                } else {
+                    argument.clone()
                 }
-            }
+            }))
+            .collect::<Vec<_>>()
+            .join(" ");
 
-            best_quality
-        };
+        fs::write(&log_path, format!("{command_line}\n\n{}", output.text()))
+            .with_context(|| format!("Unable to write failure log {log_path:?}"))?;
 
-        Ok((
-            self.encode_scene_single(scene, progress_bar, "", self.config.passes(), quality)
-                .with_context(|| {
-                    format!(
-                        "Unable to encode scene {:05} at quality {quality}",
-                        scene.index()
-                    )
-                })?,
-            quality,
-        ))
+        Ok(log_path)
     }
 
     #[expect(clippy::too_many_lines)]
@@ -573,6 +3815,7 @@ impl Encoder {
         scene: &Scene,
         progress_bar: &ProgressBar,
         progress_prefix: &str,
+        kind: EncodeKind,
         passes: usize,
         qp: f64,
     ) -> anyhow::Result<PathBuf> {
@@ -601,17 +3844,67 @@ impl Encoder {
             format!("{}-{qp:05.2}", self.config.mode)
         };
 
+        // Anti-banding encodes sit alongside the plain encode at the same quality.
+        let base_output_filename = if self.scene_debanded(scene) {
+            format!("{base_output_filename}-deband")
+        } else {
+            base_output_filename
+        };
+
+        let scene_preset = self.scene_preset(scene);
+
+        let base_output_filename = match &scene_preset {
+            Some(preset) => format!("{base_output_filename}-preset{preset}"),
+            None => base_output_filename,
+        };
+
         let temporary_output_filename = output_path.join(format!(
             "{base_output_filename}.tmp.{}",
             self.config.encoder.extension()
         ));
 
         let output_filename = output_path.join(format!(
+            "{base_output_filename}.{}",
+            self.config.encoder.chunk_extension()
+        ));
+
+        // Chunks from before elementary streams were wrapped are wrapped rather than re-encoded.
+        let elementary_filename = output_path.join(format!(
             "{base_output_filename}.{}",
             self.config.encoder.extension()
         ));
 
-        let stats_filename = output_path.join(format!("{base_output_filename}.stats.log"));
+        if self.config.encoder.elementary_stream()
+            && !output_filename.exists()
+            && elementary_filename.exists()
+        {
+            self.wrap_elementary_stream(scene, &elementary_filename, &output_filename)
+                .context("Unable to wrap existing elementary stream")?;
+        }
+
+        // A shared first pass was made with the run's preset, so an escalated scene makes its own.
+        let reusable_first_pass =
+            self.config.encoder.reusable_first_pass() && scene_preset.is_none();
+
+        let stats_filename = if reusable_first_pass {
+            self.first_pass_stats_path(scene)
+        } else {
+            output_path.join(format!("{base_output_filename}.stats.log"))
+        };
+
+        if let Some(stats_directory) = stats_filename.parent() {
+            verify_directory(stats_directory).with_context(|| {
+                format!("Unable to verify encoding stats directory {stats_directory:?}")
+            })?;
+        }
+
+        // A shared first pass is written under a temporary name so an interrupted pass is never
+        // mistaken for a complete one.
+        let pass_stats_filename = if reusable_first_pass && passes == 1 {
+            stats_filename.with_extension("log.tmp")
+        } else {
+            stats_filename.clone()
+        };
 
         if temporary_output_filename.exists() {
             fs::remove_file(&temporary_output_filename).with_context(|| {
@@ -620,131 +3913,293 @@ impl Encoder {
         }
 
         if !output_filename.exists() {
-            if passes > 1 {
-                self.encode_scene_single(scene, progress_bar, progress_prefix, passes - 1, qp)
-                    .with_context(|| {
-                        format!(
-                            "Unable to encode pass {} of scene {}",
-                            passes - 1,
-                            scene.index()
-                        )
-                    })?;
-            }
-
-            let input_filename = self
-                .config
-                .output_directory
-                .join("source")
-                .join(format!("scene-{:05}.mkv", scene.index()));
-
-            let mut decoder_pipe = create_child_read(
-                &input_filename,
-                None,
-                Stdio::null(),
-                Stdio::piped(),
-                Stdio::null(),
-            )
-            .context("Unable to spawn encoding video decoder subprocess")?;
-
-            let decoder_stdout = decoder_pipe.stdout.take().ok_or_else(|| {
-                anyhow!("Unable to access stdout for encoding video decoder subprocess")
-            })?;
-
-            update_worker_message(
-                progress_bar,
-                scene.index(),
-                &format!("{progress_prefix}Beginning encode..."),
-            );
+            let start = Instant::now();
 
-            #[expect(clippy::as_conversions)]
-            #[expect(clippy::cast_possible_truncation)]
-            #[expect(clippy::cast_precision_loss)]
-            #[expect(clippy::cast_sign_loss)]
-            let key_frame_interval =
-                (self.metadata.frame_count as f64 * 5.0 / self.metadata.duration).round() as usize;
-
-            let mut encoder_pipe = Command::new(self.config.encoder.command())
-                .args(self.config.encoder.arguments(
-                    &self.config,
-                    &self.config.preset,
-                    key_frame_interval,
-                    (self.config.passes() > 1).then_some(passes),
-                    &temporary_output_filename,
-                    Some(&stats_filename),
-                    self.config.mode,
+            if passes > 1 && !(reusable_first_pass && stats_filename.exists()) {
+                self.encode_scene_single(
+                    scene,
+                    progress_bar,
+                    progress_prefix,
+                    kind,
+                    passes - 1,
                     qp,
-                ))
-                .stdin(decoder_stdout)
-                .stdout(Stdio::null())
-                .stderr(Stdio::piped())
-                .spawn()
-                .context("Unable to spawn video encoding subprocess")?;
-
-            let mut encoder_stderr =
-                BufReader::new(encoder_pipe.stderr.take().ok_or_else(|| {
-                    anyhow!("Unable to access stderr for video encoder subprocess")
-                })?);
-
-            let mut buffer = Vec::with_capacity(256);
-            let mut old_buffer = VecDeque::with_capacity(32);
-
-            while let Ok(bytes) = encoder_stderr.read_until(b'\r', &mut buffer) {
-                if bytes == 0 {
-                    break;
-                }
-
-                if let Ok(line) = str::from_utf8(&buffer) {
-                    if !line.contains('\n') {
-                        update_worker_message(
-                            progress_bar,
-                            scene.index(),
-                            &format!("{progress_prefix}{line}"),
-                        );
-                    }
-
-                    old_buffer.push_back(line.to_owned());
-                }
+                )
+                .with_context(|| {
+                    format!(
+                        "Unable to encode pass {} of scene {}",
+                        passes - 1,
+                        scene.index()
+                    )
+                })?;
+            }
 
-                while old_buffer.len() > 32 {
-                    old_buffer.pop_front();
-                }
+            let pass_start = Instant::now();
 
-                buffer.clear();
-            }
+            let output = self
+                .encode_scene_attempt(
+                    scene,
+                    progress_bar,
+                    progress_prefix,
+                    passes,
+                    qp,
+                    &temporary_output_filename,
+                    &pass_stats_filename,
+                )
+                .context("Unable to run video encoder")?;
 
-            let result = encoder_pipe
-                .wait()
-                .context("Unable to wait for video encoder subprocess")?;
+            self.record_pass_timing(kind, passes, scene.length(), pass_start.elapsed());
 
-            if !result.success() {
-                return Err(anyhow!(
-                    "Encoder process exited with status {} and output {:#?}",
-                    result,
-                    &old_buffer
-                ));
+            if pass_stats_filename != stats_filename {
+                fs::rename(&pass_stats_filename, &stats_filename).with_context(|| {
+                    format!("Unable to rename {pass_stats_filename:?} to {stats_filename:?}")
+                })?;
             }
 
             if temporary_output_filename.exists() {
-                if result.success() {
+                if self.config.encoder.elementary_stream() {
+                    self.wrap_elementary_stream(
+                        scene,
+                        &temporary_output_filename,
+                        &output_filename,
+                    )
+                    .context("Unable to wrap encoded elementary stream")?;
+                } else {
                     fs::rename(&temporary_output_filename, &output_filename).with_context(
                         || {
-                            format!(
-                            "Unable to rename {temporary_output_filename:?} to {output_filename:?}"
-                        )
+                            format!("Unable to rename {temporary_output_filename:?} to {output_filename:?}")
                         },
                     )?;
-                } else {
-                    fs::remove_file(&temporary_output_filename).with_context(|| {
-                        format!("Unable to remove temporary file {temporary_output_filename:?}")
+                }
+            }
+
+            // Only complete encodes at the run's preset are recorded, as a lone pass or a slower
+            // preset would misstate the speed.
+            if passes == self.config.passes() {
+                if scene_preset.is_none() {
+                    self.record_fps(scene, start.elapsed())
+                        .context("Unable to record encoding speed")?;
+                }
+
+                // The summary is kept beside the chunk, since a resumed run has no output to parse.
+                if let Some(report) = report::parse(self.config.encoder, &output.text()) {
+                    let report_path = output_filename.with_extension("report.json");
+
+                    write_json(&report_path, &report).with_context(|| {
+                        format!("Unable to write encoder report {report_path:?}")
                     })?;
                 }
+
+                self.record_encoder_arguments(scene, &output_filename, output.command_line)
+                    .context("Unable to record encoder arguments")?;
             }
         }
 
-        if stats_filename.exists() && passes == self.config.passes() {
+        if stats_filename.exists()
+            && passes == self.config.passes()
+            && !reusable_first_pass
+            && !self.config.keep_stats
+        {
             fs::remove_file(stats_filename).context("Unable to remove encoding stats file")?;
         }
 
         Ok(output_filename)
     }
 }
+
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_possible_truncation)]
+#[expect(clippy::cast_precision_loss)]
+#[expect(clippy::cast_sign_loss)]
+fn key_frame_interval(config: &Config, metadata: &Metadata) -> usize {
+    // Segments can only be cut on key frames, so no GOP may be longer than a segment.
+    let seconds = config
+        .segment_duration
+        .map_or(5.0, |segment_duration| segment_duration.min(5.0));
+
+    let interval = metadata.frame_count as f64 * seconds / metadata.duration;
+
+    // A zero or missing duration would otherwise saturate to an enormous interval.
+    if interval.is_finite() {
+        interval.round() as usize
+    } else {
+        0
+    }
+}
+
+// Appends the files in order into one output, while any extra inputs follow as they are. GUI mode
+// prints machine-readable progress lines, which move the progress bar across its (offset, span)
+// share. Any other output is kept for the error message.
+fn run_mkvmerge(
+    output_path: &Path,
+    options: &[OsString],
+    files: &[PathBuf],
+    extra_inputs: &[OsString],
+    progress_bar: &ProgressBar,
+    (offset, span): (u64, u64),
+) -> anyhow::Result<()> {
+    let file_args = files
+        .iter()
+        .enumerate()
+        .map(|(index, path)| {
+            if index > 0 {
+                format!("+{}", path.to_string_lossy())
+            } else {
+                path.to_string_lossy().to_string()
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut merge_command = command("mkvmerge");
+    merge_command
+        .arg("--gui-mode")
+        .arg("-o")
+        .arg(output_path)
+        .args(options);
+
+    let mut merge_pipe = merge_command
+        .args(file_args)
+        .args(extra_inputs)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Unable to spawn mkvmerge")?;
+
+    let merge_stdout = merge_pipe
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Unable to access stdout for mkvmerge"))?;
+
+    let mut output = vec![];
+
+    for line in BufReader::new(merge_stdout).lines() {
+        let line = line.context("Unable to read mkvmerge output")?;
+
+        if let Some(percent) = line
+            .strip_prefix("#GUI#progress ")
+            .and_then(|progress| progress.trim().strip_suffix('%'))
+            .and_then(|percent| percent.parse::<u64>().ok())
+        {
+            progress_bar.set_position(offset + percent.min(100) * span / 100);
+            notify_progress("merge", progress_bar);
+        } else {
+            output.push(line);
+        }
+    }
+
+    let result = merge_pipe
+        .wait_with_output()
+        .context("Unable to wait for mkvmerge to finish")?;
+
+    if !result.status.success() {
+        return Err(anyhow!(
+            "mkvmerge returned error code {} and the following output:\n{}\n{}",
+            result.status,
+            output.join("\n"),
+            str::from_utf8(&result.stderr).context("Unable to parse mkvmerge output as UTF-8")?
+        ));
+    }
+
+    Ok(())
+}
+
+// Only crops that lose an alignment the source had are worth a warning, since the encoder would
+// have padded the uncropped source all the same.
+fn warn_crop_alignment(config: &Config, metadata: &Metadata) -> anyhow::Result<()> {
+    let Some((width, height)) = metadata.dimensions() else {
+        return Ok(());
+    };
+
+    let (source_width, source_height) = dimensions(&config.source)
+        .with_context(|| format!("Unable to determine dimensions of {:?}", &config.source))?;
+
+    let alignment = config.encoder.block_alignment();
+
+    let misaligned = |size: usize, source_size: usize| {
+        source_size.is_multiple_of(alignment) && !size.is_multiple_of(alignment)
+    };
+
+    if misaligned(width, source_width) || misaligned(height, source_height) {
+        warn!(
+            "Cropping {source_width}x{source_height} to {width}x{height} breaks the {alignment}-pixel alignment {} pads frames to, which can leave artifacts along the edges (--crop-round {alignment} avoids this)",
+            config.encoder
+        );
+    }
+
+    Ok(())
+}
+
+// Each line holds a scene index or an inclusive START-END frame range, followed by the target for
+// the matching scenes. A range applies to every scene it overlaps, and later lines take precedence.
+fn load_target_overrides(path: &Path, scenes: &[Scene]) -> anyhow::Result<BTreeMap<usize, f64>> {
+    let contents = fs::read_to_string(path).context("Unable to read target overrides file")?;
+    let mut overrides = BTreeMap::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+
+        let (Some(selector), Some(target), None) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(anyhow!(
+                "Line {} must contain a scene or frame range and a target",
+                number + 1
+            ));
+        };
+
+        let target = target
+            .parse::<f64>()
+            .with_context(|| format!("Invalid target {target:?} on line {}", number + 1))?;
+
+        let parse_frame = |value: &str| {
+            value
+                .parse::<usize>()
+                .with_context(|| format!("Invalid value {value:?} on line {}", number + 1))
+        };
+
+        // A scene index is treated as the frame range of that scene.
+        let (start, end) = if let Some((start, end)) = selector.split_once('-') {
+            (parse_frame(start)?, parse_frame(end)?)
+        } else {
+            let index = parse_frame(selector)?;
+
+            scenes
+                .iter()
+                .find(|scene| scene.index() == index)
+                .map_or((usize::MAX, 0), |scene| {
+                    (scene.start_frame(), scene.end_frame())
+                })
+        };
+
+        let mut matched = false;
+
+        for scene in scenes {
+            if scene.start_frame() <= end && scene.end_frame() >= start {
+                overrides.insert(scene.index(), target);
+                matched = true;
+            }
+        }
+
+        if !matched {
+            warn!(
+                "Target override {selector:?} on line {} does not match any scene",
+                number + 1
+            );
+        }
+    }
+
+    Ok(overrides)
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
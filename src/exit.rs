@@ -0,0 +1,54 @@
+use std::fmt;
+
+/// Exit code for an encode stopped by SIGINT or SIGTERM, following the shell convention for SIGINT.
+pub const INTERRUPTED: u8 = 130;
+
+/// Exit code for any failure that has not been classified.
+pub const GENERAL: u8 = 1;
+
+/// The stage a run failed in, which decides its exit code.
+///
+/// Usage errors are reported by the argument parser itself with exit code 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    Config,
+    Tool,
+    Encoder,
+    Metric,
+    Verification,
+}
+
+#[expect(clippy::min_ident_chars)]
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Config => write!(f, "The configuration is invalid"),
+            Self::Tool => write!(f, "A required external tool is missing or unusable"),
+            Self::Encoder => write!(f, "Encoding failed"),
+            Self::Metric => write!(f, "Metric calculation failed"),
+            Self::Verification => write!(f, "The output failed verification"),
+        }
+    }
+}
+
+impl Failure {
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::Config => 3,
+            Self::Tool => 4,
+            Self::Encoder => 5,
+            Self::Metric => 6,
+            Self::Verification => 7,
+        }
+    }
+}
+
+// A failure is attached as context where a stage is run, and anyhow finds it however much context
+// is added on top.
+#[must_use]
+pub fn code(error: &anyhow::Error) -> u8 {
+    error
+        .downcast_ref::<Failure>()
+        .map_or(GENERAL, |failure| failure.code())
+}
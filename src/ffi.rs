@@ -0,0 +1,220 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+
+use clap::Parser;
+
+use crate::config::Config;
+use crate::util::set_progress_callback;
+
+/// Opaque configuration handle built up from command line style arguments.
+pub struct VewConfig {
+    source: PathBuf,
+    output_directory: PathBuf,
+    arguments: Vec<String>,
+}
+
+/// Called with the stage name, the current position and the total length (or 0 if unknown).
+pub type VewProgressCallback = Option<
+    unsafe extern "C" fn(stage: *const c_char, position: u64, length: u64, user_data: *mut c_void),
+>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: &str) {
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = CString::new(message.replace('\0', " ")).ok();
+    });
+}
+
+// The caller is responsible for making user data safe to use from the encoding worker threads.
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl UserData {
+    const fn pointer(self) -> *mut c_void {
+        self.0
+    }
+}
+
+unsafe fn read_string(value: *const c_char, name: &str) -> Option<String> {
+    if value.is_null() {
+        set_last_error(&format!("{name} must not be null"));
+        return None;
+    }
+
+    // SAFETY: The caller guarantees the pointer refers to a NUL-terminated string.
+    match unsafe { CStr::from_ptr(value) }.to_str() {
+        Ok(value) => Some(value.to_owned()),
+        Err(_error) => {
+            set_last_error(&format!("{name} must be valid UTF-8"));
+            None
+        }
+    }
+}
+
+/// Creates a configuration for encoding `source` into `output_directory`. Returns null on failure.
+///
+/// # Safety
+///
+/// Both arguments must be valid NUL-terminated strings. The returned handle must be released with
+/// `vew_config_free`.
+#[no_mangle]
+pub unsafe extern "C" fn vew_config_new(
+    source: *const c_char,
+    output_directory: *const c_char,
+) -> *mut VewConfig {
+    // SAFETY: Forwarded from the caller's guarantees.
+    let (source, output_directory) = unsafe {
+        (
+            read_string(source, "source"),
+            read_string(output_directory, "output_directory"),
+        )
+    };
+
+    let (Some(source), Some(output_directory)) = (source, output_directory) else {
+        return ptr::null_mut();
+    };
+
+    Box::into_raw(Box::new(VewConfig {
+        source: source.into(),
+        output_directory: output_directory.into(),
+        arguments: vec![],
+    }))
+}
+
+/// Appends a command line argument such as `--encoder` or `svt-av1`. Returns 0 on success.
+///
+/// # Safety
+///
+/// `config` must be a handle returned by `vew_config_new` and `argument` a valid NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn vew_config_add_argument(
+    config: *mut VewConfig,
+    argument: *const c_char,
+) -> c_int {
+    // SAFETY: The caller guarantees the handle is valid and not used concurrently.
+    let Some(config) = (unsafe { config.as_mut() }) else {
+        set_last_error("config must not be null");
+        return -1;
+    };
+
+    // SAFETY: Forwarded from the caller's guarantees.
+    let Some(argument) = (unsafe { read_string(argument, "argument") }) else {
+        return -1;
+    };
+
+    config.arguments.push(argument);
+
+    0
+}
+
+/// Releases a configuration handle. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `config` must be null or a handle returned by `vew_config_new` that has not yet been freed.
+#[no_mangle]
+pub unsafe extern "C" fn vew_config_free(config: *mut VewConfig) {
+    if !config.is_null() {
+        // SAFETY: The handle was created by `Box::into_raw` in `vew_config_new`.
+        drop(unsafe { Box::from_raw(config) });
+    }
+}
+
+fn parse_config(config: &VewConfig) -> anyhow::Result<Config> {
+    let mut command_line = vec![env!("CARGO_PKG_NAME").into()];
+    command_line.extend(config.arguments.iter().map(Into::into));
+    command_line.push(config.source.clone().into_os_string());
+    command_line.push(config.output_directory.clone().into_os_string());
+
+    Ok(Config::try_parse_from(command_line)?)
+}
+
+/// Runs the full pipeline. Returns 0 on success, -1 on failure or -2 if the run panicked, with
+/// details available from `vew_last_error`.
+///
+/// # Safety
+///
+/// `config` must be a handle returned by `vew_config_new`. The callback, if provided, is invoked
+/// from worker threads and must be safe to call concurrently with `user_data`.
+#[no_mangle]
+pub unsafe extern "C" fn vew_run(
+    config: *const VewConfig,
+    callback: VewProgressCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    // SAFETY: The caller guarantees the handle is valid.
+    let Some(config) = (unsafe { config.as_ref() }) else {
+        set_last_error("config must not be null");
+        return -1;
+    };
+
+    let config = match parse_config(config) {
+        Ok(config) => config,
+        Err(error) => {
+            set_last_error(&format!("{error:?}"));
+            return -1;
+        }
+    };
+
+    let user_data = UserData(user_data);
+
+    set_progress_callback(callback.map(|callback| -> crate::util::ProgressCallback {
+        Box::new(move |stage, position, length| {
+            if let Ok(stage) = CString::new(stage) {
+                // SAFETY: The caller guarantees the callback is safe to invoke with its user data.
+                unsafe {
+                    callback(
+                        stage.as_ptr(),
+                        position,
+                        length.unwrap_or(0),
+                        user_data.pointer(),
+                    );
+                }
+            }
+        })
+    }));
+
+    // A panic must not unwind across the C boundary, so it is reported like any other failure.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| crate::run(&config)));
+    set_progress_callback(None);
+
+    match result {
+        Ok(Ok(())) => 0,
+        Ok(Err(error)) => {
+            set_last_error(&format!("{error:?}"));
+            -1
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| (*message).to_owned())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_owned());
+
+            set_last_error(&format!("Encoding panicked: {message}"));
+            -2
+        }
+    }
+}
+
+/// Returns the last error message on the calling thread, or null if there has been none. The
+/// pointer remains valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn vew_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
@@ -1,46 +1,88 @@
 use std::ffi::OsString;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
-use std::process::{Child, Command, Stdio};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
 use std::result::Result;
+use std::sync::Mutex;
 
 use anyhow::{anyhow, Context};
-use cached::{proc_macro::cached, UnboundCache};
+use cached::{Cached, SizedCache};
 use ffmpeg::codec::{context, decoder};
-use ffmpeg::{ffi, filter, format, frame, media, Error};
+use ffmpeg::{codec, color, ffi, filter, format, frame, media, Error};
 use indicatif::{ProgressBar, ProgressStyle};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
+use crate::cache::{publish, restore, shared_directory};
 use crate::config::Config;
-use crate::util::verify_filename;
+use crate::presplit;
+use crate::tools::command;
+use crate::util::{
+    file_cache_key, notify_progress, read_versioned_json, verify_filename, write_json, Versioned,
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct SourceRange {
+    pub start_time: f64,
+    pub frames: usize,
+}
 
 pub fn create_child_read(
     source: &Path,
     filter: Option<&str>,
+    pixel_format: &str,
+    range: Option<SourceRange>,
     stdin: Stdio,
     stdout: Stdio,
     stderr: Stdio,
 ) -> anyhow::Result<Child> {
-    let mut args: Vec<OsString> = vec![];
+    // Only errors are written, so a piped stderr holds the reason for a failure rather than a
+    // progress line per frame.
+    let mut args: Vec<OsString> = vec![
+        "-hide_banner".into(),
+        "-nostats".into(),
+        "-loglevel".into(),
+        "error".into(),
+    ];
+
+    if let Some(range) = range {
+        args.push("-ss".into());
+        args.push(format!("{:.6}", range.start_time).into());
+    }
 
     args.push("-i".into());
     args.push(source.into());
 
-    if let Some(filter) = filter {
+    // Formats such as yuvj420p imply full range, which converting to a plain YUV format would
+    // otherwise squeeze into limited range, so full range input is kept at full range.
+    let range_filter = full_range(source)
+        .with_context(|| format!("Unable to determine color range of {source:?}"))?
+        .then_some("scale=out_range=full");
+
+    let filter = [filter, range_filter]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(",");
+
+    if !filter.is_empty() {
         args.push("-vf".into());
         args.push(filter.into());
     }
 
+    if let Some(range) = range {
+        args.push("-frames:v".into());
+        args.push(range.frames.to_string().into());
+    }
+
     args.push("-pix_fmt".into());
-    args.push("yuv420p10le".into());
+    args.push(pixel_format.into());
     args.push("-f".into());
     args.push("yuv4mpegpipe".into());
     args.push("-strict".into());
     args.push("-1".into());
     args.push("-".into());
 
-    let child = Command::new("ffmpeg")
+    let child = command("ffmpeg")
         .args(&args)
         .stdin(stdin)
         .stdout(stdout)
@@ -53,19 +95,243 @@ pub fn create_child_read(
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Metadata {
+    #[serde(default)]
+    pub version: u32,
     pub frame_count: usize,
     pub duration: f64,
     pub crop_filter: Option<String>,
+    #[serde(default)]
+    pub source_hash: Option<String>,
+    #[serde(default)]
+    pub pixel_format: Option<String>,
+    #[serde(default)]
+    pub full_range: Option<bool>,
+    #[serde(default)]
+    pub crop_settings: Option<String>,
+}
+
+impl Versioned for Metadata {
+    const VERSION: u32 = 1;
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
 }
 
-#[cached(
-    result = true,
-    ty = "UnboundCache<String, Metadata>",
-    create = "{ UnboundCache::with_capacity(1) }",
-    convert = r#"{ format!("{}", config.source.to_string_lossy()) }"#
-)]
+impl Metadata {
+    // The crop filter is always of the form crop=width:height:x:y.
+    #[must_use]
+    pub fn dimensions(&self) -> Option<(usize, usize)> {
+        let mut fields = self
+            .crop_filter
+            .as_deref()?
+            .strip_prefix("crop=")?
+            .split(':');
+
+        Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+    }
+}
+
+// Returns the frame rate of the best video stream as a numerator and denominator.
+pub fn frame_rate(path: &Path) -> anyhow::Result<(i32, i32)> {
+    let input_context =
+        format::input(path).with_context(|| format!("Unable to open {path:?} with FFmpeg"))?;
+
+    let input = input_context
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(Error::StreamNotFound)
+        .with_context(|| format!("Unable to find video stream in {path:?}"))?;
+
+    let rate = if input.avg_frame_rate() > ffmpeg::Rational(0, 1) {
+        input.avg_frame_rate()
+    } else {
+        input.rate()
+    };
+
+    Ok((rate.numerator(), rate.denominator()))
+}
+
+// Returns the bit depth of the best video stream, read from the trailing component size in its
+// pixel format name, such as yuv420p10le. Packed formats such as rgb48le give the total size of
+// three or four components.
+pub fn bit_depth(path: &Path) -> anyhow::Result<u8> {
+    let name = pixel_format(path)?;
+
+    let Some(sized) = name.strip_suffix("le").or_else(|| name.strip_suffix("be")) else {
+        return Ok(8);
+    };
+
+    let digits = sized
+        .chars()
+        .rev()
+        .take_while(char::is_ascii_digit)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect::<String>();
+
+    Ok(match digits.parse::<u8>() {
+        Ok(depth @ 9..=16) => depth,
+        Ok(48 | 64) => 16,
+        _ => 8,
+    })
+}
+
+// Returns the name of the pixel format of the best video stream.
+pub fn pixel_format(path: &Path) -> anyhow::Result<String> {
+    let input_context =
+        format::input(path).with_context(|| format!("Unable to open {path:?} with FFmpeg"))?;
+
+    let input = input_context
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(Error::StreamNotFound)
+        .with_context(|| format!("Unable to find video stream in {path:?}"))?;
+
+    let decoder = context::Context::from_parameters(input.parameters())
+        .context("Unable to create FFmpeg decoder context")?
+        .decoder()
+        .video()
+        .context("Unable to access FFmpeg decoder video")?;
+
+    let name = decoder
+        .format()
+        .descriptor()
+        .ok_or_else(|| anyhow!("Unable to determine pixel format of {path:?}"))?
+        .name();
+
+    Ok(name.to_owned())
+}
+
+// Sources that do not signal their range are treated as limited range, as players treat them.
+pub fn full_range(path: &Path) -> anyhow::Result<bool> {
+    let input_context =
+        format::input(path).with_context(|| format!("Unable to open {path:?} with FFmpeg"))?;
+
+    let input = input_context
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(Error::StreamNotFound)
+        .with_context(|| format!("Unable to find video stream in {path:?}"))?;
+
+    let decoder = context::Context::from_parameters(input.parameters())
+        .context("Unable to create FFmpeg decoder context")?
+        .decoder()
+        .video()
+        .context("Unable to access FFmpeg decoder video")?;
+
+    Ok(decoder.color_range() == color::Range::JPEG)
+}
+
+// Returns the frame dimensions of the best video stream if it uses a codec in which every frame is
+// a key frame, and `None` otherwise.
+pub fn intra_only_dimensions(path: &Path) -> anyhow::Result<Option<(usize, usize)>> {
+    let input_context =
+        format::input(path).with_context(|| format!("Unable to open {path:?} with FFmpeg"))?;
+
+    let input = input_context
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(Error::StreamNotFound)
+        .with_context(|| format!("Unable to find video stream in {path:?}"))?;
+
+    if !matches!(
+        input.parameters().id(),
+        codec::Id::FFV1
+            | codec::Id::PRORES
+            | codec::Id::DNXHD
+            | codec::Id::UTVIDEO
+            | codec::Id::HUFFYUV
+            | codec::Id::FFVHUFF
+            | codec::Id::MAGICYUV
+            | codec::Id::RAWVIDEO
+            | codec::Id::V210
+    ) {
+        return Ok(None);
+    }
+
+    dimensions(path).map(Some)
+}
+
+// Returns the frame dimensions of the best video stream.
+pub fn dimensions(path: &Path) -> anyhow::Result<(usize, usize)> {
+    let input_context =
+        format::input(path).with_context(|| format!("Unable to open {path:?} with FFmpeg"))?;
+
+    let input = input_context
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(Error::StreamNotFound)
+        .with_context(|| format!("Unable to find video stream in {path:?}"))?;
+
+    let decoder = context::Context::from_parameters(input.parameters())
+        .context("Unable to create FFmpeg decoder context")?
+        .decoder()
+        .video()
+        .context("Unable to access FFmpeg decoder video")?;
+
+    Ok((
+        usize::try_from(decoder.width()).context("Unable to convert frame width")?,
+        usize::try_from(decoder.height()).context("Unable to convert frame height")?,
+    ))
+}
+
+static METADATA_CACHE: Mutex<Option<SizedCache<String, Metadata>>> = Mutex::new(None);
+
+// The output directory is part of the key since each one has its own metadata cache.
+fn metadata_cache_key(config: &Config) -> String {
+    format!(
+        "{}:{}",
+        file_cache_key(&config.source),
+        config.output_directory.to_string_lossy()
+    )
+}
+
+// The in-memory cache is bounded so that library users processing many sources in one process do
+// not accumulate metadata for every one of them.
 pub fn get_metadata(config: &Config) -> anyhow::Result<Metadata> {
-    let json_path = config.output_directory.join("config").join("metadata.json");
+    if config.metadata_cache_size == 0 {
+        return load_metadata(config);
+    }
+
+    let key = metadata_cache_key(config);
+
+    let cached = METADATA_CACHE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .as_mut()
+        .and_then(|cache| cache.cache_get(&key).cloned());
+
+    if let Some(metadata) = cached {
+        return Ok(metadata);
+    }
+
+    let metadata = load_metadata(config)?;
+
+    let mut cache = METADATA_CACHE
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    if cache.as_ref().and_then(Cached::cache_capacity) != Some(config.metadata_cache_size) {
+        *cache = Some(SizedCache::with_size(config.metadata_cache_size));
+    }
+
+    if let Some(cache) = cache.as_mut() {
+        cache.cache_set(key, metadata.clone());
+    }
+
+    drop(cache);
+
+    Ok(metadata)
+}
+
+fn load_metadata(config: &Config) -> anyhow::Result<Metadata> {
+    let json_path = config.config_directory().join("metadata.json");
 
     verify_filename(&json_path)
         .with_context(|| format!("Unable to verify metadata cache file {json_path:?}"))?;
@@ -77,14 +343,39 @@ pub fn get_metadata(config: &Config) -> anyhow::Result<Metadata> {
             .context("Unable to create metadata progress bar style")?,
     );
 
-    let metadata = if json_path.exists() {
-        let file = File::open(&json_path)
-            .with_context(|| format!("Unable to open metadata cache file {json_path:?}"))?;
-        let reader = BufReader::new(file);
+    let source_hash = presplit::source_hash(config).context("Unable to hash source")?;
 
-        let metadata: Metadata = serde_json::from_reader(reader)
-            .with_context(|| format!("Unable to deserialize metadata cache from {json_path:?}"))?;
+    let shared_json_path = shared_directory(config)
+        .context("Unable to determine shared cache directory")?
+        .map(|directory| directory.join("metadata.json"));
 
+    restore(&json_path, shared_json_path.as_deref())
+        .context("Unable to restore metadata from shared cache")?;
+
+    let cached_metadata: Option<Metadata> =
+        read_versioned_json(&json_path).context("Unable to read metadata cache")?;
+
+    // Caches written before source hashing existed are assumed to belong to the current source, and
+    // those written before crop settings were recorded used the original fixed rounding.
+    let cached_metadata = cached_metadata.and_then(|metadata| match &metadata.source_hash {
+        Some(hash) if *hash != source_hash => {
+            warn!("Source file has changed since metadata was cached. Regenerating metadata.");
+            None
+        }
+        _ if !config.pre_split
+            && metadata
+                .crop_settings
+                .as_deref()
+                .unwrap_or(ORIGINAL_CROP_SETTINGS)
+                != crop_settings(config) =>
+        {
+            warn!("Crop settings have changed since metadata was cached. Regenerating metadata.");
+            None
+        }
+        _ => Some(metadata),
+    });
+
+    let metadata = if let Some(mut metadata) = cached_metadata {
         progress_bar.set_position(
             metadata
                 .frame_count
@@ -97,17 +388,49 @@ pub fn get_metadata(config: &Config) -> anyhow::Result<Metadata> {
             metadata.crop_filter.as_deref().unwrap_or("None")
         ));
 
+        if metadata.source_hash.is_none() {
+            metadata.source_hash = Some(source_hash);
+            write_metadata(&json_path, &metadata)?;
+        }
+
+        // Caches written before the pixel format or range was recorded only need a quick probe.
+        if metadata.pixel_format.is_none() {
+            metadata.pixel_format = Some(source_pixel_format(config)?);
+            write_metadata(&json_path, &metadata)?;
+        }
+
+        if metadata.full_range.is_none() {
+            metadata.full_range = Some(source_full_range(config)?);
+            write_metadata(&json_path, &metadata)?;
+        }
+
+        if shared_json_path.as_ref().is_some_and(|path| !path.exists()) {
+            publish(&json_path, shared_json_path.as_deref())
+                .context("Unable to publish metadata to shared cache")?;
+        }
+
         metadata
     } else {
-        let metadata =
-            read_metadata(config, &progress_bar).context("Unable to read video metadata")?;
+        // Pre-split chunks already know their frame counts, so nothing needs to be decoded.
+        let mut metadata = if config.pre_split {
+            let metadata = presplit::metadata(config).context("Unable to read chunk metadata")?;
 
-        serde_json::to_writer_pretty(
-            &File::create(&json_path)
-                .with_context(|| format!("Unable to create metadata cache file {json_path:?}"))?,
-            &metadata,
-        )
-        .with_context(|| format!("Unable to serialize metadata cache to {json_path:?}"))?;
+            progress_bar.set_position(metadata.frame_count.try_into().unwrap_or(u64::MAX));
+            progress_bar.finish_with_message("None (pre-split)");
+
+            metadata
+        } else {
+            read_metadata(config, &progress_bar).context("Unable to read video metadata")?
+        };
+
+        metadata.source_hash = Some(source_hash);
+        metadata.crop_settings = Some(crop_settings(config));
+        metadata.pixel_format = Some(source_pixel_format(config)?);
+        metadata.full_range = Some(source_full_range(config)?);
+        write_metadata(&json_path, &metadata)?;
+
+        publish(&json_path, shared_json_path.as_deref())
+            .context("Unable to publish metadata to shared cache")?;
 
         metadata
     };
@@ -115,9 +438,76 @@ pub fn get_metadata(config: &Config) -> anyhow::Result<Metadata> {
     Ok(metadata)
 }
 
+// Pre-split chunks share one format, so the first stands in for the source.
+fn probed_source(config: &Config) -> anyhow::Result<PathBuf> {
+    if config.pre_split {
+        presplit::chunk(config, 0)
+    } else {
+        Ok(config.source.clone())
+    }
+}
+
+fn source_pixel_format(config: &Config) -> anyhow::Result<String> {
+    let source = probed_source(config)?;
+
+    pixel_format(&source).with_context(|| format!("Unable to determine pixel format of {source:?}"))
+}
+
+fn source_full_range(config: &Config) -> anyhow::Result<bool> {
+    let source = probed_source(config)?;
+
+    full_range(&source).with_context(|| format!("Unable to determine color range of {source:?}"))
+}
+
+const ORIGINAL_CROP_SETTINGS: &str = "round=4:margin=0";
+
+fn crop_settings(config: &Config) -> String {
+    format!("round={}:margin={}", config.crop_round, config.crop_margin)
+}
+
+// The margin is taken from every edge, after which the size is rounded down again and the crop is
+// re-centered on even offsets, as 4:2:0 chroma requires.
+#[expect(clippy::integer_division)]
+fn crop_with_margin(crop_filter: &str, margin: usize, round: usize) -> anyhow::Result<String> {
+    let fields = crop_filter
+        .strip_prefix("crop=")
+        .ok_or_else(|| anyhow!("Malformed crop filter {crop_filter:?}"))?
+        .split(':')
+        .map(str::parse::<usize>)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Unable to parse crop filter {crop_filter:?}"))?;
+
+    let [width, height, x, y] = fields[..] else {
+        return Err(anyhow!("Malformed crop filter {crop_filter:?}"));
+    };
+
+    let shrink = |size: usize, offset: usize| -> anyhow::Result<(usize, usize)> {
+        let cropped = size.saturating_sub(margin * 2) / round * round;
+
+        if cropped == 0 {
+            return Err(anyhow!(
+                "A crop margin of {margin} pixels leaves nothing of {crop_filter}"
+            ));
+        }
+
+        Ok((cropped, offset + (size - cropped) / 4 * 2))
+    };
+
+    let (width, x) = shrink(width, x)?;
+    let (height, y) = shrink(height, y)?;
+
+    Ok(format!("crop={width}:{height}:{x}:{y}"))
+}
+
+fn write_metadata(json_path: &Path, metadata: &Metadata) -> anyhow::Result<()> {
+    write_json(json_path, metadata)
+        .with_context(|| format!("Unable to write metadata cache {json_path:?}"))
+}
+
 fn create_cropdetect_filter_graph(
     decoder: &decoder::Video,
     time_base: ffmpeg::Rational,
+    round: usize,
 ) -> anyhow::Result<filter::Graph> {
     let mut filter = filter::Graph::new();
 
@@ -157,7 +547,7 @@ fn create_cropdetect_filter_graph(
         .context("Unable to initialize FFmpeg filter graph input")?
         .input("out", 0)
         .context("Unable to initialize FFmpeg filter graph output")?
-        .parse("cropdetect=round=4")
+        .parse(&format!("cropdetect=round={round}"))
         .context("Unable to add cropdetect filter to FFmpeg filter graph")?;
 
     filter
@@ -192,7 +582,7 @@ fn read_metadata(config: &Config, progress_bar: &ProgressBar) -> anyhow::Result<
         )
     };
 
-    let mut filter = create_cropdetect_filter_graph(&decoder, time_base)
+    let mut filter = create_cropdetect_filter_graph(&decoder, time_base, config.crop_round)
         .context("Unable to create FFmpeg crop detection filter graph")?;
 
     let mut frame_count: usize = 0;
@@ -207,6 +597,8 @@ fn read_metadata(config: &Config, progress_bar: &ProgressBar) -> anyhow::Result<
         progress_bar.inc(1);
 
         if packet.is_key() {
+            notify_progress("metadata", progress_bar);
+
             decoder
                 .send_packet(&packet)
                 .context("Unable to decode video packet")?;
@@ -258,13 +650,26 @@ fn read_metadata(config: &Config, progress_bar: &ProgressBar) -> anyhow::Result<
         }
     }
 
+    let crop_filter = match crop_filter {
+        Some(crop_filter) if config.crop_margin > 0 => Some(
+            crop_with_margin(&crop_filter, config.crop_margin, config.crop_round)
+                .context("Unable to apply crop margin")?,
+        ),
+        crop_filter => crop_filter,
+    };
+
     progress_bar.finish();
 
     #[expect(clippy::as_conversions)]
     #[expect(clippy::cast_precision_loss)]
     Ok(Metadata {
+        version: Metadata::VERSION,
         frame_count,
         duration: duration as f64 / f64::from(ffi::AV_TIME_BASE),
         crop_filter,
+        source_hash: None,
+        pixel_format: None,
+        full_range: None,
+        crop_settings: None,
     })
 }
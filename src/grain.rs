@@ -0,0 +1,109 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::str;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::control::track;
+use crate::ffmpeg::{create_child_read, SourceRange};
+use crate::tools::command;
+use crate::util::{read_json, verify_filename, write_json};
+
+// Grain is mostly temporal noise, so only the temporal strengths of the denoiser are used.
+const DENOISE_FILTER: &str = "hqdn3d=0:0:8:8";
+
+// A scene identical to its denoised version would otherwise score infinity, which JSON cannot hold.
+const MAXIMUM_SCORE: f64 = 100.0;
+
+/// Grain analysis of a single scene and the resulting encoder tuning decision.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct GrainDecision {
+    pub score: f64,
+    pub grain_heavy: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GrainCache {
+    score: f64,
+}
+
+// The score is the luma PSNR between a scene and a temporally denoised copy of itself, in dB. The
+// more grain the denoiser removes, the lower the score.
+pub fn analyze(
+    input: &Path,
+    filter: Option<&str>,
+    range: Option<SourceRange>,
+    cache_path: &Path,
+) -> anyhow::Result<f64> {
+    verify_filename(cache_path)
+        .with_context(|| format!("Unable to verify grain analysis cache path {cache_path:?}"))?;
+
+    if let Some(cache) = read_json::<GrainCache>(cache_path)
+        .with_context(|| format!("Unable to read grain analysis cache {cache_path:?}"))?
+    {
+        return Ok(cache.score);
+    }
+
+    let mut decoder = create_child_read(
+        input,
+        filter,
+        "yuv420p10le",
+        range,
+        Stdio::null(),
+        Stdio::piped(),
+        Stdio::null(),
+    )
+    .context("Unable to spawn grain analysis decoder subprocess")?;
+
+    let decoder_tracker = track(&decoder);
+
+    let decoder_stdout = decoder
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Unable to access stdout for grain analysis decoder"))?;
+
+    let child = command("ffmpeg")
+        .args(["-f", "yuv4mpegpipe", "-i", "-", "-lavfi"])
+        .arg(format!(
+            "split[source][copy];[copy]{DENOISE_FILTER}[denoised];[source][denoised]psnr"
+        ))
+        .args(["-f", "null", "-"])
+        .stdin(decoder_stdout)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Unable to spawn grain analysis subprocess")?;
+
+    let tracker = track(&child);
+
+    let result = tracker
+        .wait_with_output(child)
+        .context("Unable to wait for grain analysis subprocess")?;
+
+    decoder_tracker
+        .wait(&mut decoder)
+        .context("Unable to wait for grain analysis decoder subprocess")?;
+
+    let output = str::from_utf8(&result.stderr)
+        .context("Unable to decode grain analysis output as UTF-8")?;
+
+    if !result.status.success() {
+        return Err(anyhow!(
+            "Grain analysis subprocess did not complete successfully: {output}"
+        ));
+    }
+
+    let score = output
+        .lines()
+        .filter_map(|line| line.split_once("PSNR y:"))
+        .filter_map(|(_, values)| values.split_whitespace().next())
+        .find_map(|value| value.parse::<f64>().ok())
+        .ok_or_else(|| anyhow!("Unable to find PSNR in grain analysis output"))?
+        .min(MAXIMUM_SCORE);
+
+    write_json(cache_path, &GrainCache { score })
+        .with_context(|| format!("Unable to write grain analysis cache {cache_path:?}"))?;
+
+    Ok(score)
+}
@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use statrs::statistics::{Data, Distribution};
+
+use crate::scenes::Scene;
+use crate::util::{read_json, write_json};
+
+/// The encoding speed realized for each scene, keyed by encoder and preset.
+///
+/// Every encode of a source shares one history, so a run at a new preset can learn which scenes
+/// are slow from the runs that came before it.
+#[derive(Default, Serialize, Deserialize)]
+pub struct FpsHistory {
+    runs: BTreeMap<String, BTreeMap<usize, f64>>,
+}
+
+impl FpsHistory {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(read_json(path)
+            .with_context(|| format!("Unable to read FPS history {path:?}"))?
+            .unwrap_or_default())
+    }
+
+    pub fn record(&mut self, key: &str, scene_index: usize, fps: f64) {
+        if fps.is_finite() && fps > 0.0 {
+            self.runs
+                .entry(key.to_owned())
+                .or_default()
+                .insert(scene_index, fps);
+        }
+    }
+
+    // A scene's speed relative to the rest of its run cancels out the overall speed of the encoder
+    // and preset, so relative speeds from different runs can be compared and averaged. A run at
+    // the requested settings is preferred when it has seen the scene.
+    fn relative_speed(
+        &self,
+        means: &BTreeMap<&str, f64>,
+        key: &str,
+        scene_index: usize,
+    ) -> Option<f64> {
+        let relative = |(run_key, scenes): (&String, &BTreeMap<usize, f64>)| {
+            let mean = means.get(run_key.as_str())?;
+            scenes.get(&scene_index).map(|fps| fps / mean)
+        };
+
+        if let Some(speed) = self.runs.get_key_value(key).and_then(relative) {
+            return Some(speed);
+        }
+
+        Data::new(self.runs.iter().filter_map(relative).collect::<Vec<_>>()).mean()
+    }
+
+    // The expected encoding time of each scene in arbitrary units, keyed by scene index. Scenes
+    // without any history are assumed to encode at an average speed, which reduces to ordering by
+    // length. The mean speed of every run is only calculated once for all of the scenes.
+    #[must_use]
+    pub fn costs(&self, key: &str, scenes: &[Scene]) -> BTreeMap<usize, f64> {
+        let means = self
+            .runs
+            .iter()
+            .filter_map(|(run_key, scenes)| {
+                let mean = Data::new(scenes.values().copied().collect::<Vec<_>>()).mean()?;
+                Some((run_key.as_str(), mean))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        scenes
+            .iter()
+            .map(|scene| {
+                #[expect(clippy::as_conversions)]
+                #[expect(clippy::cast_precision_loss)]
+                let length = scene.length() as f64;
+
+                let speed = self
+                    .relative_speed(&means, key, scene.index())
+                    .filter(|speed| *speed > 0.0)
+                    .unwrap_or(1.0);
+
+                (scene.index(), length / speed)
+            })
+            .collect()
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        write_json(path, self).with_context(|| format!("Unable to write FPS history {path:?}"))
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::Path;
+use std::result::Result;
+
+use anyhow::Context;
+use ffmpeg::{format, media, Error};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::scenes::{get, Scene};
+use crate::util::generate_bitrate_chart;
+
+// Key frames are always the first packet of a closed GOP, so counting packets in decode order
+// gives their display position even when the encoder uses B-frames.
+fn read_key_frames(path: &Path) -> anyhow::Result<(Vec<usize>, usize)> {
+    let mut input_context =
+        format::input(&path).with_context(|| format!("Unable to open {path:?} with FFmpeg"))?;
+
+    let stream_index = input_context
+        .streams()
+        .best(media::Type::Video)
+        .ok_or(Error::StreamNotFound)
+        .with_context(|| format!("Unable to find video stream in {path:?}"))?
+        .index();
+
+    let mut key_frames = vec![];
+    let mut frame_count = 0;
+
+    for (_stream, packet) in input_context
+        .packets()
+        .filter_map(Result::ok)
+        .filter(|(stream, _packet)| stream.index() == stream_index)
+    {
+        if packet.is_key() {
+            key_frames.push(frame_count);
+        }
+
+        frame_count += 1;
+    }
+
+    Ok((key_frames, frame_count))
+}
+
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+pub fn report(
+    config: &Config,
+    output_path: &Path,
+    key_frame_interval: usize,
+) -> anyhow::Result<()> {
+    let (key_frames, frame_count) = read_key_frames(output_path)
+        .with_context(|| format!("Unable to read key frames from {output_path:?}"))?;
+
+    if key_frames.is_empty() {
+        warn!("No key frames were found in {output_path:?}");
+        return Ok(());
+    }
+
+    let scenes = get(config).context("Unable to fetch scene data")?;
+
+    let missing_scenes = scenes
+        .iter()
+        .filter(|scene| key_frames.binary_search(&scene.start_frame()).is_err())
+        .map(Scene::index)
+        .collect::<Vec<_>>();
+
+    if !missing_scenes.is_empty() {
+        warn!(
+            "{} scene(s) do not begin with a key frame: {missing_scenes:?}",
+            missing_scenes.len()
+        );
+    }
+
+    let gop_lengths = key_frames
+        .iter()
+        .zip(key_frames.iter().skip(1).chain([&frame_count]))
+        .map(|(start, end)| end - start)
+        .collect::<Vec<_>>();
+
+    let oversized = gop_lengths
+        .iter()
+        .filter(|&&length| length > key_frame_interval)
+        .count();
+
+    if oversized > 0 {
+        warn!(
+            "{oversized} GOP(s) exceed the configured key frame interval of {key_frame_interval} frames (longest: {} frames)",
+            gop_lengths.iter().max().unwrap_or(&0)
+        );
+    }
+
+    let gop_series = gop_lengths.iter().map(|&length| length as f64).collect();
+    let interval_series = vec![key_frame_interval as f64; gop_lengths.len()];
+
+    generate_bitrate_chart(
+        &config
+            .output_directory
+            .join("output")
+            .join(format!("{}-keyframes.svg", config.encode_identifier(true))),
+        "GOP Length (frames)",
+        0,
+        &vec![
+            ("GOP Length".to_owned(), &gop_series),
+            ("Key Frame Interval".to_owned(), &interval_series),
+        ],
+        &[],
+    )
+    .context("Unable to generate key frame chart")?;
+
+    Ok(())
+}
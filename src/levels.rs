@@ -0,0 +1,286 @@
+use anyhow::anyhow;
+
+use crate::config::{Config, Encoder, Mode};
+use crate::ffmpeg::Metadata;
+
+type Level = (&'static str, u64, u64, u64, u64);
+
+// Each entry is the level, the maximum luma picture size, the maximum luma sample rate, and the
+// maximum bitrate and coded picture buffer size in kbps and kbits for the lowest profile and tier.
+// H.264 limits are specified in macroblocks and have been converted to samples.
+const H264_LEVELS: [Level; 20] = [
+    ("1", 25_344, 380_160, 64, 175),
+    ("1b", 25_344, 380_160, 128, 350),
+    ("1.1", 101_376, 768_000, 192, 500),
+    ("1.2", 101_376, 1_536_000, 384, 1_000),
+    ("1.3", 101_376, 3_041_280, 768, 2_000),
+    ("2", 101_376, 3_041_280, 2_000, 2_000),
+    ("2.1", 202_752, 5_068_800, 4_000, 4_000),
+    ("2.2", 414_720, 5_184_000, 4_000, 4_000),
+    ("3", 414_720, 10_368_000, 10_000, 10_000),
+    ("3.1", 921_600, 27_648_000, 14_000, 14_000),
+    ("3.2", 1_310_720, 55_296_000, 20_000, 20_000),
+    ("4", 2_097_152, 62_914_560, 20_000, 25_000),
+    ("4.1", 2_097_152, 62_914_560, 50_000, 62_500),
+    ("4.2", 2_228_224, 133_693_440, 50_000, 62_500),
+    ("5", 5_652_480, 150_994_944, 135_000, 135_000),
+    ("5.1", 9_437_184, 251_658_240, 240_000, 240_000),
+    ("5.2", 9_437_184, 530_841_600, 240_000, 240_000),
+    ("6", 35_651_584, 1_069_547_520, 240_000, 240_000),
+    ("6.1", 35_651_584, 2_139_095_040, 480_000, 480_000),
+    ("6.2", 35_651_584, 4_278_190_080, 800_000, 800_000),
+];
+
+const HEVC_LEVELS: [Level; 13] = [
+    ("1", 36_864, 552_960, 128, 350),
+    ("2", 122_880, 3_686_400, 1_500, 1_500),
+    ("2.1", 245_760, 7_372_800, 3_000, 3_000),
+    ("3", 552_960, 16_588_800, 6_000, 6_000),
+    ("3.1", 983_040, 33_177_600, 10_000, 10_000),
+    ("4", 2_228_224, 66_846_720, 12_000, 12_000),
+    ("4.1", 2_228_224, 133_693_440, 20_000, 20_000),
+    ("5", 8_912_896, 267_386_880, 25_000, 25_000),
+    ("5.1", 8_912_896, 534_773_760, 40_000, 40_000),
+    ("5.2", 8_912_896, 1_069_547_520, 60_000, 60_000),
+    ("6", 35_651_584, 1_069_547_520, 60_000, 60_000),
+    ("6.1", 35_651_584, 2_139_095_040, 120_000, 120_000),
+    ("6.2", 35_651_584, 4_278_190_080, 240_000, 240_000),
+];
+
+const VP9_LEVELS: [Level; 14] = [
+    ("1", 36_864, 829_440, 200, 400),
+    ("1.1", 73_728, 2_764_800, 800, 1_000),
+    ("2", 122_880, 4_608_000, 1_800, 1_500),
+    ("2.1", 245_760, 9_216_000, 3_600, 2_800),
+    ("3", 552_960, 20_736_000, 7_200, 6_000),
+    ("3.1", 983_040, 36_864_000, 12_000, 10_000),
+    ("4", 2_228_224, 83_558_400, 18_000, 16_000),
+    ("4.1", 2_228_224, 160_432_128, 30_000, 18_000),
+    ("5", 8_912_896, 311_951_360, 60_000, 36_000),
+    ("5.1", 8_912_896, 588_251_136, 120_000, 46_000),
+    ("5.2", 8_912_896, 1_176_502_272, 180_000, 90_000),
+    ("6", 35_651_584, 1_176_502_272, 180_000, 90_000),
+    ("6.1", 35_651_584, 2_353_004_544, 240_000, 180_000),
+    ("6.2", 35_651_584, 4_706_009_088, 480_000, 360_000),
+];
+
+// AV1 has no separate buffer size, since the decoder model allows one second at the maximum
+// bitrate.
+const AV1_LEVELS: [Level; 14] = [
+    ("2.0", 147_456, 4_423_680, 1_500, 1_500),
+    ("2.1", 278_784, 8_363_520, 3_000, 3_000),
+    ("3.0", 665_856, 19_975_680, 6_000, 6_000),
+    ("3.1", 1_065_024, 31_950_720, 10_000, 10_000),
+    ("4.0", 2_359_296, 77_856_768, 12_000, 12_000),
+    ("4.1", 2_359_296, 155_713_536, 20_000, 20_000),
+    ("5.0", 8_912_896, 273_715_200, 30_000, 30_000),
+    ("5.1", 8_912_896, 547_430_400, 40_000, 40_000),
+    ("5.2", 8_912_896, 1_094_860_800, 60_000, 60_000),
+    ("5.3", 8_912_896, 1_176_502_272, 60_000, 60_000),
+    ("6.0", 35_651_584, 1_176_502_272, 60_000, 60_000),
+    ("6.1", 35_651_584, 2_189_721_600, 100_000, 100_000),
+    ("6.2", 35_651_584, 4_379_443_200, 160_000, 160_000),
+    ("6.3", 35_651_584, 4_706_009_088, 160_000, 160_000),
+];
+
+const fn level_table(encoder: Encoder) -> Option<&'static [Level]> {
+    match encoder {
+        Encoder::X264 => Some(&H264_LEVELS),
+        Encoder::X265 => Some(&HEVC_LEVELS),
+        Encoder::Vpxenc => Some(&VP9_LEVELS),
+        Encoder::Aomenc | Encoder::Rav1e | Encoder::SvtAv1 => Some(&AV1_LEVELS),
+        Encoder::Ffv1 | Encoder::Prores | Encoder::Dnxhr => None,
+    }
+}
+
+// Every encode is 10-bit 4:2:0, so only profiles that can carry that are accepted.
+const fn profiles(encoder: Encoder) -> &'static [&'static str] {
+    match encoder {
+        Encoder::X264 => &["high10", "high422", "high444"],
+        Encoder::X265 => &["main10", "main422-10", "main444-10"],
+        Encoder::Vpxenc => &["2"],
+        Encoder::Aomenc | Encoder::SvtAv1 => &["main", "high", "professional"],
+        Encoder::Rav1e => &["main"],
+        Encoder::Ffv1 | Encoder::Prores | Encoder::Dnxhr => &[],
+    }
+}
+
+// Higher profiles and tiers raise the bitrate and buffer limits of each level by a fixed factor.
+// Without an explicit profile, x264 picks high10 for 10-bit output and the others their lowest.
+fn bitrate_factor(encoder: Encoder, profile: Option<&str>) -> f64 {
+    match (encoder, profile) {
+        (Encoder::X264, Some("high422" | "high444")) => 4.0,
+        (Encoder::X264, _) | (Encoder::Aomenc | Encoder::SvtAv1, Some("professional")) => 3.0,
+        (Encoder::X265, Some("main422-10")) => 1.833,
+        (Encoder::X265, Some("main444-10")) => 2.2,
+        (Encoder::Aomenc | Encoder::SvtAv1, Some("high")) => 2.0,
+        _ => 1.0,
+    }
+}
+
+// Levels are compared numerically so that, for example, AV1 accepts both 5.1 and 5.10.
+fn find_level(encoder: Encoder, level: &str) -> Option<Level> {
+    let table = level_table(encoder)?;
+
+    table.iter().copied().find(|(name, _, _, _, _)| {
+        *name == level
+            || matches!(
+                (name.parse::<f64>(), level.parse::<f64>()),
+                (Ok(name), Ok(level)) if (name - level).abs() < f64::EPSILON
+            )
+    })
+}
+
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_possible_truncation)]
+#[expect(clippy::cast_precision_loss)]
+#[expect(clippy::cast_sign_loss)]
+pub fn validate(config: &Config, metadata: &Metadata) -> anyhow::Result<()> {
+    if config.encoder.is_mezzanine() && (config.profile.is_some() || config.level.is_some()) {
+        return Err(anyhow!(
+            "Profiles and levels do not apply to the {} mezzanine encoder",
+            config.encoder
+        ));
+    }
+
+    if let Some(profile) = &config.profile {
+        let supported = profiles(config.encoder);
+
+        if !supported.contains(&profile.as_str()) {
+            return Err(anyhow!(
+                "Profile {profile} is not available for 10-bit 4:2:0 output with {} (supported: {})",
+                config.encoder,
+                supported.join(", ")
+            ));
+        }
+    }
+
+    let Some(level) = &config.level else {
+        return Ok(());
+    };
+
+    let (_name, maximum_picture_size, maximum_sample_rate, _, _) =
+        find_level(config.encoder, level)
+            .ok_or_else(|| anyhow!("Level {level} is not a valid level for {}", config.encoder))?;
+
+    // A level also limits the bitrate, which only some encoders can be held to, and only outside
+    // of constant quantizer encoding.
+    match (config.encoder, config.mode) {
+        (Encoder::X264 | Encoder::X265, Mode::CRF | Mode::Bitrate)
+        | (Encoder::SvtAv1, Mode::CRF) => {}
+        (Encoder::X264 | Encoder::X265 | Encoder::SvtAv1, mode) => {
+            return Err(anyhow!(
+                "Level {level} cannot cap the bitrate of {} in {mode} mode",
+                config.encoder
+            ));
+        }
+        (encoder, _) => {
+            return Err(anyhow!(
+                "Level {level} cannot be enforced with {encoder}, which has no bitrate cap"
+            ));
+        }
+    }
+
+    let (width, height) = metadata
+        .dimensions()
+        .ok_or_else(|| anyhow!("Unable to check level {level} without the frame dimensions"))?;
+
+    let picture_size = (width * height) as u64;
+    let frame_rate = metadata.frame_count as f64 / metadata.duration;
+    let sample_rate = (picture_size as f64 * frame_rate).ceil() as u64;
+
+    if picture_size > maximum_picture_size {
+        return Err(anyhow!(
+            "A {width}x{height} frame exceeds the maximum picture size of {maximum_picture_size} samples for level {level}"
+        ));
+    }
+
+    if sample_rate > maximum_sample_rate {
+        return Err(anyhow!(
+            "{width}x{height} at {frame_rate:.3} FPS exceeds the maximum sample rate of {maximum_sample_rate} samples per second for level {level}"
+        ));
+    }
+
+    Ok(())
+}
+
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_possible_truncation)]
+#[expect(clippy::cast_precision_loss)]
+#[expect(clippy::cast_sign_loss)]
+#[must_use]
+pub fn arguments(config: &Config) -> Vec<String> {
+    let mut arguments = vec![];
+
+    if let Some(profile) = &config.profile {
+        // AV1 profiles are passed by number, in the order listed above.
+        let index = profiles(config.encoder)
+            .iter()
+            .position(|name| name == profile)
+            .unwrap_or(0);
+
+        match config.encoder {
+            Encoder::X264 | Encoder::X265 => {
+                arguments.extend(["--profile".to_owned(), profile.clone()]);
+            }
+            Encoder::Aomenc => arguments.push(format!("--profile={index}")),
+            Encoder::SvtAv1 => arguments.extend(["--profile".to_owned(), format!("{index}")]),
+            // vpxenc is always run with profile 2 and rav1e picks the profile itself.
+            Encoder::Vpxenc | Encoder::Rav1e | Encoder::Ffv1 | Encoder::Prores | Encoder::Dnxhr => {
+            }
+        }
+    }
+
+    if let Some((name, _, _, maximum_bitrate, maximum_buffer)) = config
+        .level
+        .as_deref()
+        .and_then(|level| find_level(config.encoder, level))
+    {
+        let factor = bitrate_factor(config.encoder, config.profile.as_deref());
+        let maximum_bitrate = format!("{}", (maximum_bitrate as f64 * factor).floor() as u64);
+        let maximum_buffer = format!("{}", (maximum_buffer as f64 * factor).floor() as u64);
+
+        let (major, minor) = name.split_once('.').unwrap_or((name, "0"));
+        let digits = format!("{major}{minor}");
+
+        match config.encoder {
+            Encoder::X264 => arguments.extend([
+                "--level".to_owned(),
+                name.to_owned(),
+                "--vbv-maxrate".to_owned(),
+                maximum_bitrate,
+                "--vbv-bufsize".to_owned(),
+                maximum_buffer,
+            ]),
+            Encoder::X265 => arguments.extend([
+                "--level-idc".to_owned(),
+                name.to_owned(),
+                "--vbv-maxrate".to_owned(),
+                maximum_bitrate,
+                "--vbv-bufsize".to_owned(),
+                maximum_buffer,
+            ]),
+            Encoder::SvtAv1 => {
+                arguments.extend([
+                    "--level".to_owned(),
+                    digits,
+                    "--mbr".to_owned(),
+                    maximum_bitrate,
+                ]);
+            }
+            Encoder::Rav1e => arguments.extend(["--level".to_owned(), name.to_owned()]),
+            Encoder::Vpxenc => arguments.push(format!("--target-level={digits}")),
+            // aomenc takes the sequence level index, which counts four minor levels per major level
+            // starting from 2.0.
+            Encoder::Aomenc => {
+                let index = major.parse::<usize>().unwrap_or(2).saturating_sub(2) * 4
+                    + minor.parse::<usize>().unwrap_or(0);
+
+                arguments.push(format!("--target-seq-level-idx={index}"));
+            }
+            Encoder::Ffv1 | Encoder::Prores | Encoder::Dnxhr => {}
+        }
+    }
+
+    arguments
+}
@@ -1,20 +1,80 @@
+use std::sync::Once;
+use std::time::{Instant, SystemTime};
+
 use anyhow::Context;
+use tracing::info_span;
+
+use crate::exit::Failure;
 
+pub mod alignment;
+pub mod allocation;
+pub mod animation;
+pub mod audio;
+pub mod batch;
+pub mod cache;
+pub mod clean;
+pub mod commands;
 pub mod config;
+pub mod control;
+pub mod depth;
 pub mod encoder;
+pub mod exit;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod ffmpeg;
+pub mod grain;
+pub mod history;
+pub mod keyframes;
+pub mod levels;
+pub mod manifest;
 pub mod metrics;
+pub mod package;
+pub mod pipe;
+pub mod pool;
+pub mod presplit;
+pub mod probes;
+pub mod proxy;
+#[cfg(feature = "python")]
+mod python;
+pub mod report;
+pub mod review;
 pub mod scenes;
+pub mod schedule;
+pub mod segment;
 pub mod ssimulacra2;
+pub mod status;
+pub mod tools;
 pub mod util;
 
+static THREAD_POOL: Once = Once::new();
+
+// Prevent dependent libraries from modifying the rayon global pool with arbitrary thread counts.
+// The global pool can only be built once per process, so further runs from the Python or C
+// bindings keep the pool built by the first.
+fn initialize_thread_pool(workers: usize) -> anyhow::Result<()> {
+    let mut result = Ok(());
+
+    THREAD_POOL.call_once(|| {
+        result = rayon::ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .build_global();
+    });
+
+    Ok(result?)
+}
+
 #[expect(clippy::print_stdout)]
+#[expect(clippy::too_many_lines)]
 pub fn run(config: &config::Config) -> anyhow::Result<()> {
-    // Prevent dependent libraries from modifying the rayon global pool with arbitrary thread counts.
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(config.workers)
-        .build_global()
-        .context("Unable to initialize thread pool")?;
+    let config = &config.with_staging();
+    let started_at = SystemTime::now();
+    let start = Instant::now();
+
+    let _run_span = info_span!("run", source = ?config.source).entered();
+
+    tools::verify_versions(config)
+        .context("Unable to verify external tool versions")
+        .context(Failure::Tool)?;
 
     util::verify_directory(&config.output_directory).with_context(|| {
         format!(
@@ -23,16 +83,79 @@ pub fn run(config: &config::Config) -> anyhow::Result<()> {
         )
     })?;
 
-    let _metadata = ffmpeg::get_metadata(config);
+    let metadata = info_span!("stage", name = "metadata")
+        .in_scope(|| ffmpeg::get_metadata(config))
+        .with_context(|| format!("Unable to fetch video metadata for {:?}", &config.source))?;
+
+    let config = &config
+        .with_automatic_threads(&metadata)
+        .with_color_range(&metadata);
+    let config = &depth::resolve(config, &metadata).context("Unable to choose output bit depth")?;
+
+    println!(
+        "Using {} worker(s) with {} thread(s) each",
+        config.workers, config.threads_per_worker
+    );
+
+    initialize_thread_pool(config.workers).context("Unable to initialize thread pool")?;
+
+    // The encode identifier depends on the resolved settings, so the server starts once they are
+    // known.
+    if let Some(address) = &config.status_address {
+        status::serve(
+            address,
+            config
+                .output_directory
+                .join("output")
+                .join(format!("{}-bitrate.svg", config.encode_identifier(true))),
+        )
+        .context("Unable to start status server")?;
+    }
+
+    info_span!("stage", name = "scenes").in_scope(|| -> anyhow::Result<()> {
+        scenes::export(config).context("Unable to export scenes")?;
+
+        if !config.streaming {
+            scenes::split(config)
+                .with_context(|| format!("Unable to split scenes for file {:?}", &config.source))?;
+        }
+
+        Ok(())
+    })?;
+
+    let encoder = encoder::Encoder::new(config)
+        .context("Unable to create scene encoder")
+        .context(Failure::Config)?;
+    let (output_path, mut clips, statistics) = info_span!("stage", name = "encode")
+        .in_scope(|| encoder.encode())
+        .context("Unable to encode video")
+        .context(Failure::Encoder)?;
+
+    info_span!("stage", name = "review")
+        .in_scope(|| encoder.review(&statistics))
+        .context("Unable to render review videos")?;
+
+    if let Some(container) = config.delivery {
+        info_span!("stage", name = "package")
+            .in_scope(|| package::delivery(&output_path, config.encoder, container))
+            .context("Unable to package delivery output")?;
+    }
 
-    scenes::split(config)
-        .with_context(|| format!("Unable to split scenes for file {:?}", &config.source))?;
+    scenes::export_zones(config, statistics.scene_qualities()).context("Unable to export zones")?;
 
-    let encoder = encoder::Encoder::new(config).context("Unable to create scene encoder")?;
-    let (_output_path, mut clips, statistics) =
-        encoder.encode().context("Unable to encode video")?;
+    keyframes::report(config, &output_path, encoder.key_frame_interval())
+        .context("Unable to complete key frame analysis")
+        .context(Failure::Verification)?;
 
-    metrics::print(config, &mut clips).context("Unable to print metrics")?;
+    if let Some(segment_duration) = config.segment_duration {
+        segment::write(&output_path, segment_duration)
+            .context("Unable to write output segments")?;
+    }
+
+    let metric_values = info_span!("stage", name = "metrics")
+        .in_scope(|| metrics::print(config, &mut clips, statistics.scene_qualities()))
+        .context("Unable to print metrics")
+        .context(Failure::Metric)?;
 
     println!();
 
@@ -40,7 +163,43 @@ pub fn run(config: &config::Config) -> anyhow::Result<()> {
         .print_quality_stats()
         .context("Unable to print encode quality statistics")?;
 
-    metrics::bitrate_analysis(config, &mut clips).context("Unable to complete bitrate analysis")?;
+    statistics.print_global_allocation();
+    statistics.print_animation_decisions();
+    statistics.print_grain_decisions();
+    statistics.print_banding_interventions();
+    statistics.print_preset_escalations();
+    statistics.print_short_scenes();
+    statistics.print_target_misses();
+    statistics.print_pass_timings();
+
+    metrics::bitrate_analysis(config, &mut clips, statistics.scene_qualities())
+        .context("Unable to complete bitrate analysis")
+        .context(Failure::Metric)?;
+
+    manifest::write(
+        config,
+        &output_path,
+        &statistics,
+        started_at,
+        start.elapsed(),
+    )
+    .context("Unable to write encode manifest")?;
+
+    package::publish_staged(config).context("Unable to publish staged output")?;
+    package::publish(config, &output_path).context("Unable to write output file")?;
+
+    if let Some(batch_directory) = &config.batch_report {
+        println!();
+
+        batch::update(
+            config,
+            batch_directory,
+            &output_path,
+            statistics.scene_qualities(),
+            &metric_values,
+        )
+        .context("Unable to update batch report")?;
+    }
 
     Ok(())
 }
@@ -1,21 +1,44 @@
+use std::process::ExitCode;
+
 use anyhow::{anyhow, Context};
 use clap::Parser;
 
+use video_encoding_wrapper::commands;
 use video_encoding_wrapper::config;
+use video_encoding_wrapper::control;
+use video_encoding_wrapper::exit;
 use video_encoding_wrapper::util;
 
-fn main() -> anyhow::Result<()> {
-    util::install_tracing().context("Unable to install tracing subsystem")?;
-
-    let config = config::Config::parse();
+fn try_main() -> anyhow::Result<()> {
+    let cli = config::Cli::parse();
 
-    if config.encoder == config::Encoder::Rav1e && config.mode == config::Mode::CRF {
-        return Err(anyhow!(
-            "rav1e does not currently support CRF mode. Use QP mode instead."
-        ));
+    if let Some(command) = &cli.command {
+        return commands::run(command).context("Unable to run command");
     }
 
+    let config = cli
+        .config
+        .ok_or_else(|| anyhow!("A source and output directory are required"))?;
+
+    util::install_tracing(config.log_file.as_deref(), config.otlp_endpoint.as_deref())
+        .context("Unable to install tracing subsystem")?;
+
+    control::install().context("Unable to install pause control")?;
+
     video_encoding_wrapper::run(&config).context("Unable to run application")?;
 
     Ok(())
 }
+
+// Errors are printed just as returning them from main would, but the exit code tells scripts which
+// stage failed.
+#[expect(clippy::print_stderr)]
+fn main() -> ExitCode {
+    match try_main() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            ExitCode::from(exit::code(&error))
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::allocation::Allocation;
+use crate::animation::AnimationDecision;
+use crate::config::Config;
+use crate::encoder::{
+    BandingIntervention, EncodeKind, EncodeStatistics, PassTiming, PresetEscalation, TargetMiss,
+};
+use crate::ffmpeg::get_metadata;
+use crate::grain::GrainDecision;
+use crate::report::EncoderReport;
+use crate::scenes::get;
+use crate::tools::versions;
+use crate::util::{hash_string, write_json};
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    version: &'a str,
+    config: &'a Config,
+    encode_identifier: String,
+    source_hash: Option<String>,
+    scenes_hash: String,
+    scene_qualities: &'a BTreeMap<usize, f64>,
+    target_misses: &'a BTreeMap<usize, TargetMiss>,
+    animation_decisions: &'a BTreeMap<usize, AnimationDecision>,
+    grain_decisions: &'a BTreeMap<usize, GrainDecision>,
+    banding_interventions: &'a BTreeMap<usize, BandingIntervention>,
+    preset_escalations: &'a BTreeMap<usize, PresetEscalation>,
+    pass_timings: &'a BTreeMap<EncodeKind, BTreeMap<usize, PassTiming>>,
+    encoder_reports: &'a BTreeMap<usize, EncoderReport>,
+    short_scenes: &'a BTreeMap<usize, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    global_allocation: Option<&'a Allocation>,
+    tool_versions: BTreeMap<String, String>,
+    started_at: u64,
+    elapsed_seconds: f64,
+}
+
+#[derive(Deserialize)]
+struct PreviousManifest {
+    config: serde_json::Map<String, serde_json::Value>,
+    encode_identifier: String,
+    source_hash: Option<String>,
+    scenes_hash: String,
+    scene_qualities: BTreeMap<usize, f64>,
+    started_at: u64,
+}
+
+// Settings that change what quality a scene needs. Anything else, such as the preset, only shifts
+// the answer, which the caller corrects for as scenes complete.
+const TARGET_SETTINGS: [&str; 7] = [
+    "encoder",
+    "mode",
+    "metric",
+    "rule",
+    "use_mean",
+    "percentile",
+    "quality",
+];
+
+fn read_previous(path: &Path) -> anyhow::Result<PreviousManifest> {
+    let file =
+        File::open(path).with_context(|| format!("Unable to open manifest file {path:?}"))?;
+
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("Unable to deserialize manifest file {path:?}"))
+}
+
+// Returns the per-scene qualities of the most recent earlier run over the same scenes with the same
+// quality target, if there is one.
+pub fn previous_scene_qualities(config: &Config) -> anyhow::Result<Option<BTreeMap<usize, f64>>> {
+    let output_path = config.output_directory.join("output");
+
+    if !output_path.exists() {
+        return Ok(None);
+    }
+
+    let metadata = get_metadata(config).context("Unable to fetch video metadata")?;
+    let scenes_hash = hash_string(
+        &serde_json::to_string(&get(config).context("Unable to fetch scene data")?)
+            .context("Unable to serialize scene list")?,
+    );
+
+    let serde_json::Value::Object(current_config) =
+        serde_json::to_value(config).context("Unable to serialize configuration")?
+    else {
+        return Ok(None);
+    };
+
+    let encode_identifier = config.encode_identifier(true);
+
+    let previous = fs::read_dir(&output_path)
+        .with_context(|| format!("Unable to read output directory {output_path:?}"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.to_string_lossy().ends_with(".manifest.json"))
+        .filter_map(|path| read_previous(&path).ok())
+        .filter(|manifest| {
+            manifest.encode_identifier != encode_identifier
+                && manifest.source_hash == metadata.source_hash
+                && manifest.scenes_hash == scenes_hash
+                && TARGET_SETTINGS
+                    .iter()
+                    .all(|&key| manifest.config.get(key) == current_config.get(key))
+        })
+        .max_by_key(|manifest| manifest.started_at);
+
+    Ok(previous.map(|manifest| manifest.scene_qualities))
+}
+
+// The manifest records everything needed to audit or reproduce an encode, so it is written
+// alongside the merged output rather than in the cache directories.
+pub fn write(
+    config: &Config,
+    output_path: &Path,
+    statistics: &EncodeStatistics,
+    started_at: SystemTime,
+    elapsed: Duration,
+) -> anyhow::Result<()> {
+    let metadata = get_metadata(config).context("Unable to fetch video metadata")?;
+    let scenes = get(config).context("Unable to fetch scene data")?;
+
+    let manifest = Manifest {
+        version: env!("CARGO_PKG_VERSION"),
+        config,
+        encode_identifier: config.encode_identifier(true),
+        source_hash: metadata.source_hash,
+        scenes_hash: hash_string(
+            &serde_json::to_string(&scenes).context("Unable to serialize scene list")?,
+        ),
+        scene_qualities: statistics.scene_qualities(),
+        target_misses: statistics.target_misses(),
+        animation_decisions: statistics.animation_decisions(),
+        grain_decisions: statistics.grain_decisions(),
+        banding_interventions: statistics.banding_interventions(),
+        preset_escalations: statistics.preset_escalations(),
+        pass_timings: statistics.pass_timings(),
+        encoder_reports: statistics.encoder_reports(),
+        short_scenes: statistics.short_scenes(),
+        global_allocation: statistics.global_allocation(),
+        tool_versions: versions(config)
+            .context("Unable to determine tool versions")?
+            .into_iter()
+            .collect(),
+        started_at: started_at
+            .duration_since(UNIX_EPOCH)
+            .context("System clock is set before the Unix epoch")?
+            .as_secs(),
+        elapsed_seconds: elapsed.as_secs_f64(),
+    };
+
+    let json_path = output_path.with_extension("manifest.json");
+
+    write_json(&json_path, &manifest)
+        .with_context(|| format!("Unable to write manifest {json_path:?}"))?;
+
+    Ok(())
+}
@@ -1,27 +1,46 @@
 use std::borrow::ToOwned;
+use std::collections::BTreeMap;
 use std::fs;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{ChildStdout, Stdio};
 use std::str;
-use std::time::Duration;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context};
+use crossbeam_queue::ArrayQueue;
 use ffmpeg::{ffi, format, media, Error};
 use indicatif::{HumanCount, ProgressBar};
+use prettytable::{format::consts, row, table};
 use serde::{Deserialize, Serialize};
-
-use crate::config::Config;
-use crate::ffmpeg::get_metadata;
+use statrs::statistics::{Data, Distribution, OrderStatistics};
+use tracing::debug;
+
+use crate::cache::{publish, restore};
+use crate::config::{Config, Metric, MetricsEffort};
+use crate::control::{track, wait_while_paused};
+use crate::ffmpeg::{create_child_read, get_metadata, Metadata, SourceRange};
+use crate::scenes::{self, format_timecode, Scene};
 use crate::ssimulacra2;
+use crate::tools::command;
 use crate::util::{
-    create_progress_style, generate_bitrate_chart, generate_stat_chart, generate_stat_log,
-    print_stats, verify_directory, verify_filename, HumanBitrate,
+    create_progress_style, escape_filter_path, generate_bitrate_chart, generate_scene_chart,
+    generate_stat_chart, generate_stat_log, hash_file, hash_string, notify_progress, print_stats,
+    read_versioned_json, verify_directory, verify_filename, weight_by_length, write_json,
+    HumanBitrate, Versioned,
 };
 
+/// Called with the number of frames scored since the previous call.
+pub type FrameProgress = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// Returns how many threads a calculation may currently use, which can change while it runs.
+pub type ThreadBudget = Arc<dyn Fn() -> usize + Send + Sync>;
+
 #[expect(clippy::module_name_repetitions)]
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct ClipMetrics {
     #[serde(skip)]
     path: PathBuf,
@@ -32,9 +51,42 @@ pub struct ClipMetrics {
     #[serde(skip)]
     json_path: PathBuf,
 
+    #[serde(skip)]
+    shared_json_path: Option<PathBuf>,
+
     #[serde(skip)]
     original_filter: Option<String>,
 
+    #[serde(skip)]
+    original_range: Option<SourceRange>,
+
+    #[serde(skip)]
+    display_filter: Option<String>,
+
+    #[serde(skip)]
+    progress: Option<FrameProgress>,
+
+    #[serde(skip)]
+    progress_reported: u64,
+
+    #[serde(skip)]
+    thread_budget: Option<ThreadBudget>,
+
+    #[serde(skip)]
+    context: MetricContext,
+
+    #[serde(default)]
+    version: u32,
+
+    // Caches written before fingerprints were recorded have none and are always recalculated.
+    #[serde(default)]
+    fingerprint: Option<ClipFingerprint>,
+
+    // The encoder command line that produced the clip. Since the fingerprint changes whenever the
+    // clip is re-encoded, it always describes the clip the scores belong to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoder_arguments: Option<Vec<String>>,
+
     // Single Values
     duration: Option<f64>,
 
@@ -44,6 +96,181 @@ pub struct ClipMetrics {
     psnr: Option<Vec<f64>>,
     ssim: Option<Vec<f64>>,
     ssimulacra2: Option<Vec<f64>>,
+    cambi: Option<Vec<f64>>,
+
+    // Downscaled SSIMULACRA2 scores used by quality search probes, keyed by scale.
+    #[serde(default)]
+    scaled_ssimulacra2: BTreeMap<String, Vec<f64>>,
+
+    // VMAF alone on every few frames, with each score standing in for the frames skipped after it.
+    #[serde(default)]
+    fast_vmaf: Option<Vec<f64>>,
+}
+
+// Scoring every fourth frame keeps scene averages close while cutting the libvmaf work to a quarter.
+const FAST_SUBSAMPLE: usize = 4;
+
+// Any change to how metrics are calculated, such as the VMAF model or the features passed to
+// libvmaf, must change this so that older caches are recalculated.
+const METRIC_CONFIGURATION: &str =
+    "r=60;libvmaf:model=vmaf_v0.6.1,feature=psnr|float_ssim;libvmaf:feature=cambi;ssimulacra2";
+
+/// Identity of a measured clip and the configuration it was measured with.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ClipFingerprint {
+    size: u64,
+    modified: Option<u64>,
+    hash: String,
+    configuration: String,
+}
+
+impl ClipFingerprint {
+    // The modification time only serves to skip hashing a clip that has not been touched since its
+    // metrics were cached. A clip restored from a shared cache is still accepted if its contents
+    // match.
+    fn new(path: &Path, configuration: String, previous: Option<&Self>) -> anyhow::Result<Self> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Unable to read file metadata for {path:?}"))?;
+
+        let size = metadata.len();
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .and_then(|modified| u64::try_from(modified.as_nanos()).ok());
+
+        if let Some(previous) = previous {
+            if previous.size == size
+                && previous.modified.is_some()
+                && previous.modified == modified
+                && previous.configuration == configuration
+            {
+                return Ok(previous.clone());
+            }
+        }
+
+        Ok(Self {
+            size,
+            modified,
+            hash: hash_file(path).with_context(|| format!("Unable to hash {path:?}"))?,
+            configuration,
+        })
+    }
+
+    fn matches(&self, other: &Self) -> bool {
+        self.size == other.size
+            && self.hash == other.hash
+            && self.configuration == other.configuration
+    }
+}
+
+// Each libvmaf instance already runs its own threads, so the number of concurrent instances is
+// limited across all workers of a run rather than per worker.
+struct MetricJobs {
+    limit: usize,
+    running: Mutex<usize>,
+    finished: Condvar,
+}
+
+impl Default for MetricJobs {
+    fn default() -> Self {
+        Self {
+            limit: usize::MAX,
+            running: Mutex::new(0),
+            finished: Condvar::new(),
+        }
+    }
+}
+
+/// Everything outside a clip that changes how it is measured, shared by every clip of one run.
+///
+/// Runs started from the Python or C bindings may share a process, so none of this is global.
+#[derive(Clone, Default)]
+pub struct MetricContext {
+    display_resolution: Option<(usize, usize)>,
+    reference_identity: Option<String>,
+    full_range: bool,
+    jobs: Arc<MetricJobs>,
+}
+
+impl MetricContext {
+    // Scores against an external reference are not interchangeable with scores against the source,
+    // so the identity of the reference, including its crop, is part of every cache fingerprint.
+    // libvmaf models were trained on limited range video, so full range clips are converted before
+    // scoring, while SSIMULACRA2 converts them to linear light as it would any other clip.
+    pub fn new(config: &Config, metadata: &Metadata) -> anyhow::Result<Self> {
+        let reference_identity = config
+            .reference
+            .as_ref()
+            .map(|reference| -> anyhow::Result<String> {
+                Ok(format!(
+                    "{}@{}:{}",
+                    hash_file(reference)
+                        .with_context(|| format!("Unable to hash reference {reference:?}"))?,
+                    config.reference_offset,
+                    metadata.crop_filter.as_deref().unwrap_or("none")
+                ))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            display_resolution: config.metric_resolution,
+            reference_identity,
+            full_range: config.full_range,
+            jobs: Arc::new(MetricJobs {
+                limit: config.metric_jobs().max(1),
+                ..MetricJobs::default()
+            }),
+        })
+    }
+
+    // Content is scored as it will be viewed, so a low resolution source scaled up to a large
+    // display is not penalized for detail that would never be visible, and vice versa.
+    #[must_use]
+    pub fn display_filter(&self) -> Option<String> {
+        self.display_resolution.map(|(width, height)| {
+            format!("scale={width}:{height}:force_original_aspect_ratio=decrease:force_divisible_by=2:flags=bicubic")
+        })
+    }
+}
+
+struct MetricJob(Arc<MetricJobs>);
+
+impl MetricJob {
+    fn acquire(jobs: &Arc<MetricJobs>) -> Self {
+        let mut running = jobs
+            .running
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        while *running >= jobs.limit {
+            running = jobs
+                .finished
+                .wait(running)
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+        }
+
+        *running += 1;
+        drop(running);
+
+        Self(Arc::clone(jobs))
+    }
+}
+
+impl Drop for MetricJob {
+    fn drop(&mut self) {
+        let mut running = self
+            .0
+            .running
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        *running -= 1;
+        drop(running);
+
+        self.0.finished.notify_one();
+    }
 }
 
 #[derive(Deserialize)]
@@ -63,43 +290,122 @@ struct FFmpegLog {
     frames: Vec<FFmpegLogFrame>,
 }
 
+#[derive(Deserialize)]
+struct VmafLogMetrics {
+    vmaf: f64,
+}
+
+#[derive(Deserialize)]
+struct VmafLogFrame {
+    #[serde(rename = "frameNum")]
+    frame_number: usize,
+    metrics: VmafLogMetrics,
+}
+
+#[derive(Deserialize)]
+struct VmafLog {
+    frames: Vec<VmafLogFrame>,
+}
+
+#[derive(Deserialize)]
+struct CambiLogMetrics {
+    cambi: f64,
+}
+
+#[derive(Deserialize)]
+struct CambiLogFrame {
+    metrics: CambiLogMetrics,
+}
+
+#[derive(Deserialize)]
+struct CambiLog {
+    frames: Vec<CambiLogFrame>,
+}
+
+impl Versioned for ClipMetrics {
+    const VERSION: u32 = 1;
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+}
+
 impl ClipMetrics {
     pub fn new(
         path: &Path,
         original_path: &Path,
         original_filter: Option<&str>,
+        original_range: Option<SourceRange>,
+        shared_json_path: Option<PathBuf>,
+        context: &MetricContext,
     ) -> anyhow::Result<Self> {
         let json_path = path.with_extension("metrics.json");
         verify_filename(&json_path)
             .with_context(|| format!("Unable to verify clip metrics cache path {json_path:?}"))?;
 
-        if json_path.exists() {
-            let file = File::open(&json_path)
-                .with_context(|| format!("Unable to open clip metrics cache {json_path:?}"))?;
-            let reader = BufReader::new(file);
-            let mut metrics: Self = serde_json::from_reader(reader)
-                .context("Unable to deserialize clip metrics cache")?;
+        restore(&json_path, shared_json_path.as_deref())
+            .context("Unable to restore clip metrics from shared cache")?;
 
-            metrics.path = path.to_path_buf();
-            metrics.original_path = original_path.to_path_buf();
-            metrics.json_path = json_path;
-            metrics.original_filter = original_filter.map(ToOwned::to_owned);
+        let cached: Option<Self> =
+            read_versioned_json(&json_path).context("Unable to read clip metrics cache")?;
 
-            Ok(metrics)
-        } else {
-            Ok(Self {
-                path: path.to_path_buf(),
-                original_path: original_path.to_path_buf(),
-                json_path,
-                original_filter: original_filter.map(ToOwned::to_owned),
-                sizes: None,
-                duration: None,
-                vmaf: None,
-                psnr: None,
-                ssim: None,
-                ssimulacra2: None,
-            })
+        let display_filter = context.display_filter();
+
+        let mut configuration = format!(
+            "{METRIC_CONFIGURATION};filter={original_filter:?};range={original_range:?};display={display_filter:?};reference={:?}",
+            context.reference_identity
+        );
+
+        // Limited range clips keep their existing fingerprints.
+        if context.full_range {
+            configuration.push_str(";color-range=full");
         }
+
+        let configuration = hash_string(&configuration);
+
+        let fingerprint = ClipFingerprint::new(
+            path,
+            configuration,
+            cached
+                .as_ref()
+                .and_then(|metrics| metrics.fingerprint.as_ref()),
+        )
+        .with_context(|| format!("Unable to fingerprint clip {path:?}"))?;
+
+        // A clip that was re-encoded or is measured differently than when its metrics were cached
+        // must not inherit the old scores.
+        let mut metrics = match cached {
+            Some(metrics)
+                if metrics
+                    .fingerprint
+                    .as_ref()
+                    .is_some_and(|cached| cached.matches(&fingerprint)) =>
+            {
+                metrics
+            }
+            Some(_) => {
+                debug!("Discarding stale metrics cache {json_path:?}");
+                Self::default()
+            }
+            None => Self::default(),
+        };
+
+        metrics.version = Self::VERSION;
+        metrics.path = path.to_path_buf();
+        metrics.original_path = original_path.to_path_buf();
+        metrics.json_path = json_path;
+        metrics.shared_json_path = shared_json_path;
+        metrics.original_filter = original_filter.map(ToOwned::to_owned);
+        metrics.original_range = original_range;
+        metrics.display_filter = display_filter;
+        metrics.context = context.clone();
+        metrics.fingerprint = Some(fingerprint);
+
+        Ok(metrics)
     }
 
     #[must_use]
@@ -107,6 +413,33 @@ impl ClipMetrics {
         &self.path
     }
 
+    pub fn set_encoder_arguments(&mut self, arguments: Vec<String>) -> anyhow::Result<()> {
+        self.encoder_arguments = Some(arguments);
+
+        self.update_cache()
+            .with_context(|| format!("Unable to update metrics cache for {:?}", &self.path))
+    }
+
+    // Metric passes run after this report each frame they score, once per pass.
+    pub fn set_progress(&mut self, progress: FrameProgress) {
+        self.progress = Some(progress);
+        self.progress_reported = 0;
+    }
+
+    // SSIMULACRA2 is scored in-process, so it can follow a budget between frames, with the thread
+    // count passed to it only as the upper limit.
+    pub fn set_thread_budget(&mut self, thread_budget: ThreadBudget) {
+        self.thread_budget = Some(thread_budget);
+    }
+
+    // Passes served from the cache never report frames, so whatever they would have reported is
+    // added at once.
+    pub fn complete_progress(&mut self, total: u64) {
+        if let Some(progress) = self.progress.take() {
+            progress(total.saturating_sub(self.progress_reported));
+        }
+    }
+
     pub fn sizes(&mut self) -> anyhow::Result<&Vec<usize>> {
         if self.sizes.is_none() {
             self.calculate_duration_and_size().with_context(|| {
@@ -121,7 +454,7 @@ impl ClipMetrics {
 
     pub fn psnr(&mut self, threads: usize) -> anyhow::Result<&Vec<f64>> {
         if self.psnr.is_none() {
-            self.calculate_ffmpeg_metrics(threads)
+            self.calculate_ffmpeg_metrics(threads, false)
                 .with_context(|| format!("Unable to calculate PSNR for {:?}", &self.path))?;
         }
 
@@ -132,7 +465,7 @@ impl ClipMetrics {
 
     pub fn ssim(&mut self, threads: usize) -> anyhow::Result<&Vec<f64>> {
         if self.ssim.is_none() {
-            self.calculate_ffmpeg_metrics(threads)
+            self.calculate_ffmpeg_metrics(threads, false)
                 .with_context(|| format!("Unable to calculate SSIM for {:?}", &self.path))?;
         }
 
@@ -143,7 +476,7 @@ impl ClipMetrics {
 
     pub fn vmaf(&mut self, threads: usize) -> anyhow::Result<&Vec<f64>> {
         if self.vmaf.is_none() {
-            self.calculate_ffmpeg_metrics(threads)
+            self.calculate_ffmpeg_metrics(threads, false)
                 .with_context(|| format!("Unable to calculate VMAF for {:?}", &self.path))?;
         }
 
@@ -152,6 +485,17 @@ impl ClipMetrics {
             .ok_or_else(|| anyhow!("Unreachable code reached"))
     }
 
+    pub fn fast_vmaf(&mut self, threads: usize) -> anyhow::Result<&Vec<f64>> {
+        if self.fast_vmaf.is_none() {
+            self.calculate_fast_vmaf(threads)
+                .with_context(|| format!("Unable to calculate fast VMAF for {:?}", &self.path))?;
+        }
+
+        self.fast_vmaf
+            .as_ref()
+            .ok_or_else(|| anyhow!("Unreachable code reached"))
+    }
+
     pub fn ssimulacra2(&mut self, threads: usize) -> anyhow::Result<&Vec<f64>> {
         if self.ssimulacra2.is_none() {
             self.calculate_ssimulacra2(threads)
@@ -163,6 +507,78 @@ impl ClipMetrics {
             .ok_or_else(|| anyhow!("Unreachable code reached"))
     }
 
+    // Every metric at once. While none of them are cached yet, SSIMULACRA2 is scored from the same
+    // decode as libvmaf instead of decoding both clips again.
+    pub fn calculate_all(&mut self, threads: usize) -> anyhow::Result<()> {
+        if self.vmaf.is_none() && self.ssimulacra2.is_none() {
+            self.calculate_ffmpeg_metrics(threads, true)
+                .with_context(|| format!("Unable to calculate metrics for {:?}", &self.path))?;
+        }
+
+        self.vmaf(threads)?;
+        self.ssimulacra2(threads)?;
+
+        Ok(())
+    }
+
+    // SSIMULACRA2 is fairly stable across scales, so a downscaled measurement is close enough to
+    // steer a quality search at a fraction of the cost.
+    pub fn scaled_ssimulacra2(&mut self, threads: usize, scale: f64) -> anyhow::Result<&Vec<f64>> {
+        if scale >= 1.0 {
+            return self.ssimulacra2(threads);
+        }
+
+        let key = format!("{scale}");
+
+        if !self.scaled_ssimulacra2.contains_key(&key) {
+            let scale_filter =
+                format!("scale=trunc(iw*{scale}/2)*2:trunc(ih*{scale}/2)*2:flags=bicubic");
+
+            let display_filter = match &self.display_filter {
+                Some(display_filter) => format!("{display_filter},{scale_filter}"),
+                None => scale_filter,
+            };
+
+            let scores = ssimulacra2::calculate(
+                &self.original_path,
+                self.original_filter.as_deref(),
+                self.original_range,
+                &self.path,
+                Some(&display_filter),
+                threads,
+                self.thread_budget.as_ref(),
+                self.context.full_range,
+                self.progress.as_ref(),
+            )
+            .with_context(|| {
+                format!(
+                    "Unable to calculate SSIMULACRA2 at scale {scale} for {:?}",
+                    &self.path
+                )
+            })?;
+
+            self.scaled_ssimulacra2.insert(key.clone(), scores);
+
+            self.update_cache()
+                .with_context(|| format!("Unable to update metrics cache for {:?}", &self.path))?;
+        }
+
+        self.scaled_ssimulacra2
+            .get(&key)
+            .ok_or_else(|| anyhow!("Unreachable code reached"))
+    }
+
+    pub fn cambi(&mut self, threads: usize) -> anyhow::Result<&Vec<f64>> {
+        if self.cambi.is_none() {
+            self.calculate_cambi(threads)
+                .with_context(|| format!("Unable to calculate CAMBI for {:?}", &self.path))?;
+        }
+
+        self.cambi
+            .as_ref()
+            .ok_or_else(|| anyhow!("Unreachable code reached"))
+    }
+
     pub fn duration(&mut self) -> anyhow::Result<f64> {
         if self.duration.is_none() {
             self.calculate_duration_and_size().with_context(|| {
@@ -191,7 +607,7 @@ impl ClipMetrics {
     #[expect(clippy::as_conversions)]
     #[expect(clippy::cast_precision_loss)]
     fn calculate_duration_and_size(&mut self) -> anyhow::Result<()> {
-        let (stream_index, duration, avg_frame_rate, mut input_context) = {
+        let (stream_index, duration, avg_frame_rate, time_base, mut input_context) = {
             let input_context = format::input(&self.path)
                 .with_context(|| format!("Unable to open {:?} with FFmpeg", &self.path))?;
 
@@ -201,30 +617,56 @@ impl ClipMetrics {
                 .ok_or(Error::StreamNotFound)
                 .with_context(|| format!("Unable to find video stream in {:?}", self.path))?;
 
+            // The IVF header stores its frame count where a duration belongs, which is only
+            // correct when the time base happens to match the frame rate.
+            let duration = if input_context.format().name() == "ivf" {
+                -1
+            } else {
+                input_context.duration()
+            };
+
             (
                 input.index(),
-                input_context.duration(),
+                duration,
                 if input.avg_frame_rate() > ffmpeg::Rational(0, 1) {
                     input.avg_frame_rate()
                 } else {
                     input.rate()
                 },
+                input.time_base(),
                 input_context,
             )
         };
 
         let mut packet_sizes = vec![];
+        let mut first_timestamp = i64::MAX;
+        let mut last_timestamp = i64::MIN;
 
         for (_, packet) in input_context
             .packets()
             .filter_map(Result::ok)
             .filter(|(stream, _)| stream.index() == stream_index)
         {
+            if let Some(timestamp) = packet.pts().or_else(|| packet.dts()) {
+                first_timestamp = first_timestamp.min(timestamp);
+                last_timestamp = last_timestamp.max(timestamp);
+            }
+
             packet_sizes.push(packet.size());
         }
 
+        // Without a usable container duration, the span of the timestamps is extended by one
+        // average frame interval to cover the last frame.
+        let timestamp_duration =
+            (packet_sizes.len() > 1 && last_timestamp > first_timestamp).then(|| {
+                let span = (last_timestamp - first_timestamp) as f64 * f64::from(time_base);
+                span * packet_sizes.len() as f64 / (packet_sizes.len() - 1) as f64
+            });
+
         if duration >= 0 {
             self.duration = Some(duration as f64 / f64::from(ffi::AV_TIME_BASE));
+        } else if let Some(timestamp_duration) = timestamp_duration {
+            self.duration = Some(timestamp_duration);
         } else {
             self.duration = Some(packet_sizes.len() as f64 / f64::from(avg_frame_rate));
         }
@@ -238,10 +680,21 @@ impl ClipMetrics {
     }
 
     fn calculate_ssimulacra2(&mut self, threads: usize) -> anyhow::Result<()> {
-        self.ssimulacra2 = Some(
-            ssimulacra2::calculate(&self.original_path, &self.path, threads)
-                .context("Unable to calculate SSIMULACRA2 for clip")?,
-        );
+        let scores = ssimulacra2::calculate(
+            &self.original_path,
+            self.original_filter.as_deref(),
+            self.original_range,
+            &self.path,
+            self.display_filter.as_deref(),
+            threads,
+            self.thread_budget.as_ref(),
+            self.context.full_range,
+            self.progress.as_ref(),
+        )
+        .context("Unable to calculate SSIMULACRA2 for clip")?;
+
+        self.progress_reported += scores.len().try_into().unwrap_or(u64::MAX);
+        self.ssimulacra2 = Some(scores);
 
         self.update_cache()
             .with_context(|| format!("Unable to update metrics cache for {:?}", &self.path))?;
@@ -249,41 +702,191 @@ impl ClipMetrics {
         Ok(())
     }
 
-    fn calculate_ffmpeg_metrics(&mut self, threads: usize) -> anyhow::Result<()> {
-        let log_path = self.path.with_extension("ffmpeg.metrics.json");
+    // Runs libvmaf with the given extra options, leaving its JSON log at the given path. Returns the
+    // number of frames reported to the progress bar, along with the SSIMULACRA2 scores if they were
+    // requested as well.
+    #[expect(clippy::too_many_lines)]
+    fn run_libvmaf(
+        &self,
+        threads: usize,
+        options: &str,
+        log_path: &Path,
+        with_ssimulacra2: bool,
+    ) -> anyhow::Result<(u64, Option<Vec<f64>>)> {
+        let job = MetricJob::acquire(&self.context.jobs);
+
+        // A partial reference is decoded by a separate FFmpeg subprocess and piped in, since seeking
+        // within the metric process itself is not frame-accurate once the frame rate is forced.
+        let reference_pipe = self
+            .original_range
+            .map(|range| -> anyhow::Result<ChildStdout> {
+                create_child_read(
+                    &self.original_path,
+                    self.original_filter.as_deref(),
+                    "yuv420p10le",
+                    Some(range),
+                    Stdio::null(),
+                    Stdio::piped(),
+                    Stdio::null(),
+                )
+                .context("Unable to spawn FFmpeg reference decoder subprocess")?
+                .stdout
+                .ok_or_else(|| anyhow!("Unable to access stdout for FFmpeg reference decoder"))
+            })
+            .transpose()?;
 
-        let filters = [
-            self.original_filter.as_ref().map_or_else(
-                || "[0:v]setpts=PTS-STARTPTS[reference]".to_owned(),
-                |filter| format!("[0:v]{filter},setpts=PTS-STARTPTS[reference]")
+        let reference_filter = if reference_pipe.is_some() {
+            None
+        } else {
+            self.original_filter.as_deref()
+        };
+
+        let range_filter = self
+            .context
+            .full_range
+            .then_some("scale=in_range=full:out_range=limited");
+
+        let filter_chain = |filters: &[Option<&str>]| {
+            filters
+                .iter()
+                .flatten()
+                .copied()
+                .chain(range_filter)
+                .chain(["setpts=PTS-STARTPTS"])
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
+        // For SSIMULACRA2, both decoded clips are also stacked side by side into a single stream,
+        // which is scored in-process as it arrives.
+        let split = if with_ssimulacra2 { ",split" } else { "" };
+
+        let mut filters = vec![
+            format!(
+                "[0:v]{}{split}[reference]{}",
+                filter_chain(&[reference_filter, self.display_filter.as_deref()]),
+                if with_ssimulacra2 { "[reference_copy]" } else { "" }
+            ),
+            format!(
+                "[1:v]{}{split}[distorted]{}",
+                filter_chain(&[self.display_filter.as_deref()]),
+                if with_ssimulacra2 { "[distorted_copy]" } else { "" }
             ),
-            "[1:v]setpts=PTS-STARTPTS[distorted]".to_owned(),
-            format!("[distorted][reference]libvmaf=log_fmt=json:log_path={}:n_threads={threads}:feature=name=psnr|name=float_ssim", log_path.to_string_lossy())
+            format!("[distorted][reference]libvmaf=log_fmt=json:log_path={}:n_threads={threads}:{options}[vmaf]", escape_filter_path(log_path))
         ];
 
-        let child = Command::new("ffmpeg")
-            .arg("-r")
-            .arg("60")
-            .arg("-i")
-            .arg(&self.original_path)
+        if with_ssimulacra2 {
+            filters.push(
+                "[reference_copy][distorted_copy]hstack,format=yuv420p10le[stacked]".to_owned(),
+            );
+        }
+
+        let mut command = command("ffmpeg");
+
+        if let Some(reference_pipe) = reference_pipe {
+            command
+                .arg("-f")
+                .arg("yuv4mpegpipe")
+                .arg("-r")
+                .arg("60")
+                .arg("-i")
+                .arg("-")
+                .stdin(reference_pipe);
+        } else {
+            command
+                .arg("-r")
+                .arg("60")
+                .arg("-i")
+                .arg(&self.original_path)
+                .stdin(Stdio::null());
+        }
+
+        command
             .arg("-r")
             .arg("60")
             .arg("-i")
             .arg(&self.path)
             .arg("-lavfi")
-            .arg(filters.join(";"))
+            .arg(filters.join(";"));
+
+        // With SSIMULACRA2, the stacked stream occupies standard output and progress is reported
+        // as frames are scored instead.
+        if with_ssimulacra2 {
+            command.arg("-nostats").stdout(Stdio::piped());
+        } else if self.progress.is_some() {
+            command
+                .arg("-progress")
+                .arg("pipe:1")
+                .arg("-nostats")
+                .stdout(Stdio::piped());
+        } else {
+            command.stdout(Stdio::null());
+        }
+
+        command
+            .arg("-map")
+            .arg("[vmaf]")
             .arg("-f")
             .arg("null")
-            .arg("-")
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
+            .arg("-");
+
+        if with_ssimulacra2 {
+            command
+                .arg("-map")
+                .arg("[stacked]")
+                .arg("-f")
+                .arg("yuv4mpegpipe")
+                .arg("-strict")
+                .arg("-1")
+                .arg("pipe:1");
+        }
+
+        let mut child = command
             .stderr(Stdio::piped())
             .spawn()
             .context("Unable to spawn FFmpeg subprocess")?;
 
-        let result = child
-            .wait_with_output()
-            .context("Unable to wait for FFmpeg subprocess")?;
+        // The reference decoder, if any, simply blocks on the pipe while this process is stopped.
+        let tracker = track(&child);
+
+        let output_pipe = child.stdout.take();
+
+        // Standard output is read on its own thread so that error output cannot fill its pipe
+        // meanwhile.
+        let (reported, ssimulacra2_scores, result) = thread::scope(|scope| {
+            let reader = scope.spawn(|| match output_pipe {
+                Some(stdout) if with_ssimulacra2 => {
+                    // Each frame counts once for each pass it replaces.
+                    let progress = self.progress.clone().map(|progress| -> FrameProgress {
+                        Arc::new(move |frames| progress(frames * 2))
+                    });
+
+                    let scores =
+                        ssimulacra2::calculate_stacked(stdout, threads, None, progress.as_ref());
+                    let reported = scores.as_ref().map_or(0, |scores| {
+                        u64::try_from(scores.len() * 2).unwrap_or(u64::MAX)
+                    });
+
+                    (reported, Some(scores))
+                }
+                Some(stdout) => (
+                    self.progress
+                        .as_ref()
+                        .map_or(0, |progress| report_ffmpeg_progress(stdout, progress)),
+                    None,
+                ),
+                None => (0, None),
+            });
+
+            let result = tracker.wait_with_output(child);
+            let (reported, scores) = reader.join().unwrap_or((0, None));
+
+            (reported, scores, result)
+        });
+
+        let result = result.context("Unable to wait for FFmpeg subprocess")?;
+
+        drop(job);
 
         if !result.status.success() || !log_path.exists() {
             return Err(anyhow!(
@@ -293,6 +896,33 @@ impl ClipMetrics {
             ));
         }
 
+        let ssimulacra2_scores = ssimulacra2_scores
+            .transpose()
+            .context("Unable to calculate SSIMULACRA2 from the metric decode")?;
+
+        Ok((reported, ssimulacra2_scores))
+    }
+
+    fn calculate_ffmpeg_metrics(
+        &mut self,
+        threads: usize,
+        with_ssimulacra2: bool,
+    ) -> anyhow::Result<()> {
+        let log_path = self.path.with_extension("ffmpeg.metrics.json");
+
+        let (reported, ssimulacra2_scores) = self.run_libvmaf(
+            threads,
+            "feature=name=psnr|name=float_ssim",
+            &log_path,
+            with_ssimulacra2,
+        )?;
+
+        self.progress_reported += reported;
+
+        if ssimulacra2_scores.is_some() {
+            self.ssimulacra2 = ssimulacra2_scores;
+        }
+
         let log_file = File::open(&log_path)
             .with_context(|| format!("Unable to open FFmpeg metrics file {log_path:?}"))?;
 
@@ -323,34 +953,155 @@ impl ClipMetrics {
         Ok(())
     }
 
+    fn calculate_fast_vmaf(&mut self, threads: usize) -> anyhow::Result<()> {
+        let log_path = self.path.with_extension("fast.metrics.json");
+        let frames = self.frames()?;
+
+        self.progress_reported += self
+            .run_libvmaf(
+                threads,
+                &format!("n_subsample={FAST_SUBSAMPLE}"),
+                &log_path,
+                false,
+            )?
+            .0;
+
+        let log_file = File::open(&log_path)
+            .with_context(|| format!("Unable to open fast VMAF metrics file {log_path:?}"))?;
+
+        let log: VmafLog = serde_json::from_reader(BufReader::new(log_file))
+            .context("Unable to parse fast VMAF JSON log file")?;
+
+        let mut scores = log.frames.iter().peekable();
+        let mut score = 0.0;
+        let mut vmaf = Vec::with_capacity(frames);
+
+        for frame in 0..frames {
+            while let Some(scored) = scores.next_if(|scored| scored.frame_number <= frame) {
+                score = scored.metrics.vmaf;
+            }
+
+            vmaf.push(score);
+        }
+
+        self.fast_vmaf = Some(vmaf);
+
+        fs::remove_file(&log_path).with_context(|| format!("Unable to remove {log_path:?}"))?;
+
+        self.update_cache()
+            .with_context(|| format!("Unable to update metrics cache for {:?}", &self.path))?;
+
+        Ok(())
+    }
+
+    fn calculate_cambi(&mut self, threads: usize) -> anyhow::Result<()> {
+        let log_path = self.path.with_extension("cambi.metrics.json");
+
+        self.progress_reported += self
+            .run_libvmaf(threads, "feature=name=cambi", &log_path, false)?
+            .0;
+
+        let log_file = File::open(&log_path)
+            .with_context(|| format!("Unable to open CAMBI metrics file {log_path:?}"))?;
+
+        let log: CambiLog = serde_json::from_reader(BufReader::new(log_file))
+            .context("Unable to parse CAMBI JSON log file")?;
+
+        self.cambi = Some(
+            log.frames
+                .into_iter()
+                .map(|frame| frame.metrics.cambi)
+                .collect(),
+        );
+
+        fs::remove_file(&log_path).with_context(|| format!("Unable to remove {log_path:?}"))?;
+
+        self.update_cache()
+            .with_context(|| format!("Unable to update metrics cache for {:?}", &self.path))?;
+
+        Ok(())
+    }
+
     fn update_cache(&self) -> anyhow::Result<()> {
-        let temporary_path = self.json_path.with_extension(".tmp.json");
+        write_json(&self.json_path, self)
+            .with_context(|| format!("Unable to write clip metrics cache {:?}", &self.json_path))?;
 
-        serde_json::to_writer_pretty(
-            &File::create(&temporary_path).with_context(|| {
-                format!(
-                    "Unable to create clip metrics cache file {:?}",
-                    &temporary_path
-                )
-            })?,
-            &self,
+        publish(&self.json_path, self.shared_json_path.as_deref())
+            .context("Unable to publish clip metrics to shared cache")?;
+
+        Ok(())
+    }
+}
+
+// Every per-frame value in one row, so that nothing has to be reconciled by index afterwards. The
+// timestamp assumes a constant frame rate, as the encodes themselves do.
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+fn generate_frame_table(
+    output_filename: &Path,
+    frame_rate: f64,
+    scenes: &[Scene],
+    sizes: &[usize],
+    [psnr, ssim, vmaf, ssimulacra2]: [&[f64]; 4],
+) -> anyhow::Result<()> {
+    verify_filename(output_filename)
+        .with_context(|| format!("Unable to verify frame table filename {output_filename:?}"))?;
+
+    let file = File::create(output_filename)
+        .with_context(|| format!("Unable to create frame table {output_filename:?}"))?;
+
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "frame,pts,scene,size,psnr,ssim,vmaf,ssimulacra2")
+        .context("Unable to write frame table header")?;
+
+    let mut scenes = scenes.iter().peekable();
+
+    for (frame, size) in sizes.iter().enumerate() {
+        while scenes.next_if(|scene| scene.end_frame() < frame).is_some() {}
+
+        let scene = scenes
+            .peek()
+            .map_or_else(String::new, |scene| scene.index().to_string());
+
+        let value = |values: &[f64]| {
+            values
+                .get(frame)
+                .map_or_else(String::new, ToString::to_string)
+        };
+
+        writeln!(
+            writer,
+            "{frame},{:.6},{scene},{size},{},{},{},{}",
+            frame as f64 / frame_rate,
+            value(psnr),
+            value(ssim),
+            value(vmaf),
+            value(ssimulacra2),
         )
-        .with_context(|| {
-            format!(
-                "Unable to serialize clip metrics cache to {:?}",
-                &temporary_path
-            )
-        })?;
+        .context("Unable to write frame table row")?;
+    }
 
-        fs::rename(&temporary_path, &self.json_path).with_context(|| {
-            format!(
-                "Unable to rename {temporary_path:?} to {:?}",
-                self.json_path
-            )
-        })?;
+    writer.flush().context("Unable to flush frame table")?;
 
-        Ok(())
+    Ok(())
+}
+
+// FFmpeg's progress output is a series of key=value blocks, each including the frame count so far.
+fn report_ffmpeg_progress(stdout: ChildStdout, progress: &FrameProgress) -> u64 {
+    let mut reported = 0;
+
+    for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+        if let Some(frame) = line
+            .strip_prefix("frame=")
+            .and_then(|frame| frame.trim().parse::<u64>().ok())
+        {
+            progress(frame.saturating_sub(reported));
+            reported = reported.max(frame);
+        }
     }
+
+    reported
 }
 
 fn moving_sum(data: &[f64], window_size: usize) -> Vec<f64> {
@@ -372,7 +1123,11 @@ fn moving_sum(data: &[f64], window_size: usize) -> Vec<f64> {
 #[expect(clippy::cast_possible_truncation)]
 #[expect(clippy::cast_precision_loss)]
 #[expect(clippy::cast_sign_loss)]
-pub fn bitrate_analysis(config: &Config, clips: &mut [ClipMetrics]) -> anyhow::Result<()> {
+pub fn bitrate_analysis(
+    config: &Config,
+    clips: &mut [ClipMetrics],
+    scene_qualities: &BTreeMap<usize, f64>,
+) -> anyhow::Result<()> {
     let metadata = get_metadata(config)
         .with_context(|| format!("Unable to fetch video metadata for {:?}", &config.source))?;
 
@@ -412,11 +1167,27 @@ pub fn bitrate_analysis(config: &Config, clips: &mut [ClipMetrics]) -> anyhow::R
         .zip(&averages)
         .collect();
 
+    // Each scene boundary is marked with the quality chosen for the scene that starts there, so a
+    // spike can be traced back to its scene.
+    let markers = scenes::get(config)
+        .context("Unable to fetch scene data")?
+        .iter()
+        .map(|scene| {
+            let label = scene_qualities.get(&scene.index()).map_or_else(
+                || format!("{:05}", scene.index()),
+                |quality| format!("{:05} @ {quality}", scene.index()),
+            );
+
+            (scene.start_frame(), label)
+        })
+        .collect::<Vec<_>>();
+
     generate_bitrate_chart(
         &output_path.join(format!("{}-bitrate.svg", config.encode_identifier(true))),
         "Bitrate (Mbps)",
         (1.0 * avg_frame_rate).round() as usize,
         &series,
+        &markers,
     )
     .context("Unable to generate bitrate chart")?;
 
@@ -427,11 +1198,21 @@ pub fn bitrate_analysis(config: &Config, clips: &mut [ClipMetrics]) -> anyhow::R
 #[expect(clippy::cast_precision_loss)]
 #[expect(clippy::print_stdout)]
 #[expect(clippy::too_many_lines)]
-pub fn print(config: &Config, clips: &mut [ClipMetrics]) -> anyhow::Result<()> {
+pub fn print(
+    config: &Config,
+    clips: &mut [ClipMetrics],
+    scene_qualities: &BTreeMap<usize, f64>,
+) -> anyhow::Result<Vec<(String, Vec<f64>)>> {
     let metadata = get_metadata(config)
         .with_context(|| format!("Unable to fetch video metadata for {:?}", &config.source))?;
 
-    let progress_bar = ProgressBar::new(metadata.frame_count.try_into().unwrap_or(u64::MAX));
+    // Each clip is measured by one libvmaf pass and one SSIMULACRA2 pass, or by a single
+    // subsampled VMAF pass at fast effort.
+    let fast = config.metrics_effort == MetricsEffort::Fast;
+    let passes = if fast { 1 } else { 2 };
+
+    let progress_bar =
+        ProgressBar::new(u64::try_from(metadata.frame_count * passes).unwrap_or(u64::MAX));
 
     progress_bar.set_style(
         create_progress_style(
@@ -441,6 +1222,66 @@ pub fn print(config: &Config, clips: &mut [ClipMetrics]) -> anyhow::Result<()> {
 
     progress_bar.enable_steady_tick(Duration::from_secs(1));
 
+    // Several clips are measured at once, each with a share of the threads, since a single libvmaf
+    // run leaves most cores idle while its processes start and finish.
+    let jobs = config.metric_jobs().min(clips.len()).max(1);
+    let threads = (config.workers / jobs).max(1);
+
+    let clip_queue: ArrayQueue<&mut ClipMetrics> = ArrayQueue::new(clips.len().max(1));
+
+    for clip_metrics in &mut *clips {
+        if clip_queue.push(clip_metrics).is_err() {
+            return Err(anyhow!("Metrics queue was unexpectedly full"));
+        }
+    }
+
+    thread::scope(|scope| -> anyhow::Result<()> {
+        let workers = (0..jobs)
+            .map(|_job_index| {
+                scope.spawn(|| -> anyhow::Result<()> {
+                    while let Some(clip_metrics) = clip_queue.pop() {
+                        wait_while_paused();
+
+                        let clip_progress_bar = progress_bar.clone();
+                        clip_metrics
+                            .set_progress(Arc::new(move |frames| clip_progress_bar.inc(frames)));
+
+                        let frames = clip_metrics
+                            .frames()
+                            .context("Unable to access clip frame count")?;
+
+                        if fast {
+                            clip_metrics
+                                .fast_vmaf(threads)
+                                .context("Unable to calculate clip fast VMAF")?;
+                        } else {
+                            clip_metrics
+                                .calculate_all(threads)
+                                .context("Unable to calculate clip metrics")?;
+                        }
+
+                        clip_metrics
+                            .complete_progress(u64::try_from(frames * passes).unwrap_or(u64::MAX));
+
+                        notify_progress("metrics", &progress_bar);
+                    }
+
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for worker in workers {
+            worker
+                .join()
+                .map_err(|error| anyhow!("Metrics worker panicked: {error:?}"))??;
+        }
+
+        Ok(())
+    })?;
+
+    drop(clip_queue);
+
     let mut sizes: Vec<usize> = vec![];
     let mut duration = 0.0_f64;
 
@@ -449,41 +1290,47 @@ pub fn print(config: &Config, clips: &mut [ClipMetrics]) -> anyhow::Result<()> {
     let mut vmaf = vec![];
     let mut ssimulacra2 = vec![];
 
+    // Every metric is cached by now, so the results are simply gathered in clip order.
     for clip_metrics in &mut *clips {
         duration += clip_metrics
             .duration()
             .context("Unable to access clip duration")?;
 
-        let clip_sizes = clip_metrics.sizes().context("Unable to access clip size")?;
-        sizes.extend(clip_sizes);
+        sizes.extend(clip_metrics.sizes().context("Unable to access clip size")?);
 
-        let frame_count = clip_sizes.len().try_into().unwrap_or(u64::MAX);
+        if fast {
+            vmaf.extend(
+                clip_metrics
+                    .fast_vmaf(threads)
+                    .context("Unable to access clip fast VMAF")?,
+            );
+
+            continue;
+        }
 
         psnr.extend(
             clip_metrics
-                .psnr(config.workers)
+                .psnr(threads)
                 .context("Unable to access clip PSNR")?,
         );
 
         ssim.extend(
             clip_metrics
-                .ssim(config.workers)
+                .ssim(threads)
                 .context("Unable to access clip SSIM")?,
         );
 
         vmaf.extend(
             clip_metrics
-                .vmaf(config.workers)
+                .vmaf(threads)
                 .context("Unable to access clip VMAF")?,
         );
 
         ssimulacra2.extend(
             clip_metrics
-                .ssimulacra2(config.workers)
+                .ssimulacra2(threads)
                 .context("Unable to access clip SSIMULACRA2")?,
         );
-
-        progress_bar.inc(frame_count);
     }
 
     progress_bar.finish();
@@ -514,78 +1361,348 @@ pub fn print(config: &Config, clips: &mut [ClipMetrics]) -> anyhow::Result<()> {
     verify_directory(&output_path)
         .with_context(|| format!("Unable to verify merging output directory {output_path:?}"))?;
 
-    generate_stat_log(
-        &output_path.join(format!("{}-psnr.txt", config.encode_identifier(true))),
-        "PSNR",
-        &psnr,
-    )
-    .context("Unable to generate PSNR log")?;
+    let scenes = scenes::get(config).context("Unable to fetch scene data")?;
 
-    generate_stat_chart(
-        &output_path.join(format!("{}-psnr.svg", config.encode_identifier(true))),
-        "PSNR",
-        &psnr,
+    generate_frame_table(
+        &output_path.join(format!("{}-frames.csv", config.encode_identifier(true))),
+        metadata.frame_count as f64 / metadata.duration,
+        &scenes,
+        &sizes,
+        [&psnr, &ssim, &vmaf, &ssimulacra2],
     )
-    .context("Unable to generate PSNR chart")?;
+    .context("Unable to generate per-frame metrics table")?;
 
-    generate_stat_log(
-        &output_path.join(format!("{}-ssim.txt", config.encode_identifier(true))),
-        "SSIM",
-        &ssim,
-    )
-    .context("Unable to generate SSIM log")?;
+    // Metrics skipped at fast effort are left out of the logs, charts and statistics, and their
+    // columns of the frame table stay empty.
+    let mut metrics = vec![
+        ("PSNR".to_owned(), psnr),
+        ("SSIM".to_owned(), ssim),
+        ("VMAF".to_owned(), vmaf),
+        ("SSIMULACRA2".to_owned(), ssimulacra2),
+    ];
 
-    generate_stat_chart(
-        &output_path.join(format!("{}-ssim.svg", config.encode_identifier(true))),
-        "SSIM",
-        &ssim,
-    )
-    .context("Unable to generate SSIM chart")?;
+    metrics.retain(|(_, values)| !values.is_empty());
 
-    generate_stat_log(
-        &output_path.join(format!("{}-vmaf.txt", config.encode_identifier(true))),
-        "VMAF",
-        &vmaf,
-    )
-    .context("Unable to generate VMAF log")?;
+    for (name, values) in &metrics {
+        let base_filename = format!("{}-{}", config.encode_identifier(true), name.to_lowercase());
 
-    generate_stat_chart(
-        &output_path.join(format!("{}-vmaf.svg", config.encode_identifier(true))),
-        "VMAF",
-        &vmaf,
-    )
-    .context("Unable to generate VMAF chart")?;
-
-    generate_stat_log(
-        &output_path.join(format!(
-            "{}-ssimulacra2.txt",
-            config.encode_identifier(true)
-        )),
-        "SSIMULACRA2",
-        &ssimulacra2,
-    )
-    .context("Unable to generate SSIMULACRA2 log")?;
-
-    generate_stat_chart(
-        &output_path.join(format!(
-            "{}-ssimulacra2.svg",
-            config.encode_identifier(true)
-        )),
-        "SSIMULACRA2",
-        &ssimulacra2,
+        generate_stat_log(
+            &output_path.join(format!("{base_filename}.txt")),
+            name,
+            values,
+        )
+        .with_context(|| format!("Unable to generate {name} log"))?;
+
+        generate_stat_chart(
+            &output_path.join(format!("{base_filename}.svg")),
+            name,
+            values,
+        )
+        .with_context(|| format!("Unable to generate {name} chart"))?;
+    }
+
+    generate_scene_quality_chart(
+        config,
+        &output_path.join(format!("{}-scenes.svg", config.encode_identifier(true))),
+        metadata.frame_count as f64 / metadata.duration,
+        &scenes,
+        &sizes,
+        scene_qualities,
+        &metrics,
     )
-    .context("Unable to generate SSIMULACRA2 chart")?;
+    .context("Unable to generate scene chart")?;
+
+    let scene_stats = scene_stats(config, &scenes, &metrics);
 
     println!();
 
-    let mut metrics = vec![
-        ("PSNR".to_owned(), psnr),
-        ("SSIM".to_owned(), ssim),
-        ("VMAF".to_owned(), vmaf),
-        ("SSIMULACRA2".to_owned(), ssimulacra2),
-    ];
+    // The statistics reorder the values they are given, while the scene scores below still need
+    // the frames in order.
+    print_stats(&mut metrics.clone()).context("Unable to output metrics")?;
+
+    print_scene_stats(config, scene_stats).context("Unable to output scene metrics")?;
+
+    if let Some((_, vmaf)) = metrics.iter().find(|(name, _)| name == "VMAF") {
+        print_vmaf_pooling(vmaf);
+    }
+
+    if config.worst_scenes > 0 {
+        println!();
+
+        print_worst_scenes(
+            config,
+            &output_path.join(format!(
+                "{}-worst-scenes.csv",
+                config.encode_identifier(true)
+            )),
+            metadata.frame_count as f64 / metadata.duration,
+            &scenes,
+            &sizes,
+            scene_qualities,
+            &metrics,
+        )
+        .context("Unable to list the worst scenes")?;
+    }
+
+    Ok(metrics)
+}
+
+// Matches libvmaf's harmonic mean pooling, which offsets each score by one so that frames scoring
+// zero do not dominate.
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+fn vmaf_harmonic_mean(values: &[f64]) -> f64 {
+    values.len() as f64 / values.iter().map(|value| 1.0 / (value + 1.0)).sum::<f64>() - 1.0
+}
+
+// Published comparisons usually quote pooled VMAF, so the harmonic mean and low percentiles are
+// reported alongside the arithmetic statistics.
+#[expect(clippy::print_stdout)]
+fn print_vmaf_pooling(vmaf: &[f64]) {
+    if vmaf.is_empty() {
+        return;
+    }
+
+    let mut data = Data::new(vmaf.to_vec());
+
+    println!(
+        "VMAF pooling: mean {:.3}, harmonic mean {:.3}, 1% low {:.3}, 5% low {:.3}",
+        data.mean().unwrap_or(f64::NAN),
+        vmaf_harmonic_mean(vmaf),
+        data.quantile(0.01),
+        data.quantile(0.05)
+    );
+}
+
+// Per-frame statistics already weight every scene by its length, so the scene scores that the
+// quality search aims at are summarized separately, with each scene counting once, weighted by its
+// length, or both.
+fn scene_stats(
+    config: &Config,
+    scenes: &[Scene],
+    metrics: &[(String, Vec<f64>)],
+) -> Vec<(String, Vec<f64>)> {
+    let weighting = config.scene_weighting;
+    let mut stats = vec![];
+
+    for (name, values) in metrics {
+        let scores = scenes
+            .iter()
+            .filter_map(|scene| {
+                scene_score(config, values, scene).map(|score| (score, scene.length()))
+            })
+            .collect::<Vec<_>>();
+
+        if scores.is_empty() {
+            continue;
+        }
+
+        if weighting.per_scene() {
+            stats.push((
+                format!("{name} (per scene)"),
+                scores.iter().map(|(score, _)| *score).collect(),
+            ));
+        }
+
+        if weighting.by_length() {
+            stats.push((
+                format!("{name} (length-weighted)"),
+                weight_by_length(scores),
+            ));
+        }
+    }
+
+    stats
+}
+
+#[expect(clippy::print_stdout)]
+fn print_scene_stats(config: &Config, mut stats: Vec<(String, Vec<f64>)>) -> anyhow::Result<()> {
+    if stats.is_empty() {
+        return Ok(());
+    }
+
+    let aggregation = if config.use_mean {
+        "mean".to_owned()
+    } else {
+        format!("{} percentile", config.percentile)
+    };
+
+    println!();
+    println!("Scene scores ({aggregation}):");
+
+    print_stats(&mut stats)
+}
+
+// Aggregates a scene's frames the same way the quality target does.
+fn scene_score(config: &Config, values: &[f64], scene: &Scene) -> Option<f64> {
+    let scene_values = values.get(scene.start_frame()..=scene.end_frame())?;
+
+    Some(if config.use_mean {
+        Data::new(scene_values.to_vec()).mean().unwrap_or(f64::NAN)
+    } else {
+        Data::new(scene_values.to_vec()).quantile(config.percentile)
+    })
+}
+
+// Shows how the chosen quality tracked the content over the timeline. With no metric target, VMAF
+// stands in as the achieved metric.
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+fn generate_scene_quality_chart(
+    config: &Config,
+    output_filename: &PathBuf,
+    frame_rate: f64,
+    scenes: &[Scene],
+    sizes: &[usize],
+    scene_qualities: &BTreeMap<usize, f64>,
+    metrics: &[(String, Vec<f64>)],
+) -> anyhow::Result<()> {
+    let metric_name = match config.metric {
+        Metric::PSNR => "PSNR",
+        Metric::SSIM => "SSIM",
+        Metric::SSIMULACRA2 => "SSIMULACRA2",
+        Metric::Direct | Metric::VMAF | Metric::Bitrate => "VMAF",
+    };
+
+    let mut quality = vec![];
+    let mut achieved = vec![];
+    let mut length = vec![];
+
+    for scene in scenes {
+        let start = scene.start_frame();
+        let end = scene.end_frame() + 1;
+
+        if let Some(value) = scene_qualities.get(&scene.index()) {
+            quality.push((start, end, *value));
+        }
+
+        let score = if config.metric == Metric::Bitrate {
+            sizes.get(start..end).map(|sizes| {
+                sizes.iter().sum::<usize>() as f64 * 8.0
+                    / (scene.length() as f64 / frame_rate)
+                    / 1000.0
+            })
+        } else {
+            metrics
+                .iter()
+                .find(|(name, _)| name == metric_name)
+                .and_then(|(_, values)| scene_score(config, values, scene))
+        };
+
+        if let Some(score) = score {
+            achieved.push((start, end, score));
+        }
+
+        length.push((start, end, scene.length() as f64));
+    }
+
+    let achieved_name = if config.metric == Metric::Bitrate {
+        "Bitrate (kbps)".to_owned()
+    } else if config.use_mean {
+        format!("{metric_name} (mean)")
+    } else {
+        format!("{metric_name} ({} percentile)", config.percentile)
+    };
+
+    generate_scene_chart(
+        output_filename,
+        "Scenes",
+        &[
+            (format!("Quality ({})", config.mode), quality),
+            (achieved_name, achieved),
+            ("Length (frames)".to_owned(), length),
+        ],
+    )
+}
+
+// Scenes are scored the same way as the quality target, so the worst scenes are those that most
+// likely need attention.
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_possible_truncation)]
+#[expect(clippy::cast_precision_loss)]
+#[expect(clippy::cast_sign_loss)]
+fn print_worst_scenes(
+    config: &Config,
+    output_filename: &Path,
+    frame_rate: f64,
+    scenes: &[Scene],
+    sizes: &[usize],
+    scene_qualities: &BTreeMap<usize, f64>,
+    metrics: &[(String, Vec<f64>)],
+) -> anyhow::Result<()> {
+    verify_filename(output_filename).with_context(|| {
+        format!("Unable to verify worst scene list filename {output_filename:?}")
+    })?;
+
+    let file = File::create(output_filename)
+        .with_context(|| format!("Unable to create worst scene list {output_filename:?}"))?;
+
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "metric,rank,scene,start,end,score,quality,bitrate")
+        .context("Unable to write worst scene list header")?;
+
+    let timecode_rate = (frame_rate.round() as usize).max(1);
+    let aggregation = if config.use_mean {
+        "mean".to_owned()
+    } else {
+        format!("{} percentile", config.percentile)
+    };
+
+    for (name, values) in metrics {
+        let mut scores = vec![];
+
+        for scene in scenes {
+            let Some(score) = scene_score(config, values, scene) else {
+                continue;
+            };
+
+            scores.push((score, scene));
+        }
+
+        scores.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        println!("Worst scenes by {name} ({aggregation}):");
+
+        let mut table = table!(["Rank", "Scene", "Start", "End", name, "Quality", "Bitrate"]);
+        table.set_format(*consts::FORMAT_BOX_CHARS);
+
+        for (rank, (score, scene)) in scores.iter().take(config.worst_scenes).enumerate() {
+            let start = format_timecode(scene.start_frame(), timecode_rate);
+            let end = format_timecode(scene.end_frame() + 1, timecode_rate);
+            let quality = scene_qualities
+                .get(&scene.index())
+                .map_or_else(String::new, ToString::to_string);
+            let bitrate = sizes
+                .get(scene.start_frame()..=scene.end_frame())
+                .map_or(0, |sizes| sizes.iter().sum::<usize>()) as f64
+                * 8.0
+                / (scene.length() as f64 / frame_rate);
+
+            table.add_row(row![
+                rank + 1,
+                format!("{:05}", scene.index()),
+                start,
+                end,
+                format!("{score:8.3}"),
+                quality,
+                HumanBitrate(bitrate),
+            ]);
+
+            writeln!(
+                writer,
+                "{name},{},{},{start},{end},{score},{quality},{bitrate:.0}",
+                rank + 1,
+                scene.index()
+            )
+            .context("Unable to write worst scene list row")?;
+        }
+
+        table.printstd();
+        println!();
+    }
 
-    print_stats(&mut metrics).context("Unable to output metrics")?;
+    writer.flush().context("Unable to flush worst scene list")?;
 
     Ok(())
 }
@@ -0,0 +1,227 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use indicatif::ProgressBar;
+
+use crate::config::{Config, Container, Encoder};
+use crate::tools::command;
+use crate::util::{create_progress_style, verify_filename};
+
+// WebM only carries VP8, VP9 and AV1, and neither container has a place for the mezzanine codecs.
+pub fn verify(config: &Config, container: Container) -> anyhow::Result<()> {
+    let supported = match container {
+        Container::Mp4 => !config.encoder.is_mezzanine(),
+        Container::Webm => matches!(
+            config.encoder,
+            Encoder::Aomenc | Encoder::Rav1e | Encoder::SvtAv1 | Encoder::Vpxenc
+        ),
+    };
+
+    if supported {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "The {} encoder's output cannot be packaged as {container}",
+            config.encoder
+        ))
+    }
+}
+
+// The output stays where a resumed run expects it, and is linked to the requested path, or copied
+// there on file systems without hard links.
+#[expect(clippy::print_stdout)]
+pub fn publish(config: &Config, output_path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let extension = output_path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let Some(published_path) = config.output_file_path(&extension)? else {
+        return Ok(None);
+    };
+
+    verify_filename(&published_path)
+        .with_context(|| format!("Unable to verify output path {published_path:?}"))?;
+
+    let temporary_path = published_path.with_extension(format!("tmp.{extension}"));
+
+    if temporary_path.exists() {
+        fs::remove_file(&temporary_path)
+            .with_context(|| format!("Unable to remove {temporary_path:?}"))?;
+    }
+
+    if fs::hard_link(output_path, &temporary_path).is_err() {
+        fs::copy(output_path, &temporary_path)
+            .with_context(|| format!("Unable to copy {output_path:?} to {temporary_path:?}"))?;
+    }
+
+    fs::rename(&temporary_path, &published_path)
+        .with_context(|| format!("Unable to rename {temporary_path:?} to {published_path:?}"))?;
+
+    println!("Output written to {}", published_path.display());
+
+    Ok(Some(published_path))
+}
+
+// A staged run copies its finished output directory, with the encode, its reports and its manifest,
+// into the real output directory. Each file is renamed into place, so none is seen half written.
+#[expect(clippy::print_stdout)]
+pub fn publish_staged(config: &Config) -> anyhow::Result<()> {
+    let Some(publish_directory) = &config.publish_directory else {
+        return Ok(());
+    };
+
+    copy_directory(
+        &config.output_directory.join("output"),
+        &publish_directory.join("output"),
+    )?;
+
+    println!("Output published to {}", publish_directory.display());
+
+    Ok(())
+}
+
+fn copy_directory(source: &Path, destination: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(destination)
+        .with_context(|| format!("Unable to create directory {destination:?}"))?;
+
+    for entry in
+        fs::read_dir(source).with_context(|| format!("Unable to read directory {source:?}"))?
+    {
+        let path = entry
+            .with_context(|| format!("Unable to read directory entry in {source:?}"))?
+            .path();
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let target = destination.join(name);
+
+        if path.is_dir() {
+            copy_directory(&path, &target)?;
+            continue;
+        }
+
+        let mut temporary_name = name.to_os_string();
+        temporary_name.push(".tmp");
+        let temporary_path = destination.join(temporary_name);
+
+        fs::copy(&path, &temporary_path)
+            .with_context(|| format!("Unable to copy {path:?} to {temporary_path:?}"))?;
+        fs::rename(&temporary_path, &target)
+            .with_context(|| format!("Unable to rename {temporary_path:?} to {target:?}"))?;
+    }
+
+    Ok(())
+}
+
+// The delivery file is remuxed from the merged output, keeping only the video and the first audio
+// track, so the encode itself is never repeated for a second container.
+pub fn delivery(
+    output_path: &Path,
+    encoder: Encoder,
+    container: Container,
+) -> anyhow::Result<PathBuf> {
+    remux(
+        output_path,
+        (encoder, container),
+        &["-map", "0:v:0", "-map", "0:a:0?"],
+        "delivery output",
+    )
+}
+
+// Replaces the merged output with a copy of every video and audio track in another container. The
+// chapters come along, but subtitles are left out, as neither container takes the formats found in
+// Matroska.
+pub fn convert(
+    output_path: &Path,
+    encoder: Encoder,
+    container: Container,
+) -> anyhow::Result<PathBuf> {
+    let packaged_path = remux(
+        output_path,
+        (encoder, container),
+        &["-map", "0:v", "-map", "0:a?"],
+        "output",
+    )?;
+
+    fs::remove_file(output_path)
+        .with_context(|| format!("Unable to remove merged output {output_path:?}"))?;
+
+    Ok(packaged_path)
+}
+
+fn remux(
+    output_path: &Path,
+    (encoder, container): (Encoder, Container),
+    maps: &[&str],
+    description: &str,
+) -> anyhow::Result<PathBuf> {
+    let packaged_path = output_path.with_extension(container.extension());
+
+    if packaged_path.exists() {
+        return Ok(packaged_path);
+    }
+
+    let temporary_path = output_path.with_extension(format!("tmp.{}", container.extension()));
+
+    let progress_bar = ProgressBar::new_spinner();
+    progress_bar.enable_steady_tick(Duration::from_millis(120));
+    progress_bar.set_style(
+        create_progress_style("{spinner:.green} [{elapsed_precise}] {msg}")
+            .context("Unable to create packaging progress bar style")?,
+    );
+    progress_bar.set_message(format!("Packaging {container} {description}..."));
+
+    let mut remux_command = command("ffmpeg");
+
+    remux_command
+        .arg("-hide_banner")
+        .arg("-y")
+        .arg("-i")
+        .arg(output_path)
+        .args(maps)
+        .args(["-c", "copy"]);
+
+    if container == Container::Mp4 {
+        // HEVC is tagged as hvc1, with its parameter sets only in the sample entry, as some
+        // players require. AVC and AV1 already get their avc1 and av01 sample entries.
+        if encoder == Encoder::X265 {
+            remux_command.args(["-tag:v", "hvc1"]);
+        }
+
+        // Moving the index to the front lets playback start before the whole file has downloaded.
+        remux_command.args(["-movflags", "+faststart"]);
+    }
+
+    let result = remux_command
+        .args(["-f", container.extension()])
+        .arg(&temporary_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Unable to spawn FFmpeg packaging subprocess")?;
+
+    if !result.status.success() {
+        progress_bar.set_message(format!("Packaging {container} {description}...failed!"));
+        progress_bar.finish();
+
+        return Err(anyhow!(
+            "FFmpeg returned error code {} while packaging and the following output:\n{}",
+            result.status,
+            str::from_utf8(&result.stderr).context("Unable to parse FFmpeg output as UTF-8")?
+        ));
+    }
+
+    fs::rename(&temporary_path, &packaged_path)
+        .with_context(|| format!("Unable to rename {temporary_path:?} to {packaged_path:?}"))?;
+
+    progress_bar.set_message(format!("Packaging {container} {description}...done!"));
+    progress_bar.finish();
+
+    Ok(packaged_path)
+}
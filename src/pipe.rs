@@ -0,0 +1,71 @@
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+// Large enough to hold several raw frames, so each side is woken as rarely as possible.
+const BUFFER_SIZE: usize = 4 << 20;
+
+/// Which side of a relay stopped it before the input ran out.
+#[derive(Debug)]
+pub enum RelayFailure {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+/// How much a relay passed on and how long it waited for each side.
+///
+/// Time spent waiting to read means the decoder was the bottleneck, and time spent waiting to write
+/// means the encoder was.
+#[derive(Debug, Default)]
+pub struct Relay {
+    pub bytes: u64,
+    pub read_wait: Duration,
+    pub write_wait: Duration,
+    pub longest_write: Duration,
+    pub failure: Option<RelayFailure>,
+}
+
+impl Relay {
+    #[must_use]
+    pub fn encoder_bound(&self) -> bool {
+        self.write_wait > self.read_wait
+    }
+}
+
+/// Copies everything from the reader to the writer, closing the writer at the end of the input.
+///
+/// Passing the stream through here instead of connecting the processes directly shows which side
+/// stalled or went away first.
+pub fn relay(mut reader: impl Read, mut writer: impl Write) -> Relay {
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut relay = Relay::default();
+
+    loop {
+        let start = Instant::now();
+
+        let bytes = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => {
+                relay.failure = Some(RelayFailure::Read(error));
+                break;
+            }
+        };
+
+        relay.read_wait += start.elapsed();
+
+        let start = Instant::now();
+
+        if let Err(error) = writer.write_all(buffer.get(..bytes).unwrap_or_default()) {
+            relay.failure = Some(RelayFailure::Write(error));
+            break;
+        }
+
+        let elapsed = start.elapsed();
+        relay.write_wait += elapsed;
+        relay.longest_write = relay.longest_write.max(elapsed);
+        relay.bytes += u64::try_from(bytes).unwrap_or(u64::MAX);
+    }
+
+    relay
+}
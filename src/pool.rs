@@ -0,0 +1,61 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::metrics::ThreadBudget;
+
+/// Tracks how many workers are running an encoder or measuring a metric, so that metric
+/// calculations can borrow the threads of workers that are doing neither.
+pub struct WorkerPool {
+    workers: usize,
+    encoding: AtomicUsize,
+    measuring: AtomicUsize,
+}
+
+/// Counts a worker as busy until dropped.
+pub struct Activity<'a>(&'a AtomicUsize);
+
+impl Drop for Activity<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl WorkerPool {
+    #[must_use]
+    pub const fn new(workers: usize) -> Self {
+        Self {
+            workers,
+            encoding: AtomicUsize::new(0),
+            measuring: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn encoding(&self) -> Activity<'_> {
+        self.encoding.fetch_add(1, Ordering::Relaxed);
+        Activity(&self.encoding)
+    }
+
+    pub fn measuring(&self) -> Activity<'_> {
+        self.measuring.fetch_add(1, Ordering::Relaxed);
+        Activity(&self.measuring)
+    }
+
+    // Every worker that is not encoding contributes its share, split evenly between the current
+    // measurements. Each measurement keeps at least the one thread its own worker provides.
+    #[expect(clippy::integer_division)]
+    #[expect(clippy::integer_division_remainder_used)]
+    #[must_use]
+    pub fn metric_threads(&self) -> usize {
+        let encoding = self.encoding.load(Ordering::Relaxed);
+        let measuring = self.measuring.load(Ordering::Relaxed).max(1);
+
+        (self.workers.saturating_sub(encoding) / measuring).max(1)
+    }
+
+    /// A budget that follows this pool as workers start and finish encodes.
+    #[must_use]
+    pub fn budget(self: &Arc<Self>) -> ThreadBudget {
+        let pool = Arc::clone(self);
+        Arc::new(move || pool.metric_threads())
+    }
+}
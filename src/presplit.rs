@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::ffmpeg::{dimensions, frame_rate, Metadata};
+use crate::util::{file_cache_key, hash_source, hash_string, read_json, Versioned};
+
+/// One lossless chunk listed in a pre-split index, in playback order.
+#[derive(Clone, Deserialize)]
+pub struct Chunk {
+    pub file: PathBuf,
+    pub frames: usize,
+}
+
+#[derive(Deserialize)]
+struct ChunkIndex {
+    chunks: Vec<Chunk>,
+}
+
+// Chunk paths are relative to the index, so a chunk directory can be moved as a whole. Every chunk
+// becomes one scene and is encoded as is, so options that decode the original source cannot apply.
+pub fn load(config: &Config) -> anyhow::Result<Vec<Chunk>> {
+    if config.streaming
+        || config.single_encode
+        || config.proxy
+        || config.import_scenes.is_some()
+        || config.audio_encoder.is_some()
+        || config.subtitles.is_some()
+        || config.chapters
+    {
+        return Err(anyhow!(
+            "Pre-split chunks replace the source, so streaming, whole video encoding, proxies, imported scenes, audio, subtitles and chapters cannot be used with them"
+        ));
+    }
+
+    let index: ChunkIndex = read_json(&config.source)
+        .with_context(|| format!("Unable to read chunk index {:?}", &config.source))?
+        .ok_or_else(|| anyhow!("Chunk index {:?} does not exist", &config.source))?;
+
+    let directory = config
+        .source
+        .parent()
+        .map(PathBuf::from)
+        .unwrap_or_default();
+
+    if index.chunks.is_empty() {
+        return Err(anyhow!("Chunk index {:?} lists no chunks", &config.source));
+    }
+
+    index
+        .chunks
+        .into_iter()
+        .enumerate()
+        .map(|(position, chunk)| {
+            let file = directory.join(&chunk.file);
+
+            if chunk.frames == 0 {
+                return Err(anyhow!("Chunk {position} ({file:?}) has no frames"));
+            }
+
+            if !file.is_file() {
+                return Err(anyhow!("Chunk {position} ({file:?}) does not exist"));
+            }
+
+            Ok(Chunk {
+                file,
+                frames: chunk.frames,
+            })
+        })
+        .collect()
+}
+
+// Only the first chunk is probed, since the chunks of one video share their format. Chunks are
+// assumed to be cropped already, so the crop only records their dimensions.
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+pub fn metadata(config: &Config) -> anyhow::Result<Metadata> {
+    let chunks = load(config).context("Unable to load chunk index")?;
+
+    let first = &chunks
+        .first()
+        .ok_or_else(|| anyhow!("Chunk index lists no chunks"))?
+        .file;
+
+    let (numerator, denominator) = frame_rate(first)
+        .with_context(|| format!("Unable to determine frame rate of {first:?}"))?;
+
+    let (width, height) = dimensions(first)
+        .with_context(|| format!("Unable to determine dimensions of {first:?}"))?;
+
+    let frame_count = chunks.iter().map(|chunk| chunk.frames).sum::<usize>();
+
+    Ok(Metadata {
+        version: Metadata::VERSION,
+        frame_count,
+        duration: frame_count as f64 * f64::from(denominator) / f64::from(numerator),
+        crop_filter: Some(format!("crop={width}:{height}:0:0")),
+        source_hash: None,
+        pixel_format: None,
+        full_range: None,
+        crop_settings: None,
+    })
+}
+
+pub fn chunk(config: &Config, scene_index: usize) -> anyhow::Result<PathBuf> {
+    load(config)
+        .context("Unable to load chunk index")?
+        .into_iter()
+        .nth(scene_index)
+        .map(|chunk| chunk.file)
+        .ok_or_else(|| anyhow!("Chunk index has no chunk for scene {scene_index}"))
+}
+
+// The index alone does not change when a chunk is re-exported under the same name, so the size and
+// modification time of every chunk are hashed along with it. Hashing the chunks themselves would
+// read far more than the source hash otherwise does.
+pub fn source_hash(config: &Config) -> anyhow::Result<String> {
+    let index_hash = hash_source(&config.source)
+        .with_context(|| format!("Unable to hash source file {:?}", &config.source))?;
+
+    if !config.pre_split {
+        return Ok(index_hash);
+    }
+
+    let chunks = load(config).context("Unable to load chunk index")?;
+
+    Ok(hash_string(
+        &chunks.iter().fold(index_hash, |key, chunk| {
+            format!("{key}:{}", file_cache_key(&chunk.file))
+        }),
+    ))
+}
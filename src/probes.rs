@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::util::{read_json, write_json};
+
+/// A single probe encode of a scene and the scores measured for it so far.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Probe {
+    pub quality: f64,
+    pub filename: String,
+    pub scores: BTreeMap<String, f64>,
+}
+
+/// One step of a scene's quality search, in the order the probes were made.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchStep {
+    pub probe: usize,
+    pub minimum: f64,
+    pub maximum: f64,
+    pub quality: f64,
+    pub score: f64,
+    pub bitrate: f64,
+    pub elapsed_seconds: f64,
+}
+
+/// The trajectory of a scene's quality search, written beside its probes.
+///
+/// Search settings can then be tuned against what the searches actually did. Elapsed times are
+/// cumulative from the start of the search, and probes reused from earlier runs take almost no
+/// time.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchTimeline {
+    pub metric: String,
+    pub target: f64,
+    pub steps: Vec<SearchStep>,
+    pub quality: f64,
+    pub score: f64,
+}
+
+impl SearchTimeline {
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        write_json(path, self).with_context(|| format!("Unable to write search timeline {path:?}"))
+    }
+}
+
+/// Every probe encode of a scene, keyed by its file name. Probes only depend on the encoder
+/// settings and quality value, so they remain valid when the quality target or metric changes.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ProbeIndex {
+    probes: BTreeMap<String, Probe>,
+}
+
+impl ProbeIndex {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(read_json(path)
+            .with_context(|| format!("Unable to read probe index {path:?}"))?
+            .unwrap_or_default())
+    }
+
+    // Returns whether the index changed and therefore needs to be written.
+    pub fn record(
+        &mut self,
+        quality: f64,
+        output_filename: &Path,
+        metric: &str,
+        score: f64,
+    ) -> bool {
+        let filename = output_filename
+            .file_name()
+            .map(|filename| filename.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let probe = self
+            .probes
+            .entry(filename.clone())
+            .or_insert_with(|| Probe {
+                quality,
+                filename,
+                scores: BTreeMap::new(),
+            });
+
+        probe.scores.insert(metric.to_owned(), score) != Some(score)
+    }
+
+    // The probe whose score lies closest to the target is the best starting point for a new
+    // search, since the search then only has to bracket the answer around it.
+    #[must_use]
+    pub fn closest(&self, metric: &str, target: f64) -> Option<f64> {
+        self.probes
+            .values()
+            .filter_map(|probe| {
+                probe
+                    .scores
+                    .get(metric)
+                    .map(|score| ((score - target).abs(), probe.quality))
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, quality)| quality)
+    }
+
+    #[must_use]
+    pub fn contains(&self, filename: &str) -> bool {
+        self.probes.contains_key(filename)
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        write_json(path, self).with_context(|| format!("Unable to write probe index {path:?}"))
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use indicatif::ProgressBar;
+
+use crate::config::Config;
+use crate::tools::command;
+use crate::util::{create_progress_style, hash_source, verify_directory};
+
+// Scene cuts survive downscaling far better than anything measured per pixel, so the proxy only
+// has to be good enough to tell shots apart. Crop detection needs exact source coordinates, and the
+// grain and animation analyses measure the very detail the proxy throws away, so those keep
+// decoding the source.
+const PROXY_FILTER: &str = "scale=-2:'min(480,ih)'";
+
+/// Returns a small, quickly decoded copy of the source with exactly the same frames, creating it
+/// the first time it is needed.
+///
+/// The proxy is kept beside the other caches and named after the source hash, so it is created at
+/// most once per source.
+pub fn get(config: &Config) -> anyhow::Result<PathBuf> {
+    let proxy_directory = config.output_directory.join("proxy");
+
+    verify_directory(&proxy_directory)
+        .with_context(|| format!("Unable to verify proxy directory {proxy_directory:?}"))?;
+
+    let source_hash = hash_source(&config.source)
+        .with_context(|| format!("Unable to hash source file {:?}", &config.source))?;
+
+    let proxy_path = proxy_directory.join(format!("{source_hash}.mkv"));
+
+    if proxy_path.exists() {
+        return Ok(proxy_path);
+    }
+
+    let temporary_path = proxy_path.with_extension("tmp.mkv");
+
+    let progress_bar = ProgressBar::new_spinner();
+    progress_bar.enable_steady_tick(Duration::from_millis(120));
+    progress_bar.set_style(
+        create_progress_style("{spinner:.green} [{elapsed_precise}] {msg}")
+            .context("Unable to create proxy progress bar style")?,
+    );
+    progress_bar.set_message("Creating scene detection proxy...");
+
+    // Passing every frame through unchanged keeps frame numbers on the proxy valid for the source.
+    let output = command("ffmpeg")
+        .arg("-y")
+        .arg("-i")
+        .arg(&config.source)
+        .args([
+            "-map",
+            "0:v:0",
+            "-vf",
+            PROXY_FILTER,
+            "-fps_mode",
+            "passthrough",
+        ])
+        .args(["-c:v", "libx264", "-preset", "ultrafast", "-crf", "18"])
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(&temporary_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Unable to run proxy encoding subprocess")?;
+
+    if !output.status.success() {
+        progress_bar.finish_with_message("Creating scene detection proxy...failed!");
+
+        return Err(anyhow!(
+            "Proxy encoding did not complete successfully: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    fs::rename(&temporary_path, &proxy_path)
+        .with_context(|| format!("Unable to rename {temporary_path:?} to {proxy_path:?}"))?;
+
+    progress_bar.finish_with_message("Creating scene detection proxy...done!");
+
+    Ok(proxy_path)
+}
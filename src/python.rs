@@ -0,0 +1,177 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use clap::Parser;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::config::Config;
+use crate::metrics::{ClipMetrics, MetricContext};
+use crate::scenes;
+use crate::util::{set_progress_callback, ProgressCallback};
+
+fn to_python_error(error: &anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{error:?}"))
+}
+
+#[pyclass(name = "Config", module = "video_encoding_wrapper", frozen)]
+struct PythonConfig {
+    inner: Config,
+}
+
+#[pymethods]
+impl PythonConfig {
+    // Options are accepted in their command line form so the Python API never drifts from the CLI.
+    #[new]
+    #[pyo3(signature = (source, output_directory, arguments = vec![]))]
+    fn new(source: PathBuf, output_directory: PathBuf, arguments: Vec<String>) -> PyResult<Self> {
+        let mut command_line = vec![OsString::from(env!("CARGO_PKG_NAME"))];
+        command_line.extend(arguments.into_iter().map(OsString::from));
+        command_line.push(source.into_os_string());
+        command_line.push(output_directory.into_os_string());
+
+        Config::try_parse_from(command_line)
+            .map(|inner| Self { inner })
+            .map_err(|error| PyValueError::new_err(error.to_string()))
+    }
+
+    fn encode_identifier(&self, include_quality: bool) -> String {
+        self.inner.encode_identifier(include_quality)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.inner).map_err(|error| PyValueError::new_err(error.to_string()))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.inner)
+    }
+}
+
+#[pyclass(name = "ClipMetrics", module = "video_encoding_wrapper")]
+struct PythonClipMetrics {
+    inner: ClipMetrics,
+}
+
+#[pymethods]
+impl PythonClipMetrics {
+    #[new]
+    #[expect(clippy::needless_pass_by_value)]
+    fn new(path: PathBuf, reference_path: PathBuf) -> PyResult<Self> {
+        ClipMetrics::new(
+            &path,
+            &reference_path,
+            None,
+            None,
+            None,
+            &MetricContext::default(),
+        )
+        .map(|inner| Self { inner })
+        .map_err(|error| to_python_error(&error))
+    }
+
+    fn frames(&mut self, py: Python<'_>) -> PyResult<usize> {
+        py.allow_threads(|| self.inner.frames())
+            .map_err(|error| to_python_error(&error))
+    }
+
+    fn duration(&mut self, py: Python<'_>) -> PyResult<f64> {
+        py.allow_threads(|| self.inner.duration())
+            .map_err(|error| to_python_error(&error))
+    }
+
+    fn sizes(&mut self, py: Python<'_>) -> PyResult<Vec<usize>> {
+        py.allow_threads(|| self.inner.sizes().cloned())
+            .map_err(|error| to_python_error(&error))
+    }
+
+    #[pyo3(signature = (threads = 1))]
+    fn psnr(&mut self, py: Python<'_>, threads: usize) -> PyResult<Vec<f64>> {
+        py.allow_threads(|| self.inner.psnr(threads).cloned())
+            .map_err(|error| to_python_error(&error))
+    }
+
+    #[pyo3(signature = (threads = 1))]
+    fn ssim(&mut self, py: Python<'_>, threads: usize) -> PyResult<Vec<f64>> {
+        py.allow_threads(|| self.inner.ssim(threads).cloned())
+            .map_err(|error| to_python_error(&error))
+    }
+
+    #[pyo3(signature = (threads = 1))]
+    fn vmaf(&mut self, py: Python<'_>, threads: usize) -> PyResult<Vec<f64>> {
+        py.allow_threads(|| self.inner.vmaf(threads).cloned())
+            .map_err(|error| to_python_error(&error))
+    }
+
+    #[pyo3(signature = (threads = 1))]
+    fn ssimulacra2(&mut self, py: Python<'_>, threads: usize) -> PyResult<Vec<f64>> {
+        py.allow_threads(|| self.inner.ssimulacra2(threads).cloned())
+            .map_err(|error| to_python_error(&error))
+    }
+}
+
+// Progress is reported from worker threads, so the GIL is reacquired for each call. Exceptions
+// raised by the callable are printed rather than aborting the encode.
+fn install_progress_callback(progress: Option<PyObject>) {
+    set_progress_callback(progress.map(|callable| -> ProgressCallback {
+        Box::new(move |stage, position, length| {
+            Python::with_gil(|py| {
+                if let Err(error) = callable.call1(py, (stage, position, length)) {
+                    error.print(py);
+                }
+            });
+        })
+    }));
+}
+
+fn with_progress<T>(
+    py: Python<'_>,
+    progress: Option<PyObject>,
+    function: impl FnOnce() -> anyhow::Result<T> + Send,
+) -> PyResult<T>
+where
+    T: Send,
+{
+    install_progress_callback(progress);
+    let result = py.allow_threads(function);
+    set_progress_callback(None);
+
+    result.map_err(|error| to_python_error(&error))
+}
+
+#[pyfunction]
+#[pyo3(signature = (config, progress = None))]
+fn run(py: Python<'_>, config: &PythonConfig, progress: Option<PyObject>) -> PyResult<()> {
+    with_progress(py, progress, || crate::run(&config.inner))
+}
+
+#[pyfunction]
+#[pyo3(signature = (config, progress = None))]
+fn detect_scenes(
+    py: Python<'_>,
+    config: &PythonConfig,
+    progress: Option<PyObject>,
+) -> PyResult<Vec<(usize, usize, usize)>> {
+    let scenes = with_progress(py, progress, || scenes::get(&config.inner))?;
+
+    Ok(scenes
+        .iter()
+        .map(|scene| {
+            (
+                scene.index(),
+                scene.start_frame(),
+                scene.start_frame() + scene.length() - 1,
+            )
+        })
+        .collect())
+}
+
+#[pymodule]
+fn video_encoding_wrapper(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PythonConfig>()?;
+    module.add_class::<PythonClipMetrics>()?;
+    module.add_function(wrap_pyfunction!(run, module)?)?;
+    module.add_function(wrap_pyfunction!(detect_scenes, module)?)?;
+
+    Ok(())
+}
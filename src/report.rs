@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Encoder;
+
+/// The summary an encoder printed at the end of a scene's final pass.
+///
+/// Each encoder reports a different subset, so every field is optional. The bitrate is in kbps.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EncoderReport {
+    pub average_qp: Option<f64>,
+    pub bitrate: Option<f64>,
+    pub frame_types: BTreeMap<String, usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub measured_bitrate: Option<f64>,
+}
+
+impl EncoderReport {
+    fn is_empty(&self) -> bool {
+        self.average_qp.is_none() && self.bitrate.is_none() && self.frame_types.is_empty()
+    }
+}
+
+// The number that follows a marker, ignoring padding and any unit glued to its end.
+fn number_after<T: std::str::FromStr>(line: &str, marker: &str) -> Option<T> {
+    let (_, rest) = line.split_once(marker)?;
+
+    let token = rest
+        .trim_start()
+        .split(|character: char| !(character.is_ascii_digit() || character == '.'))
+        .next()?;
+
+    token.parse().ok()
+}
+
+// Frame-type summaries give an average QP per type, which are combined weighted by frame count.
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+fn weighted_qp(types: &[(usize, f64)]) -> Option<f64> {
+    let frames = types.iter().map(|(count, _)| *count).sum::<usize>();
+
+    (frames > 0).then(|| {
+        types
+            .iter()
+            .map(|(count, qp)| *count as f64 * qp)
+            .sum::<f64>()
+            / frames as f64
+    })
+}
+
+// x264 and x265 print one line per frame type followed by an overall line.
+fn parse_x26x(lines: &[&str]) -> EncoderReport {
+    let mut report = EncoderReport::default();
+    let mut type_qps = vec![];
+
+    for line in lines {
+        if let Some((_, rest)) = line.split_once("]: frame ") {
+            let Some((frame_type, _)) = rest.split_once(':') else {
+                continue;
+            };
+
+            if let Some(count) = number_after::<usize>(rest, ":") {
+                report.frame_types.insert(frame_type.to_owned(), count);
+
+                if let Some(qp) = number_after::<f64>(rest, "Avg QP:") {
+                    type_qps.push((count, qp));
+                }
+            }
+        } else if line.contains("encoded ") {
+            report.bitrate = line
+                .split(',')
+                .find(|field| field.contains("kb/s"))
+                .and_then(|field| number_after(field, ""));
+
+            report.average_qp = number_after(line, "Avg QP:");
+        }
+    }
+
+    report.average_qp = report.average_qp.or_else(|| weighted_qp(&type_qps));
+    report
+}
+
+// libaom and libvpx redraw one status line per frame, and the last one covers the whole pass.
+fn parse_libaom(lines: &[&str]) -> EncoderReport {
+    let mut report = EncoderReport::default();
+
+    if let Some(line) = lines.iter().rev().find(|line| line.contains("b/s")) {
+        report.bitrate = line
+            .split_whitespace()
+            .find_map(|field| field.strip_suffix("b/s"))
+            .and_then(|value| value.parse::<f64>().ok())
+            .map(|bitrate| bitrate / 1000.0);
+    }
+
+    report
+}
+
+fn parse_rav1e(lines: &[&str]) -> EncoderReport {
+    let mut report = EncoderReport::default();
+    let mut type_qps = vec![];
+
+    for line in lines {
+        for frame_type in ["Key Frames", "Intra Only", "Inter", "Switch"] {
+            if let Some(count) = line
+                .trim_start()
+                .strip_prefix(frame_type)
+                .and_then(|rest| number_after::<usize>(rest, ":"))
+            {
+                report.frame_types.insert(frame_type.to_owned(), count);
+
+                if let Some(qp) = number_after::<f64>(line, "avg QP:") {
+                    type_qps.push((count, qp));
+                }
+            }
+        }
+
+        if line.starts_with("encoded ") {
+            report.bitrate = line
+                .split(',')
+                .find(|field| field.to_lowercase().contains("kb/s"))
+                .and_then(|field| number_after(field, ""));
+        }
+    }
+
+    report.average_qp = weighted_qp(&type_qps);
+    report
+}
+
+// SVT-AV1 prints tab-separated tables, with each row of values on the line after its headers.
+fn parse_svt_av1(lines: &[&str]) -> EncoderReport {
+    let mut report = EncoderReport::default();
+
+    for (headers, values) in lines.iter().zip(lines.iter().skip(1)) {
+        let headers = headers
+            .split('\t')
+            .map(str::trim)
+            .filter(|field| !field.is_empty());
+        let values = values
+            .split('\t')
+            .map(str::trim)
+            .filter(|field| !field.is_empty());
+
+        for (header, value) in headers.zip(values) {
+            match header {
+                "Average QP" => report.average_qp = number_after(value, ""),
+                "Bitrate" => report.bitrate = number_after(value, ""),
+                _ => {}
+            }
+        }
+    }
+
+    report
+}
+
+// FFmpeg's final progress line carries the size of the whole output.
+fn parse_ffmpeg(lines: &[&str]) -> EncoderReport {
+    EncoderReport {
+        bitrate: lines
+            .iter()
+            .rev()
+            .find_map(|line| number_after(line, "bitrate=")),
+        ..EncoderReport::default()
+    }
+}
+
+/// Extracts whatever summary the encoder printed, or nothing if it printed none that is recognized.
+#[must_use]
+pub fn parse(encoder: Encoder, output: &str) -> Option<EncoderReport> {
+    let lines = output
+        .split(['\r', '\n'])
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+
+    let report = match encoder {
+        Encoder::X264 | Encoder::X265 => parse_x26x(&lines),
+        Encoder::Aomenc | Encoder::Vpxenc => parse_libaom(&lines),
+        Encoder::Rav1e => parse_rav1e(&lines),
+        Encoder::SvtAv1 => parse_svt_av1(&lines),
+        Encoder::Ffv1 | Encoder::Prores | Encoder::Dnxhr => parse_ffmpeg(&lines),
+    };
+
+    (!report.is_empty()).then_some(report)
+}
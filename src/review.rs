@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use std::str;
+
+use anyhow::{anyhow, Context};
+
+use crate::config::ReviewMode;
+use crate::control::track;
+use crate::ffmpeg::{create_child_read, SourceRange};
+use crate::tools::command;
+use crate::util::verify_filename;
+
+// The difference is amplified eightfold before coloring, since most encoding errors are only a few
+// code values and would otherwise be invisible.
+const DIFFERENCE_FILTER: &str =
+    "blend=all_mode=difference,lutyuv=y='clip(val*8,0,255)':u=128:v=128,pseudocolor=preset=heat";
+
+fn filter_graph(mode: ReviewMode, display_filter: Option<&str>) -> String {
+    let display_filter = display_filter.map_or_else(String::new, |filter| format!("{filter},"));
+    let inputs = format!("[0:v]setpts=PTS-STARTPTS,format=yuv420p[source];[1:v]{display_filter}setpts=PTS-STARTPTS,format=yuv420p[encode]");
+
+    let output = match mode {
+        ReviewMode::SideBySide => "[source][encode]hstack[review]".to_owned(),
+        // The seam is kept on an even column so that both halves remain valid 4:2:0 frames.
+        ReviewMode::Split => "[source]crop=trunc(iw/4)*2:ih:0:0[left];[encode]crop=iw-trunc(iw/4)*2:ih:trunc(iw/4)*2:0[right];[left][right]hstack[review]".to_owned(),
+        ReviewMode::Difference => format!("[encode][source]{DIFFERENCE_FILTER}[review]"),
+    };
+
+    format!("{inputs};{output}")
+}
+
+// The reference is always decoded by a separate FFmpeg subprocess, which applies the same filter and
+// range the metrics were measured with. Both clips are scaled to the metric display resolution, if
+// there is one, so the review shows exactly what was scored.
+pub fn render(
+    mode: ReviewMode,
+    reference: &Path,
+    reference_filter: Option<&str>,
+    reference_range: Option<SourceRange>,
+    encoded: &Path,
+    display_filter: Option<&str>,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    if output_path.exists() {
+        return Ok(());
+    }
+
+    verify_filename(output_path)
+        .with_context(|| format!("Unable to verify review output path {output_path:?}"))?;
+
+    let temporary_path = output_path.with_extension("tmp.mkv");
+
+    let reference_filter = [reference_filter, display_filter]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut decoder = create_child_read(
+        reference,
+        (!reference_filter.is_empty()).then_some(reference_filter.as_str()),
+        "yuv420p10le",
+        reference_range,
+        Stdio::null(),
+        Stdio::piped(),
+        Stdio::null(),
+    )
+    .context("Unable to spawn review reference decoder subprocess")?;
+
+    let decoder_tracker = track(&decoder);
+
+    let decoder_stdout = decoder
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Unable to access stdout for review reference decoder"))?;
+
+    let child = command("ffmpeg")
+        .args(["-y", "-f", "yuv4mpegpipe", "-i", "-", "-i"])
+        .arg(encoded)
+        .arg("-filter_complex")
+        .arg(filter_graph(mode, display_filter))
+        .args([
+            "-map", "[review]", "-c:v", "libx264", "-preset", "medium", "-crf", "12", "-an",
+        ])
+        .arg(&temporary_path)
+        .stdin(decoder_stdout)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Unable to spawn review rendering subprocess")?;
+
+    let tracker = track(&child);
+
+    let result = tracker
+        .wait_with_output(child)
+        .context("Unable to wait for review rendering subprocess")?;
+
+    decoder_tracker
+        .wait(&mut decoder)
+        .context("Unable to wait for review reference decoder subprocess")?;
+
+    if !result.status.success() {
+        return Err(anyhow!(
+            "Review rendering subprocess did not complete successfully: {}",
+            str::from_utf8(&result.stderr).context("Unable to decode FFmpeg output as UTF-8")?
+        ));
+    }
+
+    fs::rename(&temporary_path, output_path)
+        .with_context(|| format!("Unable to rename {temporary_path:?} to {output_path:?}"))?;
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
-use std::fs::{remove_file, rename, File};
-use std::io::BufReader;
-use std::process::{ChildStdout, Command, Stdio};
+use std::collections::BTreeMap;
+use std::fs::{self, read_dir, remove_dir_all, remove_file, rename};
+use std::path::Path;
+use std::process::{ChildStdout, Stdio};
 
 use anyhow::{anyhow, Context};
 use av_scenechange::{
@@ -11,9 +12,22 @@ use indicatif::ProgressBar;
 use serde::{Deserialize, Serialize};
 use tracing::warn;
 
-use crate::config::Config;
-use crate::ffmpeg::{create_child_read, get_metadata};
-use crate::util::{create_progress_style, verify_directory, verify_filename};
+use crate::cache::{publish, restore, shared_directory};
+use crate::config::{Config, SceneDetection, SceneFormat, SourceDepth};
+use crate::ffmpeg::{
+    create_child_read, get_metadata, intra_only_dimensions, Metadata, SourceRange,
+};
+use crate::presplit;
+use crate::proxy;
+use crate::tools::command;
+use crate::util::{
+    create_progress_style, hash_file, hash_string, notify_progress, read_json, read_versioned_json,
+    verify_directory, verify_filename, write_json, Versioned,
+};
+
+// Downscaling to 540p keeps the cuts nearly identical while making detection far cheaper on UHD
+// sources. Sources that are already smaller are left alone.
+const FAST_DETECTION_FILTER: &str = "scale=-2:'min(540,ih)'";
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Scene {
@@ -22,24 +36,378 @@ pub struct Scene {
     end_frame: usize,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SceneCache {
+    Keyed {
+        #[serde(default)]
+        version: u32,
+        key: String,
+        scenes: Vec<Scene>,
+    },
+    Legacy(Vec<Scene>),
+}
+
+impl Versioned for SceneCache {
+    const VERSION: u32 = 1;
+
+    fn version(&self) -> u32 {
+        match self {
+            Self::Keyed { version, .. } => *version,
+            Self::Legacy(_) => 0,
+        }
+    }
+
+    // Legacy caches are left alone here, since keying them needs the current source and is done
+    // when they are loaded.
+    fn set_version(&mut self, new_version: u32) {
+        if let Self::Keyed { version, .. } = self {
+            *version = new_version;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SplitCache {
+    key: String,
+}
+
 impl Scene {
     #[must_use]
     pub const fn index(&self) -> usize {
         self.index
     }
 
+    #[must_use]
+    pub const fn start_frame(&self) -> usize {
+        self.start_frame
+    }
+
+    #[must_use]
+    pub const fn end_frame(&self) -> usize {
+        self.end_frame
+    }
+
     #[must_use]
     pub const fn length(&self) -> usize {
         self.end_frame - self.start_frame + 1
     }
+
+    #[must_use]
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn duration(&self, metadata: &Metadata) -> f64 {
+        self.length() as f64 * metadata.duration / metadata.frame_count as f64
+    }
+
+    #[must_use]
+    #[expect(clippy::as_conversions)]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn source_range(&self, metadata: &Metadata) -> SourceRange {
+        let frame_rate = metadata.frame_count as f64 / metadata.duration;
+
+        // Seeking to half a frame before the scene start keeps the seek frame-accurate even when
+        // container timestamps are slightly rounded.
+        let start_time = if self.start_frame == 0 {
+            0.0
+        } else {
+            (self.start_frame as f64 - 0.5) / frame_rate
+        };
+
+        SourceRange {
+            start_time,
+            frames: self.length(),
+        }
+    }
+}
+
+fn create_detection_decoder(config: &Config) -> anyhow::Result<Decoder<ChildStdout>> {
+    let source = if config.proxy {
+        proxy::get(config).context("Unable to create scene detection proxy")?
+    } else {
+        config.source.clone()
+    };
+
+    let decoder = match config.scene_detection {
+        SceneDetection::Accurate => Decoder::Ffmpeg(
+            FfmpegDecoder::new(&source)
+                .with_context(|| format!("Unable to create FFmpeg decoder for {source:?}"))?,
+        ),
+        SceneDetection::Fast => Decoder::Y4m(
+            y4m::Decoder::new(
+                create_child_read(
+                    &source,
+                    Some(FAST_DETECTION_FILTER),
+                    "yuv420p",
+                    None,
+                    Stdio::null(),
+                    Stdio::piped(),
+                    Stdio::null(),
+                )
+                .context("Unable to spawn downscaled scene detection decoder subprocess")?
+                .stdout
+                .ok_or_else(|| {
+                    anyhow!("Unable to access stdout for scene detection decoder subprocess")
+                })?,
+            )
+            .context("Unable to create scene detection YUV4MPEG decoder")?,
+        ),
+    };
+
+    Ok(decoder)
+}
+
+fn detect(
+    config: &Config,
+    metadata: &Metadata,
+    progress_bar: &ProgressBar,
+) -> anyhow::Result<Vec<Scene>> {
+    let mut decoder =
+        create_detection_decoder(config).context("Unable to create scene detection decoder")?;
+
+    // Screen captures change with hard cuts rather than motion, and flash heuristics would merge
+    // rapid window switches, so plain pixel comparison works better there.
+    let opts = DetectionOptions {
+        analysis_speed: if config.screen_content {
+            SceneDetectionSpeed::Fast
+        } else {
+            SceneDetectionSpeed::Standard
+        },
+        detect_flashes: !config.screen_content,
+        min_scenecut_distance: None,
+        max_scenecut_distance: None,
+        lookahead_distance: 5,
+    };
+
+    let progress_callback = |frames: usize, _keyframes: usize| {
+        progress_bar.set_position(frames.try_into().unwrap_or(u64::MAX));
+        notify_progress("scenes", progress_bar);
+    };
+
+    let results = match config.scene_detection {
+        SceneDetection::Accurate => {
+            detect_scene_changes::<_, u16>(&mut decoder, opts, None, Some(&progress_callback))
+        }
+        SceneDetection::Fast => {
+            detect_scene_changes::<_, u8>(&mut decoder, opts, None, Some(&progress_callback))
+        }
+    }
+    .context("Unable to detect scene changes")?;
+
+    progress_bar.finish();
+
+    if results.frame_count != metadata.frame_count {
+        if config.strict {
+            return Err(anyhow!(
+                "Source video had {} frames but {} were processed by the scene detector. \
+                The container frame count and the decoded frame count disagree, which \
+                usually indicates a damaged stream or a variable frame rate source. Every \
+                scene after the first divergence would be misaligned with the source.",
+                metadata.frame_count,
+                results.frame_count
+            ));
+        }
+
+        warn!(
+            "Source video had {} frames but {} were processed by the scene detector.",
+            metadata.frame_count, results.frame_count
+        );
+    }
+
+    let mut scene_changes = results.scene_changes;
+    scene_changes.push(metadata.frame_count);
+
+    let scenes: Vec<Scene> = scene_changes
+        .iter()
+        .zip(scene_changes.iter().skip(1))
+        .enumerate()
+        .map(|(index, (start_frame, next_start_frame))| Scene {
+            index,
+            start_frame: *start_frame,
+            end_frame: next_start_frame - 1,
+        })
+        .collect();
+
+    Ok(scenes)
+}
+
+fn write_scene_cache(json_path: &Path, key: &str, scenes: &[Scene]) -> anyhow::Result<()> {
+    write_json(
+        json_path,
+        &SceneCache::Keyed {
+            version: SceneCache::VERSION,
+            key: key.to_owned(),
+            scenes: scenes.to_vec(),
+        },
+    )
+    .with_context(|| format!("Unable to write scene cache {json_path:?}"))
+}
+
+// Screen content detection is tuned differently, so it is cached separately without disturbing
+// existing caches.
+fn detection_name(config: &Config) -> String {
+    if config.pre_split {
+        "pre-split".to_owned()
+    } else if config.import_scenes.is_some() {
+        "import".to_owned()
+    } else {
+        let name = if config.screen_content {
+            format!("{}-screen", config.scene_detection)
+        } else {
+            config.scene_detection.to_string()
+        };
+
+        // Cuts found on the proxy can differ slightly, so they are cached separately.
+        if config.proxy {
+            format!("{name}-proxy")
+        } else {
+            name
+        }
+    }
+}
+
+fn scenes_key(config: &Config) -> anyhow::Result<String> {
+    let source_hash = presplit::source_hash(config).context("Unable to hash source")?;
+
+    // Imported scenes are keyed on the file contents so that editing the file takes effect.
+    let name = if let Some(path) = &config.import_scenes {
+        format!(
+            "import-{}",
+            hash_file(path).with_context(|| format!("Unable to hash scene file {path:?}"))?
+        )
+    } else {
+        detection_name(config)
+    };
+
+    Ok(hash_string(&format!("{source_hash}:{name}")))
+}
+
+// av1an end frames are exclusive. Its scenes must tile the whole source, since every frame has to
+// belong to exactly one scene.
+fn import(path: &Path, metadata: &Metadata) -> anyhow::Result<Vec<Scene>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Unable to read scene file {path:?}"))?;
+
+    let imported: Av1anScenes = serde_json::from_str(&contents)
+        .with_context(|| format!("Unable to deserialize av1an scenes from {path:?}"))?;
+
+    if imported.frames != metadata.frame_count {
+        return Err(anyhow!(
+            "Scene file {path:?} covers {} frames but the source has {}",
+            imported.frames,
+            metadata.frame_count
+        ));
+    }
+
+    let mut next_frame = 0;
+    let mut scenes = vec![];
+
+    for (index, scene) in imported.scenes.iter().enumerate() {
+        if scene.start_frame != next_frame || scene.end_frame <= scene.start_frame {
+            return Err(anyhow!(
+                "Scene {index} in {path:?} spans frames {}-{} but should start at frame {next_frame}",
+                scene.start_frame,
+                scene.end_frame
+            ));
+        }
+
+        scenes.push(Scene {
+            index,
+            start_frame: scene.start_frame,
+            end_frame: scene.end_frame - 1,
+        });
+
+        next_frame = scene.end_frame;
+    }
+
+    if next_frame != metadata.frame_count {
+        return Err(anyhow!(
+            "Scenes in {path:?} end at frame {next_frame} but the source has {} frames",
+            metadata.frame_count
+        ));
+    }
+
+    if imported.scenes.iter().any(|scene| {
+        scene
+            .zone_overrides
+            .as_ref()
+            .is_some_and(|value| !value.is_null())
+    }) {
+        warn!("Ignoring zone overrides in {path:?}. Only scene boundaries are imported.");
+    }
+
+    Ok(scenes)
+}
+
+// Each pre-split chunk is one scene, in the order the index lists them.
+fn chunk_scenes(config: &Config) -> anyhow::Result<Vec<Scene>> {
+    let mut next_frame = 0;
+
+    Ok(presplit::load(config)
+        .context("Unable to load chunk index")?
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let scene = Scene {
+                index,
+                start_frame: next_frame,
+                end_frame: next_frame + chunk.frames - 1,
+            };
+
+            next_frame += chunk.frames;
+
+            scene
+        })
+        .collect())
+}
+
+// Split scenes are stored at 10 bits unless an 8-bit 4:2:0 source is to be kept as it is. A 10-bit
+// 4:2:0 source already matches, so its frames pass through without conversion.
+#[must_use]
+pub fn split_pixel_format(config: &Config, metadata: &Metadata) -> &'static str {
+    match metadata.pixel_format.as_deref() {
+        Some("yuv420p" | "yuvj420p") if config.source_depth == SourceDepth::Keep => "yuv420p",
+        _ => "yuv420p10le",
+    }
+}
+
+pub fn split_key(config: &Config, metadata: &Metadata) -> anyhow::Result<String> {
+    let key = format!(
+        "{}:{}",
+        scenes_key(config).context("Unable to calculate scene cache key")?,
+        metadata.crop_filter.as_deref().unwrap_or("none")
+    );
+
+    // Scenes split at the original 10 bits keep their existing key.
+    let key = match split_pixel_format(config, metadata) {
+        "yuv420p10le" => key,
+        pixel_format => format!("{key}:{pixel_format}"),
+    };
+
+    // Full range scenes were once squeezed into limited range while splitting.
+    let key = if metadata.full_range == Some(true) {
+        format!("{key}:full-range")
+    } else {
+        key
+    };
+
+    Ok(hash_string(&key))
 }
 
 pub fn get(config: &Config) -> anyhow::Result<Vec<Scene>> {
-    let json_path = config.output_directory.join("config").join("scenes.json");
+    let json_path = config.config_directory().join("scenes.json");
     verify_filename(&json_path)
         .with_context(|| format!("Unable to verify scene cache path {json_path:?}"))?;
 
     let metadata = get_metadata(config).context("Unable to fetch video metadata")?;
+    let key = scenes_key(config).context("Unable to calculate scene cache key")?;
+
+    let shared_json_path = shared_directory(config)
+        .context("Unable to determine shared cache directory")?
+        .map(|directory| directory.join(format!("scenes-{}.json", detection_name(config))));
+
+    restore(&json_path, shared_json_path.as_deref())
+        .context("Unable to restore scenes from shared cache")?;
 
     let progress_bar = ProgressBar::new(
         metadata
@@ -54,11 +422,30 @@ pub fn get(config: &Config) -> anyhow::Result<Vec<Scene>> {
         ).context("Unable to create scene change detection progress bar style")?
     );
 
-    let scenes = if json_path.exists() {
-        let file = File::open(&json_path)
-            .with_context(|| format!("Unable to open scene cache {json_path:?}"))?;
-        let reader = BufReader::new(file);
+    let cached_scenes = match read_versioned_json(&json_path)
+        .context("Unable to read scene cache")?
+    {
+        Some(SceneCache::Keyed {
+            key: cached_key,
+            scenes,
+            ..
+        }) => {
+            if cached_key == key {
+                Some(scenes)
+            } else {
+                warn!("Source file or scene detection settings have changed since scenes were cached. Detecting scenes again.");
+                None
+            }
+        }
+        Some(SceneCache::Legacy(scenes)) => {
+            // Caches written before keying existed are assumed to belong to the current source.
+            write_scene_cache(&json_path, &key, &scenes)?;
+            Some(scenes)
+        }
+        None => None,
+    };
 
+    let scenes = if let Some(scenes) = cached_scenes {
         progress_bar.set_position(
             metadata
                 .frame_count
@@ -68,63 +455,292 @@ pub fn get(config: &Config) -> anyhow::Result<Vec<Scene>> {
         progress_bar.reset_eta();
         progress_bar.finish();
 
-        serde_json::from_reader(reader).context("Unable to deserialize scene cache")?
+        scenes
     } else {
-        let mut decoder: Decoder<ChildStdout> =
-            Decoder::Ffmpeg(FfmpegDecoder::new(&config.source).with_context(|| {
-                format!("Unable to create FFmpeg decoder for {:?}", &config.source)
-            })?);
-
-        let opts = DetectionOptions {
-            analysis_speed: SceneDetectionSpeed::Standard,
-            detect_flashes: true,
-            min_scenecut_distance: None,
-            max_scenecut_distance: None,
-            lookahead_distance: 5,
+        let scenes = if config.pre_split {
+            chunk_scenes(config).context("Unable to create scenes from chunk index")?
+        } else if let Some(path) = &config.import_scenes {
+            import(path, &metadata).context("Unable to import scenes")?
+        } else {
+            detect(config, &metadata, &progress_bar)?
         };
 
-        let progress_callback = |frames: usize, _keyframes: usize| {
-            progress_bar.set_position(frames.try_into().unwrap_or(u64::MAX));
-        };
+        write_scene_cache(&json_path, &key, &scenes)?;
 
-        let results =
-            detect_scene_changes::<_, u16>(&mut decoder, opts, None, Some(&progress_callback))
-                .context("Unable to detect scene changes")?;
+        publish(&json_path, shared_json_path.as_deref())
+            .context("Unable to publish scenes to shared cache")?;
 
-        progress_bar.finish();
+        scenes
+    };
 
-        if results.frame_count != metadata.frame_count {
-            warn!(
-                "Source video had {} frames but {} were processed by the scene detector.",
-                metadata.frame_count, results.frame_count
-            );
+    Ok(scenes)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Av1anScene {
+    start_frame: usize,
+    end_frame: usize,
+    #[serde(default)]
+    zone_overrides: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Av1anScenes {
+    scenes: Vec<Av1anScene>,
+    frames: usize,
+}
+
+#[expect(clippy::integer_division)]
+#[expect(clippy::integer_division_remainder_used)]
+#[must_use]
+pub fn format_timecode(frame: usize, frame_rate: usize) -> String {
+    let seconds = frame / frame_rate;
+
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        frame % frame_rate
+    )
+}
+
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_possible_truncation)]
+#[expect(clippy::cast_precision_loss)]
+#[expect(clippy::cast_sign_loss)]
+pub fn format_scenes(
+    format: SceneFormat,
+    scenes: &[Scene],
+    metadata: &Metadata,
+) -> anyhow::Result<String> {
+    let contents = match format {
+        SceneFormat::Av1an => serde_json::to_string_pretty(&Av1anScenes {
+            // av1an scene end frames are exclusive.
+            scenes: scenes
+                .iter()
+                .map(|scene| Av1anScene {
+                    start_frame: scene.start_frame,
+                    end_frame: scene.end_frame + 1,
+                    zone_overrides: None,
+                })
+                .collect(),
+            frames: metadata.frame_count,
+        })
+        .context("Unable to serialize av1an scenes")?,
+        SceneFormat::Csv => {
+            let rows = scenes
+                .iter()
+                .map(|scene| {
+                    format!(
+                        "{},{},{},{}\n",
+                        scene.index,
+                        scene.start_frame,
+                        scene.end_frame,
+                        scene.length()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .concat();
+
+            format!("index,start_frame,end_frame,length\n{rows}")
         }
+        // CMX3600 only supports integer frame rates, so timecodes are approximate for NTSC rates.
+        SceneFormat::Edl => {
+            let frame_rate =
+                ((metadata.frame_count as f64 / metadata.duration).round() as usize).max(1);
+
+            let events = scenes
+                .iter()
+                .enumerate()
+                .map(|(event, scene)| {
+                    let start = format_timecode(scene.start_frame, frame_rate);
+                    let end = format_timecode(scene.end_frame + 1, frame_rate);
 
-        let mut scene_changes = results.scene_changes;
-        scene_changes.push(metadata.frame_count);
+                    format!(
+                        "{:03}  AX       V     C        {start} {end} {start} {end}\n",
+                        event + 1
+                    )
+                })
+                .collect::<Vec<_>>()
+                .concat();
 
-        let scenes: Vec<Scene> = scene_changes
+            format!("TITLE: Scenes\nFCM: NON-DROP FRAME\n\n{events}")
+        }
+        SceneFormat::Qpfile => scenes
             .iter()
-            .zip(scene_changes.iter().skip(1))
-            .enumerate()
-            .map(|(index, (start_frame, next_start_frame))| Scene {
-                index,
-                start_frame: *start_frame,
-                end_frame: next_start_frame - 1,
+            .map(|scene| format!("{} K\n", scene.start_frame))
+            .collect::<Vec<_>>()
+            .concat(),
+    };
+
+    Ok(contents)
+}
+
+// The quality chosen for each scene is written as an av1an zones file, so a project can be
+// continued in av1an without searching again.
+pub fn export_zones(config: &Config, scene_qualities: &BTreeMap<usize, f64>) -> anyhow::Result<()> {
+    if !config.scene_exports.contains(&SceneFormat::Av1an) {
+        return Ok(());
+    }
+
+    let Some(encoder_name) = config.encoder.av1an_name() else {
+        warn!(
+            "av1an does not support {}, so no zones were exported",
+            config.encoder
+        );
+        return Ok(());
+    };
+
+    let scenes = get(config).context("Unable to fetch scene data")?;
+
+    let zones = scenes
+        .iter()
+        .filter_map(|scene| {
+            scene_qualities.get(&scene.index).map(|quality| {
+                format!(
+                    "{} {} {encoder_name} {}\n",
+                    scene.start_frame,
+                    scene.end_frame + 1,
+                    config
+                        .encoder
+                        .quality_arguments(config.mode, *quality)
+                        .join(" ")
+                )
             })
-            .collect();
+        })
+        .collect::<Vec<_>>()
+        .concat();
+
+    let path = config
+        .output_directory
+        .join("output")
+        .join(format!("{}.zones.txt", config.encode_identifier(true)));
 
-        serde_json::to_writer_pretty(
-            &File::create(&json_path)
-                .with_context(|| format!("Unable to create scene cache file {json_path:?}"))?,
-            &scenes,
+    verify_filename(&path).with_context(|| format!("Unable to verify zones path {path:?}"))?;
+
+    fs::write(&path, zones).with_context(|| format!("Unable to write zones file {path:?}"))?;
+
+    Ok(())
+}
+
+pub fn export(config: &Config) -> anyhow::Result<()> {
+    if config.scene_exports.is_empty() {
+        return Ok(());
+    }
+
+    let scenes = get(config).context("Unable to fetch scene data")?;
+    let metadata = get_metadata(config).context("Unable to fetch video metadata")?;
+
+    for &format in &config.scene_exports {
+        let extension = match format {
+            SceneFormat::Av1an => "av1an.json",
+            SceneFormat::Csv => "csv",
+            SceneFormat::Edl => "edl",
+            SceneFormat::Qpfile => "qpfile",
+        };
+
+        let path = config
+            .config_directory()
+            .join(format!("scenes.{extension}"));
+
+        verify_filename(&path)
+            .with_context(|| format!("Unable to verify scene export path {path:?}"))?;
+
+        fs::write(
+            &path,
+            format_scenes(format, &scenes, &metadata)
+                .with_context(|| format!("Unable to format scenes as {format}"))?,
         )
-        .with_context(|| format!("Unable to serialize scene cache to {json_path:?}"))?;
+        .with_context(|| format!("Unable to write scene export {path:?}"))?;
+    }
 
-        scenes
+    Ok(())
+}
+
+// Split scenes depend on the scene boundaries and the crop, and encoded scenes depend on the split
+// scenes, so any change invalidates the split scenes and the encodes made from them.
+fn verify_split_cache(config: &Config, output_path: &Path, key: &str) -> anyhow::Result<()> {
+    let json_path = output_path.join("split.json");
+
+    // Only a readable key that differs proves the split scenes stale. A cache that fails to parse
+    // is simply rewritten from the current key rather than taking every encode with it.
+    let stale_key = if json_path.exists() {
+        read_json::<SplitCache>(&json_path)
+            .context("Unable to read split cache")?
+            .map(|cache| cache.key)
+            .filter(|cached_key| cached_key != key)
+    } else {
+        None
     };
 
-    Ok(scenes)
+    if let Some(stale_key) = stale_key {
+        warn!("Source file, scenes or crop settings have changed since scenes were split. Removing stale split and encoded scenes.");
+
+        for entry in read_dir(output_path)
+            .with_context(|| format!("Unable to read directory {output_path:?}"))?
+        {
+            let path = entry
+                .with_context(|| format!("Unable to read entry in {output_path:?}"))?
+                .path();
+
+            if path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("scene-"))
+            {
+                remove_file(&path)
+                    .with_context(|| format!("Unable to remove stale split scene {path:?}"))?;
+            }
+        }
+
+        let encode_path = config.output_directory.join("encode").join(stale_key);
+
+        if encode_path.exists() {
+            remove_dir_all(&encode_path).with_context(|| {
+                format!("Unable to remove stale encoded scenes in {encode_path:?}")
+            })?;
+        }
+    }
+
+    write_json(
+        &json_path,
+        &SplitCache {
+            key: key.to_owned(),
+        },
+    )
+    .with_context(|| format!("Unable to write split cache {json_path:?}"))
+}
+
+// The intermediates are lossless either way, so these settings only trade splitting speed and
+// integrity checks against size and never invalidate already split scenes.
+fn ffv1_arguments(config: &Config) -> anyhow::Result<Vec<String>> {
+    if config.ffv1_level < 3 && (config.ffv1_slices.is_some() || config.ffv1_slicecrc.is_some()) {
+        return Err(anyhow!(
+            "FFV1 slices and slice CRCs require FFV1 level 3, not {}",
+            config.ffv1_level
+        ));
+    }
+
+    let mut arguments = vec![
+        "-c:v".to_owned(),
+        "ffv1".to_owned(),
+        "-level".to_owned(),
+        config.ffv1_level.to_string(),
+    ];
+
+    if let Some(slices) = config.ffv1_slices {
+        arguments.extend(["-slices".to_owned(), slices.to_string()]);
+    }
+
+    if let Some(slicecrc) = config.ffv1_slicecrc {
+        arguments.extend(["-slicecrc".to_owned(), u8::from(slicecrc).to_string()]);
+    }
+
+    if let Some(threads) = config.ffv1_threads {
+        arguments.extend(["-threads".to_owned(), threads.to_string()]);
+    }
+
+    Ok(arguments)
 }
 
 #[expect(clippy::too_many_lines)]
@@ -138,9 +754,25 @@ pub fn split(config: &Config) -> anyhow::Result<()> {
     let metadata = get_metadata(config)
         .with_context(|| format!("Unable to fetch video metadata for {:?}", &config.source))?;
 
-    let complete = scenes.iter().all(|scene| {
+    let key = split_key(config, &metadata).context("Unable to calculate split cache key")?;
+
+    verify_split_cache(config, &output_path, &key).context("Unable to verify split scene cache")?;
+
+    if config.pre_split {
+        return link_chunks(config, &scenes, &output_path)
+            .context("Unable to link pre-split chunks");
+    }
+
+    let mut ffv1_arguments = ffv1_arguments(config).context("Unable to configure FFV1 encoding")?;
+
+    // The decoder keeps full range sources at full range, so the split scenes are marked as such.
+    if metadata.full_range == Some(true) {
+        ffv1_arguments.extend(["-color_range".to_owned(), "pc".to_owned()]);
+    }
+
+    let first_missing = scenes.iter().position(|scene| {
         let output_filename = output_path.join(format!("scene-{:05}.mkv", scene.index));
-        output_filename.exists()
+        !output_filename.exists()
     });
 
     let progress_bar = ProgressBar::new(metadata.frame_count.try_into().unwrap_or(u64::MAX));
@@ -151,19 +783,41 @@ pub fn split(config: &Config) -> anyhow::Result<()> {
         ).context("Unable to create scene splitting progress bar style")?
     );
 
-    if complete {
-        for scene in scenes {
-            progress_bar.inc(
-                (scene.end_frame - scene.start_frame + 1)
-                    .try_into()
-                    .unwrap_or(u64::MAX),
-            );
-        }
-    } else {
+    let first_missing = first_missing.unwrap_or(scenes.len());
+
+    for scene in &scenes[..first_missing] {
+        progress_bar.inc(scene.length().try_into().unwrap_or(u64::MAX));
+    }
+
+    // A copied stream keeps the source's frames exactly, so it is only used when splitting would
+    // neither crop nor convert them.
+    let stream_copy = first_missing < scenes.len()
+        && metadata.pixel_format.as_deref() == Some(split_pixel_format(config, &metadata))
+        && intra_only_dimensions(&config.source)
+            .context("Unable to inspect source video codec")?
+            .is_some_and(|dimensions| {
+                metadata
+                    .dimensions()
+                    .is_none_or(|cropped| cropped == dimensions)
+            });
+
+    if stream_copy {
+        split_by_copy(config, &scenes, &output_path, &progress_bar)
+            .context("Unable to split scenes by stream copy")?;
+    } else if let Some(first_scene) = scenes.get(first_missing) {
+        // Resuming a partial split seeks straight to the first missing scene rather than decoding
+        // and discarding everything before it.
+        let range = (first_scene.start_frame > 0).then(|| SourceRange {
+            start_time: first_scene.source_range(&metadata).start_time,
+            frames: metadata.frame_count.saturating_sub(first_scene.start_frame),
+        });
+
         let mut decoder = y4m::Decoder::new(
             create_child_read(
                 &config.source,
                 metadata.crop_filter.as_deref(),
+                split_pixel_format(config, &metadata),
+                range,
                 Stdio::null(),
                 Stdio::piped(),
                 Stdio::null(),
@@ -176,7 +830,7 @@ pub fn split(config: &Config) -> anyhow::Result<()> {
         )
         .context("Unable to create scene splitting YUV4MPEG decoder")?;
 
-        for scene in scenes {
+        for scene in &scenes[first_missing..] {
             let final_output_filename = output_path.join(format!("scene-{:05}.mkv", scene.index));
             let temporary_output_filename =
                 output_path.join(format!("scene-{:05}.tmp.mkv", scene.index));
@@ -198,8 +852,9 @@ pub fn split(config: &Config) -> anyhow::Result<()> {
                     })?;
                 }
 
-                let ffmpeg_pipe = Command::new("ffmpeg")
-                    .args(["-i", "-", "-c:v", "ffv1", "-level", "3"])
+                let ffmpeg_pipe = command("ffmpeg")
+                    .args(["-i", "-"])
+                    .args(&ffv1_arguments)
                     .arg(&temporary_output_filename)
                     .stdin(Stdio::piped())
                     .stdout(Stdio::null())
@@ -232,6 +887,8 @@ pub fn split(config: &Config) -> anyhow::Result<()> {
                 }
             }
 
+            notify_progress("split", &progress_bar);
+
             if temporary_output_filename.exists() {
                 rename(&temporary_output_filename, &final_output_filename).with_context(
                 || {
@@ -248,3 +905,118 @@ pub fn split(config: &Config) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+// Pre-split chunks are used where split scenes would be, so every later stage finds them as usual.
+// Hard links avoid duplicating large lossless files, with a copy when the output directory is on
+// another file system.
+fn link_chunks(config: &Config, scenes: &[Scene], output_path: &Path) -> anyhow::Result<()> {
+    let chunks = presplit::load(config).context("Unable to load chunk index")?;
+
+    for scene in scenes {
+        let final_output_filename = output_path.join(format!("scene-{:05}.mkv", scene.index));
+
+        if final_output_filename.exists() {
+            continue;
+        }
+
+        let chunk = &chunks
+            .get(scene.index)
+            .ok_or_else(|| anyhow!("Chunk index has no chunk for scene {}", scene.index))?
+            .file;
+
+        if fs::hard_link(chunk, &final_output_filename).is_err() {
+            let temporary_output_filename =
+                output_path.join(format!("scene-{:05}.tmp.mkv", scene.index));
+
+            fs::copy(chunk, &temporary_output_filename).with_context(|| {
+                format!("Unable to copy {chunk:?} to {temporary_output_filename:?}")
+            })?;
+
+            rename(&temporary_output_filename, &final_output_filename).with_context(|| {
+                format!(
+                    "Unable to rename {temporary_output_filename:?} to {final_output_filename:?}"
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+// Every frame of an intra-only source is a key frame, so the segment muxer can cut the stream at
+// exactly the first frame of each scene without decoding anything. Lossless and mezzanine sources
+// are usually already in such a format, and any cropping or pixel format conversion would still
+// require a decode.
+fn split_by_copy(
+    config: &Config,
+    scenes: &[Scene],
+    output_path: &Path,
+    progress_bar: &ProgressBar,
+) -> anyhow::Result<()> {
+    let temporary_path = output_path.join("copy.tmp");
+
+    if temporary_path.exists() {
+        remove_dir_all(&temporary_path)
+            .with_context(|| format!("Unable to remove stale directory {temporary_path:?}"))?;
+    }
+
+    verify_directory(&temporary_path)
+        .with_context(|| format!("Unable to create directory {temporary_path:?}"))?;
+
+    let mut scenes = scenes.to_vec();
+    scenes.sort_by_key(|scene| scene.start_frame);
+
+    let boundaries = scenes
+        .iter()
+        .skip(1)
+        .map(|scene| scene.start_frame.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut command = command("ffmpeg");
+
+    command
+        .arg("-i")
+        .arg(&config.source)
+        .args(["-map", "0:v:0", "-c:v", "copy", "-f", "segment"])
+        .args(["-reset_timestamps", "1"]);
+
+    if !boundaries.is_empty() {
+        command.arg("-segment_frames").arg(boundaries);
+    }
+
+    let output = command
+        .arg(temporary_path.join("scene-%05d.mkv"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Unable to run scene splitting stream copy subprocess")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Scene splitting stream copy did not complete successfully: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    for (position, scene) in scenes.iter().enumerate() {
+        let segment_filename = temporary_path.join(format!("scene-{position:05}.mkv"));
+        let final_output_filename = output_path.join(format!("scene-{:05}.mkv", scene.index));
+
+        if !final_output_filename.exists() {
+            rename(&segment_filename, &final_output_filename).with_context(|| {
+                format!("Unable to rename {segment_filename:?} to {final_output_filename:?}")
+            })?;
+
+            progress_bar.inc(scene.length().try_into().unwrap_or(u64::MAX));
+        }
+    }
+
+    notify_progress("split", progress_bar);
+
+    remove_dir_all(&temporary_path)
+        .with_context(|| format!("Unable to remove directory {temporary_path:?}"))?;
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::scenes::Scene;
+use crate::util::{read_json, write_json};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneStatus {
+    Pending,
+    Probing,
+    Encoding,
+    Done,
+    Failed,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ScheduleState {
+    order: Vec<usize>,
+    status: BTreeMap<usize, SceneStatus>,
+}
+
+/// The order in which scenes are handed to the workers and how far each has progressed, kept on
+/// disk so that an interrupted encode resumes in the same order.
+pub struct SceneSchedule {
+    path: PathBuf,
+    state: Mutex<ScheduleState>,
+}
+
+impl SceneSchedule {
+    // Prioritized scenes move to the front in the order given. Everything else keeps its previous
+    // position, and scenes that are new to the schedule follow in their natural order.
+    pub fn load(path: &Path, scenes: &[Scene], priority: &[usize]) -> anyhow::Result<Self> {
+        let previous: ScheduleState = read_json(path)
+            .with_context(|| format!("Unable to read schedule {path:?}"))?
+            .unwrap_or_default();
+
+        let known = scenes.iter().map(Scene::index).collect::<Vec<_>>();
+
+        for scene_index in priority {
+            if !known.contains(scene_index) {
+                warn!("Unable to prioritize scene {scene_index}, which does not exist");
+            }
+        }
+
+        let mut order = vec![];
+
+        for scene_index in priority
+            .iter()
+            .chain(&previous.order)
+            .chain(&known)
+            .copied()
+        {
+            if known.contains(&scene_index) && !order.contains(&scene_index) {
+                order.push(scene_index);
+            }
+        }
+
+        // Work that was underway when the previous run stopped has to start over.
+        let status = order
+            .iter()
+            .map(|scene_index| {
+                let status = match previous.status.get(scene_index) {
+                    Some(SceneStatus::Done) => SceneStatus::Done,
+                    Some(SceneStatus::Failed) => SceneStatus::Failed,
+                    _ => SceneStatus::Pending,
+                };
+
+                (*scene_index, status)
+            })
+            .collect();
+
+        let state = ScheduleState { order, status };
+
+        write_json(path, &state).context("Unable to write schedule")?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+        })
+    }
+
+    #[must_use]
+    pub fn order(&self) -> Vec<usize> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .order
+            .clone()
+    }
+
+    // The lock is held while writing so that concurrent updates cannot race on the temporary file.
+    pub fn set(&self, scene_index: usize, status: SceneStatus) -> anyhow::Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        state.status.insert(scene_index, status);
+
+        let result = write_json(&self.path, &*state);
+        drop(state);
+
+        result.with_context(|| format!("Unable to record status of scene {scene_index}"))
+    }
+}
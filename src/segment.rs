@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use indicatif::ProgressBar;
+use serde::Serialize;
+
+use crate::tools::command;
+use crate::util::{create_progress_style, verify_directory, write_json};
+
+#[derive(Serialize)]
+struct Segment {
+    filename: String,
+    start_time: f64,
+    end_time: f64,
+}
+
+#[derive(Serialize)]
+struct SegmentIndex {
+    segment_duration: f64,
+    segments: Vec<Segment>,
+}
+
+fn parse_segment_list(list_path: &Path) -> anyhow::Result<Vec<Segment>> {
+    fs::read_to_string(list_path)
+        .with_context(|| format!("Unable to read segment list {list_path:?}"))?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| -> anyhow::Result<Segment> {
+            let mut fields = line.split(',');
+
+            let (Some(filename), Some(start_time), Some(end_time)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(anyhow!("Malformed segment list entry {line:?}"));
+            };
+
+            Ok(Segment {
+                filename: filename.to_owned(),
+                start_time: start_time.parse().with_context(|| {
+                    format!("Unable to parse segment start time {start_time:?}")
+                })?,
+                end_time: end_time
+                    .parse()
+                    .with_context(|| format!("Unable to parse segment end time {end_time:?}"))?,
+            })
+        })
+        .collect()
+}
+
+// The encoder key frame interval is capped to the segment duration, so stream copying at key
+// frames yields segments that never exceed the requested duration by more than one GOP. Segments
+// share the container of the merged output.
+pub fn write(output_path: &Path, segment_duration: f64) -> anyhow::Result<PathBuf> {
+    let segment_path = output_path.with_extension("segments");
+    let index_path = segment_path.join("index.json");
+
+    if index_path.exists() {
+        return Ok(segment_path);
+    }
+
+    let progress_bar = ProgressBar::new_spinner();
+    progress_bar.enable_steady_tick(Duration::from_millis(120));
+    progress_bar.set_style(
+        create_progress_style("{spinner:.green} [{elapsed_precise}] {msg}")
+            .context("Unable to create segmenting progress bar style")?,
+    );
+    progress_bar.set_message("Writing segments...");
+
+    if segment_path.exists() {
+        fs::remove_dir_all(&segment_path).with_context(|| {
+            format!("Unable to remove incomplete segment directory {segment_path:?}")
+        })?;
+    }
+
+    verify_directory(&segment_path)
+        .with_context(|| format!("Unable to verify segment directory {segment_path:?}"))?;
+
+    let list_path = segment_path.join("segments.csv");
+    let extension = output_path.extension().map_or_else(
+        || "mkv".to_owned(),
+        |extension| extension.to_string_lossy().to_string(),
+    );
+
+    let result = command("ffmpeg")
+        .arg("-hide_banner")
+        .arg("-i")
+        .arg(output_path)
+        .args([
+            "-map",
+            "0:v",
+            "-c",
+            "copy",
+            "-f",
+            "segment",
+            "-segment_time",
+        ])
+        .arg(format!("{segment_duration}"))
+        .args(["-reset_timestamps", "1", "-segment_list_type", "csv"])
+        .arg("-segment_list")
+        .arg(&list_path)
+        .arg(segment_path.join(format!("segment-%05d.{extension}")))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .context("Unable to spawn FFmpeg segmenting subprocess")?;
+
+    if !result.status.success() {
+        progress_bar.set_message("Writing segments...failed!");
+        progress_bar.finish();
+
+        return Err(anyhow!(
+            "FFmpeg returned error code {} while segmenting and the following output:\n{}",
+            result.status,
+            str::from_utf8(&result.stderr).context("Unable to parse FFmpeg output as UTF-8")?
+        ));
+    }
+
+    let index = SegmentIndex {
+        segment_duration,
+        segments: parse_segment_list(&list_path).context("Unable to parse segment list")?,
+    };
+
+    write_json(&index_path, &index)
+        .with_context(|| format!("Unable to write segment index {index_path:?}"))?;
+
+    fs::remove_file(&list_path)
+        .with_context(|| format!("Unable to remove segment list {list_path:?}"))?;
+
+    progress_bar.set_message("Writing segments...done!");
+    progress_bar.finish();
+
+    Ok(segment_path)
+}
@@ -30,17 +30,23 @@
 use std::collections::BTreeMap;
 use std::io::Read;
 use std::path::Path;
-use std::process::ChildStdout;
-use std::sync::{mpsc, Arc, Mutex};
+use std::process::{ChildStdout, Stdio};
 use std::thread;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context};
 use av_scenechange::{decoder::Decoder, ffmpeg::FfmpegDecoder};
+use crossbeam_channel::{bounded, unbounded, Sender, TryRecvError};
 use ssimulacra2::{
-    compute_frame_ssimulacra2, ColorPrimaries, MatrixCoefficients, Pixel, TransferCharacteristic,
-    Yuv, YuvConfig,
+    compute_frame_ssimulacra2, ColorPrimaries, Frame, MatrixCoefficients, Pixel,
+    TransferCharacteristic, Yuv, YuvConfig,
 };
 
+use v_frame::pixel::ChromaSampling;
+
+use crate::ffmpeg::{create_child_read, SourceRange};
+use crate::metrics::{FrameProgress, ThreadBudget};
+
 const fn guess_matrix_coefficients(width: usize, height: usize) -> MatrixCoefficients {
     if width >= 1280 || height > 576 {
         MatrixCoefficients::BT709
@@ -71,65 +77,216 @@ fn guess_color_primaries(
     }
 }
 
-#[expect(clippy::significant_drop_tightening)]
-#[expect(clippy::type_complexity)]
-fn calc_score<S: Pixel, D: Pixel>(
-    mutex: &Mutex<(usize, (Decoder<impl Read>, Decoder<impl Read>))>,
-    reference_yuv_config: YuvConfig,
-    distorted_yuv_config: YuvConfig,
-) -> anyhow::Result<Option<(usize, f64)>> {
-    let (frame_index, (reference_frame, distorted_frame)) = {
-        let mut guard = mutex
-            .lock()
-            .map_err(|_err| anyhow!("Poison encountered when acquiring mutex lock"))?;
-        let frame_index = guard.0;
-
-        let reference_info = guard
-            .1
-             .0
-            .get_video_details()
-            .context("Unable to retreive reference video details")?;
+// Decodes frames on a dedicated thread until the stream ends. The bounded channel keeps the reader
+// only a few frames ahead of the scorers.
+fn read_frames<T: Pixel>(
+    mut decoder: Decoder<impl Read>,
+    frames: &Sender<Frame<T>>,
+) -> anyhow::Result<()> {
+    let info = decoder
+        .get_video_details()
+        .context("Unable to retrieve video details")?;
 
-        let distorted_info = guard
-            .1
-             .1
-            .get_video_details()
-            .context("Unable to retrieve distorted video detials")?;
+    // The decoders report the end of the stream as a read error.
+    while let Ok(frame) = decoder.read_video_frame::<T>(&info) {
+        if frames.send(frame).is_err() {
+            break;
+        }
+    }
 
-        let reference_frame = guard
-            .1
-             .0
-            .read_video_frame::<S>(&reference_info)
-            .context("Unable to read reference video frame")?;
+    Ok(())
+}
 
-        let distorted_frame = guard
-            .1
-             .1
-            .read_video_frame::<D>(&distorted_info)
-            .context("Unable to read distorted video frame")?;
+// Each video is decoded by its own reader thread.
+fn read_both<S: Pixel, D: Pixel>(
+    reference: Decoder<impl Read + Send>,
+    distorted: Decoder<impl Read + Send>,
+    reference_frames: &Sender<Frame<S>>,
+    distorted_frames: &Sender<Frame<D>>,
+) -> anyhow::Result<()> {
+    thread::scope(|scope| {
+        let reference = scope.spawn(move || read_frames(reference, reference_frames));
+        let distorted = scope.spawn(move || read_frames(distorted, distorted_frames));
+
+        reference
+            .join()
+            .map_err(|_err| anyhow!("SSIMULACRA2 reference reader panicked"))??;
+        distorted
+            .join()
+            .map_err(|_err| anyhow!("SSIMULACRA2 distorted reader panicked"))??;
+
+        Ok(())
+    })
+}
 
-        guard.0 += 1;
-        (frame_index, (reference_frame, distorted_frame))
-    };
+// Splits each frame of a stream carrying the reference and the encode side by side into its two
+// halves.
+#[expect(clippy::integer_division)]
+#[expect(clippy::integer_division_remainder_used)]
+fn read_stacked(
+    mut decoder: y4m::Decoder<impl Read>,
+    reference_frames: &Sender<Frame<u16>>,
+    distorted_frames: &Sender<Frame<u16>>,
+) {
+    let width = decoder.get_width() / 2;
+    let height = decoder.get_height();
+    let plane_sizes = [
+        (width, height),
+        (width.div_ceil(2), height.div_ceil(2)),
+        (width.div_ceil(2), height.div_ceil(2)),
+    ];
+
+    while let Ok(frame) = decoder.read_frame() {
+        let mut reference = Frame::new_with_padding(width, height, ChromaSampling::Cs420, 0);
+        let mut distorted = Frame::new_with_padding(width, height, ChromaSampling::Cs420, 0);
+
+        let planes = [
+            frame.get_y_plane(),
+            frame.get_u_plane(),
+            frame.get_v_plane(),
+        ];
+
+        for (((data, (plane_width, plane_height)), reference_plane), distorted_plane) in planes
+            .into_iter()
+            .zip(plane_sizes)
+            .zip(reference.planes.iter_mut())
+            .zip(distorted.planes.iter_mut())
+        {
+            // Samples are 16 bits wide, and each row holds the reference half followed by the
+            // encode half.
+            let row_bytes = plane_width * 2;
+            let mut left = Vec::with_capacity(row_bytes * plane_height);
+            let mut right = Vec::with_capacity(row_bytes * plane_height);
+
+            for row in data.chunks_exact(row_bytes * 2).take(plane_height) {
+                let (reference_row, distorted_row) = row.split_at(row_bytes);
+                left.extend_from_slice(reference_row);
+                right.extend_from_slice(distorted_row);
+            }
+
+            reference_plane.copy_from_raw_u8(&left, row_bytes, 2);
+            distorted_plane.copy_from_raw_u8(&right, row_bytes, 2);
+        }
 
-    let reference_yuv = Yuv::new(reference_frame, reference_yuv_config)
-        .context("Unable to extract reference frame YUV")?;
-    let distorted_yuv = Yuv::new(distorted_frame, distorted_yuv_config)
-        .context("Unable to extract distorted frame YUV")?;
+        if reference_frames.send(reference).is_err() || distorted_frames.send(distorted).is_err() {
+            break;
+        }
+    }
+}
+
+// How often a scoring thread outside the budget checks whether it may resume.
+const BUDGET_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// The frames are paired up and handed to the scoring workers through a bounded queue, so scoring
+// never waits on a decoder lock. With a budget, every thread up to the limit is started, but those
+// beyond the current budget idle until it grows again.
+fn score_frames<S: Pixel, D: Pixel>(
+    read: impl FnOnce(&Sender<Frame<S>>, &Sender<Frame<D>>) -> anyhow::Result<()> + Send,
+    threads: usize,
+    budget: Option<&ThreadBudget>,
+    reference_config: YuvConfig,
+    distorted_config: YuvConfig,
+    progress: Option<&FrameProgress>,
+) -> anyhow::Result<Vec<f64>> {
+    let queue_size = threads * 2;
 
-    Ok(Some((
-        frame_index,
-        compute_frame_ssimulacra2(reference_yuv, distorted_yuv)
-            .context("Unable to compute SSIMULACRA2 score")?,
-    )))
+    let (reference_tx, reference_rx) = bounded::<Frame<S>>(queue_size);
+    let (distorted_tx, distorted_rx) = bounded::<Frame<D>>(queue_size);
+    let (pair_tx, pair_rx) = bounded(queue_size);
+    let (result_tx, result_rx) = unbounded();
+    let (paired_tx, paired_rx) = bounded::<()>(0);
+
+    thread::scope(|scope| -> anyhow::Result<Vec<f64>> {
+        let reader = scope.spawn(move || read(&reference_tx, &distorted_tx));
+
+        // Scoring stops at the end of the shorter video.
+        scope.spawn(move || {
+            // Dropped once every frame has been handed out, which releases any idle threads.
+            let _paired = paired_tx;
+
+            for (frame_index, pair) in reference_rx.iter().zip(distorted_rx.iter()).enumerate() {
+                if pair_tx.send((frame_index, pair)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let workers = (0..threads)
+            .map(|worker_index| {
+                let pair_rx = pair_rx.clone();
+                let paired_rx = paired_rx.clone();
+                let result_tx = result_tx.clone();
+
+                scope.spawn(move || -> anyhow::Result<()> {
+                    loop {
+                        if worker_index > 0 && budget.is_some_and(|budget| worker_index >= budget())
+                        {
+                            if matches!(paired_rx.try_recv(), Err(TryRecvError::Disconnected)) {
+                                break;
+                            }
+
+                            thread::sleep(BUDGET_POLL_INTERVAL);
+                            continue;
+                        }
+
+                        let Ok((frame_index, (reference_frame, distorted_frame))) = pair_rx.recv()
+                        else {
+                            break;
+                        };
+
+                        let reference_yuv = Yuv::new(reference_frame, reference_config)
+                            .context("Unable to extract reference frame YUV")?;
+                        let distorted_yuv = Yuv::new(distorted_frame, distorted_config)
+                            .context("Unable to extract distorted frame YUV")?;
+
+                        let score = compute_frame_ssimulacra2(reference_yuv, distorted_yuv)
+                            .context("Unable to compute SSIMULACRA2 score")?;
+
+                        result_tx
+                            .send((frame_index, score))
+                            .context("Unable to send SSIMULACRA2 result to parent thread")?;
+                    }
+
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        drop(pair_rx);
+        drop(paired_rx);
+        drop(result_tx);
+
+        let mut results = BTreeMap::new();
+
+        for (frame_index, score) in result_rx {
+            results.insert(frame_index, score);
+
+            if let Some(progress) = progress {
+                progress(1);
+            }
+        }
+
+        reader
+            .join()
+            .map_err(|_err| anyhow!("SSIMULACRA2 reader thread panicked"))??;
+
+        for handle in workers {
+            handle
+                .join()
+                .map_err(|_err| anyhow!("SSIMULACRA2 thread panicked"))??;
+        }
+
+        Ok(results.into_values().collect())
+    })
 }
 
 #[expect(clippy::too_many_arguments)]
 #[expect(clippy::too_many_lines)]
 fn compare_videos(
-    reference_path: &Path,
-    distorted_path: &Path,
+    reference: Decoder<ChildStdout>,
+    distorted: Decoder<ChildStdout>,
     threads: usize,
+    budget: Option<&ThreadBudget>,
     mut reference_matrix: MatrixCoefficients,
     mut reference_transfer: TransferCharacteristic,
     mut reference_primaries: ColorPrimaries,
@@ -138,17 +295,8 @@ fn compare_videos(
     mut distorted_transfer: TransferCharacteristic,
     mut distorted_primaries: ColorPrimaries,
     distorted_full_range: bool,
+    progress: Option<&FrameProgress>,
 ) -> anyhow::Result<Vec<f64>> {
-    let reference: Decoder<ChildStdout> = Decoder::Ffmpeg(
-        FfmpegDecoder::new(reference_path)
-            .context("Unable to create SSIMULACRA2 reference YUV4MPEG decoder")?,
-    );
-
-    let distorted: Decoder<ChildStdout> = Decoder::Ffmpeg(
-        FfmpegDecoder::new(distorted_path)
-            .context("Unable to create SSIMULACRA2 distorted YUV4MPEG decoder")?,
-    );
-
     let reference_info = reference
         .get_video_details()
         .context("Unable to retrieve reference video details")?;
@@ -237,76 +385,192 @@ fn compare_videos(
         color_primaries: distorted_primaries,
     };
 
-    let (result_tx, result_rx) = mpsc::channel();
-
-    let current_frame = 0_usize;
-    let decoders = Arc::new(Mutex::new((current_frame, (reference, distorted))));
-
-    thread::scope(|scope| -> anyhow::Result<Vec<f64>> {
-        for _ in 0..threads {
-            let decoders = Arc::clone(&decoders);
-            let result_tx = result_tx.clone();
-
-            scope.spawn(move || -> anyhow::Result<()> {
-                loop {
-                    let score = match (reference_info.bit_depth, distorted_info.bit_depth) {
-                        (8, 8) => {
-                            calc_score::<u8, u8>(&decoders, reference_config, distorted_config)
-                        }
-                        (8, _) => {
-                            calc_score::<u8, u16>(&decoders, reference_config, distorted_config)
-                        }
-                        (_, 8) => {
-                            calc_score::<u16, u8>(&decoders, reference_config, distorted_config)
-                        }
-                        (_, _) => {
-                            calc_score::<u16, u16>(&decoders, reference_config, distorted_config)
-                        }
-                    }
-                    .context("Unable to calculate SSIMULACRA2 score")?;
-
-                    if let Some(result) = score {
-                        result_tx
-                            .send(result)
-                            .context("Unable to send SSIMULACRA2 result to parent thread")?;
-                    } else {
-                        break;
-                    }
-                }
-
-                Ok(())
-            });
-        }
-
-        drop(result_tx);
-
-        let mut results = BTreeMap::new();
-
-        for score in result_rx {
-            results.insert(score.0, score.1);
-        }
-
-        Ok(results.into_values().collect())
-    })
+    match (reference_info.bit_depth, distorted_info.bit_depth) {
+        (8, 8) => score_frames::<u8, u8>(
+            |reference_tx, distorted_tx| {
+                read_both(reference, distorted, reference_tx, distorted_tx)
+            },
+            threads,
+            budget,
+            reference_config,
+            distorted_config,
+            progress,
+        ),
+        (8, _) => score_frames::<u8, u16>(
+            |reference_tx, distorted_tx| {
+                read_both(reference, distorted, reference_tx, distorted_tx)
+            },
+            threads,
+            budget,
+            reference_config,
+            distorted_config,
+            progress,
+        ),
+        (_, 8) => score_frames::<u16, u8>(
+            |reference_tx, distorted_tx| {
+                read_both(reference, distorted, reference_tx, distorted_tx)
+            },
+            threads,
+            budget,
+            reference_config,
+            distorted_config,
+            progress,
+        ),
+        (_, _) => score_frames::<u16, u16>(
+            |reference_tx, distorted_tx| {
+                read_both(reference, distorted, reference_tx, distorted_tx)
+            },
+            threads,
+            budget,
+            reference_config,
+            distorted_config,
+            progress,
+        ),
+    }
     .context("Unable to calculate SSIMULACRA2 scores")
 }
 
+#[expect(clippy::too_many_arguments)]
 pub fn calculate(
-    distorted_path: &Path,
     reference_path: &Path,
+    reference_filter: Option<&str>,
+    reference_range: Option<SourceRange>,
+    distorted_path: &Path,
+    display_filter: Option<&str>,
     threads: usize,
+    budget: Option<&ThreadBudget>,
+    full_range: bool,
+    progress: Option<&FrameProgress>,
 ) -> anyhow::Result<Vec<f64>> {
+    let reference_filter = match (reference_filter, display_filter) {
+        (Some(reference_filter), Some(display_filter)) => {
+            Some(format!("{reference_filter},{display_filter}"))
+        }
+        (reference_filter, display_filter) => {
+            reference_filter.or(display_filter).map(ToOwned::to_owned)
+        }
+    };
+
+    // A filtered or partial reference has to be decoded by an FFmpeg subprocess, but a plain file
+    // can be decoded in-process.
+    let reference: Decoder<ChildStdout> = if reference_filter.is_some() || reference_range.is_some()
+    {
+        Decoder::Y4m(
+            y4m::Decoder::new(
+                create_child_read(
+                    reference_path,
+                    reference_filter.as_deref(),
+                    "yuv420p10le",
+                    reference_range,
+                    Stdio::null(),
+                    Stdio::piped(),
+                    Stdio::null(),
+                )
+                .context("Unable to spawn SSIMULACRA2 reference decoder subprocess")?
+                .stdout
+                .ok_or_else(|| {
+                    anyhow!("Unable to access stdout for SSIMULACRA2 reference decoder subprocess")
+                })?,
+            )
+            .context("Unable to create SSIMULACRA2 reference YUV4MPEG decoder")?,
+        )
+    } else {
+        Decoder::Ffmpeg(
+            FfmpegDecoder::new(reference_path)
+                .context("Unable to create SSIMULACRA2 reference decoder")?,
+        )
+    };
+
+    // Likewise, the encode only needs a subprocess when it has to be scaled.
+    let distorted: Decoder<ChildStdout> = if display_filter.is_some() {
+        Decoder::Y4m(
+            y4m::Decoder::new(
+                create_child_read(
+                    distorted_path,
+                    display_filter,
+                    "yuv420p10le",
+                    None,
+                    Stdio::null(),
+                    Stdio::piped(),
+                    Stdio::null(),
+                )
+                .context("Unable to spawn SSIMULACRA2 distorted decoder subprocess")?
+                .stdout
+                .ok_or_else(|| {
+                    anyhow!("Unable to access stdout for SSIMULACRA2 distorted decoder subprocess")
+                })?,
+            )
+            .context("Unable to create SSIMULACRA2 distorted YUV4MPEG decoder")?,
+        )
+    } else {
+        Decoder::Ffmpeg(
+            FfmpegDecoder::new(distorted_path)
+                .context("Unable to create SSIMULACRA2 distorted decoder")?,
+        )
+    };
+
     compare_videos(
-        distorted_path,
-        reference_path,
+        reference,
+        distorted,
         threads,
+        budget,
         MatrixCoefficients::Unspecified,
         TransferCharacteristic::Unspecified,
         ColorPrimaries::Unspecified,
-        false,
+        full_range,
         MatrixCoefficients::Unspecified,
         TransferCharacteristic::Unspecified,
         ColorPrimaries::Unspecified,
-        false,
+        full_range,
+        progress,
     )
 }
+
+// Scores the side by side output of the libvmaf filtergraph, so that the clips are decoded only once
+// for every metric.
+pub fn calculate_stacked(
+    pairs: impl Read + Send,
+    threads: usize,
+    budget: Option<&ThreadBudget>,
+    progress: Option<&FrameProgress>,
+) -> anyhow::Result<Vec<f64>> {
+    let decoder =
+        y4m::Decoder::new(pairs).context("Unable to create SSIMULACRA2 YUV4MPEG decoder")?;
+
+    if !matches!(decoder.get_colorspace(), y4m::Colorspace::C420p10) {
+        return Err(anyhow!(
+            "Unexpected colorspace {:?} in side by side metric stream",
+            decoder.get_colorspace()
+        ));
+    }
+
+    #[expect(clippy::integer_division)]
+    #[expect(clippy::integer_division_remainder_used)]
+    let width = decoder.get_width() / 2;
+    let height = decoder.get_height();
+    let matrix = guess_matrix_coefficients(width, height);
+
+    // The metric filtergraph has already brought full range clips down to limited range.
+    let config = YuvConfig {
+        bit_depth: 10,
+        subsampling_x: 1,
+        subsampling_y: 1,
+        full_range: false,
+        matrix_coefficients: matrix,
+        transfer_characteristics: TransferCharacteristic::BT1886,
+        color_primaries: guess_color_primaries(matrix, width, height),
+    };
+
+    score_frames::<u16, u16>(
+        |reference_tx, distorted_tx| {
+            read_stacked(decoder, reference_tx, distorted_tx);
+            Ok(())
+        },
+        threads,
+        budget,
+        config,
+        config,
+        progress,
+    )
+    .context("Unable to calculate SSIMULACRA2 scores")
+}
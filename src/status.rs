@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use tiny_http::{Header, Response, Server};
+use tracing::warn;
+use tracing_subscriber::fmt::MakeWriter;
+
+const LOG_LINES: usize = 50;
+
+#[derive(Clone, Serialize)]
+struct StageProgress {
+    position: u64,
+    length: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+struct Status {
+    stages: BTreeMap<String, StageProgress>,
+    workers: BTreeMap<String, String>,
+    bitrate: Option<String>,
+    log: VecDeque<String>,
+}
+
+static STATUS: Mutex<Status> = Mutex::new(Status {
+    stages: BTreeMap::new(),
+    workers: BTreeMap::new(),
+    bitrate: None,
+    log: VecDeque::new(),
+});
+
+// A poisoned lock only means another thread panicked mid-update, which is harmless for a status
+// display.
+fn status() -> MutexGuard<'static, Status> {
+    STATUS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+pub fn set_stage_progress(stage: &str, position: u64, length: Option<u64>) {
+    status()
+        .stages
+        .insert(stage.to_owned(), StageProgress { position, length });
+}
+
+// Workers are keyed by thread, since that is the only identity shared by every call site.
+pub fn set_worker_message(message: &str) {
+    status()
+        .workers
+        .insert(format!("{:?}", thread::current().id()), message.to_owned());
+}
+
+pub fn set_bitrate(bitrate: String) {
+    status().bitrate = Some(bitrate);
+}
+
+fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut characters = text.chars();
+
+    while let Some(character) = characters.next() {
+        if character == '\u{1b}' {
+            for escaped in characters.by_ref() {
+                if escaped.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            result.push(character);
+        }
+    }
+
+    result
+}
+
+/// Tracing writer that keeps the most recent log lines for the status page.
+#[derive(Clone, Copy)]
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        let text = strip_ansi(&String::from_utf8_lossy(buffer));
+        let mut status = status();
+
+        status.log.extend(text.lines().map(ToOwned::to_owned));
+
+        while status.log.len() > LOG_LINES {
+            status.log.pop_front();
+        }
+
+        drop(status);
+
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        *self
+    }
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[expect(clippy::as_conversions)]
+#[expect(clippy::cast_precision_loss)]
+fn render_page(status: &Status) -> String {
+    let stages = status
+        .stages
+        .iter()
+        .map(|(stage, progress)| {
+            let percent =
+                progress
+                    .length
+                    .filter(|&length| length > 0)
+                    .map_or(String::new(), |length| {
+                        format!(
+                            " ({:.1}%)",
+                            progress.position as f64 * 100.0 / length as f64
+                        )
+                    });
+
+            format!(
+                "<tr><td>{}</td><td>{}{percent}</td></tr>",
+                escape_html(stage),
+                progress.position
+            )
+        })
+        .collect::<Vec<_>>()
+        .concat();
+
+    let workers = status
+        .workers
+        .values()
+        .map(|message| format!("<li>{}</li>", escape_html(message)))
+        .collect::<Vec<_>>()
+        .concat();
+
+    let log = status
+        .log
+        .iter()
+        .map(|line| escape_html(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"5\"><title>video-encoding-wrapper</title></head><body>\
+        <h1>Progress</h1><table>{stages}</table>\
+        <p>Current bitrate: {}</p>\
+        <h1>Workers</h1><ul>{workers}</ul>\
+        <h1>Recent Log</h1><pre>{log}</pre>\
+        <h1>Bitrate</h1><img src=\"/bitrate.svg\" alt=\"Bitrate chart (available once the encode completes)\">\
+        </body></html>",
+        escape_html(status.bitrate.as_deref().unwrap_or("N/A"))
+    )
+}
+
+fn content_type(value: &str) -> anyhow::Result<Header> {
+    Header::from_bytes("Content-Type", value)
+        .map_err(|()| anyhow!("Unable to create Content-Type header"))
+}
+
+fn respond(request: tiny_http::Request, chart_path: &PathBuf) -> anyhow::Result<()> {
+    let snapshot = status().clone();
+
+    let response = match request.url() {
+        "/" => Response::from_string(render_page(&snapshot))
+            .with_header(content_type("text/html; charset=utf-8")?),
+        "/status.json" => Response::from_string(
+            serde_json::to_string(&snapshot).context("Unable to serialize status")?,
+        )
+        .with_header(content_type("application/json")?),
+        "/bitrate.svg" => match fs::read_to_string(chart_path) {
+            Ok(chart) => Response::from_string(chart).with_header(content_type("image/svg+xml")?),
+            Err(_error) => Response::from_string("Not Found").with_status_code(404),
+        },
+        _ => Response::from_string("Not Found").with_status_code(404),
+    };
+
+    request
+        .respond(response)
+        .context("Unable to send status page response")?;
+
+    Ok(())
+}
+
+// Addresses may omit the host, as in ":8080", to listen on all interfaces.
+pub fn serve(address: &str, chart_path: PathBuf) -> anyhow::Result<()> {
+    let address = if address.starts_with(':') {
+        format!("0.0.0.0{address}")
+    } else {
+        address.to_owned()
+    };
+
+    let server = Server::http(&address)
+        .map_err(|error| anyhow!("Unable to start status server on {address}: {error}"))?;
+
+    thread::Builder::new()
+        .name("status-server".to_owned())
+        .spawn(move || {
+            for request in server.incoming_requests() {
+                if let Err(error) = respond(request, &chart_path) {
+                    warn!("Unable to serve status request: {error:?}");
+                }
+            }
+        })
+        .context("Unable to spawn status server thread")?;
+
+    Ok(())
+}
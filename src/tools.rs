@@ -0,0 +1,204 @@
+#[cfg(windows)]
+use std::env;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Context};
+use cached::{proc_macro::cached, UnboundCache};
+
+use tracing::warn;
+
+use crate::config::Config;
+
+// Oldest versions known to work with the arguments and output formats this tool relies on.
+const MINIMUM_VERSIONS: [(&str, &str); 8] = [
+    ("ffmpeg", "6.0"),
+    ("mkvmerge", "60.0"),
+    ("aomenc", "3.6.0"),
+    ("rav1e", "0.6.0"),
+    ("SvtAv1EncApp", "1.8.0"),
+    ("vpxenc", "1.12.0"),
+    ("x264", "0.160"),
+    ("x265", "3.4"),
+];
+
+// Windows needs the extension to find an executable on the search path, so names are resolved to a
+// full path there. Elsewhere the name is passed through unchanged.
+#[cfg(windows)]
+fn executable(program: &str) -> PathBuf {
+    if std::path::Path::new(program).extension().is_some() {
+        return program.into();
+    }
+
+    let name = format!("{program}.exe");
+
+    env::var_os("PATH")
+        .and_then(|paths| {
+            env::split_paths(&paths)
+                .map(|directory| directory.join(&name))
+                .find(|path| path.is_file())
+        })
+        .unwrap_or_else(|| name.into())
+}
+
+#[cfg(not(windows))]
+fn executable(program: &str) -> PathBuf {
+    program.into()
+}
+
+#[must_use]
+pub fn command(program: &str) -> Command {
+    Command::new(executable(program))
+}
+
+// aomenc and vpxenc have no version flag, but list the version of each codec in their help output.
+fn version_argument(command: &str) -> &'static str {
+    match command {
+        "ffmpeg" => "-version",
+        "aomenc" | "vpxenc" => "--help",
+        _ => "--version",
+    }
+}
+
+#[cached(
+    result = true,
+    ty = "UnboundCache<String, String>",
+    create = "{ UnboundCache::new() }",
+    convert = r#"{ command.to_owned() }"#
+)]
+pub fn version(command: &str) -> anyhow::Result<String> {
+    let output = self::command(command)
+        .arg(version_argument(command))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Unable to run {command} to determine its version"))?;
+
+    let text = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let line = match command {
+        "aomenc" | "vpxenc" => text.lines().find(|line| line.contains("Encoder")),
+        _ => text.lines().find(|line| !line.trim().is_empty()),
+    };
+
+    line.map(|line| line.trim().to_owned())
+        .ok_or_else(|| anyhow!("Unable to determine version of {command}"))
+}
+
+pub fn versions(config: &Config) -> anyhow::Result<Vec<(String, String)>> {
+    [
+        "ffmpeg".to_owned(),
+        "mkvmerge".to_owned(),
+        config.encoder.command(),
+    ]
+    .into_iter()
+    .map(|command| {
+        let version = version(&command)?;
+        Ok((command, version))
+    })
+    .collect()
+}
+
+// Version strings vary wildly between tools, so the first dotted number is taken as the version,
+// ignoring any leading "v" or "n" prefix.
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    version.split_whitespace().find_map(|word| {
+        let word = word.trim_start_matches(['v', 'n']);
+        let end = word
+            .find(|character: char| !character.is_ascii_digit() && character != '.')
+            .unwrap_or(word.len());
+        let number = word.get(..end)?.trim_end_matches('.');
+
+        if number.contains('.') && number.starts_with(|character: char| character.is_ascii_digit())
+        {
+            number
+                .split('.')
+                .map(|part| part.parse().ok())
+                .collect::<Option<Vec<u64>>>()
+        } else {
+            None
+        }
+    })
+}
+
+fn verify_version(config: &Config, command: &str) -> anyhow::Result<()> {
+    let minimum = config
+        .minimum_tool_versions
+        .iter()
+        .rev()
+        .find(|(tool, _version)| tool == command)
+        .map(|(_tool, version)| version.as_str())
+        .or_else(|| {
+            MINIMUM_VERSIONS
+                .iter()
+                .find(|(tool, _version)| *tool == command)
+                .map(|(_tool, version)| *version)
+        });
+
+    let Some(minimum) = minimum else {
+        return Ok(());
+    };
+
+    let required = parse_version(minimum)
+        .ok_or_else(|| anyhow!("Unable to parse minimum version {minimum:?} for {command}"))?;
+
+    let version = version(command)?;
+
+    if let Some(installed) = parse_version(&version) {
+        if installed < required {
+            return Err(anyhow!(
+                "{command} {minimum} or newer is required, but the installed version is {version:?}. \
+                Upgrade it, or use --min-tool-version {command}=VERSION to override the check."
+            ));
+        }
+    } else {
+        warn!("Unable to parse the version of {command} from {version:?}. Skipping version check.");
+    }
+
+    Ok(())
+}
+
+fn verify_libvmaf() -> anyhow::Result<()> {
+    let output = command("ffmpeg")
+        .args(["-hide_banner", "-filters"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .context("Unable to run ffmpeg to list available filters")?;
+
+    if String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some("libvmaf"))
+    {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "ffmpeg was built without the libvmaf filter, which is required to calculate metrics"
+        ))
+    }
+}
+
+pub fn verify_versions(config: &Config) -> anyhow::Result<()> {
+    if config.skip_version_check {
+        return Ok(());
+    }
+
+    for command in [
+        "ffmpeg".to_owned(),
+        "mkvmerge".to_owned(),
+        config.encoder.command(),
+    ] {
+        verify_version(config, &command)
+            .with_context(|| format!("Unable to verify version of {command}"))?;
+    }
+
+    verify_libvmaf().context("Unable to verify ffmpeg libvmaf support")?;
+
+    Ok(())
+}
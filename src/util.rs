@@ -1,24 +1,34 @@
 use std::cmp::min;
 use std::fmt::{Display, Formatter, Result, Write};
-use std::fs::{create_dir_all, File};
-use std::io::{BufWriter, Write as IoWrite};
+use std::fs::{create_dir_all, read_to_string, rename, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write as IoWrite};
+use std::iter;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context};
+use base16ct::lower::encode_string;
+use cached::{proc_macro::cached, SizedCache};
 use ffmpeg::util::log::level::Level as FFmpegLogLevel;
 use ffmpeg::util::log::set_level as ffmpeg_set_log_level;
-use indicatif::{HumanDuration, ProgressState, ProgressStyle};
+use indicatif::{HumanDuration, ProgressBar, ProgressState, ProgressStyle};
 use number_prefix::NumberPrefix;
 use plotters::prelude::*;
 use prettytable::{format::consts, row, table};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
 use statrs::statistics::{Data, Distribution, Max, Min, OrderStatistics};
-use tracing::{error, level_filters::LevelFilter};
+use tracing::{debug, error, level_filters::LevelFilter, warn};
 use tracing_error::ErrorLayer;
-use tracing_subscriber::fmt::layer;
+use tracing_subscriber::fmt::{format::FmtSpan, layer, writer::MakeWriterExt};
+#[cfg(not(feature = "otlp"))]
+use tracing_subscriber::layer::Identity;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 
+use crate::status::{set_stage_progress, LogWriter};
+
 pub const MINUS_THREE_SIGMA: f64 = 0.001_349_898;
 pub const MINUS_TWO_SIGMA: f64 = 0.022_750_132;
 pub const MINUS_ONE_SIGMA: f64 = 0.158_655_254;
@@ -26,6 +36,30 @@ pub const PLUS_ONE_SIGMA: f64 = 0.841_344_746;
 pub const PLUS_TWO_SIGMA: f64 = 0.977_249_868;
 pub const PLUS_THREE_SIGMA: f64 = 0.998_650_102;
 
+const SOURCE_HASH_SAMPLE_SIZE: u64 = 16 * 1024 * 1024;
+
+pub type ProgressCallback = Box<dyn Fn(&str, u64, Option<u64>) + Send + Sync>;
+
+static PROGRESS_CALLBACK: RwLock<Option<ProgressCallback>> = RwLock::new(None);
+
+// Embedders have no access to the terminal progress bars, so the major stages additionally report
+// their progress to an optional callback.
+pub fn set_progress_callback(callback: Option<ProgressCallback>) {
+    if let Ok(mut current) = PROGRESS_CALLBACK.write() {
+        *current = callback;
+    }
+}
+
+pub fn notify_progress(stage: &str, progress_bar: &ProgressBar) {
+    set_stage_progress(stage, progress_bar.position(), progress_bar.length());
+
+    if let Ok(callback) = PROGRESS_CALLBACK.read() {
+        if let Some(callback) = callback.as_ref() {
+            callback(stage, progress_bar.position(), progress_bar.length());
+        }
+    }
+}
+
 #[expect(clippy::cast_possible_truncation)]
 #[expect(clippy::cast_precision_loss)]
 #[expect(clippy::cast_sign_loss)]
@@ -63,18 +97,92 @@ pub fn create_progress_style(template: &str) -> anyhow::Result<ProgressStyle> {
     Ok(progress_style)
 }
 
-pub fn install_tracing() -> anyhow::Result<()> {
+// Spans are exported as they close rather than in batches, which needs no async runtime and loses
+// nothing when a run ends in an error. Only stages and scenes are spans, so the cost is negligible.
+#[cfg(feature = "otlp")]
+fn otlp_layer<S>(
+    endpoint: &str,
+) -> anyhow::Result<tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .with_context(|| format!("Unable to create OTLP exporter for {endpoint}"))?;
+
+    let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_simple_exporter(exporter)
+        .with_resource(opentelemetry_sdk::Resource::new([
+            opentelemetry::KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+        ]))
+        .build();
+
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    opentelemetry::global::set_tracer_provider(provider);
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+// Exiting directly skips the exporter's own shutdown, so anything it still holds is flushed first.
+#[cfg(feature = "otlp")]
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(not(feature = "otlp"))]
+pub const fn shutdown_tracing() {}
+
+// The log file ignores the terminal filter and records every span as it closes, with its timing, so
+// a failed unattended run can be reconstructed afterwards.
+pub fn install_tracing(log_file: Option<&Path>, otlp_endpoint: Option<&str>) -> anyhow::Result<()> {
     ffmpeg_set_log_level(FFmpegLogLevel::Fatal);
 
     let env_filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::WARN.into())
         .from_env_lossy();
 
-    let fmt_layer = layer();
+    let fmt_layer = layer().with_writer(io::stdout.and(LogWriter));
+
+    let file_layer = log_file
+        .map(|path| -> anyhow::Result<_> {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Unable to open log file {path:?}"))?;
+
+            Ok(layer()
+                .with_writer(Mutex::new(file))
+                .with_ansi(false)
+                .with_thread_names(true)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_filter(LevelFilter::DEBUG))
+        })
+        .transpose()?;
+
+    #[cfg(feature = "otlp")]
+    let otlp_layer = otlp_endpoint.map(otlp_layer).transpose()?;
+
+    #[cfg(not(feature = "otlp"))]
+    let otlp_layer = match otlp_endpoint {
+        Some(_) => {
+            return Err(anyhow!(
+                "OTLP export requires building with the otlp feature"
+            ))
+        }
+        None => None::<Identity>,
+    };
 
     tracing_subscriber::registry()
         .with(ErrorLayer::default())
         .with(fmt_layer.with_filter(env_filter))
+        .with(file_layer)
+        .with(otlp_layer)
         .try_init()
         .context("Unable to initialize global default subscriber")?;
 
@@ -124,11 +232,14 @@ pub fn print_histogram(data: &[f64]) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Markers are vertical lines at the given x positions, each labelled with its text if there is
+// room before the next one.
 pub fn generate_bitrate_chart(
     output_filename: &PathBuf,
     title: &str,
     offset: usize,
     series: &Vec<(String, &Vec<f64>)>,
+    markers: &[(usize, String)],
 ) -> anyhow::Result<()> {
     let mut y_min = f64::MAX;
     let mut y_max = f64::MIN;
@@ -181,6 +292,34 @@ pub fn generate_bitrate_chart(
         .draw()
         .with_context(|| format!("Unable to configure mesh for {title} chart"))?;
 
+    // Roughly the width of a short label, as a share of the horizontal axis.
+    let minimum_label_gap = length / 60;
+    let marker_style = BLACK.mix(0.15);
+
+    chart
+        .draw_series(
+            markers
+                .iter()
+                .map(|(x, _)| PathElement::new(vec![(*x, y_min), (*x, y_max)], marker_style)),
+        )
+        .with_context(|| format!("Unable to draw markers for {title} chart"))?;
+
+    chart
+        .draw_series(
+            markers
+                .iter()
+                .zip(markers.iter().skip(1).map(|(x, _)| *x).chain([length]))
+                .filter(|((start, _), end)| end.saturating_sub(*start) >= minimum_label_gap)
+                .map(|((x, label), _)| {
+                    Text::new(
+                        label.clone(),
+                        (*x, y_max),
+                        ("Arial", 12_i32).into_font().color(&BLACK.mix(0.6)),
+                    )
+                }),
+        )
+        .with_context(|| format!("Unable to draw marker labels for {title} chart"))?;
+
     for (i, (name, data)) in series.iter().enumerate() {
         let series_offset = length - data.len();
 
@@ -305,6 +444,84 @@ pub fn generate_stat_chart(
     Ok(())
 }
 
+// Each panel holds one value per scene as (start frame, end frame exclusive, value), drawn as a
+// step line so that scene boundaries stay visible.
+#[expect(clippy::type_complexity)]
+pub fn generate_scene_chart(
+    output_filename: &PathBuf,
+    title: &str,
+    panels: &[(String, Vec<(usize, usize, f64)>)],
+) -> anyhow::Result<()> {
+    verify_filename(output_filename).with_context(|| {
+        format!("Unable to verify {title} chart output filename {output_filename:?}")
+    })?;
+
+    let root = SVGBackend::new(output_filename, (1600, 1200)).into_drawing_area();
+
+    root.fill(&WHITE)
+        .with_context(|| format!("Unable to fill {title} chart background"))?;
+
+    let root = root
+        .titled(title, ("Arial", 32_i32).into_font())
+        .with_context(|| format!("Unable to draw {title} chart title"))?;
+
+    let length = panels
+        .iter()
+        .flat_map(|(_, data)| data.iter().map(|(_, end, _)| *end))
+        .max()
+        .unwrap_or(1);
+
+    for (i, (area, (name, data))) in root
+        .split_evenly((panels.len(), 1))
+        .iter()
+        .zip(panels)
+        .enumerate()
+    {
+        let values: Vec<f64> = data
+            .iter()
+            .map(|(_, _, value)| *value)
+            .filter(|value| value.is_finite())
+            .collect();
+
+        if values.is_empty() {
+            continue;
+        }
+
+        let stats = Data::new(values);
+        let y_range = (stats.max() - stats.min()).max(f64::EPSILON);
+        let y_min = y_range.mul_add(-0.05, stats.min());
+        let y_max = y_range.mul_add(0.05, stats.max());
+
+        let mut chart = ChartBuilder::on(area)
+            .caption(name, ("Arial", 20_i32).into_font())
+            .margin(5_i32)
+            .set_label_area_size(LabelAreaPosition::Bottom, 30_i32)
+            .set_label_area_size(LabelAreaPosition::Left, 60_i32)
+            .set_label_area_size(LabelAreaPosition::Right, 50_i32)
+            .build_cartesian_2d(0..length, y_min..y_max)
+            .with_context(|| format!("Unable to build {name} panel for {title} chart"))?;
+
+        chart
+            .configure_mesh()
+            .draw()
+            .with_context(|| format!("Unable to configure mesh for {name} panel"))?;
+
+        chart
+            .draw_series(LineSeries::new(
+                data.iter()
+                    .filter(|(_, _, value)| value.is_finite())
+                    .flat_map(|(start, end, value)| [(*start, *value), (*end, *value)]),
+                Palette99::pick(i),
+            ))
+            .with_context(|| format!("Unable to draw {name} panel for {title} chart"))?;
+    }
+
+    root.present()
+        .with_context(|| format!("Unable to finalize {title} chart"))?;
+
+    Ok(())
+}
+
 pub fn generate_stat_log(
     output_filename: &PathBuf,
     title: &str,
@@ -335,6 +552,16 @@ pub fn generate_stat_log(
     Ok(())
 }
 
+// Repeats each value once for every frame it covers, so that statistics of the result weight each
+// value by its length.
+#[must_use]
+pub fn weight_by_length(values: impl IntoIterator<Item = (f64, usize)>) -> Vec<f64> {
+    values
+        .into_iter()
+        .flat_map(|(value, length)| iter::repeat_n(value, length))
+        .collect()
+}
+
 pub fn print_stats(stats: &mut Vec<(String, Vec<f64>)>) -> anyhow::Result<()> {
     let mut table = table!([
         "",
@@ -394,6 +621,108 @@ pub fn verify_filename(path: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Caches are written to a temporary file that is synced to disk before being renamed over the
+// original, so an interrupted run leaves either the old contents or the new ones but never a
+// truncated file.
+pub fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> anyhow::Result<()> {
+    verify_filename(path).with_context(|| format!("Unable to verify path {path:?}"))?;
+
+    let temporary_path = path.with_extension("tmp.json");
+
+    let file = File::create(&temporary_path)
+        .with_context(|| format!("Unable to create {temporary_path:?}"))?;
+    let mut writer = BufWriter::new(file);
+
+    serde_json::to_writer_pretty(&mut writer, value)
+        .with_context(|| format!("Unable to serialize {temporary_path:?}"))?;
+
+    writer
+        .into_inner()
+        .with_context(|| format!("Unable to flush {temporary_path:?}"))?
+        .sync_all()
+        .with_context(|| format!("Unable to sync {temporary_path:?}"))?;
+
+    rename(&temporary_path, path)
+        .with_context(|| format!("Unable to rename {temporary_path:?} to {path:?}"))?;
+
+    Ok(())
+}
+
+// A cache that cannot be parsed, such as one truncated by an older version that wrote in place, is
+// treated as missing so that it is regenerated instead of aborting the run.
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Option<T>> {
+    if !path.exists() {
+        debug!("Cache miss for {path:?}");
+        return Ok(None);
+    }
+
+    let file = File::open(path).with_context(|| format!("Unable to open {path:?}"))?;
+
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(value) => {
+            debug!("Cache hit for {path:?}");
+            Ok(Some(value))
+        }
+        Err(error) => {
+            warn!("Unable to parse {path:?} ({error}). Regenerating it.");
+            Ok(None)
+        }
+    }
+}
+
+/// A cache format that records the version of its schema.
+pub trait Versioned: Sized {
+    /// The schema version written by this build.
+    const VERSION: u32;
+
+    fn version(&self) -> u32;
+
+    fn set_version(&mut self, version: u32);
+
+    /// Converts a cache written with an older schema to the current one, or returns `None` if it
+    /// has to be regenerated instead. The version itself is updated afterwards, so schemas that
+    /// only differ in it need nothing here.
+    fn migrate(self) -> Option<Self> {
+        Some(self)
+    }
+}
+
+// Caches written before versioning existed report version 0, which only lacked the version itself.
+// Older caches are migrated and rewritten in place. A cache from a newer build is refused rather
+// than overwritten, since that build may still need it and this one could misread fields whose
+// meaning has since changed.
+pub fn read_versioned_json<T: DeserializeOwned + Serialize + Versioned>(
+    path: &Path,
+) -> anyhow::Result<Option<T>> {
+    let Some(value) = read_json::<T>(path)? else {
+        return Ok(None);
+    };
+
+    let version = value.version();
+
+    if version == T::VERSION {
+        return Ok(Some(value));
+    }
+
+    if version > T::VERSION {
+        return Err(anyhow!(
+            "{path:?} uses schema version {version}, which is newer than the supported version {}",
+            T::VERSION
+        ));
+    }
+
+    let Some(mut value) = value.migrate() else {
+        warn!("Unable to migrate {path:?} from schema version {version}. Regenerating it.");
+        return Ok(None);
+    };
+
+    value.set_version(T::VERSION);
+
+    write_json(path, &value).with_context(|| format!("Unable to write migrated {path:?}"))?;
+
+    Ok(Some(value))
+}
+
 pub fn verify_directory(path: &Path) -> anyhow::Result<()> {
     if path.exists() {
         if !path.is_dir() {
@@ -417,3 +746,109 @@ impl Display for HumanBitrate {
         }
     }
 }
+
+// Paths inside a filter graph are quoted, and Windows drive letters and separators would otherwise
+// be taken as option delimiters and escapes.
+#[must_use]
+pub fn escape_filter_path(path: &Path) -> String {
+    let path = path
+        .to_string_lossy()
+        .replace('\\', "/")
+        .replace(':', "\\:")
+        .replace('\'', "'\\''");
+
+    format!("'{path}'")
+}
+
+// Only Linux reports the available memory in a convenient form, so elsewhere it is not considered.
+#[must_use]
+pub fn available_memory() -> Option<u64> {
+    let meminfo = read_to_string("/proc/meminfo").ok()?;
+
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(|kilobytes| kilobytes * 1024)
+}
+
+#[must_use]
+pub fn hash_string(value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(value);
+    encode_string(&hasher.finalize())
+}
+
+// A file replaced in place must not be served stale cached results, so keys for it include its
+// size and modification time.
+#[must_use]
+pub fn file_cache_key(path: &Path) -> String {
+    let file_metadata = path.metadata().ok();
+
+    let modified = file_metadata
+        .as_ref()
+        .and_then(|metadata| metadata.modified().ok())
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default()
+        .as_nanos();
+
+    format!(
+        "{}:{}:{modified}",
+        path.to_string_lossy(),
+        file_metadata.map_or(0, |metadata| metadata.len())
+    )
+}
+
+// Only a few sources are hashed in one run, but library users may go through many of them in one
+// process.
+#[cached(
+    result = true,
+    ty = "SizedCache<String, String>",
+    create = "{ SizedCache::with_size(16) }",
+    convert = r#"{ file_cache_key(path) }"#
+)]
+pub fn hash_source(path: &Path) -> anyhow::Result<String> {
+    hash_file(path)
+}
+
+// Hashing an entire multi-gigabyte file on every run would be prohibitively slow, so only the file
+// size and the first and last 16 MiB are hashed. This is enough to distinguish different files
+// that happen to share a name. Unlike hash_source, this is not cached, since files such as encoded
+// chunks may be replaced under the same name.
+pub fn hash_file(path: &Path) -> anyhow::Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Unable to open {path:?} for hashing"))?;
+
+    let length = file
+        .metadata()
+        .with_context(|| format!("Unable to read file metadata for {path:?}"))?
+        .len();
+
+    let mut hasher = Sha256::new();
+    hasher.update(length.to_le_bytes());
+
+    let mut buffer = vec![];
+
+    (&mut file)
+        .take(SOURCE_HASH_SAMPLE_SIZE)
+        .read_to_end(&mut buffer)
+        .with_context(|| format!("Unable to read the beginning of {path:?}"))?;
+
+    if length > SOURCE_HASH_SAMPLE_SIZE {
+        file.seek(SeekFrom::Start(
+            (length - SOURCE_HASH_SAMPLE_SIZE).max(SOURCE_HASH_SAMPLE_SIZE),
+        ))
+        .with_context(|| format!("Unable to seek within {path:?}"))?;
+
+        file.read_to_end(&mut buffer)
+            .with_context(|| format!("Unable to read the end of {path:?}"))?;
+    }
+
+    hasher.update(&buffer);
+
+    Ok(encode_string(&hasher.finalize()))
+}